@@ -1,29 +1,78 @@
+use crate::bigint::BigInt;
+use crate::token::Span;
 use std::fmt;
 
 pub type Identifier = String;
 
+/// Where the variable a name refers to is kept while the evaluator runs, as
+/// its resolver works out before a program starts. The parser leaves every
+/// name `Unresolved`, as the resolver leaves a name bound nowhere, which is
+/// looked up by name when it's reached; the compiler keeps its own symbol
+/// table instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Slot {
+    #[default]
+    Unresolved,
+    /// Slot `index` of the environment `depth` scopes out from where the name
+    /// appears: a parameter, a loop or catch variable, or a name bound inside
+    /// a function or loop body. Until it's bound, it's null.
+    Local { depth: usize, index: usize },
+    /// Slot `index` of the globals of the outermost environment, which the
+    /// program was started in: a name bound at the top level.
+    Global { index: usize },
+    /// A builtin or a function the host registered, looked up by name.
+    Builtin,
+}
+
+// Every node carries the span of source text it was parsed from as its last field.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
-    Let(Identifier, Expression),
-    Return(Expression),
-    Expression(Expression),
+    Let(Identifier, Slot, Expression, Span),
+    Return(Expression, Span),
+    Expression(Expression, Span),
+    While(Expression, BlockStatement, Span),
+    // the loop variables (one, or a key and value), the iterable and the body
+    For(Vec<Identifier>, Expression, BlockStatement, Span),
+    Break(Span),
+    Continue(Span),
+    // the path as written and the name the module is bound to
+    Import(String, Identifier, Span),
+    Throw(Expression, Span),
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
-    Ident(Identifier),
-    IntegerLiteral(i64),
-    StringLiteral(String),
-    Prefix(Identifier, Box<Expression>),
-    Infix(Box<Expression>, Identifier, Box<Expression>),
-    Boolean(bool),
-    IfExpression(Box<Expression>, BlockStatement, Option<BlockStatement>),
-    FunctionLiteral(Vec<Identifier>, BlockStatement),
-    CallExpression(Box<Expression>, Vec<Expression>),
-    ArrayLiteral(Vec<Expression>),
-    IndexExpression(Box<Expression>, Box<Expression>),
-    HashLiteral(Vec<(Expression, Expression)>),
-    None,
+    Ident(Identifier, Slot, Span),
+    IntegerLiteral(i64, Span),
+    // an integer literal too large for an i64
+    BigIntLiteral(BigInt, Span),
+    FloatLiteral(f64, Span),
+    StringLiteral(String, Span),
+    Prefix(Identifier, Box<Expression>, Span),
+    Infix(Box<Expression>, Identifier, Box<Expression>, Span),
+    Boolean(bool, Span),
+    IfExpression(
+        Box<Expression>,
+        BlockStatement,
+        Option<BlockStatement>,
+        Span,
+    ),
+    FunctionLiteral(Vec<Identifier>, BlockStatement, Span),
+    CallExpression(Box<Expression>, Vec<Expression>, Span),
+    ArrayLiteral(Vec<Expression>, Span),
+    IndexExpression(Box<Expression>, Box<Expression>, Span),
+    HashLiteral(Vec<(Expression, Expression)>, Span),
+    // target, operator (`=`, `+=`, ...), value
+    Assign(Box<Expression>, Identifier, Box<Expression>, Span),
+    // the body, the catch variable and block, and the finally block; at least
+    // one of the last two is present
+    Try(
+        BlockStatement,
+        Option<(Identifier, BlockStatement)>,
+        Option<BlockStatement>,
+        Span,
+    ),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -36,6 +85,60 @@ pub struct Program {
     pub statements: Vec<Statement>,
 }
 
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Let(_, _, _, span) => *span,
+            Statement::Return(_, span) => *span,
+            Statement::Expression(_, span) => *span,
+            Statement::While(_, _, span) => *span,
+            Statement::For(_, _, _, span) => *span,
+            Statement::Break(span) => *span,
+            Statement::Continue(span) => *span,
+            Statement::Import(_, _, span) => *span,
+            Statement::Throw(_, span) => *span,
+        }
+    }
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Ident(_, _, span) => *span,
+            Expression::IntegerLiteral(_, span) => *span,
+            Expression::BigIntLiteral(_, span) => *span,
+            Expression::FloatLiteral(_, span) => *span,
+            Expression::StringLiteral(_, span) => *span,
+            Expression::Prefix(_, _, span) => *span,
+            Expression::Infix(_, _, _, span) => *span,
+            Expression::Boolean(_, span) => *span,
+            Expression::IfExpression(_, _, _, span) => *span,
+            Expression::FunctionLiteral(_, _, span) => *span,
+            Expression::CallExpression(_, _, span) => *span,
+            Expression::ArrayLiteral(_, span) => *span,
+            Expression::IndexExpression(_, _, span) => *span,
+            Expression::HashLiteral(_, span) => *span,
+            Expression::Assign(_, _, _, span) => *span,
+            Expression::Try(_, _, _, span) => *span,
+        }
+    }
+
+    /// Splits an assignment target such as `a[1]["k"]` into the variable it
+    /// updates and the indexes leading to the assigned element, outermost
+    /// first. Returns `None` for expressions that can't be assigned to.
+    pub fn assignment_target(&self) -> Option<(&Identifier, Vec<&Expression>)> {
+        match self {
+            Expression::Ident(name, _, _) => Some((name, vec![])),
+            Expression::IndexExpression(left, index, _) => {
+                let (name, mut indexes) = left.assignment_target()?;
+                indexes.push(index);
+                Some((name, indexes))
+            }
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut output = String::from("");
@@ -49,9 +152,17 @@ impl fmt::Display for Program {
 impl fmt::Display for Statement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let output = match &self {
-            Statement::Let(ident, expr) => format!("let {} = {};", ident, expr),
-            Statement::Return(expr) => format!("return {};", expr),
-            Statement::Expression(expr) => expr.to_string(),
+            Statement::Let(ident, _, expr, _) => format!("let {} = {};", ident, expr),
+            Statement::Return(expr, _) => format!("return {};", expr),
+            Statement::Expression(expr, _) => expr.to_string(),
+            Statement::While(condition, body, _) => format!("while{} {}", condition, body),
+            Statement::For(names, iterable, body, _) => {
+                format!("for({} in {}) {}", names.join(", "), iterable, body)
+            }
+            Statement::Break(_) => "break;".to_string(),
+            Statement::Continue(_) => "continue;".to_string(),
+            Statement::Import(path, name, _) => format!("import {:?} as {};", path, name),
+            Statement::Throw(expr, _) => format!("throw {};", expr),
         };
         write!(f, "{}", output)
     }
@@ -60,23 +171,25 @@ impl fmt::Display for Statement {
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let output = match &self {
-            Expression::Ident(ident) => ident.to_string(),
-            Expression::IntegerLiteral(int) => int.to_string(),
-            Expression::StringLiteral(s) => s.to_string(),
-            Expression::Prefix(operator, expr) => format!("({}{})", operator, expr),
-            Expression::Infix(left, operator, right) => {
+            Expression::Ident(ident, _, _) => ident.to_string(),
+            Expression::IntegerLiteral(int, _) => int.to_string(),
+            Expression::BigIntLiteral(int, _) => int.to_string(),
+            Expression::FloatLiteral(float, _) => format!("{:?}", float),
+            Expression::StringLiteral(s, _) => s.to_string(),
+            Expression::Prefix(operator, expr, _) => format!("({}{})", operator, expr),
+            Expression::Infix(left, operator, right, _) => {
                 format!("({} {} {})", left, operator, right)
             }
-            Expression::Boolean(b) => b.to_string(),
-            Expression::IfExpression(condition, consequence, alternative) => {
-                let s = format!("if{} {}", condition.to_string(), consequence.to_string());
+            Expression::Boolean(b, _) => b.to_string(),
+            Expression::IfExpression(condition, consequence, alternative, _) => {
+                let s = format!("if{} {}", condition, consequence);
                 if let Some(alt) = alternative {
-                    format!("{}else {}", s, alt.to_string())
+                    format!("{}else {}", s, alt)
                 } else {
                     s
                 }
             }
-            Expression::CallExpression(function, arguments) => {
+            Expression::CallExpression(function, arguments, _) => {
                 let mut args = vec![];
                 for arg in arguments {
                     args.push(arg.to_string());
@@ -84,25 +197,39 @@ impl fmt::Display for Expression {
 
                 format!("{}({})", function, args.join(", "))
             }
-            Expression::FunctionLiteral(parms, body) => format!("{}({})", parms.join(", "), body),
-            Expression::ArrayLiteral(elements) => {
+            Expression::FunctionLiteral(parms, body, _) => {
+                format!("{}({})", parms.join(", "), body)
+            }
+            Expression::ArrayLiteral(elements, _) => {
                 let mut e = vec![];
                 for element in elements {
                     e.push(element.to_string());
                 }
                 format!("[{}]", e.join(", "))
             }
-            Expression::IndexExpression(left, index) => {
-                format!("({}[{}])", left.to_string(), index.to_string())
+            Expression::IndexExpression(left, index, _) => {
+                format!("({}[{}])", left, index)
             }
-            Expression::HashLiteral(pairs) => {
+            Expression::HashLiteral(pairs, _) => {
                 let mut s = vec![];
                 for (k, v) in pairs {
-                    s.push(format!("{}:{}", k.to_string(), v.to_string()));
+                    s.push(format!("{}:{}", k, v));
                 }
                 format!("{{{}}}", s.join(", "))
             }
-            Expression::None => String::from(""),
+            Expression::Assign(target, operator, value, _) => {
+                format!("({} {} {})", target, operator, value)
+            }
+            Expression::Try(body, catch, finally, _) => {
+                let mut s = format!("try {}", body);
+                if let Some((name, handler)) = catch {
+                    s.push_str(&format!("catch({}) {}", name, handler));
+                }
+                if let Some(finally) = finally {
+                    s.push_str(&format!("finally {}", finally));
+                }
+                s
+            }
         };
         write!(f, "{}", output)
     }
@@ -0,0 +1,365 @@
+// Arbitrary-precision integers, for arithmetic whose results don't fit in an
+// INTEGER. Digits are stored in base 10^9, which keeps printing cheap and lets
+// every intermediate product fit in a u64.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
+
+const BASE: u64 = 1_000_000_000;
+
+/// A signed integer of any size. `digits` are base 10^9 and least significant
+/// first, with no leading zeros, so zero has no digits and is never negative.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigInt {
+            negative: negative && !digits.is_empty(),
+            digits,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// The value as an `i64`, if it fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        self.to_i128().and_then(|i| i64::try_from(i).ok())
+    }
+
+    /// The value as an `i128`, if it fits.
+    pub fn to_i128(&self) -> Option<i128> {
+        let magnitude = self.magnitude()?;
+        if !self.negative {
+            return i128::try_from(magnitude).ok();
+        }
+        // i128::MIN's magnitude is one more than i128::MAX
+        if magnitude > i128::MAX as u128 + 1 {
+            return None;
+        }
+        Some((magnitude as i128).wrapping_neg())
+    }
+
+    /// The value as a `u128`, if it fits.
+    pub fn to_u128(&self) -> Option<u128> {
+        if self.negative {
+            return None;
+        }
+        self.magnitude()
+    }
+
+    fn magnitude(&self) -> Option<u128> {
+        // 5 digits hold up to 10^45, more than any u128
+        if self.digits.len() > 5 {
+            return None;
+        }
+        self.digits.iter().rev().try_fold(0u128, |acc, d| {
+            acc.checked_mul(BASE as u128)?.checked_add(*d as u128)
+        })
+    }
+
+    /// The nearest `f64`, which is infinite for values beyond its range.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .digits
+            .iter()
+            .rev()
+            .fold(0.0, |acc, d| acc * BASE as f64 + *d as f64);
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// Divides, truncating toward zero like INTEGER division. Returns `None`
+    /// when `divisor` is zero.
+    pub fn div(&self, divisor: &BigInt) -> Option<BigInt> {
+        if divisor.is_zero() {
+            return None;
+        }
+        let (quotient, _) = div_rem_magnitude(&self.digits, &divisor.digits);
+        Some(BigInt::new(self.negative != divisor.negative, quotient))
+    }
+
+    /// The remainder of `div`, which takes the sign of `self`. Returns `None`
+    /// when `divisor` is zero.
+    pub fn rem(&self, divisor: &BigInt) -> Option<BigInt> {
+        if divisor.is_zero() {
+            return None;
+        }
+        let (_, remainder) = div_rem_magnitude(&self.digits, &divisor.digits);
+        Some(BigInt::new(self.negative, remainder))
+    }
+}
+
+/// Parses decimal digits with an optional leading `-` or `+`.
+impl FromStr for BigInt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BigInt, String> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("invalid digit in {:?}", s));
+        }
+        let mut chunks = vec![];
+        let mut end = digits.len();
+        while end > 0 {
+            let start = end.saturating_sub(9);
+            // a run of at most nine ASCII digits always fits
+            chunks.push(digits[start..end].parse::<u32>().unwrap());
+            end = start;
+        }
+        Ok(BigInt::new(negative, chunks))
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(i: i64) -> BigInt {
+        BigInt::from(i128::from(i))
+    }
+}
+
+impl From<u64> for BigInt {
+    fn from(u: u64) -> BigInt {
+        BigInt::from(u128::from(u))
+    }
+}
+
+impl From<i128> for BigInt {
+    fn from(i: i128) -> BigInt {
+        let magnitude = BigInt::from(i.unsigned_abs());
+        BigInt::new(i < 0, magnitude.digits)
+    }
+}
+
+impl From<u128> for BigInt {
+    fn from(mut magnitude: u128) -> BigInt {
+        let mut digits = vec![];
+        while magnitude > 0 {
+            digits.push((magnitude % BASE as u128) as u32);
+            magnitude /= BASE as u128;
+        }
+        BigInt::new(false, digits)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.digits, &other.digits),
+            (true, true) => cmp_magnitude(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitude(&self.digits, &other.digits));
+        }
+        // the sign of the result is the sign of the larger magnitude
+        match cmp_magnitude(&self.digits, &other.digits) {
+            Ordering::Less => {
+                BigInt::new(other.negative, sub_magnitude(&other.digits, &self.digits))
+            }
+            _ => BigInt::new(self.negative, sub_magnitude(&self.digits, &other.digits)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut product = vec![0u32; self.digits.len() + other.digits.len()];
+        for (i, a) in self.digits.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.digits.iter().enumerate() {
+                let t = product[i + j] as u64 + *a as u64 * *b as u64 + carry;
+                product[i + j] = (t % BASE) as u32;
+                carry = t / BASE;
+            }
+            product[i + other.digits.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, product)
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut digits = self.digits.iter().rev();
+        match digits.next() {
+            Some(most_significant) => {
+                if self.negative {
+                    write!(f, "-")?;
+                }
+                write!(f, "{}", most_significant)?;
+                for d in digits {
+                    write!(f, "{:09}", d)?;
+                }
+                Ok(())
+            }
+            None => write!(f, "0"),
+        }
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let t = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push((t % BASE) as u32);
+        carry = t / BASE;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+// Requires `a` to be at least as large as `b`.
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, d) in a.iter().enumerate() {
+        let mut t = *d as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if t < 0 {
+            t += BASE as i64;
+            borrow = 1;
+        }
+        difference.push(t as u32);
+    }
+    difference
+}
+
+fn mul_small(a: &[u32], factor: u64) -> Vec<u32> {
+    let mut product = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u64;
+    for d in a {
+        let t = *d as u64 * factor + carry;
+        product.push((t % BASE) as u32);
+        carry = t / BASE;
+    }
+    product.push(carry as u32);
+    product
+}
+
+fn div_rem_small(a: &[u32], divisor: u64) -> (Vec<u32>, u64) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+    for (i, d) in a.iter().enumerate().rev() {
+        let t = remainder * BASE + *d as u64;
+        quotient[i] = (t / divisor) as u32;
+        remainder = t % divisor;
+    }
+    (quotient, remainder)
+}
+
+// Long division of magnitudes (Knuth's algorithm D). `b` must not be zero.
+fn div_rem_magnitude(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_magnitude(a, b) == Ordering::Less {
+        return (vec![], a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, remainder) = div_rem_small(a, b[0] as u64);
+        return (quotient, vec![remainder as u32]);
+    }
+
+    // scale both so the divisor's top digit is at least BASE / 2, which keeps
+    // each estimated quotient digit at most two too large
+    let scale = BASE / (*b.last().unwrap() as u64 + 1);
+    let mut u = mul_small(a, scale);
+    let v = mul_small(b, scale);
+    let n = b.len();
+    let (v_top, v_next) = (v[n - 1] as u64, v[n - 2] as u64);
+    let mut quotient = vec![0u32; a.len() - n + 1];
+
+    for j in (0..quotient.len()).rev() {
+        let numerator = u[j + n] as u64 * BASE + u[j + n - 1] as u64;
+        let mut q = numerator / v_top;
+        let mut r = numerator % v_top;
+        while q >= BASE || q * v_next > r * BASE + u[j + n - 2] as u64 {
+            q -= 1;
+            r += v_top;
+            if r >= BASE {
+                break;
+            }
+        }
+
+        // subtract q * v from the window of u starting at j
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..=n {
+            let p = q * *v.get(i).unwrap_or(&0) as u64 + carry;
+            carry = p / BASE;
+            let mut t = u[i + j] as i64 - (p % BASE) as i64 - borrow;
+            borrow = 0;
+            if t < 0 {
+                t += BASE as i64;
+                borrow = 1;
+            }
+            u[i + j] = t as u32;
+        }
+        if borrow != 0 {
+            // q was one too large: add v back
+            q -= 1;
+            let mut carry = 0u64;
+            for i in 0..=n {
+                let t = u[i + j] as u64 + *v.get(i).unwrap_or(&0) as u64 + carry;
+                u[i + j] = (t % BASE) as u32;
+                carry = t / BASE;
+            }
+        }
+        quotient[j] = q as u32;
+    }
+
+    let (remainder, _) = div_rem_small(&u[..n], scale);
+    (quotient, remainder)
+}
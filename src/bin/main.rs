@@ -1,20 +1,43 @@
-use monkey;
+use monkey::diagnostics::Style;
 use monkey::repl;
+use monkey::{Backend, Interpreter};
 use std::env;
 use std::error::Error;
 use std::fs;
 
+const BACKEND_FLAG: &str = "--backend=";
+// directories to look for imported modules in, separated like PATH
+const SEARCH_PATH_VAR: &str = "MONKEY_PATH";
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<_> = env::args().collect();
-    if args.len() < 2 {
+    let mut backend = Backend::default();
+    let mut files = vec![];
+    for arg in env::args().skip(1) {
+        match arg.strip_prefix(BACKEND_FLAG) {
+            Some(name) => backend = name.parse()?,
+            None => files.push(arg),
+        }
+    }
+
+    if files.is_empty() {
         println!("Hello!  This is the Monkey programming Language.");
-        repl::start()?;
+        repl::start(backend)?;
     } else {
         //load file
-        let file = &args[1];
+        let file = &files[0];
         let contents = fs::read_to_string(file)?;
-        let output = monkey::interpret_text(&contents);
-        println!("{}", output);
+        let mut interpreter = Interpreter::with_backend(backend);
+        interpreter.set_script_path(file);
+        if let Some(paths) = env::var_os(SEARCH_PATH_VAR) {
+            for dir in env::split_paths(&paths) {
+                interpreter.add_search_dir(dir);
+            }
+        }
+        let output = interpreter.interpret(file, &contents, Style::detect());
+        // a program ending in null, e.g. a call to puts, has nothing to add
+        if !output.is_empty() {
+            println!("{}", output);
+        }
     }
 
     Ok(())
@@ -0,0 +1,226 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+pub type Instructions = Vec<u8>;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Constant,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pop,
+    True,
+    False,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    Minus,
+    Bang,
+    JumpNotTruthy,
+    Jump,
+    Null,
+    GetGlobal,
+    SetGlobal,
+    Array,
+    Hash,
+    Index,
+    Call,
+    ReturnValue,
+    Return,
+    GetLocal,
+    SetLocal,
+    GetBuiltin,
+    Closure,
+    GetFree,
+    CurrentClosure,
+    SetIndex,
+    SetFree,
+    CaptureLocal,
+    CaptureFree,
+    Range,
+    // `for` loops keep what they walk over and how far they've got in two
+    // hidden locals, starting at the slot in the first operand
+    Iter,
+    IterRange,
+    IterNext,
+    ResetLocals,
+    Mod,
+    // runs a module, the first time, and pushes its namespace
+    Import,
+    // `try` blocks: an error inside one truncates the stack to where it was
+    // at `Try`, pushes the error and jumps to the operand
+    Try,
+    EndTry,
+    // turns the error a handler pushed into the value `catch` binds
+    Catch,
+    Throw,
+    // raises the error for a name nothing defines, whose message is the
+    // constant in the operand, when the code using the name runs
+    Unbound,
+}
+
+/// The assignment operators `SetIndex` supports, indexed by its second operand.
+pub const ASSIGN_OPERATORS: [&str; 6] = ["=", "+=", "-=", "*=", "/=", "%="];
+
+// Indexed by the opcode's byte value, so the order must match the enum.
+const OPCODES: [Opcode; 47] = [
+    Opcode::Constant,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Pop,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::GreaterThan,
+    Opcode::LessThan,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::JumpNotTruthy,
+    Opcode::Jump,
+    Opcode::Null,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Index,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Return,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetBuiltin,
+    Opcode::Closure,
+    Opcode::GetFree,
+    Opcode::CurrentClosure,
+    Opcode::SetIndex,
+    Opcode::SetFree,
+    Opcode::CaptureLocal,
+    Opcode::CaptureFree,
+    Opcode::Range,
+    Opcode::Iter,
+    Opcode::IterRange,
+    Opcode::IterNext,
+    Opcode::ResetLocals,
+    Opcode::Mod,
+    Opcode::Import,
+    Opcode::Try,
+    Opcode::EndTry,
+    Opcode::Catch,
+    Opcode::Throw,
+    Opcode::Unbound,
+];
+
+impl TryFrom<u8> for Opcode {
+    type Error = String;
+
+    fn try_from(byte: u8) -> Result<Opcode, String> {
+        OPCODES
+            .get(byte as usize)
+            .copied()
+            .ok_or_else(|| format!("opcode {} undefined", byte))
+    }
+}
+
+impl Opcode {
+    /// The width in bytes of each of the opcode's operands.
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            Opcode::Constant
+            | Opcode::JumpNotTruthy
+            | Opcode::Jump
+            | Opcode::GetGlobal
+            | Opcode::SetGlobal
+            | Opcode::Array
+            | Opcode::Hash
+            | Opcode::Try
+            | Opcode::Unbound => &[2],
+            Opcode::Call
+            | Opcode::GetLocal
+            | Opcode::SetLocal
+            | Opcode::GetBuiltin
+            | Opcode::GetFree
+            | Opcode::SetFree
+            | Opcode::CaptureLocal
+            | Opcode::CaptureFree
+            | Opcode::IterRange => &[1],
+            Opcode::Closure => &[2, 1],
+            Opcode::SetIndex | Opcode::Iter | Opcode::ResetLocals => &[1, 1],
+            Opcode::IterNext => &[1, 1, 2],
+            // the module's function, the global slot keeping its namespace
+            // and the path it was imported by, as constants
+            Opcode::Import => &[2, 2, 2],
+            _ => &[],
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Op{:?}", self)
+    }
+}
+
+/// Encodes an instruction. Operands are stored big-endian.
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let mut instruction = vec![op as u8];
+    for (operand, width) in operands.iter().zip(op.operand_widths()) {
+        match width {
+            2 => instruction.extend_from_slice(&(*operand as u16).to_be_bytes()),
+            1 => instruction.push(*operand as u8),
+            _ => (),
+        }
+    }
+    instruction
+}
+
+/// Decodes the operands following an opcode, returning them with the number of bytes read.
+pub fn read_operands(op: Opcode, ins: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = vec![];
+    let mut offset = 0;
+    for width in op.operand_widths() {
+        match width {
+            2 => operands.push(read_u16(&ins[offset..]) as usize),
+            1 => operands.push(ins[offset] as usize),
+            _ => (),
+        }
+        offset += width;
+    }
+    (operands, offset)
+}
+
+pub fn read_u16(ins: &[u8]) -> u16 {
+    u16::from_be_bytes([ins[0], ins[1]])
+}
+
+/// Renders instructions one per line as `offset opcode operands`, for tests and debugging.
+#[allow(dead_code)]
+pub fn disassemble(ins: &[u8]) -> String {
+    let mut output = String::new();
+    let mut i = 0;
+    while i < ins.len() {
+        match Opcode::try_from(ins[i]) {
+            Ok(op) => {
+                let (operands, read) = read_operands(op, &ins[i + 1..]);
+                let mut line = format!("{:04} {}", i, op);
+                for operand in operands {
+                    line.push_str(&format!(" {}", operand));
+                }
+                output.push_str(&line);
+                output.push('\n');
+                i += 1 + read;
+            }
+            Err(msg) => {
+                output.push_str(&format!("ERROR: {}\n", msg));
+                i += 1;
+            }
+        }
+    }
+    output
+}
@@ -0,0 +1,933 @@
+use crate::ast::{BlockStatement, Expression, Identifier, Program, Statement};
+use crate::code::{self, Instructions, Opcode};
+use crate::diagnostics::ErrorCode;
+use crate::module::{self, ModuleLoader};
+use crate::object::{BuiltIn, CompiledFunction, Object, RuntimeError};
+use crate::resolver;
+use crate::symbol_table::{Symbol, SymbolScope, SymbolTable};
+use crate::token::Span;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// Placeholder operand for jumps that are patched once their target is known.
+const UNPATCHED: usize = 9999;
+
+/// An error found while compiling, such as a name that is never bound.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // like a runtime error, one in a module's code says which file it's in
+        match module::source_file(self.span.file) {
+            Some((name, _)) => write!(f, "{}:{}: {}", name, self.span, self.message),
+            None => write!(f, "{}: {}", self.span, self.message),
+        }
+    }
+}
+
+/// The output of the compiler, ready to be run by the `Vm`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub positions: Vec<(usize, Span)>,
+    pub constants: Vec<Object>,
+    pub global_names: Vec<String>,
+    // local slots the top level uses for its blocks
+    pub num_locals: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EmittedInstruction {
+    opcode: Opcode,
+    position: usize,
+}
+
+// The jumps of a loop being compiled, patched once the loop's next iteration
+// and exit are known.
+#[derive(Debug, Default)]
+struct Loop {
+    continues: Vec<usize>,
+    breaks: Vec<usize>,
+    // the `try` regions open where the loop starts
+    tries: usize,
+}
+
+// A `try` body or `catch` block being compiled. A jump or return leaving it
+// first drops its handler, if it has one, and runs its finally block.
+#[derive(Debug, Clone)]
+struct TryRegion {
+    handler: bool,
+    finally: Option<BlockStatement>,
+}
+
+#[derive(Debug, Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    positions: Vec<(usize, Span)>,
+    last_instruction: Option<EmittedInstruction>,
+    previous_instruction: Option<EmittedInstruction>,
+    loops: Vec<Loop>,
+    tries: Vec<TryRegion>,
+}
+
+// A module compiled into the program: the constants holding its function,
+// and the global slot its namespace is kept in once it has run.
+#[derive(Debug, Clone, Copy)]
+struct CompiledModule {
+    function: usize,
+    namespace: usize,
+}
+
+/// Compiles programs to bytecode. A compiler keeps its globals and constants
+/// between calls to `compile`, so a REPL can compile one line at a time.
+///
+/// Imported modules are compiled along with the program that first imports
+/// them, each into a function that runs the module and returns its namespace.
+/// Their globals get slots of their own that only the module's code sees.
+#[derive(Debug)]
+pub struct Compiler {
+    constants: Vec<Object>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+    modules: ModuleLoader,
+    compiled_modules: HashMap<PathBuf, CompiledModule>,
+    // names of the module globals, for the VM's error messages
+    module_globals: Vec<(usize, String)>,
+}
+
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Compiler {
+        let mut symbol_table = SymbolTable::default();
+        for (index, name) in BuiltIn::NAMES.iter().enumerate() {
+            symbol_table.define_builtin(index, name);
+        }
+        Compiler {
+            constants: vec![],
+            symbol_table,
+            scopes: vec![CompilationScope::default()],
+            modules: ModuleLoader::default(),
+            compiled_modules: HashMap::new(),
+            module_globals: vec![],
+        }
+    }
+
+    pub fn modules(&mut self) -> &mut ModuleLoader {
+        &mut self.modules
+    }
+
+    /// Makes `name` refer to the VM's builtin at `index`.
+    pub fn define_builtin(&mut self, index: usize, name: &str) {
+        self.symbol_table.define_builtin(index, name);
+    }
+
+    /// Defines a global binding ahead of any program, returning its slot.
+    pub fn define_global(&mut self, name: &str) -> usize {
+        self.symbol_table.define(name).index
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<Bytecode, CompileError> {
+        let symbol_table = self.symbol_table.clone();
+        let compiled_modules = self.compiled_modules.clone();
+        let module_globals = self.module_globals.len();
+        self.scopes = vec![CompilationScope::default()];
+        self.symbol_table.num_top_level_locals = 0;
+        match self.compile_program(program) {
+            Ok(()) => {
+                let scope = &self.scopes[0];
+                let mut global_names = self.symbol_table.global_names();
+                for (index, name) in &self.module_globals {
+                    global_names[*index] = name.clone();
+                }
+                Ok(Bytecode {
+                    instructions: scope.instructions.clone(),
+                    positions: scope.positions.clone(),
+                    constants: self.constants.clone(),
+                    global_names,
+                    num_locals: self.symbol_table.num_top_level_locals,
+                })
+            }
+            Err(err) => {
+                // forget the names and modules the failed program defined
+                self.symbol_table = symbol_table;
+                self.compiled_modules = compiled_modules;
+                self.module_globals.truncate(module_globals);
+                Err(err)
+            }
+        }
+    }
+
+    fn compile_program(&mut self, program: &Program) -> Result<(), CompileError> {
+        // top-level functions may refer to globals that are bound further down
+        for statement in &program.statements {
+            if let Statement::Let(name, _, _, _) | Statement::Import(_, name, _) = statement {
+                self.symbol_table.define(name);
+            }
+        }
+
+        for statement in &program.statements {
+            self.compile_statement(statement)?;
+        }
+
+        // like the evaluator, a program ending in `let` evaluates to the bound
+        // value and one ending in a loop evaluates to null
+        match program.statements.last() {
+            Some(Statement::Let(name, _, _, span)) | Some(Statement::Import(_, name, span)) => {
+                self.load_name(name, *span);
+                self.emit(Opcode::Pop, &[], *span);
+            }
+            Some(Statement::While(_, _, span)) | Some(Statement::For(_, _, _, span)) => {
+                self.emit(Opcode::Null, &[], *span);
+                self.emit(Opcode::Pop, &[], *span);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        match statement {
+            Statement::Expression(expr, span) => {
+                self.compile_expression(expr)?;
+                self.emit(Opcode::Pop, &[], *span);
+            }
+            Statement::Return(expr, span) => {
+                self.compile_expression(expr)?;
+                self.exit_tries(0, *span)?;
+                self.emit(Opcode::ReturnValue, &[], *span);
+            }
+            Statement::Let(name, _, expr, span) => {
+                match expr {
+                    Expression::FunctionLiteral(parms, body, span) => {
+                        self.compile_function(parms, body, Some(name), *span)?
+                    }
+                    _ => self.compile_expression(expr)?,
+                }
+                let symbol = self.symbol_table.define(name);
+                match symbol.scope {
+                    SymbolScope::Global => self.emit(Opcode::SetGlobal, &[symbol.index], *span),
+                    _ => self.emit(Opcode::SetLocal, &[symbol.index], *span),
+                };
+            }
+            Statement::While(condition, body, span) => {
+                let start = self.current_instructions().len();
+                self.compile_expression(condition)?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[UNPATCHED], *span);
+                let the_loop = self.compile_loop_body(body)?;
+                self.emit(Opcode::Jump, &[start], *span);
+
+                let after_loop = self.current_instructions().len();
+                self.change_operand(jump_not_truthy, after_loop);
+                self.patch_loop(the_loop, start, after_loop);
+            }
+            Statement::For(names, iterable, body, span) => {
+                self.compile_for(names, iterable, body, *span)?
+            }
+            Statement::Break(span) => {
+                let tries = self.current_loop().tries;
+                self.exit_tries(tries, *span)?;
+                let position = self.emit(Opcode::Jump, &[UNPATCHED], *span);
+                self.current_loop().breaks.push(position);
+            }
+            Statement::Continue(span) => {
+                let tries = self.current_loop().tries;
+                self.exit_tries(tries, *span)?;
+                let position = self.emit(Opcode::Jump, &[UNPATCHED], *span);
+                self.current_loop().continues.push(position);
+            }
+            Statement::Throw(expr, span) => {
+                self.compile_expression(expr)?;
+                self.emit(Opcode::Throw, &[], *span);
+            }
+            Statement::Import(path, name, span) => {
+                // errors in the module's code are located there, others at
+                // the import
+                let module = self.compile_module(path).map_err(|err| CompileError {
+                    code: err.code,
+                    message: err.message,
+                    span: err.span.unwrap_or(*span),
+                })?;
+                let path = self.add_constant(Object::String(path.clone()));
+                let operands = [module.function, module.namespace, path];
+                self.emit(Opcode::Import, &operands, *span);
+                let symbol = self.symbol_table.define(name);
+                self.emit(Opcode::SetGlobal, &[symbol.index], *span);
+            }
+        }
+        Ok(())
+    }
+
+    // Compiles the module `path` names, unless the program already has it.
+    fn compile_module(&mut self, path: &str) -> Result<CompiledModule, RuntimeError> {
+        let file = self.modules.resolve(path)?;
+        if let Some(module) = self.compiled_modules.get(&file) {
+            return Ok(*module);
+        }
+        let program = self.modules.begin(&file)?;
+        let compiled = self.compile_module_function(&program);
+        self.modules.end();
+        let function = compiled.map_err(|err| {
+            let err = RuntimeError {
+                span: Some(err.span),
+                ..RuntimeError::new(err.code, err.message)
+            };
+            module::in_module(path, err)
+        })?;
+        let module = CompiledModule {
+            function: self.add_constant(Object::CompiledFunction(Rc::new(function))),
+            namespace: self.symbol_table.define_hidden_global(),
+        };
+        self.compiled_modules.insert(file, module);
+        Ok(module)
+    }
+
+    // The module's code in a function of its own, with a global table of its
+    // own, that ends by returning the module's namespace.
+    fn compile_module_function(
+        &mut self,
+        program: &Program,
+    ) -> Result<CompiledFunction, CompileError> {
+        let module_table = self.symbol_table.new_module();
+        let importer_table = std::mem::replace(&mut self.symbol_table, module_table);
+        self.scopes.push(CompilationScope::default());
+        let compiled = self.compile_program(program);
+        let globals = self.symbol_table.globals();
+        if compiled.is_ok() {
+            let span = program
+                .statements
+                .last()
+                .map(Statement::span)
+                .unwrap_or_default();
+            for global in &globals {
+                let name = self.add_constant(Object::String(global.name.clone()));
+                self.emit(Opcode::Constant, &[name], span);
+                self.emit(Opcode::GetGlobal, &[global.index], span);
+            }
+            self.emit(Opcode::Hash, &[globals.len() * 2], span);
+            self.emit(Opcode::ReturnValue, &[], span);
+        }
+        let scope = self.scopes.pop().expect("compiler has no scope");
+        let module_table = std::mem::replace(&mut self.symbol_table, importer_table);
+        // the module's slots stay taken
+        self.symbol_table.num_definitions = module_table.num_definitions;
+        compiled?;
+
+        for global in globals {
+            self.module_globals.push((global.index, global.name));
+        }
+        Ok(CompiledFunction {
+            instructions: scope.instructions,
+            positions: scope.positions,
+            num_locals: module_table.num_top_level_locals,
+            parameters: vec![],
+            body: String::new(),
+        })
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) -> Result<(), CompileError> {
+        let span = expression.span();
+        match expression {
+            Expression::IntegerLiteral(i, _) => {
+                let constant = self.add_constant(Object::Integer(*i));
+                self.emit(Opcode::Constant, &[constant], span);
+            }
+            Expression::BigIntLiteral(i, _) => {
+                let constant = self.add_constant(Object::from_bigint(i.clone()));
+                self.emit(Opcode::Constant, &[constant], span);
+            }
+            Expression::FloatLiteral(f, _) => {
+                let constant = self.add_constant(Object::Float(*f));
+                self.emit(Opcode::Constant, &[constant], span);
+            }
+            Expression::StringLiteral(s, _) => {
+                let constant = self.add_constant(Object::String(s.clone()));
+                self.emit(Opcode::Constant, &[constant], span);
+            }
+            Expression::Boolean(true, _) => {
+                self.emit(Opcode::True, &[], span);
+            }
+            Expression::Boolean(false, _) => {
+                self.emit(Opcode::False, &[], span);
+            }
+            Expression::Prefix(operator, right, _) => {
+                self.compile_expression(right)?;
+                let op = match operator.as_str() {
+                    "!" => Opcode::Bang,
+                    "-" => Opcode::Minus,
+                    _ => return Err(unknown_operator(operator, span)),
+                };
+                self.emit(op, &[], span);
+            }
+            Expression::Infix(left, operator, right, _) => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                let op = match operator.as_str() {
+                    "+" => Opcode::Add,
+                    "-" => Opcode::Sub,
+                    "*" => Opcode::Mul,
+                    "/" => Opcode::Div,
+                    "%" => Opcode::Mod,
+                    "==" => Opcode::Equal,
+                    "!=" => Opcode::NotEqual,
+                    ">" => Opcode::GreaterThan,
+                    "<" => Opcode::LessThan,
+                    ".." => Opcode::Range,
+                    _ => return Err(unknown_operator(operator, span)),
+                };
+                self.emit(op, &[], span);
+            }
+            Expression::IfExpression(condition, consequence, alternative, _) => {
+                self.compile_expression(condition)?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[UNPATCHED], span);
+                self.compile_block_value(consequence, span)?;
+                let jump = self.emit(Opcode::Jump, &[UNPATCHED], span);
+                let after_consequence = self.current_instructions().len();
+                self.change_operand(jump_not_truthy, after_consequence);
+                match alternative {
+                    Some(alternative) => self.compile_block_value(alternative, span)?,
+                    None => {
+                        self.emit(Opcode::Null, &[], span);
+                    }
+                }
+                let after_alternative = self.current_instructions().len();
+                self.change_operand(jump, after_alternative);
+            }
+            Expression::Ident(name, _, _) => self.load_name(name, span),
+            Expression::FunctionLiteral(parms, body, _) => {
+                self.compile_function(parms, body, None, span)?
+            }
+            Expression::CallExpression(function, arguments, _) => {
+                self.compile_expression(function)?;
+                for argument in arguments {
+                    self.compile_expression(argument)?;
+                }
+                self.emit(Opcode::Call, &[arguments.len()], span);
+            }
+            Expression::ArrayLiteral(elements, _) => {
+                for element in elements {
+                    self.compile_expression(element)?;
+                }
+                self.emit(Opcode::Array, &[elements.len()], span);
+            }
+            Expression::IndexExpression(left, index, _) => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit(Opcode::Index, &[], span);
+            }
+            Expression::HashLiteral(pairs, _) => {
+                for (key, value) in pairs {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+                self.emit(Opcode::Hash, &[pairs.len() * 2], span);
+            }
+            Expression::Assign(target, operator, value, _) => {
+                self.compile_assignment(target, operator, value, span)?
+            }
+            Expression::Try(body, catch, finally, _) => {
+                self.compile_try(body, catch.as_ref(), finally.as_ref(), span)?
+            }
+        }
+        Ok(())
+    }
+
+    // The body runs under a handler that leads to the catch block, or when
+    // there's none to a cleanup path that runs the finally block and throws
+    // the error again. The finally block is compiled into every way out: after
+    // the body, after the catch block, on the cleanup path, and before any
+    // jump or return that leaves them.
+    fn compile_try(
+        &mut self,
+        body: &BlockStatement,
+        catch: Option<&(Identifier, BlockStatement)>,
+        finally: Option<&BlockStatement>,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        let mut handler = self.emit(Opcode::Try, &[UNPATCHED], span);
+        self.current_scope().tries.push(TryRegion {
+            handler: true,
+            finally: finally.cloned(),
+        });
+        let compiled = self.compile_block_value(body, span);
+        self.current_scope().tries.pop();
+        compiled?;
+        self.emit(Opcode::EndTry, &[], span);
+        if let Some(finally) = finally {
+            self.compile_finally(finally)?;
+        }
+        let mut jumps_to_end = vec![self.emit(Opcode::Jump, &[UNPATCHED], span)];
+
+        if let Some((name, block)) = catch {
+            let after_body = self.current_instructions().len();
+            self.change_operand(handler, after_body);
+            self.emit(Opcode::Catch, &[], span);
+            // a fresh variable each time, like the evaluator's environment
+            let block_start = self.symbol_table.enter_block();
+            let reset = self.emit(Opcode::ResetLocals, &[block_start, UNPATCHED], span);
+            let symbol = self.symbol_table.define(name);
+            self.declare_bindings(block);
+            self.emit(Opcode::SetLocal, &[symbol.index], span);
+            if finally.is_some() {
+                handler = self.emit(Opcode::Try, &[UNPATCHED], span);
+                self.current_scope().tries.push(TryRegion {
+                    handler: true,
+                    finally: finally.cloned(),
+                });
+            }
+            let compiled = self.compile_block_value(block, span);
+            if finally.is_some() {
+                self.current_scope().tries.pop();
+            }
+            let block_end = self.symbol_table.leave_block();
+            compiled?;
+            self.change_operand(reset, block_end - block_start);
+            if let Some(finally) = finally {
+                self.emit(Opcode::EndTry, &[], span);
+                self.compile_finally(finally)?;
+                jumps_to_end.push(self.emit(Opcode::Jump, &[UNPATCHED], span));
+            }
+        }
+
+        if let Some(finally) = finally {
+            let cleanup = self.current_instructions().len();
+            self.change_operand(handler, cleanup);
+            self.symbol_table.enter_block();
+            let error = self.symbol_table.define_hidden();
+            self.symbol_table.leave_block();
+            self.emit(Opcode::SetLocal, &[error.index], span);
+            self.compile_finally(finally)?;
+            self.emit(Opcode::GetLocal, &[error.index], span);
+            self.emit(Opcode::Throw, &[], span);
+        }
+
+        let after_try = self.current_instructions().len();
+        for jump in jumps_to_end {
+            self.change_operand(jump, after_try);
+        }
+        Ok(())
+    }
+
+    // A finally block's value is dropped.
+    fn compile_finally(&mut self, finally: &BlockStatement) -> Result<(), CompileError> {
+        for statement in &finally.statements {
+            self.compile_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    // Leaves the `try` regions past the first `outermost` ones, innermost
+    // first, on the way to a jump or return.
+    fn exit_tries(&mut self, outermost: usize, span: Span) -> Result<(), CompileError> {
+        let tries = self.current_scope().tries.clone();
+        for (depth, region) in tries.iter().enumerate().skip(outermost).rev() {
+            // the finally block itself only runs under the regions outside it
+            self.current_scope().tries.truncate(depth);
+            if region.handler {
+                self.emit(Opcode::EndTry, &[], span);
+            }
+            if let Some(finally) = &region.finally {
+                self.compile_finally(finally)?;
+            }
+        }
+        self.current_scope().tries = tries;
+        Ok(())
+    }
+
+    // Each iteration runs in a block of its own whose locals are reset before
+    // the next one, so closures made by different iterations don't share
+    // variables, like the fresh environments the evaluator gives them.
+    fn compile_for(
+        &mut self,
+        names: &[Identifier],
+        iterable: &Expression,
+        body: &BlockStatement,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        let block_start = self.symbol_table.enter_block();
+        let compiled = self.compile_for_block(names, iterable, body, span, block_start);
+        let block_end = self.symbol_table.leave_block();
+        compiled?;
+        self.emit(
+            Opcode::ResetLocals,
+            &[block_start, block_end - block_start],
+            span,
+        );
+        Ok(())
+    }
+
+    fn compile_for_block(
+        &mut self,
+        names: &[Identifier],
+        iterable: &Expression,
+        body: &BlockStatement,
+        span: Span,
+        state: usize,
+    ) -> Result<(), CompileError> {
+        self.symbol_table.define_hidden();
+        self.symbol_table.define_hidden();
+        match iterable {
+            // counts without building the range's array, like the evaluator
+            Expression::Infix(start, operator, end, _) if operator == ".." && names.len() == 1 => {
+                self.compile_expression(start)?;
+                self.compile_expression(end)?;
+                self.emit(Opcode::IterRange, &[state], iterable.span());
+            }
+            _ => {
+                self.compile_expression(iterable)?;
+                self.emit(Opcode::Iter, &[state, names.len()], iterable.span());
+            }
+        }
+        let symbols: Vec<Symbol> = names
+            .iter()
+            .map(|name| self.symbol_table.define(name))
+            .collect();
+        self.declare_bindings(body);
+        let bindings_start = state + 2;
+
+        let start = self.emit(Opcode::IterNext, &[state, names.len(), UNPATCHED], span);
+        for symbol in symbols.iter().rev() {
+            self.emit(Opcode::SetLocal, &[symbol.index], span);
+        }
+        let the_loop = self.compile_loop_body(body)?;
+        let next = self.current_instructions().len();
+        let block_end = self.symbol_table.next_local();
+        self.emit(
+            Opcode::ResetLocals,
+            &[bindings_start, block_end - bindings_start],
+            span,
+        );
+        self.emit(Opcode::Jump, &[start], span);
+
+        let after_loop = self.current_instructions().len();
+        self.change_operand(start, after_loop);
+        self.patch_loop(the_loop, next, after_loop);
+        Ok(())
+    }
+
+    // Declares the names `body` binds in the innermost block or function
+    // before it's compiled, so the functions in it can refer to names bound
+    // after them, as the resolver lets them in the evaluator.
+    fn declare_bindings(&mut self, body: &BlockStatement) {
+        let mut names = vec![];
+        resolver::block_bindings(body, &mut names);
+        for name in &names {
+            self.symbol_table.declare(name);
+        }
+    }
+
+    fn compile_loop_body(&mut self, body: &BlockStatement) -> Result<Loop, CompileError> {
+        let tries = self.current_scope().tries.len();
+        self.current_scope().loops.push(Loop {
+            tries,
+            ..Loop::default()
+        });
+        let compiled = body
+            .statements
+            .iter()
+            .try_for_each(|statement| self.compile_statement(statement));
+        let the_loop = self.current_scope().loops.pop().expect("loop was entered");
+        compiled.map(|()| the_loop)
+    }
+
+    fn patch_loop(&mut self, the_loop: Loop, next: usize, after_loop: usize) {
+        for position in the_loop.continues {
+            self.change_operand(position, next);
+        }
+        for position in the_loop.breaks {
+            self.change_operand(position, after_loop);
+        }
+    }
+
+    // Loads the variable, updates it with `SetIndex` and stores it back, which
+    // leaves the assigned value on the stack.
+    fn compile_assignment(
+        &mut self,
+        target: &Expression,
+        operator: &str,
+        value: &Expression,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        let (name, indexes) = target.assignment_target().ok_or_else(|| CompileError {
+            code: ErrorCode::InvalidAssignment,
+            message: format!("invalid assignment target: {}", target),
+            span,
+        })?;
+        let undefined = format!("cannot assign to undefined variable: {}", name);
+        let symbol = match self.symbol_table.resolve(name) {
+            Some(symbol) => symbol,
+            None => {
+                self.emit_unbound(undefined, span);
+                return Ok(());
+            }
+        };
+        let store = match symbol.scope {
+            SymbolScope::Global => Opcode::SetGlobal,
+            SymbolScope::Local => Opcode::SetLocal,
+            SymbolScope::Free => Opcode::SetFree,
+            // builtins aren't variables, like in the evaluator
+            SymbolScope::Builtin => {
+                self.emit_unbound(undefined, span);
+                return Ok(());
+            }
+            SymbolScope::Function => {
+                return Err(CompileError {
+                    code: ErrorCode::InvalidAssignment,
+                    message: format!("cannot assign to {} inside its own definition", name),
+                    span,
+                })
+            }
+        };
+        let operator = code::ASSIGN_OPERATORS
+            .iter()
+            .position(|op| *op == operator)
+            .ok_or_else(|| unknown_operator(operator, span))?;
+
+        self.load_symbol(&symbol, span);
+        for index in &indexes {
+            self.compile_expression(index)?;
+        }
+        self.compile_expression(value)?;
+        self.emit(Opcode::SetIndex, &[indexes.len(), operator], span);
+        self.emit(store, &[symbol.index], span);
+        Ok(())
+    }
+
+    // Compiles a block so that it leaves the value of its last statement on the stack.
+    fn compile_block_value(
+        &mut self,
+        block: &BlockStatement,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        for statement in &block.statements {
+            self.compile_statement(statement)?;
+        }
+        match block.statements.last() {
+            Some(Statement::Expression(_, _)) => self.remove_last_pop(),
+            Some(Statement::Let(name, _, _, span)) | Some(Statement::Import(_, name, span)) => {
+                self.load_name(name, *span)
+            }
+            Some(Statement::Return(_, _))
+            | Some(Statement::Break(_))
+            | Some(Statement::Continue(_))
+            | Some(Statement::Throw(_, _)) => (),
+            Some(Statement::While(_, _, _)) | Some(Statement::For(_, _, _, _)) | None => {
+                self.emit(Opcode::Null, &[], span);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_function(
+        &mut self,
+        parms: &[Identifier],
+        body: &BlockStatement,
+        name: Option<&Identifier>,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        self.enter_scope();
+        if let Some(name) = name {
+            self.symbol_table.define_function_name(name);
+        }
+        for parm in parms {
+            self.symbol_table.define(parm);
+        }
+        self.declare_bindings(body);
+
+        let compiled = self.compile_function_body(body, span);
+        let free_symbols = self.symbol_table.free_symbols.clone();
+        let num_locals = self.symbol_table.num_definitions;
+        let scope = self.leave_scope();
+        compiled?;
+
+        for symbol in &free_symbols {
+            self.capture_symbol(symbol, span);
+        }
+        let function = CompiledFunction {
+            instructions: scope.instructions,
+            positions: scope.positions,
+            num_locals,
+            parameters: parms.to_vec(),
+            body: body.to_string(),
+        };
+        let constant = self.add_constant(Object::CompiledFunction(Rc::new(function)));
+        self.emit(Opcode::Closure, &[constant, free_symbols.len()], span);
+        Ok(())
+    }
+
+    // A function returns the value of its last statement, like a block does.
+    fn compile_function_body(
+        &mut self,
+        body: &BlockStatement,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        for statement in &body.statements {
+            self.compile_statement(statement)?;
+        }
+        match body.statements.last() {
+            Some(Statement::Expression(_, _)) => self.replace_last_pop_with_return(),
+            Some(Statement::Let(name, _, _, span)) | Some(Statement::Import(_, name, span)) => {
+                self.load_name(name, *span);
+                self.emit(Opcode::ReturnValue, &[], *span);
+            }
+            Some(Statement::Return(_, _))
+            | Some(Statement::Break(_))
+            | Some(Statement::Continue(_))
+            | Some(Statement::Throw(_, _)) => (),
+            Some(Statement::While(_, _, _)) | Some(Statement::For(_, _, _, _)) | None => {
+                self.emit(Opcode::Return, &[], span);
+            }
+        }
+        Ok(())
+    }
+
+    fn load_name(&mut self, name: &str, span: Span) {
+        match self.symbol_table.resolve(name) {
+            Some(symbol) => self.load_symbol(&symbol, span),
+            None => self.emit_unbound(format!("identifier not found: {}", name), span),
+        }
+    }
+
+    // A name nothing defines is only an error if the code using it runs, so
+    // that `try` can catch it as in the evaluator.
+    fn emit_unbound(&mut self, message: String, span: Span) {
+        let message = self.add_constant(Object::String(message));
+        self.emit(Opcode::Unbound, &[message], span);
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol, span: Span) {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(Opcode::GetGlobal, &[symbol.index], span),
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[symbol.index], span),
+            SymbolScope::Builtin => self.emit(Opcode::GetBuiltin, &[symbol.index], span),
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[symbol.index], span),
+            SymbolScope::Function => self.emit(Opcode::CurrentClosure, &[], span),
+        };
+    }
+
+    // Pushes a variable for a closure to capture. Locals and free variables are
+    // captured as cells, so assignments reach every function that sees them.
+    fn capture_symbol(&mut self, symbol: &Symbol, span: Span) {
+        match symbol.scope {
+            SymbolScope::Local => {
+                self.emit(Opcode::CaptureLocal, &[symbol.index], span);
+            }
+            SymbolScope::Free => {
+                self.emit(Opcode::CaptureFree, &[symbol.index], span);
+            }
+            _ => self.load_symbol(symbol, span),
+        }
+    }
+
+    fn add_constant(&mut self, obj: Object) -> usize {
+        self.constants.push(obj);
+        self.constants.len() - 1
+    }
+
+    // Appends an instruction to the current scope and returns its offset.
+    fn emit(&mut self, op: Opcode, operands: &[usize], span: Span) -> usize {
+        let instruction = code::make(op, operands);
+        let scope = self.current_scope();
+        let position = scope.instructions.len();
+        scope.instructions.extend(instruction);
+        scope.positions.push((position, span));
+        scope.previous_instruction = scope.last_instruction;
+        scope.last_instruction = Some(EmittedInstruction {
+            opcode: op,
+            position,
+        });
+        position
+    }
+
+    fn current_scope(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().expect("compiler has no scope")
+    }
+
+    // The parser only accepts `break` and `continue` inside a loop.
+    fn current_loop(&mut self) -> &mut Loop {
+        self.current_scope()
+            .loops
+            .last_mut()
+            .expect("loop control outside of a loop")
+    }
+
+    fn current_instructions(&mut self) -> &Instructions {
+        &self.current_scope().instructions
+    }
+
+    fn last_instruction_is(&mut self, op: Opcode) -> bool {
+        matches!(self.current_scope().last_instruction, Some(last) if last.opcode == op)
+    }
+
+    fn remove_last_pop(&mut self) {
+        if !self.last_instruction_is(Opcode::Pop) {
+            return;
+        }
+        let scope = self.current_scope();
+        if let Some(last) = scope.last_instruction {
+            scope.instructions.truncate(last.position);
+            scope.positions.pop();
+            scope.last_instruction = scope.previous_instruction;
+        }
+    }
+
+    fn replace_last_pop_with_return(&mut self) {
+        if !self.last_instruction_is(Opcode::Pop) {
+            return;
+        }
+        let scope = self.current_scope();
+        if let Some(last) = scope.last_instruction.as_mut() {
+            scope.instructions[last.position] = Opcode::ReturnValue as u8;
+            last.opcode = Opcode::ReturnValue;
+        }
+    }
+
+    // Replaces the last operand of the instruction at `position`, which is
+    // where jumps keep their target.
+    fn change_operand(&mut self, position: usize, operand: usize) {
+        let scope = self.current_scope();
+        let op = Opcode::try_from(scope.instructions[position]).expect("undefined opcode");
+        let (mut operands, _) = code::read_operands(op, &scope.instructions[position + 1..]);
+        if let Some(last) = operands.last_mut() {
+            *last = operand;
+        }
+        let instruction = code::make(op, &operands);
+        scope.instructions[position..position + instruction.len()].copy_from_slice(&instruction);
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        let outer = std::mem::take(&mut self.symbol_table);
+        self.symbol_table = SymbolTable::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> CompilationScope {
+        let outer = self
+            .symbol_table
+            .outer
+            .take()
+            .expect("left the global scope");
+        self.symbol_table = *outer;
+        self.scopes.pop().expect("compiler has no scope")
+    }
+}
+
+fn unknown_operator(operator: &str, span: Span) -> CompileError {
+    CompileError {
+        code: ErrorCode::UnknownOperator,
+        message: format!("unknown operator: {}", operator),
+        span,
+    }
+}
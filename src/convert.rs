@@ -0,0 +1,388 @@
+use crate::bigint::BigInt;
+use crate::object::{self, HashPair, HashTable, Object};
+use crate::value::Value;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+
+/// Why an `Object` couldn't be converted to a Rust type. `path` locates the
+/// offending part of a nested value, e.g. `[2]["name"]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError {
+    pub expected: String,
+    pub found: String,
+    pub path: String,
+}
+
+impl ConversionError {
+    pub fn new(expected: &str, found: &Object) -> ConversionError {
+        ConversionError {
+            expected: expected.to_string(),
+            found: found.obj_type().to_string(),
+            path: String::new(),
+        }
+    }
+
+    // Prefixes the path with the step taken from the enclosing value.
+    fn within(mut self, step: String) -> ConversionError {
+        self.path = format!("{}{}", step, self.path);
+        self
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "expected {}, got {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// Converts a Rust value into a Monkey object.
+pub trait IntoMonkey {
+    fn into_monkey(self) -> Object;
+}
+
+/// Converts a Monkey object into a Rust value.
+pub trait FromMonkey: Sized {
+    fn from_monkey(obj: &Object) -> Result<Self, ConversionError>;
+}
+
+/// Types whose objects can be used as hash keys.
+pub trait IntoMonkeyKey: IntoMonkey {}
+
+impl IntoMonkey for Object {
+    fn into_monkey(self) -> Object {
+        self
+    }
+}
+
+impl FromMonkey for Object {
+    fn from_monkey(obj: &Object) -> Result<Object, ConversionError> {
+        Ok(obj.clone())
+    }
+}
+
+impl FromMonkey for Value {
+    fn from_monkey(obj: &Object) -> Result<Value, ConversionError> {
+        Value::try_from(obj.clone())
+    }
+}
+
+impl IntoMonkey for () {
+    fn into_monkey(self) -> Object {
+        Object::Null
+    }
+}
+
+impl IntoMonkey for bool {
+    fn into_monkey(self) -> Object {
+        Object::Boolean(self)
+    }
+}
+
+impl IntoMonkeyKey for bool {}
+
+impl FromMonkey for bool {
+    fn from_monkey(obj: &Object) -> Result<bool, ConversionError> {
+        match obj {
+            Object::Boolean(b) => Ok(*b),
+            _ => Err(ConversionError::new("BOOLEAN", obj)),
+        }
+    }
+}
+
+impl IntoMonkey for i64 {
+    fn into_monkey(self) -> Object {
+        Object::Integer(self)
+    }
+}
+
+impl IntoMonkeyKey for i64 {}
+
+impl FromMonkey for i64 {
+    fn from_monkey(obj: &Object) -> Result<i64, ConversionError> {
+        match obj {
+            Object::Integer(i) => Ok(*i),
+            Object::BigInt(i) => Err(ConversionError {
+                expected: format!("an integer between {} and {}", i64::MIN, i64::MAX),
+                found: i.to_string(),
+                path: String::new(),
+            }),
+            _ => Err(ConversionError::new("INTEGER", obj)),
+        }
+    }
+}
+
+impl IntoMonkey for BigInt {
+    fn into_monkey(self) -> Object {
+        Object::from_bigint(self)
+    }
+}
+
+impl FromMonkey for BigInt {
+    fn from_monkey(obj: &Object) -> Result<BigInt, ConversionError> {
+        match obj {
+            Object::Integer(i) => Ok(BigInt::from(*i)),
+            Object::BigInt(i) => Ok(i.clone()),
+            _ => Err(ConversionError::new("INTEGER", obj)),
+        }
+    }
+}
+
+// Integer types narrower than i64 convert in both directions through it.
+macro_rules! integer_conversions {
+    (into: $($into:ty),*; from: $($from:ty),*) => {
+        $(
+            impl IntoMonkey for $into {
+                fn into_monkey(self) -> Object {
+                    Object::Integer(i64::from(self))
+                }
+            }
+
+            impl IntoMonkeyKey for $into {}
+        )*
+        $(
+            impl FromMonkey for $from {
+                fn from_monkey(obj: &Object) -> Result<$from, ConversionError> {
+                    let i = i64::from_monkey(obj)?;
+                    <$from>::try_from(i).map_err(|_| ConversionError {
+                        expected: format!(
+                            "an integer between {} and {}",
+                            <$from>::MIN,
+                            <$from>::MAX
+                        ),
+                        found: i.to_string(),
+                        path: String::new(),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+integer_conversions!(
+    into: i8, i16, i32, u8, u16, u32;
+    from: i8, i16, i32, u8, u16, u32
+);
+
+// The others may not fit in an i64, so they convert through `BigInt`, which
+// `$wide` widens them to: their values become INTEGERs that are only big
+// integers when they have to be.
+macro_rules! wide_integer_conversions {
+    ($($int:ty => $wide:ty),*) => {
+        $(
+            impl IntoMonkey for $int {
+                fn into_monkey(self) -> Object {
+                    match i64::try_from(self) {
+                        Ok(i) => Object::Integer(i),
+                        Err(_) => Object::BigInt(BigInt::from(self as $wide)),
+                    }
+                }
+            }
+
+            impl IntoMonkeyKey for $int {}
+
+            impl FromMonkey for $int {
+                fn from_monkey(obj: &Object) -> Result<$int, ConversionError> {
+                    let i = BigInt::from_monkey(obj)?;
+                    let fitted = i
+                        .to_i128()
+                        .and_then(|i| <$int>::try_from(i).ok())
+                        .or_else(|| i.to_u128().and_then(|u| <$int>::try_from(u).ok()));
+                    fitted.ok_or_else(|| ConversionError {
+                        expected: format!(
+                            "an integer between {} and {}",
+                            <$int>::MIN,
+                            <$int>::MAX
+                        ),
+                        found: i.to_string(),
+                        path: String::new(),
+                    })
+                }
+            }
+        )*
+    };
+}
+
+wide_integer_conversions!(
+    u64 => u128,
+    usize => u128,
+    u128 => u128,
+    isize => i128,
+    i128 => i128
+);
+
+impl IntoMonkey for f64 {
+    fn into_monkey(self) -> Object {
+        Object::Float(self)
+    }
+}
+
+impl IntoMonkey for f32 {
+    fn into_monkey(self) -> Object {
+        Object::Float(f64::from(self))
+    }
+}
+
+// Integers are accepted too, as they are by Monkey's arithmetic.
+impl FromMonkey for f64 {
+    fn from_monkey(obj: &Object) -> Result<f64, ConversionError> {
+        match obj {
+            Object::Float(f) => Ok(*f),
+            Object::Integer(i) => Ok(*i as f64),
+            Object::BigInt(i) => Ok(i.to_f64()),
+            _ => Err(ConversionError::new("FLOAT", obj)),
+        }
+    }
+}
+
+impl IntoMonkey for String {
+    fn into_monkey(self) -> Object {
+        Object::String(self)
+    }
+}
+
+impl IntoMonkeyKey for String {}
+
+impl IntoMonkey for &str {
+    fn into_monkey(self) -> Object {
+        Object::String(self.to_string())
+    }
+}
+
+impl IntoMonkeyKey for &str {}
+
+impl FromMonkey for String {
+    fn from_monkey(obj: &Object) -> Result<String, ConversionError> {
+        match obj {
+            Object::String(s) => Ok(s.clone()),
+            _ => Err(ConversionError::new("STRING", obj)),
+        }
+    }
+}
+
+impl<T: IntoMonkey> IntoMonkey for Option<T> {
+    fn into_monkey(self) -> Object {
+        match self {
+            Some(value) => value.into_monkey(),
+            None => Object::Null,
+        }
+    }
+}
+
+impl<T: FromMonkey> FromMonkey for Option<T> {
+    fn from_monkey(obj: &Object) -> Result<Option<T>, ConversionError> {
+        match obj {
+            Object::Null => Ok(None),
+            _ => T::from_monkey(obj).map(Some),
+        }
+    }
+}
+
+impl<T: IntoMonkey> IntoMonkey for Vec<T> {
+    fn into_monkey(self) -> Object {
+        Object::array(self.into_iter().map(IntoMonkey::into_monkey).collect())
+    }
+}
+
+impl<T: FromMonkey> FromMonkey for Vec<T> {
+    fn from_monkey(obj: &Object) -> Result<Vec<T>, ConversionError> {
+        match obj {
+            Object::Array(elements) => elements
+                .borrow()
+                .iter()
+                .enumerate()
+                .map(|(i, element)| {
+                    T::from_monkey(element).map_err(|e| e.within(format!("[{}]", i)))
+                })
+                .collect(),
+            _ => Err(ConversionError::new("ARRAY", obj)),
+        }
+    }
+}
+
+impl<K: IntoMonkeyKey, V: IntoMonkey> IntoMonkey for HashMap<K, V> {
+    fn into_monkey(self) -> Object {
+        let mut map = HashTable::new();
+        for (key, value) in self {
+            let key = key.into_monkey();
+            if let Some(hash_key) = object::create_hash_key(key.clone()) {
+                let value = value.into_monkey();
+                map.insert(hash_key, HashPair { key, value });
+            }
+        }
+        Object::hash(map)
+    }
+}
+
+impl<K, V> FromMonkey for HashMap<K, V>
+where
+    K: FromMonkey + Eq + Hash,
+    V: FromMonkey,
+{
+    fn from_monkey(obj: &Object) -> Result<HashMap<K, V>, ConversionError> {
+        match obj {
+            Object::Hash(pairs) => {
+                let mut map = HashMap::new();
+                for pair in pairs.borrow().values() {
+                    let step = format!("[{}]", describe_key(&pair.key));
+                    let key = K::from_monkey(&pair.key).map_err(|e| ConversionError {
+                        path: format!("key {}", step),
+                        ..e
+                    })?;
+                    let value = V::from_monkey(&pair.value).map_err(|e| e.within(step))?;
+                    map.insert(key, value);
+                }
+                Ok(map)
+            }
+            _ => Err(ConversionError::new("HASH", obj)),
+        }
+    }
+}
+
+fn describe_key(key: &Object) -> String {
+    match key {
+        Object::String(s) => format!("{:?}", s),
+        _ => key.inspect(),
+    }
+}
+
+macro_rules! tuple_conversions {
+    ($len:expr => $($name:ident $index:tt),+) => {
+        impl<$($name: IntoMonkey),+> IntoMonkey for ($($name,)+) {
+            fn into_monkey(self) -> Object {
+                Object::array(vec![$(self.$index.into_monkey()),+])
+            }
+        }
+
+        impl<$($name: FromMonkey),+> FromMonkey for ($($name,)+) {
+            fn from_monkey(obj: &Object) -> Result<($($name,)+), ConversionError> {
+                let elements = match obj {
+                    Object::Array(elements) => elements.borrow(),
+                    _ => return Err(ConversionError::new(&format!("ARRAY of length {}", $len), obj)),
+                };
+                if elements.len() != $len {
+                    return Err(ConversionError {
+                        expected: format!("ARRAY of length {}", $len),
+                        found: format!("ARRAY of length {}", elements.len()),
+                        path: String::new(),
+                    });
+                }
+                Ok(($(
+                    $name::from_monkey(&elements[$index])
+                        .map_err(|e| e.within(format!("[{}]", $index)))?,
+                )+))
+            }
+        }
+    };
+}
+
+tuple_conversions!(1 => A 0);
+tuple_conversions!(2 => A 0, B 1);
+tuple_conversions!(3 => A 0, B 1, C 2);
+tuple_conversions!(4 => A 0, B 1, C 2, D 3);
@@ -0,0 +1,386 @@
+use crate::lexer::LexError;
+use crate::module;
+use crate::object::RuntimeError;
+use crate::parser::ParseError;
+use crate::token::Span;
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+
+/// Stable identifiers for every error the interpreter can report. The numeric
+/// codes are part of the public output and must never be reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    UnexpectedToken,
+    ExpectedExpression,
+    TypeMismatch,
+    UnknownOperator,
+    UnknownIdentifier,
+    NotCallable,
+    UnhashableKey,
+    IndexNotSupported,
+    ArgumentCount,
+    ArgumentType,
+    InvalidLiteral,
+    StackOverflow,
+    IllegalCharacter,
+    UnterminatedString,
+    MisplacedLoopControl,
+    InvalidAssignment,
+    IndexOutOfRange,
+    NotIterable,
+    DivisionByZero,
+    IntegerOverflow,
+    MisplacedImport,
+    BadImport,
+    Thrown,
+    OutOfFuel,
+    Timeout,
+    Cancelled,
+    OutOfMemory,
+}
+
+impl ErrorCode {
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedToken => "E0001",
+            ErrorCode::ExpectedExpression => "E0002",
+            ErrorCode::TypeMismatch => "E0003",
+            ErrorCode::UnknownOperator => "E0004",
+            ErrorCode::UnknownIdentifier => "E0005",
+            ErrorCode::NotCallable => "E0006",
+            ErrorCode::UnhashableKey => "E0007",
+            ErrorCode::IndexNotSupported => "E0008",
+            ErrorCode::ArgumentCount => "E0009",
+            ErrorCode::ArgumentType => "E0010",
+            ErrorCode::InvalidLiteral => "E0011",
+            ErrorCode::StackOverflow => "E0012",
+            ErrorCode::IllegalCharacter => "E0013",
+            ErrorCode::UnterminatedString => "E0014",
+            ErrorCode::MisplacedLoopControl => "E0015",
+            ErrorCode::InvalidAssignment => "E0016",
+            ErrorCode::IndexOutOfRange => "E0017",
+            ErrorCode::NotIterable => "E0018",
+            ErrorCode::DivisionByZero => "E0019",
+            ErrorCode::IntegerOverflow => "E0020",
+            ErrorCode::MisplacedImport => "E0021",
+            ErrorCode::BadImport => "E0022",
+            ErrorCode::Thrown => "E0023",
+            ErrorCode::OutOfFuel => "E0024",
+            ErrorCode::Timeout => "E0025",
+            ErrorCode::Cancelled => "E0026",
+            ErrorCode::OutOfMemory => "E0027",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedToken => "unexpected token",
+            ErrorCode::ExpectedExpression => "expected expression",
+            ErrorCode::TypeMismatch => "type mismatch",
+            ErrorCode::UnknownOperator => "unknown operator",
+            ErrorCode::UnknownIdentifier => "unknown identifier",
+            ErrorCode::NotCallable => "not callable",
+            ErrorCode::UnhashableKey => "unusable hash key",
+            ErrorCode::IndexNotSupported => "index not supported",
+            ErrorCode::ArgumentCount => "wrong number of arguments",
+            ErrorCode::ArgumentType => "invalid argument",
+            ErrorCode::InvalidLiteral => "invalid literal",
+            ErrorCode::StackOverflow => "stack overflow",
+            ErrorCode::IllegalCharacter => "illegal character",
+            ErrorCode::UnterminatedString => "unterminated string",
+            ErrorCode::MisplacedLoopControl => "break or continue outside a loop",
+            ErrorCode::InvalidAssignment => "invalid assignment",
+            ErrorCode::IndexOutOfRange => "index out of range",
+            ErrorCode::NotIterable => "not iterable",
+            ErrorCode::DivisionByZero => "division by zero",
+            ErrorCode::IntegerOverflow => "integer overflow",
+            ErrorCode::MisplacedImport => "import outside the top level",
+            ErrorCode::BadImport => "import failed",
+            ErrorCode::Thrown => "uncaught exception",
+            ErrorCode::OutOfFuel => "out of fuel",
+            ErrorCode::Timeout => "time limit exceeded",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::OutOfMemory => "memory limit exceeded",
+        }
+    }
+
+    /// The error's class as a program sees it: the `"kind"` of a caught error.
+    pub fn kind(self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedToken => "UnexpectedToken",
+            ErrorCode::ExpectedExpression => "ExpectedExpression",
+            ErrorCode::TypeMismatch => "TypeMismatch",
+            ErrorCode::UnknownOperator => "UnknownOperator",
+            ErrorCode::UnknownIdentifier => "UnknownIdentifier",
+            ErrorCode::NotCallable => "NotCallable",
+            ErrorCode::UnhashableKey => "UnhashableKey",
+            ErrorCode::IndexNotSupported => "IndexNotSupported",
+            ErrorCode::ArgumentCount => "ArgumentCount",
+            ErrorCode::ArgumentType => "ArgumentType",
+            ErrorCode::InvalidLiteral => "InvalidLiteral",
+            ErrorCode::StackOverflow => "StackOverflow",
+            ErrorCode::IllegalCharacter => "IllegalCharacter",
+            ErrorCode::UnterminatedString => "UnterminatedString",
+            ErrorCode::MisplacedLoopControl => "MisplacedLoopControl",
+            ErrorCode::InvalidAssignment => "InvalidAssignment",
+            ErrorCode::IndexOutOfRange => "IndexOutOfRange",
+            ErrorCode::NotIterable => "NotIterable",
+            ErrorCode::DivisionByZero => "DivisionByZero",
+            ErrorCode::IntegerOverflow => "IntegerOverflow",
+            ErrorCode::MisplacedImport => "MisplacedImport",
+            ErrorCode::BadImport => "BadImport",
+            ErrorCode::Thrown => "Error",
+            ErrorCode::OutOfFuel => "OutOfFuel",
+            ErrorCode::Timeout => "Timeout",
+            ErrorCode::Cancelled => "Cancelled",
+            ErrorCode::OutOfMemory => "OutOfMemory",
+        }
+    }
+
+    /// Whether `try` can catch the error. The host's limits stop a program
+    /// outright, so a program can't keep running by catching them.
+    pub fn is_catchable(self) -> bool {
+        !matches!(
+            self,
+            ErrorCode::OutOfFuel
+                | ErrorCode::Timeout
+                | ErrorCode::Cancelled
+                | ErrorCode::OutOfMemory
+        )
+    }
+
+    // General advice for the error class, shown under the snippet.
+    fn help(self) -> Option<&'static str> {
+        match self {
+            ErrorCode::UnhashableKey => Some(
+                "hash keys must be INTEGER, BOOLEAN or STRING values, or ARRAY and HASH values \
+                 made of them",
+            ),
+            ErrorCode::UnknownIdentifier => {
+                Some("bind the name with `let` before it is used, or check its spelling")
+            }
+            ErrorCode::IndexNotSupported => Some("only ARRAY and HASH values can be indexed"),
+            ErrorCode::NotIterable => {
+                Some("`for` loops walk ARRAY, HASH and STRING values and ranges like `0..n`")
+            }
+            ErrorCode::IntegerOverflow => {
+                Some("INTEGER values range from -9223372036854775808 to 9223372036854775807")
+            }
+            ErrorCode::BadImport => Some(
+                "modules are looked up relative to the importing file, then in the search path",
+            ),
+            ErrorCode::InvalidAssignment => {
+                Some("only variables and their elements, like `x` or `x[i]`, can be assigned to")
+            }
+            ErrorCode::Thrown => Some("errors can be caught with `try { ... } catch (e) { ... }`"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.title())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let output = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+/// Whether rendered diagnostics may contain ANSI escape sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Color,
+}
+
+impl Style {
+    /// Colors are used when stdout is a terminal and `NO_COLOR` isn't set.
+    pub fn detect() -> Style {
+        if env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal() {
+            Style::Color
+        } else {
+            Style::Plain
+        }
+    }
+
+    fn paint(self, ansi: &str, text: &str) -> String {
+        match self {
+            Style::Plain => text.to_string(),
+            Style::Color => format!("\x1b[{}m{}\x1b[0m", ansi, text),
+        }
+    }
+}
+
+const BOLD_RED: &str = "1;31";
+const BOLD_YELLOW: &str = "1;33";
+const BOLD_CYAN: &str = "1;36";
+const BOLD_BLUE: &str = "1;34";
+const BOLD: &str = "1";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: ErrorCode, message: String, span: Option<Span>) -> Diagnostic {
+        let notes = code.help().map(|h| vec![h.to_string()]).unwrap_or_default();
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message,
+            span,
+            notes,
+        }
+    }
+
+    pub fn with_note(mut self, note: &str) -> Diagnostic {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    /// Renders the diagnostic with the offending line of `source`, the text of
+    /// the file called `name`, and a caret underline below the span, e.g.
+    ///
+    /// ```text
+    /// error[E0003]: type mismatch: INTEGER + BOOLEAN
+    ///  --> script.mk:2:9
+    ///   |
+    /// 2 | let b = a + true;
+    ///   |         ^^^^^^^^
+    /// ```
+    pub fn render(&self, name: &str, source: &str, style: Style) -> String {
+        let severity_color = match self.severity {
+            Severity::Error => BOLD_RED,
+            Severity::Warning => BOLD_YELLOW,
+            Severity::Note => BOLD_CYAN,
+        };
+        let mut out = format!(
+            "{}{}\n",
+            style.paint(
+                severity_color,
+                &format!("{}[{}]", self.severity, self.code.code())
+            ),
+            style.paint(BOLD, &format!(": {}", self.message))
+        );
+
+        // a span in an imported module's code is shown in that file
+        let module = self.span.and_then(|span| module::source_file(span.file));
+        let (name, source) = match &module {
+            Some((name, text)) => (name.as_str(), &**text),
+            None => (name, source),
+        };
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                out.push_str(&format!(" {} {}\n", style.paint(BOLD_BLUE, "-->"), name));
+                self.render_notes(&mut out, "", style);
+                return out;
+            }
+        };
+
+        let line_number = span.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let bar = style.paint(BOLD_BLUE, "|");
+        out.push_str(&format!(
+            "{}{} {}:{}\n",
+            gutter,
+            style.paint(BOLD_BLUE, "-->"),
+            name,
+            span
+        ));
+
+        let line = source.lines().nth(span.line - 1).unwrap_or("");
+        out.push_str(&format!("{} {}\n", gutter, bar));
+        out.push_str(&format!(
+            "{} {} {}\n",
+            style.paint(BOLD_BLUE, &line_number),
+            bar,
+            line
+        ));
+
+        // tabs are kept so the carets line up however the terminal renders them
+        let prefix: String = line
+            .chars()
+            .take(span.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // the underline stops at the end of the first line of a multi-line span
+        let remaining = line.chars().count().saturating_sub(span.column - 1);
+        let width = source
+            .get(span.start..span.end)
+            .map(|s| s.lines().next().unwrap_or("").chars().count())
+            .unwrap_or(0)
+            .min(remaining)
+            .max(1);
+        out.push_str(&format!(
+            "{} {} {}{}\n",
+            gutter,
+            bar,
+            prefix,
+            style.paint(severity_color, &"^".repeat(width))
+        ));
+
+        self.render_notes(&mut out, &gutter, style);
+        out
+    }
+
+    fn render_notes(&self, out: &mut String, gutter: &str, style: Style) {
+        for note in &self.notes {
+            out.push_str(&format!(
+                "{} {} {}\n",
+                gutter,
+                style.paint(BOLD_BLUE, "="),
+                style.paint(BOLD, &format!("help: {}", note))
+            ));
+        }
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(err: &LexError) -> Diagnostic {
+        Diagnostic::error(err.code, err.message.clone(), Some(err.span))
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Diagnostic {
+        Diagnostic::error(err.code, err.message.clone(), Some(err.span))
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(err: &RuntimeError) -> Diagnostic {
+        Diagnostic::error(err.code, err.message.clone(), err.span)
+    }
+}
+
+/// Renders a list of diagnostics separated by blank lines.
+pub fn render_all(diagnostics: &[Diagnostic], name: &str, source: &str, style: Style) -> String {
+    let rendered: Vec<String> = diagnostics
+        .iter()
+        .map(|d| d.render(name, source, style))
+        .collect();
+    rendered.join("\n")
+}
@@ -1,36 +1,242 @@
-use crate::object::Object;
+use crate::limits::Budget;
+use crate::module::ModuleLoader;
+use crate::native::NativeFunction;
+use crate::object::{IntegerOverflow, Object};
+use crate::output::Sink;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-#[derive(Debug, Clone, PartialEq)]
+/// The variables of a scope. The locals of a function call, loop iteration or
+/// catch block are kept in `slots`, and the names bound at the top level in
+/// the `globals` of the outermost environment, where the resolver found them
+/// a place before the program ran, so reading either is an index rather than
+/// a search. Later programs and the host refer to top-level names by name,
+/// which the outermost environment maps to their slots.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Environment {
-    pub store: HashMap<String, Object>,
+    /// The values of the names bound at the top level, by slot. A slot is
+    /// empty until its `let` runs. Only the outermost environment has any.
+    pub globals: Vec<Option<Object>>,
+    global_slots: HashMap<String, usize>,
+    pub slots: Vec<Object>,
     pub outer: Option<Rc<RefCell<Environment>>>,
+    // the outermost environment, unless this is it
+    pub(crate) root: Option<Rc<RefCell<Environment>>>,
+    // shared with the environments enclosed in this one and the modules it
+    // imports
+    settings: Rc<RefCell<Settings>>,
+    modules: Rc<RefCell<ModuleLoader>>,
+    budget: Rc<RefCell<Budget>>,
+}
+
+// How the host set up the programs it runs.
+#[derive(Debug, Clone, PartialEq)]
+struct Settings {
+    // Functions registered by the host. They are looked up after the bindings,
+    // like the builtins, so a program can shadow them.
+    natives: HashMap<String, Object>,
+    integer_overflow: IntegerOverflow,
+    output: Sink,
+    error_output: Sink,
 }
 
-impl Default for Environment {
-    fn default() -> Environment {
-        let store = HashMap::new();
-        Environment { store, outer: None }
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            natives: HashMap::new(),
+            integer_overflow: IntegerOverflow::default(),
+            output: Sink::Stdout,
+            error_output: Sink::Stderr,
+        }
     }
 }
 
 impl Environment {
     pub fn new_enclosed_environment(outer: Rc<RefCell<Environment>>) -> Environment {
-        let mut env = Environment::default();
-        env.outer = Some(outer);
-        env
+        let (root, settings, modules, budget) = {
+            let env = outer.borrow();
+            (
+                env.root.clone().unwrap_or_else(|| outer.clone()),
+                env.settings.clone(),
+                env.modules.clone(),
+                env.budget.clone(),
+            )
+        };
+        Environment {
+            globals: vec![],
+            global_slots: HashMap::new(),
+            slots: vec![],
+            outer: Some(outer),
+            root: Some(root),
+            settings,
+            modules,
+            budget,
+        }
     }
 
+    /// The value of the name bound at the top level, if it's been bound.
     pub fn get(&self, name: String) -> Option<Object> {
-        match self.store.get(&name) {
-            Some(item) => Some(item.clone()),
-            None => self.outer.as_ref().and_then(|item| item.borrow().get(name)),
+        match &self.root {
+            Some(root) => root.borrow().get(name),
+            None => self.get_global(*self.global_slots.get(&name)?),
+        }
+    }
+
+    /// The value of the local in slot `index` of the environment `depth`
+    /// scopes out. A slot nothing was stored in yet holds null, like a local
+    /// the VM hasn't set.
+    pub fn get_slot(&self, depth: usize, index: usize) -> Object {
+        match (depth, &self.outer) {
+            (0, _) => self.slots.get(index).cloned().unwrap_or(Object::Null),
+            (_, Some(outer)) => outer.borrow().get_slot(depth - 1, index),
+            (_, None) => Object::Null,
+        }
+    }
+
+    pub fn set_slot(&mut self, depth: usize, index: usize, obj: Object) {
+        if depth > 0 {
+            if let Some(outer) = &self.outer {
+                outer.borrow_mut().set_slot(depth - 1, index, obj);
+            }
+            return;
+        }
+        if index >= self.slots.len() {
+            self.slots.resize(index + 1, Object::Null);
+        }
+        self.slots[index] = obj;
+    }
+
+    /// The value of the name bound at the top level in slot `index`, if it's
+    /// been bound.
+    pub fn get_global(&self, index: usize) -> Option<Object> {
+        match &self.root {
+            Some(root) => root.borrow().get_global(index),
+            None => self.globals.get(index).cloned().flatten(),
+        }
+    }
+
+    pub fn set_global(&mut self, index: usize, obj: Object) {
+        match &self.root {
+            Some(root) => root.borrow_mut().set_global(index, obj),
+            None => {
+                if index >= self.globals.len() {
+                    self.globals.resize(index + 1, None);
+                }
+                self.globals[index] = Some(obj);
+            }
+        }
+    }
+
+    /// The slot the top-level name `name` is kept in, which it's given if it
+    /// has none yet.
+    pub fn global_slot(&mut self, name: &str) -> usize {
+        match &self.root {
+            Some(root) => root.borrow_mut().global_slot(name),
+            None => {
+                let next = self.global_slots.len();
+                *self.global_slots.entry(name.to_string()).or_insert(next)
+            }
+        }
+    }
+
+    /// Whether `name` is bound at the top level.
+    pub fn is_global(&self, name: &str) -> bool {
+        self.get(name.to_string()).is_some()
+    }
+
+    /// The names bound at the top level and their values.
+    pub fn global_bindings(&self) -> Vec<(String, Object)> {
+        match &self.root {
+            Some(root) => root.borrow().global_bindings(),
+            None => self
+                .global_slots
+                .iter()
+                .filter_map(|(name, &index)| Some((name.clone(), self.get_global(index)?)))
+                .collect(),
         }
     }
 
+    /// Binds `name` at the top level, returning what it was bound to before.
     pub fn set(&mut self, name: String, obj: Object) -> Option<Object> {
-        self.store.insert(name, obj)
+        let index = self.global_slot(&name);
+        let previous = self.get_global(index);
+        self.set_global(index, obj);
+        previous
+    }
+
+    /// Rebinds the name bound at the top level. Returns false, changing
+    /// nothing, if it isn't bound.
+    pub fn assign(&mut self, name: &str, obj: Object) -> bool {
+        if !self.is_global(name) {
+            return false;
+        }
+        self.set(name.to_string(), obj);
+        true
+    }
+
+    /// How arithmetic in this environment handles integer overflow. The
+    /// setting is shared by the outermost environment and every one enclosed
+    /// in it, so changing it affects functions that were already defined.
+    pub fn integer_overflow(&self) -> IntegerOverflow {
+        self.settings.borrow().integer_overflow
+    }
+
+    pub fn set_integer_overflow(&mut self, mode: IntegerOverflow) {
+        self.settings.borrow_mut().integer_overflow = mode;
+    }
+
+    /// Where `puts` and `print` write. Like the overflow setting, the sinks
+    /// are shared by every environment enclosed in the outermost one.
+    pub fn output(&self) -> Sink {
+        self.settings.borrow().output.clone()
+    }
+
+    pub fn set_output(&mut self, sink: Sink) {
+        self.settings.borrow_mut().output = sink;
+    }
+
+    /// Where `eprint` writes.
+    pub fn error_output(&self) -> Sink {
+        self.settings.borrow().error_output.clone()
+    }
+
+    pub fn set_error_output(&mut self, sink: Sink) {
+        self.settings.borrow_mut().error_output = sink;
+    }
+
+    /// The module loader, which like the settings is shared by every
+    /// environment enclosed in the outermost one.
+    pub(crate) fn modules(&self) -> Rc<RefCell<ModuleLoader>> {
+        self.modules.clone()
+    }
+
+    /// The limits of the running program and what's left of them, which are
+    /// shared in the same way.
+    pub(crate) fn budget(&self) -> Rc<RefCell<Budget>> {
+        self.budget.clone()
+    }
+
+    /// A new outermost environment for a module this one imports. It has no
+    /// bindings, but shares the host's functions and settings.
+    pub(crate) fn new_module_environment(&self) -> Environment {
+        Environment {
+            settings: self.settings.clone(),
+            modules: self.modules.clone(),
+            budget: self.budget.clone(),
+            ..Environment::default()
+        }
+    }
+
+    pub fn define_native(&mut self, native: NativeFunction) {
+        self.settings
+            .borrow_mut()
+            .natives
+            .insert(native.name.clone(), Object::Native(native));
+    }
+
+    /// Looks up a native function the host registered.
+    pub fn get_native(&self, name: &str) -> Option<Object> {
+        self.settings.borrow().natives.get(name).cloned()
     }
 }
@@ -0,0 +1,66 @@
+use crate::convert::ConversionError;
+use crate::diagnostics::{self, Diagnostic, ErrorCode, Style};
+use crate::lexer::LexError;
+use crate::object::RuntimeError;
+use crate::parser::ParseError;
+use crate::token::Span;
+use std::error::Error;
+use std::fmt;
+
+/// Why a program couldn't produce a value.
+///
+/// Input that doesn't lex is reported as `Lex` only, since the parse errors
+/// that follow from a bad token are just noise.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonkeyError {
+    Lex(Vec<LexError>),
+    Parse(Vec<ParseError>),
+    Runtime(RuntimeError),
+    /// The program ran, but its value doesn't have the requested Rust type.
+    Conversion(ConversionError),
+}
+
+impl MonkeyError {
+    /// Location of the first error, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            MonkeyError::Lex(errors) => errors.first().map(|e| e.span),
+            MonkeyError::Parse(errors) => errors.first().map(|e| e.span),
+            MonkeyError::Runtime(err) => err.span,
+            MonkeyError::Conversion(_) => None,
+        }
+    }
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            MonkeyError::Lex(errors) => errors.iter().map(Diagnostic::from).collect(),
+            MonkeyError::Parse(errors) => errors.iter().map(Diagnostic::from).collect(),
+            MonkeyError::Runtime(err) => vec![Diagnostic::from(err)],
+            MonkeyError::Conversion(err) => vec![Diagnostic::error(
+                ErrorCode::TypeMismatch,
+                err.to_string(),
+                None,
+            )],
+        }
+    }
+
+    /// Renders every error against `source`, which must be the program that failed.
+    /// Errors in the code of a module it imported are shown in that file.
+    pub fn render(&self, name: &str, source: &str, style: Style) -> String {
+        diagnostics::render_all(&self.diagnostics(), name, source, style)
+    }
+}
+
+impl fmt::Display for MonkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = match self {
+            MonkeyError::Lex(errors) => errors.iter().map(|e| e.to_string()).collect(),
+            MonkeyError::Parse(errors) => errors.iter().map(|e| e.to_string()).collect(),
+            MonkeyError::Runtime(err) => vec![err.to_string()],
+            MonkeyError::Conversion(err) => vec![err.to_string()],
+        };
+        write!(f, "{}", messages.join("\n"))
+    }
+}
+
+impl Error for MonkeyError {}
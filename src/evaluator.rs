@@ -1,18 +1,38 @@
-use crate::ast::{BlockStatement, Expression, Program, Statement};
+use crate::ast::{BlockStatement, Expression, Identifier, Program, Slot, Statement};
+use crate::bigint::BigInt;
+use crate::diagnostics::ErrorCode;
 use crate::environment::Environment;
+use crate::gc;
+use crate::limits::Budget;
+use crate::module;
 use crate::object;
-use crate::object::{BuiltIn, Object, ObjectType};
+use crate::object::{
+    BuiltIn, CallContext, HashTable, IntegerOverflow, Object, ObjectType, RuntimeError,
+};
+use crate::output::Sink;
+use crate::resolver;
+use crate::token::Span;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::fmt;
+use std::mem;
 use std::rc::Rc;
 
-pub fn eval(program: Program, env: Rc<RefCell<Environment>>) -> Object {
+// Monkey calls nest Rust calls here, so without a limit from the host they
+// nest as deeply as the thread's stack allows, and no deeper.
+const MAX_CALL_DEPTH: usize = usize::MAX;
+
+// The most elements a range outside a `for` loop can have, since it's made
+// into an array all at once. A `for` loop over a range visits it lazily.
+const MAX_RANGE_LENGTH: i128 = 10_000_000;
+
+pub fn eval(mut program: Program, env: Rc<RefCell<Environment>>) -> Object {
+    resolver::resolve(&mut program, &mut env.borrow_mut());
     let mut result = Object::Null;
-    for statement in program.statements {
+    for statement in &program.statements {
         result = eval_statement(statement, env.clone());
         match result {
             Object::ReturnValue(val) => {
-                return *val;
+                return make_tail_call(*val, &env);
             }
             Object::Error(_) => {
                 return result;
@@ -23,123 +43,744 @@ pub fn eval(program: Program, env: Rc<RefCell<Environment>>) -> Object {
     result
 }
 
-fn eval_statement(statement: Statement, env: Rc<RefCell<Environment>>) -> Object {
+fn eval_statement(statement: &Statement, env: Rc<RefCell<Environment>>) -> Object {
     match statement {
-        Statement::Expression(expr) => eval_expression(expr, env),
-        Statement::Return(expr) => {
-            let val = eval_expression(expr, env);
-            if is_error(&val) {
-                return val;
-            }
-            Object::ReturnValue(Box::new(val))
+        Statement::Expression(expr, _) => eval_expression(expr, env),
+        Statement::Return(expr, _) => eval_return_statement(expr, env),
+        Statement::While(condition, body, _) => eval_while_statement(condition, body, env),
+        Statement::For(names, iterable, body, _) => eval_for_statement(names, iterable, body, env),
+        Statement::Break(_) => Object::Break,
+        Statement::Continue(_) => Object::Continue,
+        Statement::Let(ident, slot, expr, _) => eval_let_statement(ident, *slot, expr, env),
+        Statement::Import(path, name, span) => eval_import_statement(path, name, *span, env),
+        Statement::Throw(expr, span) => eval_throw_statement(expr, *span, env),
+    }
+}
+
+fn eval_return_statement(expr: &Expression, env: Rc<RefCell<Environment>>) -> Object {
+    let val = eval_tail_expression(expr, env);
+    if is_error(&val) {
+        return val;
+    }
+    Object::ReturnValue(Box::new(val))
+}
+
+fn eval_let_statement(
+    ident: &Identifier,
+    slot: Slot,
+    expr: &Expression,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    let val = eval_expression(expr, env.clone());
+    if is_error(&val) {
+        return val;
+    }
+    match slot {
+        Slot::Local { depth, index } => env.borrow_mut().set_slot(depth, index, val.clone()),
+        Slot::Global { index } => env.borrow_mut().set_global(index, val.clone()),
+        Slot::Builtin | Slot::Unresolved => {
+            env.borrow_mut().set(ident.clone(), val.clone());
         }
-        Statement::Let(ident, expr) => {
-            let val = eval_expression(expr, env.clone());
-            if !is_error(&val) {
-                env.borrow_mut().set(ident, val.clone());
-            }
-            val
+    }
+    val
+}
+
+fn eval_import_statement(
+    path: &str,
+    name: &Identifier,
+    span: Span,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    let namespace = match import_module(path, &env) {
+        Ok(namespace) => namespace,
+        Err(err) => return with_span(Object::Error(err), span),
+    };
+    env.borrow_mut().set(name.clone(), namespace.clone());
+    namespace
+}
+
+fn eval_throw_statement(expr: &Expression, span: Span, env: Rc<RefCell<Environment>>) -> Object {
+    let val = eval_expression(expr, env);
+    if is_error(&val) {
+        return val;
+    }
+    with_span(Object::Error(RuntimeError::thrown(val)), span)
+}
+
+// Runs the module `path` names in an environment of its own, the first time
+// it's imported, and returns its namespace.
+fn import_module(path: &str, env: &Rc<RefCell<Environment>>) -> Result<Object, RuntimeError> {
+    let modules = env.borrow().modules();
+    let file = modules.borrow().resolve(path)?;
+    if let Some(namespace) = modules.borrow().namespace(&file) {
+        return Ok(namespace);
+    }
+    let program = modules.borrow_mut().begin(&file)?;
+    let module_env = env.borrow().new_module_environment();
+    let module_env = gc::track_environment(Rc::new(RefCell::new(module_env)));
+    let result = eval(program, module_env.clone());
+    modules.borrow_mut().end();
+    if let Object::Error(err) = result {
+        return Err(module::in_module(path, err));
+    }
+    let namespace = module::namespace(module_env.borrow().global_bindings());
+    modules.borrow_mut().remember(file, namespace.clone());
+    Ok(namespace)
+}
+
+fn eval_expression(expression: &Expression, env: Rc<RefCell<Environment>>) -> Object {
+    let span = expression.span();
+    if let Err(err) = step(&env) {
+        return with_span(Object::Error(err), span);
+    }
+    let result = match expression {
+        Expression::IntegerLiteral(i, _) => Object::Integer(*i),
+        Expression::BigIntLiteral(i, _) => Object::from_bigint(i.clone()),
+        Expression::FloatLiteral(f, _) => Object::Float(*f),
+        Expression::Boolean(b, _) => Object::Boolean(*b),
+        Expression::Prefix(operator, right, _) => eval_prefix(operator, right, env),
+        Expression::Infix(left, operator, right, _) => eval_infix(left, operator, right, env),
+        Expression::IfExpression(condition, consequence, alt, _) => {
+            eval_if_expression(condition, consequence, alt.as_ref(), env)
+        }
+        Expression::Ident(ident, slot, _) => eval_identifier(ident, *slot, env),
+        Expression::FunctionLiteral(parms, body, _) => {
+            Object::Function(parms.clone(), body.clone(), env)
+        }
+        Expression::CallExpression(function, arguments, _) => {
+            eval_call_expression(function, arguments, env)
+        }
+        Expression::ArrayLiteral(elements, _) => eval_array_literal(elements, env),
+        Expression::IndexExpression(left, index, _) => eval_index(left, index, env),
+        Expression::HashLiteral(pairs, _) => eval_hash_literal(pairs, env),
+        Expression::StringLiteral(s, _) => match allocate(&env, s.len()) {
+            Ok(()) => Object::String(s.clone()),
+            Err(err) => Object::Error(err),
+        },
+        Expression::Assign(target, operator, value, _) => {
+            eval_assign_expression(target, operator, value, env)
+        }
+        Expression::Try(body, catch, finally, _) => {
+            eval_try_expression(body, catch.as_ref(), finally.as_ref(), env)
+        }
+    };
+    with_span(result, span)
+}
+
+fn step(env: &Rc<RefCell<Environment>>) -> Result<(), RuntimeError> {
+    budget(env).borrow_mut().step()
+}
+
+fn budget(env: &Rc<RefCell<Environment>>) -> Rc<RefCell<Budget>> {
+    env.borrow().budget()
+}
+
+fn allocate(env: &Rc<RefCell<Environment>>, bytes: usize) -> Result<(), RuntimeError> {
+    if bytes == 0 {
+        return Ok(());
+    }
+    budget(env).borrow_mut().allocate(bytes)
+}
+
+// The arms of `eval_expression` that recurse live in functions of their own,
+// so a nested call only takes the stack the arm it runs needs.
+fn eval_prefix(operator: &str, right: &Expression, env: Rc<RefCell<Environment>>) -> Object {
+    let overflow = env.borrow().integer_overflow();
+    let r = eval_expression(right, env);
+    if is_error(&r) {
+        return r;
+    }
+    eval_prefix_expression(operator, r, overflow)
+}
+
+fn eval_infix(
+    left: &Expression,
+    operator: &str,
+    right: &Expression,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    let l = eval_expression(left, env.clone());
+    if is_error(&l) {
+        return l;
+    }
+    let overflow = env.borrow().integer_overflow();
+    let r = eval_expression(right, env.clone());
+    if is_error(&r) {
+        return r;
+    }
+    if let Err(err) = allocate(&env, infix_allocation(operator, &l, &r)) {
+        return Object::Error(err);
+    }
+    eval_infix_expression(operator, l, r, overflow)
+}
+
+fn eval_if_expression(
+    condition: &Expression,
+    consequence: &BlockStatement,
+    alt: Option<&BlockStatement>,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    let c = eval_expression(condition, env.clone());
+    if is_error(&c) {
+        return c;
+    }
+    if is_truthy(c) {
+        eval_block_statement(consequence, env)
+    } else {
+        match alt {
+            Some(val) => eval_block_statement(val, env),
+            None => Object::Null,
         }
     }
 }
 
-fn eval_expression(expression: Expression, env: Rc<RefCell<Environment>>) -> Object {
+// Evaluates an expression whose value the function being run returns. A call
+// there to a Monkey function isn't made, but handed back as a `TailCall` for
+// `apply_function` to make in place of the current one, and likewise for the
+// branches of an `if`.
+fn eval_tail_expression(expression: &Expression, env: Rc<RefCell<Environment>>) -> Object {
     match expression {
-        Expression::IntegerLiteral(i) => Object::Integer(i),
-        Expression::Boolean(b) => Object::Boolean(b),
-        Expression::Prefix(operator, right) => {
-            let r = eval_expression(*right, env);
-            if is_error(&r) {
-                return r;
-            }
-            eval_prefix_expression(&operator, r)
+        Expression::CallExpression(function, arguments, span) => {
+            with_span(eval_tail_call(function, arguments, *span, env), *span)
         }
-        Expression::Infix(left, operator, right) => {
-            let l = eval_expression(*left, env.clone());
-            if is_error(&l) {
-                return l;
-            }
-            let r = eval_expression(*right, env);
-            if is_error(&r) {
-                return r;
-            }
-            eval_infix_expression(&operator, l, r)
+        Expression::IfExpression(condition, consequence, alt, span) => with_span(
+            eval_tail_if(condition, consequence, alt.as_ref(), env),
+            *span,
+        ),
+        expression => eval_expression(expression, env),
+    }
+}
+
+fn eval_tail_call(
+    function: &Expression,
+    arguments: &[Expression],
+    span: Span,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    if let Err(err) = step(&env) {
+        return Object::Error(err);
+    }
+    let function = eval_expression(function, env.clone());
+    if is_error(&function) {
+        return function;
+    }
+    let args = eval_expressions(arguments, env.clone());
+    if args.len() == 1 && is_error(&args[0]) {
+        return args[0].clone();
+    }
+    // builtins may call back into Monkey, so they run inside the caller,
+    // whose call counts against the depth limit
+    match function {
+        Object::Function(..) => Object::TailCall(Box::new(function), args, span),
+        function => apply_function(function, args, &env),
+    }
+}
+
+fn eval_tail_if(
+    condition: &Expression,
+    consequence: &BlockStatement,
+    alt: Option<&BlockStatement>,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    if let Err(err) = step(&env) {
+        return Object::Error(err);
+    }
+    let c = eval_expression(condition, env.clone());
+    if is_error(&c) {
+        return c;
+    }
+    if is_truthy(c) {
+        eval_function_body(consequence, env)
+    } else {
+        match alt {
+            Some(val) => eval_function_body(val, env),
+            None => Object::Null,
         }
-        Expression::IfExpression(condition, consequence, alt) => {
-            let c = eval_expression(*condition, env.clone());
-            if is_error(&c) {
-                return c;
-            }
-            if is_truthy(c) {
-                return eval_block_statement(consequence, env);
-            }
-            match alt {
-                Some(val) => eval_block_statement(val, env),
-                None => Object::Null,
+    }
+}
+
+// Makes the call a tail call stands for, where its value is needed after all.
+fn make_tail_call(obj: Object, env: &Rc<RefCell<Environment>>) -> Object {
+    match obj {
+        Object::TailCall(function, args, span) => {
+            with_span(apply_function(*function, args, env), span)
+        }
+        obj => obj,
+    }
+}
+
+fn eval_call_expression(
+    function: &Expression,
+    arguments: &[Expression],
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    let function = eval_expression(function, env.clone());
+    if is_error(&function) {
+        return function;
+    }
+    let args = eval_expressions(arguments, env.clone());
+    if args.len() == 1 && is_error(&args[0]) {
+        return args[0].clone();
+    }
+    apply_function(function, args, &env)
+}
+
+fn eval_array_literal(elements: &[Expression], env: Rc<RefCell<Environment>>) -> Object {
+    let elements = eval_expressions(elements, env.clone());
+    if elements.len() == 1 && is_error(&elements[0]) {
+        return elements[0].clone();
+    }
+    let array = Object::array(elements);
+    match allocate(&env, array.heap_size()) {
+        Ok(()) => array,
+        Err(err) => Object::Error(err),
+    }
+}
+
+fn eval_index(left: &Expression, index: &Expression, env: Rc<RefCell<Environment>>) -> Object {
+    let left = eval_expression(left, env.clone());
+    if is_error(&left) {
+        return left;
+    }
+    let index = eval_expression(index, env);
+    if is_error(&index) {
+        return index;
+    }
+    eval_index_expression(left, index)
+}
+
+// Errors are located at the innermost expression that produced them, so only
+// errors that don't have a span yet pick up the current one.
+fn with_span(obj: Object, span: Span) -> Object {
+    match obj {
+        Object::Error(mut err) if err.span.is_none() => {
+            err.span = Some(span);
+            Object::Error(err)
+        }
+        _ => obj,
+    }
+}
+
+// An error leaving the body is bound to the catch variable, in an environment
+// of its own, and the catch block's value becomes the expression's. The
+// finally block runs last however the others ended, and only changes the
+// outcome if it ends early itself.
+fn eval_try_expression(
+    body: &BlockStatement,
+    catch: Option<&(Identifier, BlockStatement)>,
+    finally: Option<&BlockStatement>,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    let body = finish_return(eval_block_statement(body, env.clone()), &env);
+    let result = match (body, catch) {
+        // the host's limits end the program, finally blocks and all
+        (Object::Error(err), _) if !err.code.is_catchable() => return Object::Error(err),
+        (Object::Error(err), Some((_, handler))) => {
+            let mut catch_env = Environment::new_enclosed_environment(env.clone());
+            // the catch variable is the block's first local
+            catch_env.set_slot(0, 0, err.to_object());
+            let catch_env = gc::track_environment(Rc::new(RefCell::new(catch_env)));
+            let result = eval_block_statement(handler, catch_env);
+            finish_return(result, &env)
+        }
+        (result, _) => result,
+    };
+    if let Some(finally) = finally {
+        match finish_return(eval_block_statement(finally, env.clone()), &env) {
+            cleanup @ Object::ReturnValue(_)
+            | cleanup @ Object::Error(_)
+            | cleanup @ Object::Break
+            | cleanup @ Object::Continue => return cleanup,
+            _ => (),
+        }
+    }
+    result
+}
+
+// A `return` leaving a `try` can't leave its call for later, since the try has
+// to see whether it fails.
+fn finish_return(result: Object, env: &Rc<RefCell<Environment>>) -> Object {
+    match result {
+        Object::ReturnValue(value) => match make_tail_call(*value, env) {
+            err @ Object::Error(_) => err,
+            value => Object::ReturnValue(Box::new(value)),
+        },
+        result => result,
+    }
+}
+
+// Runs the body of a function. Its last statement is in tail position, so
+// its value may be a `TailCall`.
+fn eval_function_body(block: &BlockStatement, env: Rc<RefCell<Environment>>) -> Object {
+    let mut result = Object::Null;
+    let mut statements = block.statements.iter().peekable();
+    while let Some(statement) = statements.next() {
+        result = match statement {
+            Statement::Expression(expr, _) if statements.peek().is_none() => {
+                eval_tail_expression(expr, env.clone())
             }
+            statement => eval_statement(statement, env.clone()),
+        };
+        match result.obj_type() {
+            ObjectType::ReturnValue
+            | ObjectType::Error
+            | ObjectType::Break
+            | ObjectType::Continue => return result,
+            _ => (),
+        }
+    }
+    result
+}
+
+fn eval_block_statement(block_statement: &BlockStatement, env: Rc<RefCell<Environment>>) -> Object {
+    let mut result = Object::Null;
+    for statement in &block_statement.statements {
+        result = eval_statement(statement, env.clone());
+        match result.obj_type() {
+            ObjectType::ReturnValue
+            | ObjectType::Error
+            | ObjectType::Break
+            | ObjectType::Continue => return result,
+            _ => (),
+        }
+    }
+    result
+}
+
+// Runs the body until the condition is falsy or a `break` reaches the loop.
+// `return` and errors leave the loop and keep unwinding.
+fn eval_while_statement(
+    condition: &Expression,
+    body: &BlockStatement,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    loop {
+        let c = eval_expression(condition, env.clone());
+        if is_error(&c) {
+            return c;
+        }
+        if !is_truthy(c) {
+            return Object::Null;
+        }
+        match eval_block_statement(body, env.clone()) {
+            Object::Break => return Object::Null,
+            result @ Object::ReturnValue(_) | result @ Object::Error(_) => return result,
+            _ => (),
+        }
+    }
+}
+
+// Runs the body once per element of the iterable, each time in a fresh
+// environment holding the loop variables, so bindings made by one iteration
+// don't leak into the next or outlive the loop.
+fn eval_for_statement(
+    names: &[Identifier],
+    iterable: &Expression,
+    body: &BlockStatement,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    let span = iterable.span();
+    // `for (i in a..b)` counts from a to b without building the range's array
+    let range = match iterable {
+        Expression::Infix(start, operator, end, _) if operator == ".." && names.len() == 1 => {
+            Some((start, end))
+        }
+        _ => None,
+    };
+    if let Some((start, end)) = range {
+        let start = eval_expression(start, env.clone());
+        if is_error(&start) {
+            return start;
+        }
+        let end = eval_expression(end, env.clone());
+        if is_error(&end) {
+            return end;
         }
-        Expression::Ident(ident) => eval_identifier(ident, env),
-        Expression::FunctionLiteral(parms, body) => Object::Function(parms, body, env),
-        Expression::CallExpression(function, arguments) => {
-            let function = eval_expression(*function, env.clone());
-            if is_error(&function) {
-                return function;
+        return match (start, end) {
+            (Object::Integer(start), Object::Integer(end)) => {
+                for i in start..end {
+                    if let Some(result) = eval_for_body(names, vec![Object::Integer(i)], body, &env)
+                    {
+                        return result;
+                    }
+                }
+                Object::Null
             }
-            let args = eval_expressions(arguments, env);
-            if args.len() == 1 && is_error(&args[0]) {
-                return args[0].clone();
+            (start, end) => {
+                let overflow = env.borrow().integer_overflow();
+                with_span(eval_infix_expression("..", start, end, overflow), span)
             }
-            apply_function(function, args)
+        };
+    }
+
+    let iterable = eval_expression(iterable, env.clone());
+    if is_error(&iterable) {
+        return iterable;
+    }
+    let entries = match iteration_entries(iterable, names.len()) {
+        Ok(entries) => entries,
+        Err(err) => return with_span(Object::Error(err), span),
+    };
+    for entry in entries {
+        let values = match entry {
+            Object::Array(pair) if names.len() == 2 => pair.borrow().clone(),
+            value => vec![value],
+        };
+        if let Some(result) = eval_for_body(names, values, body, &env) {
+            return result;
         }
-        Expression::ArrayLiteral(elements) => {
-            let elements = eval_expressions(elements, env);
-            if elements.len() == 1 && is_error(&elements[0]) {
-                return elements[0].clone();
-            }
-            Object::Array(elements)
+    }
+    Object::Null
+}
+
+// Runs one iteration of a `for` body. Returns the loop's result once it should
+// stop: `Null` after a `break`, or the `return` value or error unwinding past it.
+fn eval_for_body(
+    names: &[Identifier],
+    values: Vec<Object>,
+    body: &BlockStatement,
+    env: &Rc<RefCell<Environment>>,
+) -> Option<Object> {
+    if let Err(err) = step(env) {
+        return Some(Object::Error(err));
+    }
+    let mut loop_env = Environment::new_enclosed_environment(env.clone());
+    for (index, value) in values.into_iter().take(names.len()).enumerate() {
+        loop_env.set_slot(0, index, value);
+    }
+    let loop_env = gc::track_environment(Rc::new(RefCell::new(loop_env)));
+    match eval_block_statement(body, loop_env) {
+        Object::Break => Some(Object::Null),
+        result @ Object::ReturnValue(_) | result @ Object::Error(_) => Some(result),
+        _ => None,
+    }
+}
+
+/// Lists what a `for` loop over `iterable` visits: the elements of an array,
+/// the characters of a string or the keys of a hash. With two loop variables
+/// each entry is instead a two-element array of the index (or key) and value.
+pub(crate) fn iteration_entries(
+    iterable: Object,
+    names: usize,
+) -> Result<Vec<Object>, RuntimeError> {
+    let pair = |first: Object, second: Object| {
+        if names == 2 {
+            Object::array(vec![first, second])
+        } else {
+            second
+        }
+    };
+    match iterable {
+        // a snapshot, so changes the loop body makes aren't visited
+        Object::Array(elements) => Ok(elements
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(i, element)| pair(Object::Integer(i as i64), element.clone()))
+            .collect()),
+        Object::String(s) => Ok(s
+            .chars()
+            .enumerate()
+            .map(|(i, c)| pair(Object::Integer(i as i64), Object::String(c.to_string())))
+            .collect()),
+        Object::Hash(map) => Ok(map
+            .borrow()
+            .values()
+            .map(|entry| match names {
+                2 => Object::array(vec![entry.key.clone(), entry.value.clone()]),
+                _ => entry.key.clone(),
+            })
+            .collect()),
+        _ => Err(RuntimeError::new(
+            ErrorCode::NotIterable,
+            format!("cannot iterate over {}", iterable.obj_type()),
+        )),
+    }
+}
+
+fn eval_identifier(ident: &str, slot: Slot, env: Rc<RefCell<Environment>>) -> Object {
+    let val = match slot {
+        Slot::Local { depth, index } => return env.borrow().get_slot(depth, index),
+        Slot::Global { index } => env.borrow().get_global(index),
+        Slot::Builtin => None,
+        Slot::Unresolved => env.borrow().get(ident.to_string()),
+    };
+    if let Some(val) = val {
+        return val;
+    }
+    if let Some(native) = env.borrow().get_native(ident) {
+        return native;
+    }
+    match BuiltIn::lookup_builtin(ident) {
+        Some(built_in) => Object::BuiltIn(built_in),
+        None => {
+            let msg = format!("identifier not found: {}", ident);
+            Object::error(ErrorCode::UnknownIdentifier, msg)
+        }
+    }
+}
+
+fn eval_assign_expression(
+    target: &Expression,
+    operator: &str,
+    value: &Expression,
+    env: Rc<RefCell<Environment>>,
+) -> Object {
+    let (name, indexes) = match target.assignment_target() {
+        Some(target) => target,
+        None => {
+            let msg = format!("invalid assignment target: {}", target);
+            return Object::error(ErrorCode::InvalidAssignment, msg);
         }
-        Expression::IndexExpression(left, index) => {
-            let left = eval_expression(*left, env.clone());
-            if is_error(&left) {
-                return left;
+    };
+    let slot = variable_slot(target);
+    let current = match slot {
+        Slot::Local { depth, index } => Some(env.borrow().get_slot(depth, index)),
+        Slot::Global { index } => env.borrow().get_global(index),
+        Slot::Builtin | Slot::Unresolved => env.borrow().get(name.clone()),
+    };
+    let current = match current {
+        Some(current) => current,
+        None => return undefined_variable(name),
+    };
+    let indexes = eval_expressions(indexes, env.clone());
+    if indexes.len() == 1 && is_error(&indexes[0]) {
+        return indexes[0].clone();
+    }
+    let value = eval_expression(value, env.clone());
+    if is_error(&value) {
+        return value;
+    }
+
+    let overflow = env.borrow().integer_overflow();
+    match assign_index(current, &indexes, operator, value, overflow) {
+        Ok((updated, value, allocated)) => {
+            match slot {
+                Slot::Local { depth, index } => env.borrow_mut().set_slot(depth, index, updated),
+                Slot::Global { index } => env.borrow_mut().set_global(index, updated),
+                Slot::Builtin | Slot::Unresolved => {
+                    env.borrow_mut().assign(name, updated);
+                }
             }
-            let index = eval_expression(*index, env);
-            if is_error(&index) {
-                return index;
+            match allocate(&env, allocated) {
+                Ok(()) => value,
+                Err(err) => Object::Error(err),
             }
-            eval_index_expression(left, index)
         }
-        Expression::HashLiteral(pairs) => eval_hash_literal(pairs, env),
-        Expression::StringLiteral(s) => Object::String(s),
-        _ => Object::Null,
+        Err(err) => Object::Error(err),
     }
 }
 
-fn eval_block_statement(block_statement: BlockStatement, env: Rc<RefCell<Environment>>) -> Object {
-    let mut result = Object::Null;
-    for statement in block_statement.statements {
-        result = eval_statement(statement, env.clone());
-        if result.obj_type() == ObjectType::ReturnValue || result.obj_type() == ObjectType::Error {
-            return result;
+// Where the variable an assignment updates is kept.
+fn variable_slot(target: &Expression) -> Slot {
+    match target {
+        Expression::Ident(_, slot, _) => *slot,
+        Expression::IndexExpression(left, _, _) => variable_slot(left),
+        _ => Slot::Unresolved,
+    }
+}
+
+/// How many bytes `left operator right` allocates, so the memory limit can be
+/// checked before it does: joined strings and ranges are new values.
+pub(crate) fn infix_allocation(operator: &str, left: &Object, right: &Object) -> usize {
+    match (operator, left, right) {
+        ("+", Object::String(l), Object::String(r)) => l.len() + r.len(),
+        ("..", Object::Integer(start), Object::Integer(end)) if start < end => {
+            let len = (*end as i128 - *start as i128) as usize;
+            len.saturating_mul(std::mem::size_of::<Object>())
         }
+        _ => 0,
     }
-    result
 }
 
-fn eval_identifier(ident: String, env: Rc<RefCell<Environment>>) -> Object {
-    match env.borrow().get(ident.clone()) {
-        Some(val) => val,
-        None => match BuiltIn::lookup_builtin(&ident) {
-            Some(built_in) => Object::BuiltIn(built_in),
-            None => {
-                let msg = format!("identifier not found: {}", ident);
-                Object::Error(msg)
+pub(crate) fn undefined_variable(name: &str) -> Object {
+    let msg = format!("cannot assign to undefined variable: {}", name);
+    Object::error(ErrorCode::UnknownIdentifier, msg)
+}
+
+/// Replaces the element of `container` found by following `indexes`, and
+/// returns the updated container along with the element's new value and how
+/// many bytes the assignment allocated. With a compound `operator` such as
+/// `+=` the new value is computed from the old one, and is new itself, while
+/// a plain one stores a value that already exists; a new hash entry is
+/// counted as a hash literal's entries are.
+pub(crate) fn assign_index(
+    container: Object,
+    indexes: &[Object],
+    operator: &str,
+    value: Object,
+    overflow: IntegerOverflow,
+) -> Result<(Object, Object, usize), RuntimeError> {
+    let (index, rest) = match indexes.split_first() {
+        Some(split) => split,
+        None => {
+            return match operator.strip_suffix('=') {
+                Some(op) if !op.is_empty() => {
+                    match eval_infix_expression(op, container, value, overflow) {
+                        Object::Error(err) => Err(err),
+                        value => Ok((value.clone(), value.clone(), value.heap_size())),
+                    }
+                }
+                _ => Ok((value.clone(), value, 0)),
+            };
+        }
+    };
+
+    match container {
+        Object::Array(ref elements) => {
+            let len = elements.borrow().len();
+            let i = match index {
+                Object::Integer(i) if *i >= 0 && (*i as usize) < len => *i as usize,
+                Object::Integer(i) => {
+                    let msg = format!("index out of range: {} (length {})", i, len);
+                    return Err(RuntimeError::new(ErrorCode::IndexOutOfRange, msg));
+                }
+                _ => {
+                    let msg = format!("array index must be INTEGER, got {}", index.obj_type());
+                    return Err(RuntimeError::new(ErrorCode::TypeMismatch, msg));
+                }
+            };
+            // no borrow is held while assigning into the element, which may
+            // be this same array
+            let element = elements.borrow()[i].clone();
+            let (element, value, allocated) =
+                assign_index(element, rest, operator, value, overflow)?;
+            elements.borrow_mut()[i] = element;
+            Ok((container, value, allocated))
+        }
+        Object::Hash(ref map) => {
+            let key = match object::create_hash_key(index.clone()) {
+                Some(key) => key,
+                None => {
+                    let msg = format!("unusable as hash key: {}", index.obj_type());
+                    return Err(RuntimeError::new(ErrorCode::UnhashableKey, msg));
+                }
+            };
+            let existing = map.borrow().get(&key).map(|pair| pair.value.clone());
+            let added = existing.is_none();
+            let element = existing.unwrap_or(Object::Null);
+            let (element, value, mut allocated) =
+                assign_index(element, rest, operator, value, overflow)?;
+            if added {
+                allocated += mem::size_of::<(object::HashKey, object::HashPair)>()
+                    + index.heap_size()
+                    + element.heap_size();
             }
-        },
+            map.borrow_mut()
+                .insert(key, object::HashPair::new(index.clone(), element));
+            Ok((container, value, allocated))
+        }
+        _ => {
+            let msg = format!("index assignment not supported: {}", container.obj_type());
+            Err(RuntimeError::new(ErrorCode::IndexNotSupported, msg))
+        }
     }
 }
 
-fn eval_expressions(expressions: Vec<Expression>, env: Rc<RefCell<Environment>>) -> Vec<Object> {
+fn eval_expressions<'a>(
+    expressions: impl IntoIterator<Item = &'a Expression>,
+    env: Rc<RefCell<Environment>>,
+) -> Vec<Object> {
     let mut result = vec![];
     for exp in expressions {
         let eval = eval_expression(exp, env.clone());
@@ -152,11 +793,8 @@ fn eval_expressions(expressions: Vec<Expression>, env: Rc<RefCell<Environment>>)
     result
 }
 
-fn eval_hash_literal(
-    pairs: Vec<(Expression, Expression)>,
-    env: Rc<RefCell<Environment>>,
-) -> Object {
-    let mut map = HashMap::new();
+fn eval_hash_literal(pairs: &[(Expression, Expression)], env: Rc<RefCell<Environment>>) -> Object {
+    let mut map = HashTable::new();
 
     for (k, v) in pairs {
         let key = eval_expression(k, env.clone());
@@ -171,54 +809,54 @@ fn eval_hash_literal(
                 if is_error(&value) {
                     return value;
                 }
-                let hash_pair = object::HashPair {
-                    key: key_clone,
-                    value,
-                };
-                map.insert(hash_key, hash_pair);
+                map.insert(hash_key, object::HashPair::new(key_clone, value));
             }
             None => {
-                let msg = format!("unusable as hash key: {}", key_clone.obj_type().to_string());
-                return Object::Error(msg);
+                let msg = format!("unusable as hash key: {}", key_clone.obj_type());
+                return Object::error(ErrorCode::UnhashableKey, msg);
             }
         }
     }
 
-    Object::Hash(map)
+    let hash = Object::hash(map);
+    match allocate(&env, hash.heap_size()) {
+        Ok(()) => hash,
+        Err(err) => Object::Error(err),
+    }
 }
 
-fn eval_prefix_expression(operator: &str, right: Object) -> Object {
+pub(crate) fn eval_prefix_expression(
+    operator: &str,
+    right: Object,
+    overflow: IntegerOverflow,
+) -> Object {
     match operator {
         "!" => eval_bang_operator_expression(right),
-        "-" => eval_minus_prefix_operator_expression(right),
+        "-" => eval_minus_prefix_operator_expression(right, overflow),
         _ => {
             let msg = format!("unknown operator: {}{}", operator, right.obj_type());
-            Object::Error(msg)
+            Object::error(ErrorCode::UnknownOperator, msg)
         }
     }
 }
 
-fn eval_index_expression(left: Object, index: Object) -> Object {
+pub(crate) fn eval_index_expression(left: Object, index: Object) -> Object {
     if left.obj_type() == ObjectType::Array && index.obj_type() == ObjectType::Integer {
         return eval_array_index_expression(left, index);
     } else if left.obj_type() == ObjectType::Hash {
         return eval_hash_index_expression(left, index);
     }
     let msg = format!("index operator not supported: {}", left.obj_type());
-    Object::Error(msg)
+    Object::error(ErrorCode::IndexNotSupported, msg)
 }
 
 fn eval_array_index_expression(array: Object, index: Object) -> Object {
     match array {
         Object::Array(elements) => match index {
-            Object::Integer(i) => {
-                let idx = i as usize;
-                let max = elements.len() - 1;
-                if idx > max {
-                    return Object::Null;
-                }
-                elements[idx].clone()
-            }
+            Object::Integer(i) if i >= 0 => match elements.borrow().get(i as usize) {
+                Some(element) => element.clone(),
+                None => Object::Null,
+            },
             _ => Object::Null,
         },
         _ => Object::Null,
@@ -228,21 +866,38 @@ fn eval_array_index_expression(array: Object, index: Object) -> Object {
 fn eval_hash_index_expression(hash: Object, index: Object) -> Object {
     match hash {
         Object::Hash(hash_object) => match object::create_hash_key(index) {
-            Some(key) => match hash_object.get(&key) {
+            Some(key) => match hash_object.borrow().get(&key) {
                 Some(pair) => pair.value.clone(),
                 None => Object::Null,
             },
-            _ => Object::Error("unusable as hash key.".to_string()),
+            _ => Object::error(
+                ErrorCode::UnhashableKey,
+                "unusable as hash key.".to_string(),
+            ),
         },
-        _ => Object::Error("expected hash.".to_string()),
+        _ => Object::error(ErrorCode::TypeMismatch, "expected hash.".to_string()),
     }
 }
 
-fn eval_infix_expression(operator: &str, left: Object, right: Object) -> Object {
-    if let Object::Integer(_) = left {
-        if let Object::Integer(_) = right {
-            return eval_integer_infix_expression(operator, left, right);
+pub(crate) fn eval_infix_expression(
+    operator: &str,
+    left: Object,
+    right: Object,
+    overflow: IntegerOverflow,
+) -> Object {
+    match (&left, &right) {
+        (Object::Integer(l), Object::Integer(r)) => {
+            return eval_integer_infix_expression(operator, *l, *r, overflow)
         }
+        (Object::Integer(_) | Object::BigInt(_), Object::Integer(_) | Object::BigInt(_)) => {
+            return eval_bigint_infix_expression(operator, &as_bigint(&left), &as_bigint(&right))
+        }
+        // an integer mixed with a float is promoted to a float
+        (
+            Object::Integer(_) | Object::BigInt(_) | Object::Float(_),
+            Object::Integer(_) | Object::BigInt(_) | Object::Float(_),
+        ) => return eval_float_infix_expression(operator, left, right),
+        _ => (),
     }
     match operator {
         "==" => Object::Boolean(left == right),
@@ -255,7 +910,7 @@ fn eval_infix_expression(operator: &str, left: Object, right: Object) -> Object
                     operator,
                     right.obj_type()
                 );
-                Object::Error(msg)
+                Object::error(ErrorCode::TypeMismatch, msg)
             } else if left.obj_type() == ObjectType::String
                 && right.obj_type() == ObjectType::String
             {
@@ -267,37 +922,131 @@ fn eval_infix_expression(operator: &str, left: Object, right: Object) -> Object
                     operator,
                     right.obj_type()
                 );
-                Object::Error(msg)
+                Object::error(ErrorCode::UnknownOperator, msg)
             }
         }
     }
 }
 
-fn eval_integer_infix_expression(operator: &str, left: Object, right: Object) -> Object {
-    if let Object::Integer(left_value) = left {
-        if let Object::Integer(right_value) = right {
-            return match operator {
-                "+" => Object::Integer(left_value + right_value),
-                "-" => Object::Integer(left_value - right_value),
-                "*" => Object::Integer(left_value * right_value),
-                "/" => Object::Integer(left_value / right_value),
-                "<" => Object::Boolean(left_value < right_value),
-                ">" => Object::Boolean(left_value > right_value),
-                "==" => Object::Boolean(left_value == right_value),
-                "!=" => Object::Boolean(left_value != right_value),
-                _ => {
-                    let msg = format!(
-                        "unknown operator: {} {} {}",
-                        left.obj_type(),
-                        operator,
-                        right.obj_type()
-                    );
-                    Object::Error(msg)
-                }
-            };
-        };
+fn eval_integer_infix_expression(
+    operator: &str,
+    left: i64,
+    right: i64,
+    overflow: IntegerOverflow,
+) -> Object {
+    let result = match operator {
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" | "%" if right == 0 => return division_by_zero(left, operator, right),
+        // only i64::MIN / -1 overflows
+        "/" => left.checked_div(right),
+        // i64::MIN % -1 is 0, which the wrapping remainder gets right
+        "%" => Some(left.wrapping_rem(right)),
+        "<" => return Object::Boolean(left < right),
+        ">" => return Object::Boolean(left > right),
+        "==" => return Object::Boolean(left == right),
+        "!=" => return Object::Boolean(left != right),
+        ".." if right as i128 - left as i128 > MAX_RANGE_LENGTH => {
+            return range_too_large(left, right)
+        }
+        ".." => return Object::array((left..right).map(Object::Integer).collect()),
+        _ => {
+            let msg = format!("unknown operator: INTEGER {} INTEGER", operator);
+            return Object::error(ErrorCode::UnknownOperator, msg);
+        }
     };
-    Object::Null
+    match (result, overflow) {
+        (Some(i), _) => Object::Integer(i),
+        (None, IntegerOverflow::Error) => {
+            let msg = format!(
+                "integer overflow in {}: {} {} {}",
+                operator, left, operator, right
+            );
+            Object::error(ErrorCode::IntegerOverflow, msg)
+        }
+        (None, IntegerOverflow::Promote) => {
+            eval_bigint_infix_expression(operator, &BigInt::from(left), &BigInt::from(right))
+        }
+    }
+}
+
+// At least one operand is outside the 64-bit range, so the result of an
+// arithmetic operator usually is too.
+fn eval_bigint_infix_expression(operator: &str, left: &BigInt, right: &BigInt) -> Object {
+    match operator {
+        "+" => Object::from_bigint(left + right),
+        "-" => Object::from_bigint(left - right),
+        "*" => Object::from_bigint(left * right),
+        "/" => match left.div(right) {
+            Some(quotient) => Object::from_bigint(quotient),
+            None => division_by_zero(left, operator, right),
+        },
+        "%" => match left.rem(right) {
+            Some(remainder) => Object::from_bigint(remainder),
+            None => division_by_zero(left, operator, right),
+        },
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        ".." => range_too_large(left, right),
+        _ => {
+            let msg = format!("unknown operator: INTEGER {} INTEGER", operator);
+            Object::error(ErrorCode::UnknownOperator, msg)
+        }
+    }
+}
+
+fn range_too_large(start: impl fmt::Display, end: impl fmt::Display) -> Object {
+    let msg = format!("range too large: {}..{}", start, end);
+    Object::error(ErrorCode::IntegerOverflow, msg)
+}
+
+fn division_by_zero(left: impl fmt::Display, operator: &str, right: impl fmt::Display) -> Object {
+    let msg = format!("division by zero: {} {} {}", left, operator, right);
+    Object::error(ErrorCode::DivisionByZero, msg)
+}
+
+fn as_bigint(obj: &Object) -> BigInt {
+    match obj {
+        Object::Integer(i) => BigInt::from(*i),
+        Object::BigInt(i) => i.clone(),
+        _ => BigInt::from(0i64),
+    }
+}
+
+fn eval_float_infix_expression(operator: &str, left: Object, right: Object) -> Object {
+    let (l, r) = (as_float(&left), as_float(&right));
+    match operator {
+        "+" => Object::Float(l + r),
+        "-" => Object::Float(l - r),
+        "*" => Object::Float(l * r),
+        "/" => Object::Float(l / r),
+        "%" => Object::Float(l % r),
+        "<" => Object::Boolean(l < r),
+        ">" => Object::Boolean(l > r),
+        "==" => Object::Boolean(l == r),
+        "!=" => Object::Boolean(l != r),
+        _ => {
+            let msg = format!(
+                "unknown operator: {} {} {}",
+                left.obj_type(),
+                operator,
+                right.obj_type()
+            );
+            Object::error(ErrorCode::UnknownOperator, msg)
+        }
+    }
+}
+
+fn as_float(obj: &Object) -> f64 {
+    match obj {
+        Object::Integer(i) => *i as f64,
+        Object::BigInt(i) => i.to_f64(),
+        Object::Float(f) => *f,
+        _ => f64::NAN,
+    }
 }
 
 fn eval_string_infix_expression(operator: String, left: Object, right: Object) -> Object {
@@ -308,7 +1057,7 @@ fn eval_string_infix_expression(operator: String, left: Object, right: Object) -
             operator,
             right.obj_type()
         );
-        return Object::Error(msg);
+        return Object::error(ErrorCode::UnknownOperator, msg);
     }
 
     let left_val = match left {
@@ -324,20 +1073,85 @@ fn eval_string_infix_expression(operator: String, left: Object, right: Object) -
     Object::String(format!("{}{}", left_val, right_val))
 }
 
-fn apply_function(function: Object, args: Vec<Object>) -> Object {
-    match &function {
-        Object::Function(_parms, body, _env) => {
-            let extended_env = extend_function_env(&function, args);
+// `env` is the caller's, which builtins reach the program's output through.
+//
+// A function that ends in a call hands it back instead of making it, and it's
+// made here in turn, so tail recursion runs in a loop rather than nesting.
+// Errors from making such a call are located at it, as they would be had the
+// function made it.
+fn apply_function(function: Object, args: Vec<Object>, env: &Rc<RefCell<Environment>>) -> Object {
+    let (mut function, mut args, mut tail_call) = (function, args, None);
+    loop {
+        let result = call_function(&function, args, env);
+        let result = match tail_call {
+            Some(span) => with_span(result, span),
+            None => result,
+        };
+        match result {
+            Object::TailCall(next, next_args, span) => {
+                function = *next;
+                args = next_args;
+                tail_call = Some(span);
+            }
+            result => return result,
+        }
+    }
+}
+
+fn call_function(function: &Object, args: Vec<Object>, env: &Rc<RefCell<Environment>>) -> Object {
+    match function {
+        Object::Function(parms, body, _env) => {
+            if parms.len() != args.len() {
+                let msg = format!(
+                    "wrong number of arguments. got={}, want={}",
+                    args.len(),
+                    parms.len()
+                );
+                return Object::error(ErrorCode::ArgumentCount, msg);
+            }
+            let extended_env = extend_function_env(function, args);
             match extended_env {
                 Some(extended_env) => {
-                    let evaluated = eval_block_statement(body.clone(), extended_env);
+                    let budget = budget(env);
+                    if let Err(err) = budget.borrow_mut().enter(MAX_CALL_DEPTH) {
+                        return Object::Error(err);
+                    }
+                    let evaluated = eval_function_body(body, extended_env);
+                    budget.borrow_mut().exit();
                     unwrap_return_value(evaluated)
                 }
-                _ => Object::Error("extended env error".to_string()),
+                _ => Object::error(ErrorCode::NotCallable, "extended env error".to_string()),
             }
         }
-        Object::BuiltIn(built_in) => built_in.get_fn(args),
-        _ => Object::Error("not a function".to_string()),
+        Object::BuiltIn(built_in) => built_in.get_fn(args, &mut Callbacks { env }),
+        Object::Native(native) => native.call(args),
+        _ => Object::error(ErrorCode::NotCallable, "not a function".to_string()),
+    }
+}
+
+// Runs the functions builtins are given, e.g. `map`'s.
+struct Callbacks<'a> {
+    env: &'a Rc<RefCell<Environment>>,
+}
+
+impl CallContext for Callbacks<'_> {
+    fn apply(&mut self, function: &Object, args: Vec<Object>) -> Result<Object, RuntimeError> {
+        match apply_function(function.clone(), args, self.env) {
+            Object::Error(err) => Err(err),
+            value => Ok(value),
+        }
+    }
+
+    fn output(&self) -> Sink {
+        self.env.borrow().output()
+    }
+
+    fn error_output(&self) -> Sink {
+        self.env.borrow().error_output()
+    }
+
+    fn allocate(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        allocate(self.env, bytes)
     }
 }
 
@@ -345,11 +1159,10 @@ fn extend_function_env(function: &Object, args: Vec<Object>) -> Option<Rc<RefCel
     match function {
         Object::Function(parms, _body, env) => {
             let mut env = Environment::new_enclosed_environment(env.clone());
-            let items: Vec<_> = parms.iter().zip(args.iter()).collect();
-            for (parm, arg) in items {
-                env.set(parm.to_string(), arg.clone());
+            for (index, arg) in args.iter().take(parms.len()).enumerate() {
+                env.set_slot(0, index, arg.clone());
             }
-            Some(Rc::new(RefCell::new(env)))
+            Some(gc::track_environment(Rc::new(RefCell::new(env))))
         }
         _ => None,
     }
@@ -371,17 +1184,26 @@ fn eval_bang_operator_expression(right: Object) -> Object {
     }
 }
 
-fn eval_minus_prefix_operator_expression(right: Object) -> Object {
+fn eval_minus_prefix_operator_expression(right: Object, overflow: IntegerOverflow) -> Object {
     match right {
-        Object::Integer(i) => Object::Integer(-i),
+        Object::Integer(i) => match (i.checked_neg(), overflow) {
+            (Some(negated), _) => Object::Integer(negated),
+            (None, IntegerOverflow::Error) => {
+                let msg = format!("integer overflow in -: -({})", i);
+                Object::error(ErrorCode::IntegerOverflow, msg)
+            }
+            (None, IntegerOverflow::Promote) => Object::from_bigint(-&BigInt::from(i)),
+        },
+        Object::BigInt(i) => Object::from_bigint(-&i),
+        Object::Float(f) => Object::Float(-f),
         _ => {
             let msg = format!("unknown operator: -{}", right.obj_type());
-            Object::Error(msg)
+            Object::error(ErrorCode::UnknownOperator, msg)
         }
     }
 }
 
-fn is_truthy(obj: Object) -> bool {
+pub(crate) fn is_truthy(obj: Object) -> bool {
     match obj {
         Object::Null => false,
         Object::Boolean(true) => true,
@@ -0,0 +1,347 @@
+use crate::environment::Environment;
+use crate::object::{Closure, HashTable, Object};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+
+// Environments, arrays, hashes and the VM's closures and cells are counted
+// references, which free them as soon as nothing refers to them, except when
+// they refer to each other: a recursive function is bound in the environment
+// it closes over, and an array can hold itself. The collector finds the
+// values only such cycles keep alive and breaks the cycles, freeing them.
+// This is the scheme CPython uses, which needs no list of roots: a value is
+// in use if there are more references to it than the heap accounts for.
+//
+// It runs as values are made, and when a program calls `gc()`. Each thread
+// has its own heap, shared by the interpreters running on it.
+
+// How many values are made before the collector first runs. After that it
+// runs when the number tracked doubles.
+const THRESHOLD: usize = 10_000;
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap::default());
+}
+
+/// What the cycle collector has seen of the current thread's heap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    /// Environments, arrays, hashes, closures and cells that are still alive,
+    /// whether or not anything can reach them.
+    pub live: usize,
+    /// How many times the collector has run.
+    pub collections: usize,
+    /// How many values it has freed in all.
+    pub freed: usize,
+}
+
+/// Frees the values only cycles keep alive, returning how many it freed.
+/// Programs can do the same by calling `gc()`.
+///
+/// ```
+/// use monkey::Interpreter;
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter
+///     .eval("let f = fn() { let xs = []; push(xs, xs); len(xs) }; f()")
+///     .unwrap();
+/// assert_eq!(monkey::collect_garbage(), 1);
+/// assert_eq!(monkey::gc_stats().freed, 1);
+/// ```
+pub fn collect_garbage() -> usize {
+    let nodes = HEAP.with(|heap| heap.borrow_mut().upgrade());
+
+    // What refers to each value from outside the heap: everything but the
+    // references between tracked values, and the one `nodes` holds. Values
+    // that are borrowed can't be looked into, so are taken to be in use.
+    let mut outside: HashMap<usize, usize> = nodes
+        .iter()
+        .map(|(&id, node)| (id, node.strong_count() - 1))
+        .collect();
+    let mut edges = HashMap::new();
+    for (&id, node) in &nodes {
+        let mut refs = vec![];
+        if node.references(&mut refs) {
+            for r in &refs {
+                if let Some(count) = outside.get_mut(r) {
+                    *count -= 1;
+                }
+            }
+            edges.insert(id, refs);
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    let mut pending: Vec<usize> = outside
+        .iter()
+        .filter(|&(id, &count)| count > 0 || !edges.contains_key(id))
+        .map(|(&id, _)| id)
+        .collect();
+    while let Some(id) = pending.pop() {
+        if reachable.insert(id) {
+            if let Some(refs) = edges.get(&id) {
+                pending.extend(refs.iter().filter(|r| nodes.contains_key(r)));
+            }
+        }
+    }
+
+    let garbage: Vec<&Node> = nodes
+        .iter()
+        .filter(|(id, _)| !reachable.contains(id))
+        .map(|(_, node)| node)
+        .collect();
+    for node in &garbage {
+        node.clear();
+    }
+    let freed = garbage.len();
+    drop(garbage);
+    drop(nodes);
+    HEAP.with(|heap| heap.borrow_mut().collected(freed));
+    freed
+}
+
+pub fn gc_stats() -> GcStats {
+    HEAP.with(|heap| heap.borrow().stats())
+}
+
+/// Has the collector watch an array, hash, closure or cell. Other objects are
+/// returned as they are.
+pub(crate) fn track(obj: Object) -> Object {
+    let tracked = match &obj {
+        Object::Array(elements) => Tracked::Array(Rc::downgrade(elements)),
+        Object::Hash(map) => Tracked::Hash(Rc::downgrade(map)),
+        Object::Cell(cell) => Tracked::Cell(Rc::downgrade(cell)),
+        Object::Closure(closure) => Tracked::Closure(Rc::downgrade(closure)),
+        _ => return obj,
+    };
+    add(tracked);
+    obj
+}
+
+pub(crate) fn track_environment(env: Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+    add(Tracked::Environment(Rc::downgrade(&env)));
+    env
+}
+
+fn add(tracked: Tracked) {
+    let due = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.tracked.push(tracked);
+        heap.tracked.len() >= heap.next_collection.max(THRESHOLD)
+    });
+    if due {
+        collect_garbage();
+    }
+}
+
+#[derive(Debug, Default)]
+struct Heap {
+    tracked: Vec<Tracked>,
+    next_collection: usize,
+    collections: usize,
+    freed: usize,
+}
+
+impl Heap {
+    // The values still alive, by address, forgetting the rest.
+    fn upgrade(&mut self) -> HashMap<usize, Node> {
+        let mut nodes = HashMap::new();
+        self.tracked.retain(|tracked| match tracked.upgrade() {
+            Some(node) if !nodes.contains_key(&node.id()) => {
+                nodes.insert(node.id(), node);
+                true
+            }
+            _ => false,
+        });
+        nodes
+    }
+
+    fn collected(&mut self, freed: usize) {
+        self.tracked.retain(Tracked::is_alive);
+        self.next_collection = 2 * self.tracked.len();
+        self.collections += 1;
+        self.freed += freed;
+    }
+
+    fn stats(&self) -> GcStats {
+        let live: HashSet<usize> = self
+            .tracked
+            .iter()
+            .filter(|tracked| tracked.is_alive())
+            .map(Tracked::id)
+            .collect();
+        GcStats {
+            live: live.len(),
+            collections: self.collections,
+            freed: self.freed,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Tracked {
+    Environment(Weak<RefCell<Environment>>),
+    Array(Weak<RefCell<Vec<Object>>>),
+    Hash(Weak<RefCell<HashTable>>),
+    Cell(Weak<RefCell<Object>>),
+    Closure(Weak<Closure>),
+}
+
+impl Tracked {
+    fn upgrade(&self) -> Option<Node> {
+        Some(match self {
+            Tracked::Environment(env) => Node::Environment(env.upgrade()?),
+            Tracked::Array(elements) => Node::Array(elements.upgrade()?),
+            Tracked::Hash(map) => Node::Hash(map.upgrade()?),
+            Tracked::Cell(cell) => Node::Cell(cell.upgrade()?),
+            Tracked::Closure(closure) => Node::Closure(closure.upgrade()?),
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        match self {
+            Tracked::Environment(env) => env.strong_count() > 0,
+            Tracked::Array(elements) => elements.strong_count() > 0,
+            Tracked::Hash(map) => map.strong_count() > 0,
+            Tracked::Cell(cell) => cell.strong_count() > 0,
+            Tracked::Closure(closure) => closure.strong_count() > 0,
+        }
+    }
+
+    fn id(&self) -> usize {
+        match self {
+            Tracked::Environment(env) => env.as_ptr() as *const () as usize,
+            Tracked::Array(elements) => elements.as_ptr() as *const () as usize,
+            Tracked::Hash(map) => map.as_ptr() as *const () as usize,
+            Tracked::Cell(cell) => cell.as_ptr() as *const () as usize,
+            Tracked::Closure(closure) => closure.as_ptr() as *const () as usize,
+        }
+    }
+}
+
+enum Node {
+    Environment(Rc<RefCell<Environment>>),
+    Array(Rc<RefCell<Vec<Object>>>),
+    Hash(Rc<RefCell<HashTable>>),
+    Cell(Rc<RefCell<Object>>),
+    Closure(Rc<Closure>),
+}
+
+impl Node {
+    fn id(&self) -> usize {
+        match self {
+            Node::Environment(env) => id(env),
+            Node::Array(elements) => id(elements),
+            Node::Hash(map) => id(map),
+            Node::Cell(cell) => id(cell),
+            Node::Closure(closure) => id(closure),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Environment(env) => Rc::strong_count(env),
+            Node::Array(elements) => Rc::strong_count(elements),
+            Node::Hash(map) => Rc::strong_count(map),
+            Node::Cell(cell) => Rc::strong_count(cell),
+            Node::Closure(closure) => Rc::strong_count(closure),
+        }
+    }
+
+    // Adds the values this one refers to, once per reference. Returns false
+    // if it's borrowed, so they can't be known.
+    fn references(&self, refs: &mut Vec<usize>) -> bool {
+        match self {
+            Node::Environment(env) => match env.try_borrow() {
+                Ok(env) => {
+                    env.globals
+                        .iter()
+                        .flatten()
+                        .for_each(|obj| references(obj, refs));
+                    env.slots.iter().for_each(|obj| references(obj, refs));
+                    refs.extend(env.outer.as_ref().map(id));
+                    refs.extend(env.root.as_ref().map(id));
+                }
+                Err(_) => return false,
+            },
+            Node::Array(elements) => match elements.try_borrow() {
+                Ok(elements) => elements.iter().for_each(|obj| references(obj, refs)),
+                Err(_) => return false,
+            },
+            Node::Hash(map) => match map.try_borrow() {
+                Ok(map) => {
+                    for pair in map.values() {
+                        references(&pair.key, refs);
+                        references(&pair.value, refs);
+                    }
+                }
+                Err(_) => return false,
+            },
+            Node::Cell(cell) => match cell.try_borrow() {
+                Ok(obj) => references(&obj, refs),
+                Err(_) => return false,
+            },
+            Node::Closure(closure) => closure.free.iter().for_each(|obj| references(obj, refs)),
+        }
+        true
+    }
+
+    // Drops what an unreachable value holds, which breaks the cycles it's in.
+    // A closure's captured values can't change, but any cycle through one
+    // also runs through a cell, whose contents can.
+    fn clear(&self) {
+        match self {
+            Node::Environment(env) => {
+                if let Ok(mut env) = env.try_borrow_mut() {
+                    env.globals.clear();
+                    env.slots.clear();
+                    env.outer = None;
+                    env.root = None;
+                }
+            }
+            Node::Array(elements) => {
+                if let Ok(mut elements) = elements.try_borrow_mut() {
+                    elements.clear();
+                }
+            }
+            Node::Hash(map) => {
+                if let Ok(mut map) = map.try_borrow_mut() {
+                    *map = HashTable::new();
+                }
+            }
+            Node::Cell(cell) => {
+                if let Ok(mut obj) = cell.try_borrow_mut() {
+                    *obj = Object::Null;
+                }
+            }
+            Node::Closure(_) => (),
+        }
+    }
+}
+
+// The tracked values `obj` holds references to, looking through the values
+// that are held in place.
+fn references(obj: &Object, refs: &mut Vec<usize>) {
+    match obj {
+        Object::Array(elements) => refs.push(id(elements)),
+        Object::Hash(map) => refs.push(id(map)),
+        Object::Cell(cell) => refs.push(id(cell)),
+        Object::Closure(closure) => refs.push(id(closure)),
+        Object::Function(_, _, env) => refs.push(id(env)),
+        Object::ReturnValue(value) => references(value, refs),
+        Object::TailCall(function, args, _) => {
+            references(function, refs);
+            args.iter().for_each(|arg| references(arg, refs));
+        }
+        Object::Error(err) => {
+            if let Some(thrown) = &err.thrown {
+                references(thrown, refs);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn id<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
@@ -0,0 +1,329 @@
+use crate::ast::Program;
+use crate::compiler::Compiler;
+use crate::convert::{FromMonkey, IntoMonkey};
+use crate::diagnostics::Style;
+use crate::environment::Environment;
+use crate::error::MonkeyError;
+use crate::evaluator;
+use crate::gc;
+use crate::lexer::Lexer;
+use crate::limits::{CancelHandle, Limits};
+use crate::module::ModuleLoader;
+use crate::native::{Args, Arity, NativeFunction, NativeResult};
+use crate::object::{IntegerOverflow, Object, RuntimeError};
+use crate::output::Sink;
+use crate::parser::Parser;
+use crate::value::Value;
+use crate::vm::Vm;
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// The engine that runs programs: the tree-walking evaluator, or the bytecode
+/// compiler together with the virtual machine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Evaluator,
+    Vm,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backend, String> {
+        match s {
+            "eval" => Ok(Backend::Evaluator),
+            "vm" => Ok(Backend::Vm),
+            _ => Err(format!("unknown backend '{}', expected 'eval' or 'vm'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let output = match self {
+            Backend::Evaluator => "eval",
+            Backend::Vm => "vm",
+        };
+        write!(f, "{}", output)
+    }
+}
+
+enum Engine {
+    Evaluator(Rc<RefCell<Environment>>),
+    Vm {
+        compiler: Box<Compiler>,
+        vm: Box<Vm>,
+    },
+}
+
+/// Runs Monkey programs for embedding code. Bindings made by one call to
+/// `eval` are visible to the next.
+///
+/// ```
+/// use monkey::{Interpreter, Value};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.eval("let double = fn(x) { x * 2 };").unwrap();
+/// assert_eq!(interpreter.eval("double(21)").unwrap(), Value::Integer(42));
+/// ```
+pub struct Interpreter {
+    engine: Engine,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::with_backend(Backend::default())
+    }
+
+    pub fn with_backend(backend: Backend) -> Interpreter {
+        let engine = match backend {
+            Backend::Evaluator => {
+                let env = Rc::new(RefCell::new(Environment::default()));
+                Engine::Evaluator(gc::track_environment(env))
+            }
+            Backend::Vm => Engine::Vm {
+                compiler: Box::default(),
+                vm: Box::default(),
+            },
+        };
+        Interpreter { engine }
+    }
+
+    /// An evaluator that binds names in `env`.
+    pub fn with_environment(env: Rc<RefCell<Environment>>) -> Interpreter {
+        Interpreter {
+            engine: Engine::Evaluator(gc::track_environment(env)),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self.engine {
+            Engine::Evaluator(_) => Backend::Evaluator,
+            Engine::Vm { .. } => Backend::Vm,
+        }
+    }
+
+    /// Makes a Rust closure callable from programs under `name`. Calls with an
+    /// argument count outside `arity` fail before the closure runs.
+    ///
+    /// ```
+    /// use monkey::object::Object;
+    /// use monkey::{Interpreter, Value};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.register_fn("shout", 1, |args| {
+    ///     Ok(Object::String(args.string(0)?.to_uppercase()))
+    /// });
+    /// let value = interpreter.eval("shout(\"hi\")").unwrap();
+    /// assert_eq!(value, Value::String("HI".to_string()));
+    /// ```
+    pub fn register_fn<F>(&mut self, name: &str, arity: impl Into<Arity>, func: F)
+    where
+        F: Fn(&Args) -> NativeResult + 'static,
+    {
+        let native = NativeFunction::new(name, arity.into(), func);
+        match &mut self.engine {
+            Engine::Evaluator(env) => env.borrow_mut().define_native(native),
+            Engine::Vm { compiler, vm } => {
+                let index = vm.define_builtin(Object::Native(native));
+                compiler.define_builtin(index, name);
+            }
+        }
+    }
+
+    /// Chooses what integer arithmetic does when a result doesn't fit in 64
+    /// bits. By default it's an error.
+    ///
+    /// ```
+    /// use monkey::{IntegerOverflow, Interpreter};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.set_integer_overflow(IntegerOverflow::Promote);
+    /// let value = interpreter.eval("9223372036854775807 + 1").unwrap();
+    /// assert_eq!(value.to_string(), "9223372036854775808");
+    /// ```
+    pub fn set_integer_overflow(&mut self, mode: IntegerOverflow) {
+        match &mut self.engine {
+            Engine::Evaluator(env) => env.borrow_mut().set_integer_overflow(mode),
+            Engine::Vm { vm, .. } => vm.set_integer_overflow(mode),
+        }
+    }
+
+    /// Sends what `puts` and `print` write to `sink` instead of stdout.
+    ///
+    /// ```
+    /// use monkey::{Interpreter, Sink};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// let output = Sink::buffer();
+    /// interpreter.set_output(output.clone());
+    /// interpreter.eval("puts(\"hello\", 1); print(2)").unwrap();
+    /// assert_eq!(output.contents().unwrap(), "hello 1\n2");
+    /// ```
+    pub fn set_output(&mut self, sink: Sink) {
+        match &mut self.engine {
+            Engine::Evaluator(env) => env.borrow_mut().set_output(sink),
+            Engine::Vm { vm, .. } => vm.set_output(sink),
+        }
+    }
+
+    /// Sends what `eprint` writes to `sink` instead of stderr.
+    pub fn set_error_output(&mut self, sink: Sink) {
+        match &mut self.engine {
+            Engine::Evaluator(env) => env.borrow_mut().set_error_output(sink),
+            Engine::Vm { vm, .. } => vm.set_error_output(sink),
+        }
+    }
+
+    /// Bounds the steps, call depth and time each program may take from now
+    /// on. See [`Limits`].
+    pub fn set_limits(&mut self, limits: Limits) {
+        match &mut self.engine {
+            Engine::Evaluator(env) => env.borrow().budget().borrow_mut().set_limits(limits),
+            Engine::Vm { vm, .. } => vm.set_limits(limits),
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        match &self.engine {
+            Engine::Evaluator(env) => env.borrow().budget().borrow().limits(),
+            Engine::Vm { vm, .. } => vm.limits(),
+        }
+    }
+
+    /// A handle another thread can stop the running program with.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        match &self.engine {
+            Engine::Evaluator(env) => env.borrow().budget().borrow().cancel_handle(),
+            Engine::Vm { vm, .. } => vm.cancel_handle(),
+        }
+    }
+
+    /// Resolves the imports of the programs this runs relative to the
+    /// directory of `path`, the file they come from. Otherwise they are
+    /// resolved relative to the working directory.
+    pub fn set_script_path(&mut self, path: impl AsRef<Path>) {
+        self.with_modules(|modules| modules.set_script_path(path.as_ref()));
+    }
+
+    /// Adds a directory to look for imported modules in, after the importing
+    /// file's own directory.
+    ///
+    /// ```no_run
+    /// use monkey::Interpreter;
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.add_search_dir("/usr/share/monkey");
+    /// interpreter.eval("import \"strings\"; strings[\"upcase\"](\"hi\")").unwrap();
+    /// ```
+    pub fn add_search_dir(&mut self, dir: impl Into<PathBuf>) {
+        let dir = dir.into();
+        self.with_modules(|modules| modules.add_search_dir(dir));
+    }
+
+    fn with_modules(&mut self, f: impl FnOnce(&mut ModuleLoader)) {
+        match &mut self.engine {
+            Engine::Evaluator(env) => f(&mut env.borrow().modules().borrow_mut()),
+            Engine::Vm { compiler, .. } => f(compiler.modules()),
+        }
+    }
+
+    /// Binds `name` to `value` in the global scope, as `let` would.
+    pub fn set(&mut self, name: &str, value: impl IntoMonkey) {
+        let obj = value.into_monkey();
+        match &mut self.engine {
+            Engine::Evaluator(env) => {
+                env.borrow_mut().set(name.to_string(), obj);
+            }
+            Engine::Vm { compiler, vm } => {
+                let index = compiler.define_global(name);
+                vm.set_global(index, obj);
+            }
+        }
+    }
+
+    /// Runs `input` and returns the value of its last statement.
+    pub fn eval(&mut self, input: &str) -> Result<Value, MonkeyError> {
+        let obj = self.eval_object(input)?;
+        Value::try_from(obj).map_err(MonkeyError::Conversion)
+    }
+
+    /// Like `eval`, but converts the value to a Rust type.
+    ///
+    /// ```
+    /// use monkey::Interpreter;
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.set("xs", vec![1, 2, 3]);
+    /// let doubled: Vec<i64> = interpreter.eval_as("[xs[0] * 2, xs[2] * 2]").unwrap();
+    /// assert_eq!(doubled, vec![2, 6]);
+    /// ```
+    pub fn eval_as<T: FromMonkey>(&mut self, input: &str) -> Result<T, MonkeyError> {
+        let obj = self.eval_object(input)?;
+        T::from_monkey(&obj).map_err(MonkeyError::Conversion)
+    }
+
+    /// Runs `input` and returns what the REPL would print: the value, or the
+    /// rendered diagnostics.
+    pub fn interpret(&mut self, name: &str, input: &str, style: Style) -> String {
+        match self.eval_object(input) {
+            Ok(evaluated) => evaluated.inspect(),
+            Err(err @ MonkeyError::Runtime(_)) => {
+                err.render(name, input, style).trim_end().to_string()
+            }
+            Err(err) => {
+                let mut output = String::from("Woops! We ran into some monkey business here!\n");
+                output.push_str(&err.render(name, input, style));
+                output.trim_end().to_string()
+            }
+        }
+    }
+
+    fn eval_object(&mut self, input: &str) -> Result<Object, MonkeyError> {
+        let program = parse(input)?;
+        let evaluated = match &mut self.engine {
+            Engine::Evaluator(env) => {
+                env.borrow().budget().borrow_mut().start();
+                evaluator::eval(program, env.clone())
+            }
+            Engine::Vm { compiler, vm } => match compiler.compile(&program) {
+                Ok(bytecode) => vm.run(bytecode),
+                // the evaluator reports the same mistakes at run time
+                Err(err) => Object::Error(RuntimeError {
+                    code: err.code,
+                    message: err.message,
+                    span: Some(err.span),
+                    thrown: None,
+                }),
+            },
+        };
+        match evaluated {
+            Object::Error(err) => Err(MonkeyError::Runtime(err)),
+            _ => Ok(evaluated),
+        }
+    }
+}
+
+fn parse(input: &str) -> Result<Program, MonkeyError> {
+    let lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program();
+    if !parser.lexer_errors().is_empty() {
+        return Err(MonkeyError::Lex(parser.lexer_errors().to_vec()));
+    }
+    if !parser.errors().is_empty() {
+        return Err(MonkeyError::Parse(parser.errors().clone()));
+    }
+    Ok(program)
+}
@@ -1,18 +1,59 @@
-use crate::token::Token;
+use crate::diagnostics::ErrorCode;
+use crate::token::{Span, Token};
+use std::fmt;
 use std::iter::Peekable;
 use std::str::Chars;
 
+/// Source text that doesn't form a valid token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+/// Splits source text into tokens. Invalid input still yields a token
+/// (`Token::Illegal`, or the partial string) so parsing can carry on, and is
+/// recorded in `errors()`.
 pub struct Lexer<'a> {
+    source: &'a str,
     input: Peekable<Chars<'a>>,
+    offset: usize,
+    line: usize,
+    column: usize,
+    // the `file` of the spans it makes
+    file: usize,
+    errors: Vec<LexError>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Lexer<'a> {
+        Lexer::with_file(input, 0)
+    }
+
+    /// A lexer for the text of the imported file numbered `file`.
+    pub fn with_file(input: &'a str, file: usize) -> Lexer<'a> {
         Lexer {
+            source: input,
             input: input.chars().peekable(),
+            offset: 0,
+            line: 1,
+            column: 1,
+            file,
+            errors: vec![],
         }
     }
 
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
     fn peek_char(&mut self) -> Option<&char> {
         self.input.peek()
     }
@@ -21,7 +62,7 @@ impl<'a> Lexer<'a> {
         let mut s = String::new();
         s.push(c);
         while let Some(&c) = self.peek_char() {
-            if c.is_alphabetic() {
+            if is_letter(c) {
                 s.push(self.read_char().unwrap());
             } else {
                 break;
@@ -30,24 +71,61 @@ impl<'a> Lexer<'a> {
         s
     }
 
-    fn read_number(&mut self, c: char) -> String {
+    // Reads an integer, or a float if a fraction or exponent follows the
+    // digits. A `.` only starts a fraction when a digit follows it, so `0..n`
+    // is still a range.
+    fn read_number(&mut self, c: char) -> Token {
         let mut num = String::new();
         num.push(c);
+        self.read_digits(&mut num);
+        let mut is_float = false;
+
+        let rest = &self.source[self.offset..];
+        if rest.starts_with('.') && starts_with_digit(&rest[1..]) {
+            is_float = true;
+            num.push(self.read_char().unwrap());
+            self.read_digits(&mut num);
+        }
+
+        let rest = &self.source[self.offset..];
+        if rest.starts_with(['e', 'E']) {
+            // the `e` and the exponent's sign, if any
+            let prefix = if rest[1..].starts_with(['+', '-']) {
+                2
+            } else {
+                1
+            };
+            if starts_with_digit(&rest[prefix..]) {
+                is_float = true;
+                for _ in 0..prefix {
+                    num.push(self.read_char().unwrap());
+                }
+                self.read_digits(&mut num);
+            }
+        }
+
+        if is_float {
+            Token::Float(num)
+        } else {
+            Token::Int(num)
+        }
+    }
+
+    fn read_digits(&mut self, num: &mut String) {
         while let Some(&c) = self.peek_char() {
-            if c.is_digit(10) {
+            if c.is_ascii_digit() {
                 num.push(self.read_char().unwrap());
             } else {
                 break;
             }
         }
-        num
     }
 
     fn read_string(&mut self) -> String {
         let mut s = String::new();
         while let Some(&c) = self.peek_char() {
             let ch = self.read_char().unwrap();
-            if c == '"' || c == '\u{0}' || c == '\"' {
+            if c == '"' || c == '\u{0}' {
                 break;
             } else if c != '\n' {
                 s.push(ch);
@@ -67,11 +145,67 @@ impl<'a> Lexer<'a> {
     }
 
     fn read_char(&mut self) -> Option<char> {
-        self.input.next()
+        let c = self.input.next()?;
+        self.offset += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Returns an empty span at the lexer's current position in the input.
+    pub fn position(&self) -> Span {
+        Span {
+            file: self.file,
+            ..Span::new(self.offset, self.offset, self.line, self.column)
+        }
     }
 
-    pub fn next_token(&mut self) -> Option<Token> {
+    pub fn next_token(&mut self) -> Option<(Token, Span)> {
         self.skip_whitespace();
+        let (start, line, column) = (self.offset, self.line, self.column);
+        let token = self.read_token()?;
+        let span = Span {
+            file: self.file,
+            ..Span::new(start, self.offset, line, column)
+        };
+        let text = &self.source[start..self.offset];
+        match token {
+            Token::Illegal => {
+                let msg = format!("illegal character '{}'", text);
+                self.error(ErrorCode::IllegalCharacter, msg, span);
+            }
+            Token::String(_) if text.len() < 2 || !text.ends_with('"') => {
+                let msg = "unterminated string literal".to_string();
+                self.error(ErrorCode::UnterminatedString, msg, span);
+            }
+            _ => (),
+        }
+        Some((token, span))
+    }
+
+    fn error(&mut self, code: ErrorCode, message: String, span: Span) {
+        self.errors.push(LexError {
+            code,
+            message,
+            span,
+        });
+    }
+
+    // Reads the `=` of a compound assignment operator such as `+=`.
+    fn with_assign(&mut self, operator: Token, compound: Token) -> Token {
+        if let Some(&'=') = self.peek_char() {
+            self.read_char();
+            compound
+        } else {
+            operator
+        }
+    }
+
+    fn read_token(&mut self) -> Option<Token> {
         if let Some(c) = self.read_char() {
             match c {
                 '=' => {
@@ -82,8 +216,8 @@ impl<'a> Lexer<'a> {
                         Some(Token::Assign)
                     }
                 }
-                '+' => Some(Token::Plus),
-                '-' => Some(Token::Minus),
+                '+' => Some(self.with_assign(Token::Plus, Token::PlusAssign)),
+                '-' => Some(self.with_assign(Token::Minus, Token::MinusAssign)),
                 '!' => {
                     if let Some(&'=') = self.peek_char() {
                         self.read_char();
@@ -92,8 +226,9 @@ impl<'a> Lexer<'a> {
                         Some(Token::Bang)
                     }
                 }
-                '/' => Some(Token::Slash),
-                '*' => Some(Token::Asterisk),
+                '/' => Some(self.with_assign(Token::Slash, Token::SlashAssign)),
+                '*' => Some(self.with_assign(Token::Asterisk, Token::AsteriskAssign)),
+                '%' => Some(self.with_assign(Token::Percent, Token::PercentAssign)),
                 '<' => Some(Token::Lt),
                 '>' => Some(Token::Gt),
                 ';' => Some(Token::Semicolon),
@@ -109,11 +244,19 @@ impl<'a> Lexer<'a> {
                 '[' => Some(Token::LBracket),
                 ']' => Some(Token::RBracket),
                 ':' => Some(Token::Colon),
+                '.' => {
+                    if let Some(&'.') = self.peek_char() {
+                        self.read_char();
+                        Some(Token::DotDot)
+                    } else {
+                        Some(Token::Illegal)
+                    }
+                }
                 _ => {
                     if is_letter(c) {
                         Some(Token::lookup_ident(self.read_identifier(c)))
-                    } else if c.is_digit(10) {
-                        Some(Token::Int(self.read_number(c)))
+                    } else if c.is_ascii_digit() {
+                        Some(self.read_number(c))
                     } else {
                         Some(Token::Illegal)
                     }
@@ -125,6 +268,10 @@ impl<'a> Lexer<'a> {
     }
 }
 
+fn starts_with_digit(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_digit())
+}
+
 fn is_letter(ch: char) -> bool {
     ch.is_alphabetic() || ch == '_'
 }
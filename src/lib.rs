@@ -1,38 +1,78 @@
-mod ast;
+pub mod ast;
+mod bigint;
+mod code;
+mod compiler;
+mod convert;
+pub mod diagnostics;
 pub mod environment;
+mod error;
 mod evaluator;
-mod lexer;
-mod object;
-mod parser;
+mod gc;
+mod interpreter;
+pub mod lexer;
+mod limits;
+mod module;
+pub mod native;
+pub mod object;
+mod output;
+pub mod parser;
 pub mod repl;
+mod resolver;
+#[cfg(feature = "serde")]
+mod serialization;
+mod symbol_table;
 mod tests;
-mod token;
+pub mod token;
+mod value;
+mod vm;
 
+pub use crate::bigint::BigInt;
+pub use crate::convert::{ConversionError, FromMonkey, IntoMonkey, IntoMonkeyKey};
+pub use crate::error::MonkeyError;
+pub use crate::gc::{collect_garbage, gc_stats, GcStats};
+pub use crate::interpreter::{Backend, Interpreter};
+pub use crate::limits::{CancelHandle, Limits};
+pub use crate::object::IntegerOverflow;
+pub use crate::output::Sink;
+pub use crate::value::{Function, Value};
+
+use crate::diagnostics::Style;
 use crate::environment::Environment;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+// Name used in error locations when the input doesn't come from a file.
+const DEFAULT_SOURCE_NAME: &str = "<input>";
+
 pub fn interpret_text(input: &str) -> String {
-    let env = Rc::new(RefCell::new(Environment::default()));
-    interpret_text_env(input, env)
+    interpret_source(DEFAULT_SOURCE_NAME, input)
 }
 
 pub fn interpret_text_env(input: &str, env: Rc<RefCell<Environment>>) -> String {
-    let lexer = Lexer::new(&input);
-    let mut parser = Parser::new(lexer);
-    let program = parser.parse_program();
-    if !parser.errors().is_empty() {
-        let mut output = vec![];
-        output.push("Woops! We ran into some monkey business here!");
-        output.push(" parser errors: ");
-        for s in parser.errors() {
-            output.push(s);
-        }
-        return output.join("\n");
-    }
-
-    let evaluated = evaluator::eval(program, env);
-    evaluated.inspect()
+    interpret_source_env(DEFAULT_SOURCE_NAME, input, env)
+}
+
+/// Like `interpret_text`, but errors are reported as `name:line:col`.
+pub fn interpret_source(name: &str, input: &str) -> String {
+    let env = gc::track_environment(Rc::new(RefCell::new(Environment::default())));
+    interpret_source_env(name, input, env)
+}
+
+pub fn interpret_source_env(name: &str, input: &str, env: Rc<RefCell<Environment>>) -> String {
+    interpret_styled(name, input, env, Style::Plain)
+}
+
+/// Interprets `input` on the given backend with a fresh set of bindings.
+pub fn interpret_with(backend: Backend, name: &str, input: &str, style: Style) -> String {
+    Interpreter::with_backend(backend).interpret(name, input, style)
+}
+
+/// Interprets `input`, rendering any parse or runtime errors as diagnostics in the given style.
+pub fn interpret_styled(
+    name: &str,
+    input: &str,
+    env: Rc<RefCell<Environment>>,
+    style: Style,
+) -> String {
+    Interpreter::with_environment(env).interpret(name, input, style)
 }
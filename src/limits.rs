@@ -0,0 +1,272 @@
+use crate::diagnostics::ErrorCode;
+use crate::object::RuntimeError;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How many steps run between looks at the clock and the cancel flag.
+const CHECK_INTERVAL: u32 = 1024;
+
+// How much of a thread's stack is kept free below the deepest Monkey call
+// that nests Rust calls, for what the call does before the next one is
+// checked and for the builtins and host functions it calls.
+const STACK_RESERVE: usize = 256 * 1024;
+
+// How much stack to assume a thread has when its size can't be found out,
+// measured from the first call that's checked.
+const ASSUMED_STACK_SIZE: usize = 1024 * 1024;
+
+thread_local! {
+    // The lowest address calls may reach on this thread's stack.
+    static STACK_LIMIT: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Bounds on the work a single program may do, for hosts running code they
+/// don't trust. Each limit that's hit stops the program with an error of its
+/// own, which `try` can't catch. Nothing is limited by default, apart from
+/// how deeply calls can nest.
+///
+/// ```
+/// use monkey::diagnostics::ErrorCode;
+/// use monkey::{Interpreter, Limits, MonkeyError};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_limits(Limits {
+///     fuel: Some(10_000),
+///     ..Limits::default()
+/// });
+/// match interpreter.eval("while (true) {}") {
+///     Err(MonkeyError::Runtime(err)) => assert_eq!(err.code, ErrorCode::OutOfFuel),
+///     other => panic!("unexpected {:?}", other),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// How many steps a program may take: roughly one per expression the
+    /// evaluator evaluates, or per instruction the virtual machine executes.
+    pub fuel: Option<u64>,
+    /// How many function calls may be in progress at once. Without a limit
+    /// the virtual machine allows 1024, and the evaluator, which nests Rust
+    /// calls for Monkey ones, as many as fit in the thread's stack. Either
+    /// way the evaluator stops with a stack overflow before the stack runs
+    /// out.
+    pub max_call_depth: Option<usize>,
+    /// How long a program may run.
+    pub timeout: Option<Duration>,
+    /// How many bytes of strings, arrays and hashes a program may allocate.
+    /// Like fuel, memory is counted as it's taken and not given back, so this
+    /// bounds the work a program does as well as what it keeps.
+    pub max_memory: Option<usize>,
+}
+
+/// Stops the program an interpreter is running, from any thread. A
+/// cancellation is used up by the run it stops; if nothing is running, it
+/// stops the next one.
+///
+/// ```
+/// use monkey::Interpreter;
+/// use std::thread;
+/// use std::time::Duration;
+///
+/// let mut interpreter = Interpreter::new();
+/// let cancel = interpreter.cancel_handle();
+/// thread::spawn(move || {
+///     thread::sleep(Duration::from_millis(10));
+///     cancel.cancel();
+/// });
+/// assert!(interpreter.eval("while (true) {}").is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn take(&self) -> bool {
+        self.cancelled.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancelHandle {
+    fn eq(&self, other: &CancelHandle) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+/// What's left of the limits while a program runs, with the calls in
+/// progress and the memory taken. `start` resets it for each program.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Budget {
+    limits: Limits,
+    cancel: CancelHandle,
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    until_check: u32,
+    depth: usize,
+    allocated: usize,
+}
+
+impl Budget {
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub fn start(&mut self) {
+        self.fuel = self.limits.fuel;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.until_check = 0;
+        self.depth = 0;
+        self.allocated = 0;
+    }
+
+    /// Takes one step's fuel, and every so often checks the clock and
+    /// whether the program was cancelled.
+    pub fn step(&mut self) -> Result<(), RuntimeError> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                let msg = format!("out of fuel after {} steps", self.limits.fuel.unwrap_or(0));
+                return Err(RuntimeError::new(ErrorCode::OutOfFuel, msg));
+            }
+            *fuel -= 1;
+        }
+        if self.until_check > 0 {
+            self.until_check -= 1;
+            return Ok(());
+        }
+        self.until_check = CHECK_INTERVAL;
+        if self.cancel.take() {
+            let msg = "cancelled by the host".to_string();
+            return Err(RuntimeError::new(ErrorCode::Cancelled, msg));
+        }
+        match (self.deadline, self.limits.timeout) {
+            (Some(deadline), Some(timeout)) if Instant::now() >= deadline => {
+                let msg = format!("time limit of {:?} exceeded", timeout);
+                Err(RuntimeError::new(ErrorCode::Timeout, msg))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts `bytes` the program allocates, failing once they add up to
+    /// more than the limit.
+    pub fn allocate(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        self.allocated = self.allocated.saturating_add(bytes);
+        match self.limits.max_memory {
+            Some(max) if self.allocated > max => {
+                let msg = format!("memory limit exceeded: more than {} bytes allocated", max);
+                Err(RuntimeError::new(ErrorCode::OutOfMemory, msg))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The deepest calls may nest: the host's limit, or else `default`.
+    pub fn max_call_depth(&self, default: usize) -> usize {
+        self.limits.max_call_depth.unwrap_or(default)
+    }
+
+    /// Counts a call the evaluator makes, until the matching `exit`.
+    pub fn enter(&mut self, default: usize) -> Result<(), RuntimeError> {
+        let max = self.max_call_depth(default);
+        if self.depth >= max {
+            return Err(too_deep(max));
+        }
+        check_stack(self.depth)?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn exit(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+}
+
+pub(crate) fn too_deep(max: usize) -> RuntimeError {
+    let msg = format!("stack overflow: more than {} nested calls", max);
+    RuntimeError::new(ErrorCode::StackOverflow, msg)
+}
+
+/// Fails if a call that nests Rust calls, made `depth` calls deep, would come
+/// too close to the end of the thread's stack, so that a program overflowing
+/// it gets an error rather than aborting the host.
+pub(crate) fn check_stack(depth: usize) -> Result<(), RuntimeError> {
+    let here = stack_address();
+    let limit = STACK_LIMIT.with(|limit| match limit.get() {
+        Some(address) => address,
+        None => {
+            let bottom =
+                stack_bottom(here).unwrap_or_else(|| here.saturating_sub(ASSUMED_STACK_SIZE));
+            let address = bottom.saturating_add(STACK_RESERVE);
+            limit.set(Some(address));
+            address
+        }
+    });
+    if here < limit {
+        let msg = format!("stack overflow: {} nested calls used up the stack", depth);
+        return Err(RuntimeError::new(ErrorCode::StackOverflow, msg));
+    }
+    Ok(())
+}
+
+// Roughly where the stack is now: it grows down, toward lower addresses.
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+// The lowest address the stack holding `here` may grow to: the start of a
+// thread's stack, or for the main thread, whose stack grows on demand, as far
+// down as its size limit and the mapping below it allow.
+#[cfg(target_os = "linux")]
+fn stack_bottom(here: usize) -> Option<usize> {
+    // the gap the kernel keeps between a growing stack and what's below it
+    const STACK_GUARD_GAP: usize = 1024 * 1024;
+    let maps = std::fs::read_to_string("/proc/self/maps").ok()?;
+    let mut below: usize = 0;
+    for line in maps.lines() {
+        let (start, end) = line.split_whitespace().next()?.split_once('-')?;
+        let start = usize::from_str_radix(start, 16).ok()?;
+        let end = usize::from_str_radix(end, 16).ok()?;
+        if (start..end).contains(&here) {
+            if !line.ends_with("[stack]") {
+                return Some(start);
+            }
+            let lowest = below.saturating_add(STACK_GUARD_GAP);
+            return Some(match main_stack_size() {
+                Some(size) => end.saturating_sub(size).max(lowest),
+                None => lowest,
+            });
+        }
+        below = end;
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn stack_bottom(_here: usize) -> Option<usize> {
+    None
+}
+
+// The main thread's stack size limit, or None if it's unlimited.
+#[cfg(target_os = "linux")]
+fn main_stack_size() -> Option<usize> {
+    let limits = std::fs::read_to_string("/proc/self/limits").ok()?;
+    let line = limits
+        .lines()
+        .find(|line| line.starts_with("Max stack size"))?;
+    line.split_whitespace().nth(3)?.parse().ok()
+}
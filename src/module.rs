@@ -0,0 +1,197 @@
+use crate::ast::{Program, Statement};
+use crate::diagnostics::ErrorCode;
+use crate::lexer::Lexer;
+use crate::object::{self, HashPair, HashTable, Object, RuntimeError};
+use crate::parser::Parser;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Added to an imported path that has no extension of its own.
+const EXTENSION: &str = "monkey";
+
+thread_local! {
+    // The imported files spans can be in, numbered from 1 in the order they
+    // were first read, with their names and text so errors in them can be
+    // shown. Each thread has its own, shared by the interpreters on it.
+    static FILES: RefCell<Vec<SourceFile>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    path: PathBuf,
+    name: String,
+    text: Rc<str>,
+}
+
+/// The name and text of the imported file a span's `file` numbers, or `None`
+/// for the program being run.
+pub(crate) fn source_file(file: usize) -> Option<(String, Rc<str>)> {
+    let index = file.checked_sub(1)?;
+    FILES.with(|files| {
+        let files = files.borrow();
+        let file = files.get(index)?;
+        Some((file.name.clone(), file.text.clone()))
+    })
+}
+
+// Numbers the file at `path`. A file read again unchanged keeps its number.
+fn add_source_file(path: &Path, text: &str) -> usize {
+    FILES.with(|files| {
+        let mut files = files.borrow_mut();
+        if let Some(index) = files
+            .iter()
+            .position(|file| file.path == path && &*file.text == text)
+        {
+            return index + 1;
+        }
+        files.push(SourceFile {
+            path: path.to_path_buf(),
+            name: name(path),
+            text: Rc::from(text),
+        });
+        files.len()
+    })
+}
+
+/// Finds, reads and parses the files programs import. An imported path is
+/// looked up relative to the importing file, or at the top level to the
+/// script's directory, or the working directory if there's no script, and
+/// then in each directory of the search path.
+///
+/// The loader also keeps the files being imported, innermost last, to report
+/// circular imports, and the namespaces of modules the evaluator has run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModuleLoader {
+    search_path: Vec<PathBuf>,
+    script_dir: Option<PathBuf>,
+    loading: Vec<PathBuf>,
+    namespaces: HashMap<PathBuf, Object>,
+}
+
+impl ModuleLoader {
+    pub fn add_search_dir(&mut self, dir: PathBuf) {
+        self.search_path.push(dir);
+    }
+
+    pub fn set_script_path(&mut self, path: &Path) {
+        self.script_dir = path.parent().map(Path::to_path_buf);
+    }
+
+    /// The file `path` names, as a canonical path so each file has one.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, RuntimeError> {
+        // an empty path joins to one relative to the working directory
+        let base = match self.loading.last() {
+            Some(importer) => importer.parent().map(Path::to_path_buf),
+            None => Some(self.script_dir.clone().unwrap_or_default()),
+        };
+        let dirs = base.into_iter().chain(self.search_path.iter().cloned());
+        let candidates: Vec<PathBuf> = if Path::new(path).is_absolute() {
+            vec![PathBuf::from(path)]
+        } else {
+            dirs.map(|dir| dir.join(path)).collect()
+        };
+        for candidate in candidates {
+            let file = match candidate.extension() {
+                Some(_) => candidate,
+                None => candidate.with_extension(EXTENSION),
+            };
+            if file.is_file() {
+                return fs::canonicalize(&file).map_err(|err| cannot_read(&file, &err));
+            }
+        }
+        let msg = format!("module not found: {:?}", path);
+        Err(RuntimeError::new(ErrorCode::BadImport, msg))
+    }
+
+    /// Reads and parses `file` and marks it as being imported until `end`.
+    pub fn begin(&mut self, file: &Path) -> Result<Program, RuntimeError> {
+        if let Some(start) = self.loading.iter().position(|f| f == file) {
+            let mut cycle: Vec<String> = self.loading[start..].iter().map(|f| name(f)).collect();
+            cycle.push(name(file));
+            let msg = format!("circular import: {}", cycle.join(" -> "));
+            return Err(RuntimeError::new(ErrorCode::BadImport, msg));
+        }
+        let source = fs::read_to_string(file).map_err(|err| cannot_read(file, &err))?;
+        let program = parse(file, &source)?;
+        self.loading.push(file.to_path_buf());
+        Ok(program)
+    }
+
+    pub fn end(&mut self) {
+        self.loading.pop();
+    }
+
+    pub fn namespace(&self, file: &Path) -> Option<Object> {
+        self.namespaces.get(file).cloned()
+    }
+
+    pub fn remember(&mut self, file: PathBuf, namespace: Object) {
+        self.namespaces.insert(file, namespace);
+    }
+}
+
+/// The hash a module is imported as: its top-level bindings, by name.
+pub fn namespace(mut bindings: Vec<(String, Object)>) -> Object {
+    bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut map = HashTable::new();
+    for (name, value) in bindings {
+        let key = Object::String(name);
+        if let Some(hash_key) = object::create_hash_key(key.clone()) {
+            map.insert(hash_key, HashPair { key, value });
+        }
+    }
+    Object::hash(map)
+}
+
+/// An error from running a module. It's reported where it happened in the
+/// module, or else at the `import` that ran it.
+pub fn in_module(path: &str, err: RuntimeError) -> RuntimeError {
+    RuntimeError {
+        message: format!("in module {:?}: {}", path, err.message),
+        ..err
+    }
+}
+
+fn parse(file: &Path, source: &str) -> Result<Program, RuntimeError> {
+    let lexer = Lexer::with_file(source, add_source_file(file, source));
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program();
+    let first_error = match (parser.lexer_errors().first(), parser.errors().first()) {
+        (Some(err), _) => Some((err.span, err.message.clone())),
+        (None, Some(err)) => Some((err.span, err.message.clone())),
+        (None, None) => None,
+    };
+    if let Some((span, message)) = first_error {
+        let msg = format!("{}:{}: {}", name(file), span, message);
+        return Err(RuntimeError::new(ErrorCode::BadImport, msg));
+    }
+    // a module's value is its namespace, so there's nothing to return
+    if let Some(statement) = program
+        .statements
+        .iter()
+        .find(|statement| matches!(statement, Statement::Return(_, _)))
+    {
+        let msg = format!(
+            "{}:{}: 'return' outside of a function in a module",
+            name(file),
+            statement.span()
+        );
+        return Err(RuntimeError::new(ErrorCode::BadImport, msg));
+    }
+    Ok(program)
+}
+
+fn name(file: &Path) -> String {
+    match file.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => file.display().to_string(),
+    }
+}
+
+fn cannot_read(file: &Path, err: &std::io::Error) -> RuntimeError {
+    let msg = format!("cannot read {}: {}", file.display(), err);
+    RuntimeError::new(ErrorCode::BadImport, msg)
+}
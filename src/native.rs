@@ -0,0 +1,206 @@
+use crate::convert::{ConversionError, FromMonkey};
+use crate::diagnostics::ErrorCode;
+use crate::object::{Object, RuntimeError};
+use std::cell::Ref;
+use std::fmt;
+use std::ops::{RangeFrom, RangeInclusive};
+use std::rc::Rc;
+
+/// What a native function returns: a value, or the error to raise in the program.
+pub type NativeResult = Result<Object, RuntimeError>;
+
+/// How many arguments a native function accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    Range(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    fn accepts(self, n: usize) -> bool {
+        match self {
+            Arity::Exact(want) => n == want,
+            Arity::Range(min, max) => n >= min && n <= max,
+            Arity::AtLeast(min) => n >= min,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exact(n) => write!(f, "{}", n),
+            Arity::Range(min, max) => write!(f, "{}..={}", min, max),
+            Arity::AtLeast(min) => write!(f, "{}..", min),
+        }
+    }
+}
+
+impl From<usize> for Arity {
+    fn from(n: usize) -> Arity {
+        Arity::Exact(n)
+    }
+}
+
+impl From<RangeInclusive<usize>> for Arity {
+    fn from(range: RangeInclusive<usize>) -> Arity {
+        Arity::Range(*range.start(), *range.end())
+    }
+}
+
+impl From<RangeFrom<usize>> for Arity {
+    fn from(range: RangeFrom<usize>) -> Arity {
+        Arity::AtLeast(range.start)
+    }
+}
+
+/// The arguments a native function was called with. The typed getters fail
+/// with an error naming the function, the argument and the type it got.
+pub struct Args<'a> {
+    name: &'a str,
+    args: &'a [Object],
+}
+
+impl<'a> Args<'a> {
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a Object> {
+        self.args.get(index)
+    }
+
+    pub fn as_slice(&self) -> &'a [Object] {
+        self.args
+    }
+
+    /// Converts the argument at `index` to any `FromMonkey` type.
+    pub fn get_as<T: FromMonkey>(&self, index: usize) -> Result<T, RuntimeError> {
+        let arg = self
+            .get(index)
+            .ok_or_else(|| self.type_error(index, "an argument", None))?;
+        T::from_monkey(arg).map_err(|err| self.conversion_error(index, err))
+    }
+
+    pub fn integer(&self, index: usize) -> Result<i64, RuntimeError> {
+        self.get_as(index)
+    }
+
+    pub fn boolean(&self, index: usize) -> Result<bool, RuntimeError> {
+        self.get_as(index)
+    }
+
+    pub fn string(&self, index: usize) -> Result<&'a str, RuntimeError> {
+        match self.get(index) {
+            Some(Object::String(s)) => Ok(s),
+            other => Err(self.type_error(index, "STRING", other)),
+        }
+    }
+
+    pub fn array(&self, index: usize) -> Result<Ref<'a, Vec<Object>>, RuntimeError> {
+        match self.get(index) {
+            Some(Object::Array(elements)) => Ok(elements.borrow()),
+            other => Err(self.type_error(index, "ARRAY", other)),
+        }
+    }
+
+    /// An error raised by the function itself, e.g. for an out-of-range value.
+    pub fn error(&self, message: &str) -> RuntimeError {
+        RuntimeError::new(
+            ErrorCode::ArgumentType,
+            format!("{}: {}", self.name, message),
+        )
+    }
+
+    fn type_error(&self, index: usize, want: &str, got: Option<&Object>) -> RuntimeError {
+        let found = match got {
+            Some(obj) => obj.obj_type().to_string(),
+            None => "nothing".to_string(),
+        };
+        let err = ConversionError {
+            expected: want.to_string(),
+            found,
+            path: String::new(),
+        };
+        self.conversion_error(index, err)
+    }
+
+    fn conversion_error(&self, index: usize, err: ConversionError) -> RuntimeError {
+        let part = if err.path.is_empty() {
+            String::new()
+        } else {
+            format!("{} of ", err.path)
+        };
+        let msg = format!(
+            "{}argument {} to '{}' must be {}, got {}",
+            part,
+            index + 1,
+            self.name,
+            err.expected,
+            err.found
+        );
+        RuntimeError::new(ErrorCode::ArgumentType, msg)
+    }
+}
+
+type NativeFn = dyn Fn(&Args) -> NativeResult;
+
+/// A builtin implemented by the embedding program.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Arity,
+    func: Rc<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, arity: Arity, func: F) -> NativeFunction
+    where
+        F: Fn(&Args) -> NativeResult + 'static,
+    {
+        NativeFunction {
+            name: name.to_string(),
+            arity,
+            func: Rc::new(func),
+        }
+    }
+
+    /// Checks the argument count and calls the function. Errors are returned
+    /// as `Object::Error`, like the other builtins.
+    pub fn call(&self, args: Vec<Object>) -> Object {
+        if !self.arity.accepts(args.len()) {
+            let msg = format!(
+                "wrong number of arguments. got={}, want={}",
+                args.len(),
+                self.arity
+            );
+            return Object::error(ErrorCode::ArgumentCount, msg);
+        }
+        let args = Args {
+            name: &self.name,
+            args: &args,
+        };
+        match (self.func)(&args) {
+            Ok(obj) => obj,
+            Err(err) => Object::Error(err),
+        }
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFunction({}, {})", self.name, self.arity)
+    }
+}
+
+// Two natives are the same function only if they share the closure.
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &NativeFunction) -> bool {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
+}
@@ -1,36 +1,168 @@
 use crate::ast::{BlockStatement, Identifier};
+use crate::bigint::BigInt;
+use crate::code::Instructions;
+use crate::diagnostics::ErrorCode;
 use crate::environment::Environment;
+use crate::evaluator;
+use crate::gc;
+use crate::module;
+use crate::native::NativeFunction;
+use crate::output::Sink;
+use crate::token::Span;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::mem;
 use std::rc::Rc;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Object {
     Integer(i64),
+    /// An INTEGER outside the 64-bit range, produced when arithmetic
+    /// overflows under `IntegerOverflow::Promote`.
+    BigInt(BigInt),
+    Float(f64),
     Boolean(bool),
     ReturnValue(Box<Object>),
-    Error(String),
+    /// A call in tail position the evaluator hasn't made yet, with the span of
+    /// the call. The function being returned from hands it back so the call
+    /// runs in its place, without nesting deeper. Programs never see one.
+    TailCall(Box<Object>, Vec<Object>, Span),
+    Break,
+    Continue,
+    Error(RuntimeError),
     Function(Vec<Identifier>, BlockStatement, Rc<RefCell<Environment>>),
     String(String),
     BuiltIn(BuiltIn),
-    Array(Vec<Object>),
-    Hash(HashMap<HashKey, HashPair>),
+    Native(NativeFunction),
+    /// Arrays and hashes are shared: copies of the object refer to the same
+    /// elements, so changes made through one are seen by all.
+    Array(Rc<RefCell<Vec<Object>>>),
+    Hash(Rc<RefCell<HashTable>>),
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Rc<Closure>),
+    /// A VM local captured by a closure, shared so assignments on either side
+    /// are seen by the other. Reading a variable never yields a cell.
+    Cell(Rc<RefCell<Object>>),
     Null,
 }
 
+// Shared values are equal to themselves without looking inside, so a hash
+// holding itself, or a function whose environment holds it, can be compared.
+// Functions are only equal when they close over the same environment.
+impl PartialEq for Object {
+    fn eq(&self, other: &Object) -> bool {
+        self.eq_within(other, &mut vec![])
+    }
+}
+
+impl Object {
+    // `open` holds the pairs of collections being compared. Meeting a pair
+    // again means it's equal as far as it goes, since the cycle back to it
+    // can't add a difference, so collections that contain themselves compare
+    // instead of recursing forever.
+    fn eq_within(&self, other: &Object, open: &mut Vec<(*const (), *const ())>) -> bool {
+        match (self, other) {
+            (Object::Integer(a), Object::Integer(b)) => a == b,
+            (Object::BigInt(a), Object::BigInt(b)) => a == b,
+            (Object::Float(a), Object::Float(b)) => a == b,
+            (Object::Boolean(a), Object::Boolean(b)) => a == b,
+            (Object::ReturnValue(a), Object::ReturnValue(b)) => a.eq_within(b, open),
+            (Object::Break, Object::Break)
+            | (Object::Continue, Object::Continue)
+            | (Object::Null, Object::Null) => true,
+            (Object::Error(a), Object::Error(b)) => a == b,
+            (Object::Function(pa, ba, ea), Object::Function(pb, bb, eb)) => {
+                pa == pb && ba == bb && Rc::ptr_eq(ea, eb)
+            }
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::BuiltIn(a), Object::BuiltIn(b)) => a == b,
+            (Object::Native(a), Object::Native(b)) => a == b,
+            (Object::Array(a), Object::Array(b)) => {
+                Rc::ptr_eq(a, b)
+                    || within(open, (id(a), id(b)), |open| {
+                        let (a, b) = (a.borrow(), b.borrow());
+                        a.len() == b.len()
+                            && a.iter().zip(b.iter()).all(|(x, y)| x.eq_within(y, open))
+                    })
+            }
+            (Object::Hash(a), Object::Hash(b)) => {
+                Rc::ptr_eq(a, b)
+                    || within(open, (id(a), id(b)), |open| {
+                        let (a, b) = (a.borrow(), b.borrow());
+                        a.len() == b.len()
+                            && a.pairs.iter().all(|(key, pair)| match b.get(key) {
+                                Some(other) => pair.value.eq_within(&other.value, open),
+                                None => false,
+                            })
+                    })
+            }
+            (Object::CompiledFunction(a), Object::CompiledFunction(b)) => a == b,
+            (Object::Closure(a), Object::Closure(b)) => {
+                Rc::ptr_eq(a, b)
+                    || (a.function == b.function
+                        && a.free.len() == b.free.len()
+                        && a.free
+                            .iter()
+                            .zip(&b.free)
+                            .all(|(x, y)| x.eq_within(y, open)))
+            }
+            (Object::Cell(a), Object::Cell(b)) => {
+                Rc::ptr_eq(a, b) || a.borrow().eq_within(&b.borrow(), open)
+            }
+            _ => false,
+        }
+    }
+}
+
+// Compares two collections with `compare`, unless they're already being
+// compared further out.
+fn within(
+    open: &mut Vec<(*const (), *const ())>,
+    ids: (*const (), *const ()),
+    compare: impl FnOnce(&mut Vec<(*const (), *const ())>) -> bool,
+) -> bool {
+    if open.contains(&ids) {
+        return true;
+    }
+    open.push(ids);
+    let equal = compare(open);
+    open.pop();
+    equal
+}
+
+fn id<T>(rc: &Rc<T>) -> *const () {
+    Rc::as_ptr(rc) as *const ()
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ObjectType {
     Null,
     Error,
     Integer,
+    Float,
     Boolean,
     ReturnValue,
+    TailCall,
+    Break,
+    Continue,
     Function,
     String,
     BuiltIn,
     Array,
     Hash,
+    CompiledFunction,
+}
+
+/// What integer arithmetic does when a result doesn't fit in 64 bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntegerOverflow {
+    /// Fail with an "integer overflow" error.
+    #[default]
+    Error,
+    /// Continue with arbitrary-precision integers.
+    Promote,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -41,12 +173,54 @@ pub enum BuiltIn {
     Rest,
     Push,
     Puts,
+    Int,
+    Float,
+    Pop,
+    Set,
+    Delete,
+    Copy,
+    DeepCopy,
+    Map,
+    Filter,
+    Reduce,
+    SortBy,
+    Any,
+    All,
+    Find,
+    Each,
+    Print,
+    Eprint,
+    Gc,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct HashKey {
-    obj_type: ObjectType,
-    value: u64,
+/// What a builtin can reach of the running program: it can call a function
+/// value, such as the one given to `map`, and write output. Each backend runs
+/// calls itself, so a callback behaves as it would if the program had called
+/// it.
+pub trait CallContext {
+    fn apply(&mut self, function: &Object, args: Vec<Object>) -> Result<Object, RuntimeError>;
+
+    /// Where `puts` and `print` write.
+    fn output(&self) -> Sink;
+
+    /// Where `eprint` writes.
+    fn error_output(&self) -> Sink;
+
+    /// Counts memory the builtin allocated against the program's limit.
+    fn allocate(&mut self, bytes: usize) -> Result<(), RuntimeError>;
+}
+
+/// A hash key: a copy of the key's value, so distinct keys never collide.
+/// `Hash` keys list their pairs sorted, since the order they were inserted in
+/// doesn't affect equality.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HashKey {
+    Integer(i64),
+    BigInt(BigInt),
+    Boolean(bool),
+    String(String),
+    Array(Vec<HashKey>),
+    Hash(Vec<(HashKey, HashKey)>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -55,48 +229,413 @@ pub struct HashPair {
     pub value: Object,
 }
 
+impl HashPair {
+    /// A pair for a key a program used. An array or hash key is copied, as
+    /// its hash key is, so changing the original later doesn't change what
+    /// the hash shows the pair is stored under.
+    pub fn new(key: Object, value: Object) -> HashPair {
+        let key = match key {
+            Object::Array(_) | Object::Hash(_) => key.deep_copy(),
+            _ => key,
+        };
+        HashPair { key, value }
+    }
+}
+
+/// The pairs of a HASH, which iterate in the order their keys were first
+/// inserted.
+#[derive(Debug, Clone, Default)]
+pub struct HashTable {
+    pairs: Vec<(HashKey, HashPair)>,
+    // position of each key in `pairs`
+    index: HashMap<HashKey, usize>,
+}
+
+impl HashTable {
+    pub fn new() -> HashTable {
+        HashTable::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&HashPair> {
+        self.index.get(key).map(|&i| &self.pairs[i].1)
+    }
+
+    pub fn get_mut(&mut self, key: &HashKey) -> Option<&mut HashPair> {
+        let i = *self.index.get(key)?;
+        Some(&mut self.pairs[i].1)
+    }
+
+    /// Adds a pair, returning the one it replaced. A replaced pair keeps its
+    /// place in the iteration order.
+    pub fn insert(&mut self, key: HashKey, pair: HashPair) -> Option<HashPair> {
+        match self.index.get(&key) {
+            Some(&i) => Some(std::mem::replace(&mut self.pairs[i].1, pair)),
+            None => {
+                self.index.insert(key.clone(), self.pairs.len());
+                self.pairs.push((key, pair));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &HashKey) -> Option<HashPair> {
+        let i = self.index.remove(key)?;
+        let (_, pair) = self.pairs.remove(i);
+        for (key, _) in &self.pairs[i..] {
+            *self.index.get_mut(key).unwrap() -= 1;
+        }
+        Some(pair)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &HashPair> {
+        self.pairs.iter().map(|(_, pair)| pair)
+    }
+
+    pub fn into_values(self) -> impl Iterator<Item = HashPair> {
+        self.pairs.into_iter().map(|(_, pair)| pair)
+    }
+}
+
+// Like a map, two tables are equal when they hold the same pairs in any order.
+impl PartialEq for HashTable {
+    fn eq(&self, other: &HashTable) -> bool {
+        self.len() == other.len()
+            && self
+                .pairs
+                .iter()
+                .all(|(key, pair)| other.get(key).map(|p| p.value == pair.value) == Some(true))
+    }
+}
+
+/// A function literal lowered to bytecode. `positions` maps instruction offsets
+/// to the source they were compiled from, in ascending offset order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    pub positions: Vec<(usize, Span)>,
+    pub num_locals: usize,
+    pub parameters: Vec<Identifier>,
+    pub body: String,
+}
+
+impl CompiledFunction {
+    /// Returns the span of the instruction at `offset`.
+    pub fn span_at(&self, offset: usize) -> Option<Span> {
+        let index = match self.positions.binary_search_by_key(&offset, |(o, _)| *o) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        Some(self.positions[index].1)
+    }
+}
+
+/// A compiled function together with the free variables it captured. To
+/// Monkey programs a closure is just a FUNCTION.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Closure {
+    pub function: Rc<CompiledFunction>,
+    pub free: Vec<Object>,
+}
+
+/// An evaluation error. The span is filled in by the evaluator with the innermost
+/// expression that produced the error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Option<Span>,
+    /// The hash a `throw` raised, which `catch` hands back as it was.
+    pub thrown: Option<Box<Object>>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.span {
+            // an error in a module's code says which file it's in
+            Some(span) => match module::source_file(span.file) {
+                Some((name, _)) => write!(f, "{}:{}: {}", name, span, self.message),
+                None => write!(f, "{}: {}", span, self.message),
+            },
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl RuntimeError {
+    pub fn new(code: ErrorCode, message: String) -> RuntimeError {
+        RuntimeError {
+            code,
+            message,
+            span: None,
+            thrown: None,
+        }
+    }
+
+    /// The error `throw value` raises. A thrown hash is described by its
+    /// `"message"`, and anything else by the value itself.
+    pub fn thrown(value: Object) -> RuntimeError {
+        match value {
+            Object::String(message) => RuntimeError::new(ErrorCode::Thrown, message),
+            Object::Hash(ref map) => {
+                let key = HashKey::String("message".to_string());
+                let message = match map.borrow().get(&key) {
+                    Some(HashPair {
+                        value: Object::String(message),
+                        ..
+                    }) => message.clone(),
+                    _ => value.inspect(),
+                };
+                RuntimeError {
+                    thrown: Some(Box::new(value)),
+                    ..RuntimeError::new(ErrorCode::Thrown, message)
+                }
+            }
+            _ => RuntimeError::new(ErrorCode::Thrown, value.inspect()),
+        }
+    }
+
+    /// The value `catch` binds: the hash that was thrown, or a hash of the
+    /// error's kind, message, line and column.
+    pub fn to_object(&self) -> Object {
+        if let Some(thrown) = &self.thrown {
+            return *thrown.clone();
+        }
+        let (line, column) = match self.span {
+            Some(span) => (
+                Object::Integer(span.line as i64),
+                Object::Integer(span.column as i64),
+            ),
+            None => (Object::Null, Object::Null),
+        };
+        let entries = vec![
+            ("kind", Object::String(self.code.kind().to_string())),
+            ("message", Object::String(self.message.clone())),
+            ("line", line),
+            ("column", column),
+        ];
+        let mut map = HashTable::new();
+        for (name, value) in entries {
+            let key = Object::String(name.to_string());
+            map.insert(HashKey::String(name.to_string()), HashPair { key, value });
+        }
+        Object::hash(map)
+    }
+}
+
 impl Object {
+    pub fn error(code: ErrorCode, message: String) -> Object {
+        Object::Error(RuntimeError::new(code, message))
+    }
+
+    pub fn array(elements: Vec<Object>) -> Object {
+        gc::track(Object::Array(Rc::new(RefCell::new(elements))))
+    }
+
+    pub fn hash(pairs: HashTable) -> Object {
+        gc::track(Object::Hash(Rc::new(RefCell::new(pairs))))
+    }
+
+    /// Roughly how many bytes the value holds on the heap, which is what the
+    /// memory limit counts. The elements of an array or hash are values of
+    /// their own, so they aren't included.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Object::String(s) => s.len(),
+            Object::Array(elements) => elements.borrow().len() * mem::size_of::<Object>(),
+            Object::Hash(map) => map.borrow().len() * mem::size_of::<(HashKey, HashPair)>(),
+            _ => 0,
+        }
+    }
+
+    /// Like `heap_size`, but including everything arrays and hashes hold,
+    /// counting each collection once however often it's shared.
+    pub fn deep_heap_size(&self) -> usize {
+        self.deep_heap_size_within(&mut HashSet::new())
+    }
+
+    fn deep_heap_size_within(&self, seen: &mut HashSet<*const ()>) -> usize {
+        match self {
+            Object::Array(elements) => {
+                if !seen.insert(Rc::as_ptr(elements) as *const ()) {
+                    return 0;
+                }
+                let elements = elements.borrow();
+                let inner: usize = elements.iter().map(|e| e.deep_heap_size_within(seen)).sum();
+                self.heap_size() + inner
+            }
+            Object::Hash(map) => {
+                if !seen.insert(Rc::as_ptr(map) as *const ()) {
+                    return 0;
+                }
+                let map = map.borrow();
+                let inner: usize = map
+                    .values()
+                    .map(|pair| {
+                        pair.key.deep_heap_size_within(seen)
+                            + pair.value.deep_heap_size_within(seen)
+                    })
+                    .sum();
+                self.heap_size() + inner
+            }
+            _ => self.heap_size(),
+        }
+    }
+
+    /// A copy that shares nothing with `self`: arrays and hashes are copied
+    /// all the way down.
+    pub fn deep_copy(&self) -> Object {
+        self.deep_copy_within(&mut HashMap::new())
+    }
+
+    // `copies` maps each collection already copied to its copy, so shared
+    // and cyclic structure comes out the same shape rather than recursing
+    // forever.
+    fn deep_copy_within(&self, copies: &mut HashMap<*const (), Object>) -> Object {
+        match self {
+            Object::Array(elements) => {
+                let id = Rc::as_ptr(elements) as *const ();
+                if let Some(copy) = copies.get(&id) {
+                    return copy.clone();
+                }
+                let copy = Rc::new(RefCell::new(vec![]));
+                copies.insert(id, gc::track(Object::Array(copy.clone())));
+                let elements: Vec<Object> = elements.borrow().clone();
+                let elements = elements
+                    .iter()
+                    .map(|e| e.deep_copy_within(copies))
+                    .collect();
+                *copy.borrow_mut() = elements;
+                Object::Array(copy)
+            }
+            Object::Hash(map) => {
+                let id = Rc::as_ptr(map) as *const ();
+                if let Some(copy) = copies.get(&id) {
+                    return copy.clone();
+                }
+                let copy = Rc::new(RefCell::new(HashTable::new()));
+                copies.insert(id, gc::track(Object::Hash(copy.clone())));
+                let pairs = map.borrow().pairs.clone();
+                for (key, pair) in pairs {
+                    let pair = HashPair {
+                        key: pair.key.deep_copy_within(copies),
+                        value: pair.value.deep_copy_within(copies),
+                    };
+                    copy.borrow_mut().insert(key, pair);
+                }
+                Object::Hash(copy)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// An INTEGER holding `i`, which is only a `BigInt` when it doesn't fit in
+    /// an `i64`.
+    pub fn from_bigint(i: BigInt) -> Object {
+        match i.to_i64() {
+            Some(i) => Object::Integer(i),
+            None => Object::BigInt(i),
+        }
+    }
+
     pub fn inspect(&self) -> String {
+        self.inspect_within(&mut vec![])
+    }
+
+    // `open` holds the collections being printed, so one that contains
+    // itself shows as `[...]` or `{...}` there instead of recursing forever.
+    fn inspect_within(&self, open: &mut Vec<*const ()>) -> String {
         match &self {
             Object::Integer(i) => i.to_string(),
+            Object::BigInt(i) => i.to_string(),
+            // always shows a fraction or exponent, so 1.0 doesn't look like 1
+            Object::Float(f) => format!("{:?}", f),
             Object::Boolean(b) => b.to_string(),
             Object::Null => String::from(""),
-            Object::ReturnValue(value) => String::from(&*value.inspect()),
-            Object::Error(msg) => format!("ERROR: {}", msg),
+            Object::ReturnValue(value) => value.inspect_within(open),
+            Object::TailCall(function, _, _) => {
+                format!("tail call to {}", function.inspect_within(open))
+            }
+            Object::Break => String::from("break"),
+            Object::Continue => String::from("continue"),
+            Object::Error(err) => format!("ERROR: {}", err.message),
             Object::Function(parms, body, _) => {
-                format!("fn({}) {{\n{}\n}}", parms.join(", "), body.to_string())
+                format!("fn({}) {{\n{}\n}}", parms.join(", "), body)
             }
             Object::String(s) => s.to_string(),
             Object::BuiltIn(bi) => bi.to_string(),
+            Object::Native(native) => native.name.clone(),
             Object::Array(elements) => {
+                let id = Rc::as_ptr(elements) as *const ();
+                if open.contains(&id) {
+                    return String::from("[...]");
+                }
+                open.push(id);
                 let mut s = vec![];
-                for element in elements {
-                    s.push(element.inspect());
+                for element in elements.borrow().iter() {
+                    s.push(element.inspect_within(open));
                 }
+                open.pop();
                 format!("[{}]", s.join(", "))
             }
             Object::Hash(map) => {
+                let id = Rc::as_ptr(map) as *const ();
+                if open.contains(&id) {
+                    return String::from("{...}");
+                }
+                open.push(id);
                 let mut pairs = vec![];
-                for pair in map.values() {
-                    pairs.push(format!("{}: {}", pair.key.inspect(), pair.value.inspect()));
+                for pair in map.borrow().values() {
+                    pairs.push(format!(
+                        "{}: {}",
+                        pair.key.inspect_within(open),
+                        pair.value.inspect_within(open)
+                    ));
                 }
+                open.pop();
                 format!("{{{}}}", pairs.join(", "))
             }
+            Object::CompiledFunction(function) => {
+                format!(
+                    "fn({}) {{\n{}\n}}",
+                    function.parameters.join(", "),
+                    function.body
+                )
+            }
+            Object::Closure(closure) => {
+                Object::CompiledFunction(closure.function.clone()).inspect()
+            }
+            Object::Cell(cell) => cell.borrow().inspect_within(open),
         }
     }
 
     pub fn obj_type(&self) -> ObjectType {
         match &self {
-            Object::Integer(_) => ObjectType::Integer,
+            Object::Integer(_) | Object::BigInt(_) => ObjectType::Integer,
+            Object::Float(_) => ObjectType::Float,
             Object::Boolean(_) => ObjectType::Boolean,
             Object::Null => ObjectType::Null,
             Object::ReturnValue(_) => ObjectType::ReturnValue,
+            Object::TailCall(_, _, _) => ObjectType::TailCall,
+            Object::Break => ObjectType::Break,
+            Object::Continue => ObjectType::Continue,
             Object::Error(_) => ObjectType::Error,
             Object::Function(_, _, _) => ObjectType::Function,
             Object::String(_) => ObjectType::String,
-            Object::BuiltIn(_) => ObjectType::BuiltIn,
+            Object::BuiltIn(_) | Object::Native(_) => ObjectType::BuiltIn,
             Object::Array(_) => ObjectType::Array,
             Object::Hash(_) => ObjectType::Hash,
+            Object::CompiledFunction(_) => ObjectType::CompiledFunction,
+            Object::Closure(_) => ObjectType::Function,
+            Object::Cell(cell) => cell.borrow().obj_type(),
         }
     }
 }
@@ -107,13 +646,18 @@ impl fmt::Display for ObjectType {
             ObjectType::Null => "NULL",
             ObjectType::Error => "ERROR",
             ObjectType::Integer => "INTEGER",
+            ObjectType::Float => "FLOAT",
             ObjectType::Boolean => "BOOLEAN",
             ObjectType::ReturnValue => "RETURN_VALUE",
+            ObjectType::TailCall => "TAIL_CALL",
+            ObjectType::Break => "BREAK",
+            ObjectType::Continue => "CONTINUE",
             ObjectType::Function => "FUNCTION",
             ObjectType::String => "STRING",
             ObjectType::BuiltIn => "BUILTIN",
             ObjectType::Array => "ARRAY",
             ObjectType::Hash => "HASH",
+            ObjectType::CompiledFunction => "COMPILED_FUNCTION",
         };
         write!(f, "{}", output)
     }
@@ -128,23 +672,88 @@ impl fmt::Display for BuiltIn {
             BuiltIn::Rest => "Rest",
             BuiltIn::Push => "Push",
             BuiltIn::Puts => "Puts",
+            BuiltIn::Int => "Int",
+            BuiltIn::Float => "Float",
+            BuiltIn::Pop => "Pop",
+            BuiltIn::Set => "Set",
+            BuiltIn::Delete => "Delete",
+            BuiltIn::Copy => "Copy",
+            BuiltIn::DeepCopy => "DeepCopy",
+            BuiltIn::Map => "Map",
+            BuiltIn::Filter => "Filter",
+            BuiltIn::Reduce => "Reduce",
+            BuiltIn::SortBy => "SortBy",
+            BuiltIn::Any => "Any",
+            BuiltIn::All => "All",
+            BuiltIn::Find => "Find",
+            BuiltIn::Each => "Each",
+            BuiltIn::Print => "Print",
+            BuiltIn::Eprint => "Eprint",
+            BuiltIn::Gc => "Gc",
         };
         write!(f, "{}", output)
     }
 }
 
 impl BuiltIn {
-    pub fn get_fn(&self, args: Vec<Object>) -> Object {
+    /// Runs the builtin. Those taking a function call it through `ctx`.
+    pub fn get_fn(&self, args: Vec<Object>, ctx: &mut dyn CallContext) -> Object {
         match self {
             BuiltIn::Len => builtin_len(args),
             BuiltIn::First => builtin_first(args),
             BuiltIn::Last => builtin_last(args),
-            BuiltIn::Rest => builtin_rest(args),
-            BuiltIn::Push => builtin_push(args),
-            BuiltIn::Puts => builtin_puts(args),
+            BuiltIn::Rest => builtin_rest(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Push => builtin_push(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Puts => builtin_puts(args, ctx),
+            BuiltIn::Int => builtin_int(args),
+            BuiltIn::Float => builtin_float(args),
+            BuiltIn::Pop => builtin_pop(args),
+            BuiltIn::Set => builtin_set(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Delete => builtin_delete(args),
+            BuiltIn::Copy => builtin_copy(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::DeepCopy => builtin_deep_copy(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Map => builtin_map(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Filter => builtin_filter(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Reduce => builtin_reduce(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::SortBy => builtin_sort_by(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Any => builtin_any(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::All => builtin_all(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Find => builtin_find(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Each => builtin_each(args, ctx).unwrap_or_else(Object::Error),
+            BuiltIn::Print => builtin_print(args, ctx),
+            BuiltIn::Eprint => builtin_eprint(args, ctx),
+            BuiltIn::Gc => builtin_gc(args),
         }
     }
 
+    /// Names of the builtins in the order the compiler numbers them.
+    pub const NAMES: [&'static str; 24] = [
+        "len",
+        "first",
+        "last",
+        "rest",
+        "push",
+        "puts",
+        "int",
+        "float",
+        "pop",
+        "set",
+        "delete",
+        "copy",
+        "deep_copy",
+        "map",
+        "filter",
+        "reduce",
+        "sort_by",
+        "any",
+        "all",
+        "find",
+        "each",
+        "print",
+        "eprint",
+        "gc",
+    ];
+
     pub fn lookup_builtin(s: &str) -> Option<BuiltIn> {
         match s {
             "len" => Some(BuiltIn::Len),
@@ -153,6 +762,24 @@ impl BuiltIn {
             "rest" => Some(BuiltIn::Rest),
             "push" => Some(BuiltIn::Push),
             "puts" => Some(BuiltIn::Puts),
+            "int" => Some(BuiltIn::Int),
+            "float" => Some(BuiltIn::Float),
+            "pop" => Some(BuiltIn::Pop),
+            "set" => Some(BuiltIn::Set),
+            "delete" => Some(BuiltIn::Delete),
+            "copy" => Some(BuiltIn::Copy),
+            "deep_copy" => Some(BuiltIn::DeepCopy),
+            "map" => Some(BuiltIn::Map),
+            "filter" => Some(BuiltIn::Filter),
+            "reduce" => Some(BuiltIn::Reduce),
+            "sort_by" => Some(BuiltIn::SortBy),
+            "any" => Some(BuiltIn::Any),
+            "all" => Some(BuiltIn::All),
+            "find" => Some(BuiltIn::Find),
+            "each" => Some(BuiltIn::Each),
+            "print" => Some(BuiltIn::Print),
+            "eprint" => Some(BuiltIn::Eprint),
+            "gc" => Some(BuiltIn::Gc),
             _ => None,
         }
     }
@@ -161,7 +788,7 @@ impl BuiltIn {
 fn builtin_len(args: Vec<Object>) -> Object {
     if args.len() != 1 {
         let msg = format!("wrong number of arguments. got={}, want=1", args.len());
-        return Object::Error(msg);
+        return Object::error(ErrorCode::ArgumentCount, msg);
     }
     match &args[0] {
         Object::String(s) => {
@@ -169,12 +796,12 @@ fn builtin_len(args: Vec<Object>) -> Object {
             Object::Integer(l)
         }
         Object::Array(elements) => {
-            let l = elements.len() as i64;
+            let l = elements.borrow().len() as i64;
             Object::Integer(l)
         }
         _ => {
             let msg = "argument to 'len' not supported.".to_string();
-            Object::Error(msg)
+            Object::error(ErrorCode::ArgumentType, msg)
         }
     }
 }
@@ -182,18 +809,16 @@ fn builtin_len(args: Vec<Object>) -> Object {
 fn builtin_first(args: Vec<Object>) -> Object {
     if args.len() != 1 {
         let msg = format!("wrong number of arguments. got={}, want=1", args.len());
-        return Object::Error(msg);
+        return Object::error(ErrorCode::ArgumentCount, msg);
     }
     match &args[0] {
-        Object::Array(elements) => {
-            if elements.is_empty() {
-                return Object::Null;
-            }
-            elements[0].clone()
-        }
+        Object::Array(elements) => match elements.borrow().first() {
+            Some(element) => element.clone(),
+            None => Object::Null,
+        },
         _ => {
             let msg = "argument to 'first' must be ARRAY".to_string();
-            Object::Error(msg)
+            Object::error(ErrorCode::ArgumentType, msg)
         }
     }
 }
@@ -201,95 +826,475 @@ fn builtin_first(args: Vec<Object>) -> Object {
 fn builtin_last(args: Vec<Object>) -> Object {
     if args.len() != 1 {
         let msg = format!("wrong number of arguments. got={}, want=1", args.len());
-        return Object::Error(msg);
+        return Object::error(ErrorCode::ArgumentCount, msg);
     }
     match &args[0] {
-        Object::Array(elements) => {
-            if elements.is_empty() {
-                return Object::Null;
-            }
-
-            elements[elements.len() - 1].clone()
-        }
+        Object::Array(elements) => match elements.borrow().last() {
+            Some(element) => element.clone(),
+            None => Object::Null,
+        },
         _ => {
             let msg = "argument to 'last' must be ARRAY".to_string();
-            Object::Error(msg)
+            Object::error(ErrorCode::ArgumentType, msg)
         }
     }
 }
 
-fn builtin_rest(args: Vec<Object>) -> Object {
+fn builtin_rest(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
     if args.len() != 1 {
         let msg = format!("wrong number of arguments. got={}, want=1", args.len());
-        return Object::Error(msg);
+        return Err(RuntimeError::new(ErrorCode::ArgumentCount, msg));
     }
     match &args[0] {
         Object::Array(elements) => {
+            let elements = elements.borrow();
             if elements.is_empty() {
-                return Object::Null;
+                return Ok(Object::Null);
             }
 
             let (_head, tail) = elements.split_at(1);
-            Object::Array(tail.to_vec())
+            let tail = Object::array(tail.to_vec());
+            ctx.allocate(tail.heap_size())?;
+            Ok(tail)
         }
         _ => {
             let msg = "argument to 'rest' must be ARRAY".to_string();
-            Object::Error(msg)
+            Err(RuntimeError::new(ErrorCode::ArgumentType, msg))
         }
     }
 }
 
-fn builtin_push(args: Vec<Object>) -> Object {
+fn builtin_push(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
     if args.len() != 2 {
         let msg = format!("wrong number of arguments. got={}, want=2", args.len());
-        return Object::Error(msg);
+        return Err(RuntimeError::new(ErrorCode::ArgumentCount, msg));
     }
     match &args[0] {
+        // in place, returning the array so `xs = push(xs, x)` still works
         Object::Array(elements) => {
-            if elements.is_empty() {
-                return Object::Null;
+            // a pushed string is copied, while arrays and hashes are shared
+            let copied = match &args[1] {
+                Object::String(s) => s.len(),
+                _ => 0,
+            };
+            ctx.allocate(mem::size_of::<Object>() + copied)?;
+            elements.borrow_mut().push(args[1].clone());
+            Ok(args[0].clone())
+        }
+        _ => {
+            let msg = "argument to 'push' must be ARRAY".to_string();
+            Err(RuntimeError::new(ErrorCode::ArgumentType, msg))
+        }
+    }
+}
+
+fn builtin_pop(args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        let msg = format!("wrong number of arguments. got={}, want=1", args.len());
+        return Object::error(ErrorCode::ArgumentCount, msg);
+    }
+    match &args[0] {
+        Object::Array(elements) => elements.borrow_mut().pop().unwrap_or(Object::Null),
+        _ => {
+            let msg = "argument to 'pop' must be ARRAY".to_string();
+            Object::error(ErrorCode::ArgumentType, msg)
+        }
+    }
+}
+
+// `set(xs, i, x)` is `xs[i] = x`, but returns the collection.
+fn builtin_set(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    if args.len() != 3 {
+        let msg = format!("wrong number of arguments. got={}, want=3", args.len());
+        return Err(RuntimeError::new(ErrorCode::ArgumentCount, msg));
+    }
+    let mut args = args.into_iter();
+    let (collection, index, value) = match (args.next(), args.next(), args.next()) {
+        (Some(collection), Some(index), Some(value)) => (collection, index, value),
+        _ => return Ok(Object::Null),
+    };
+    match collection {
+        Object::Array(_) | Object::Hash(_) => {
+            let overflow = IntegerOverflow::default();
+            let (collection, _, allocated) =
+                evaluator::assign_index(collection, &[index], "=", value, overflow)?;
+            ctx.allocate(allocated)?;
+            Ok(collection)
+        }
+        other => {
+            let msg = format!(
+                "argument to 'set' must be ARRAY or HASH, got {}",
+                other.obj_type()
+            );
+            Err(RuntimeError::new(ErrorCode::ArgumentType, msg))
+        }
+    }
+}
+
+// Removes an element or pair and returns its value.
+fn builtin_delete(args: Vec<Object>) -> Object {
+    if args.len() != 2 {
+        let msg = format!("wrong number of arguments. got={}, want=2", args.len());
+        return Object::error(ErrorCode::ArgumentCount, msg);
+    }
+    match (&args[0], &args[1]) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            let mut elements = elements.borrow_mut();
+            if *i < 0 || *i as usize >= elements.len() {
+                let msg = format!("index out of range: {} (length {})", i, elements.len());
+                return Object::error(ErrorCode::IndexOutOfRange, msg);
+            }
+            elements.remove(*i as usize)
+        }
+        (Object::Array(_), index) => {
+            let msg = format!("array index must be INTEGER, got {}", index.obj_type());
+            Object::error(ErrorCode::TypeMismatch, msg)
+        }
+        (Object::Hash(map), key) => match create_hash_key(key.clone()) {
+            Some(key) => match map.borrow_mut().remove(&key) {
+                Some(pair) => pair.value,
+                None => Object::Null,
+            },
+            None => {
+                let msg = format!("unusable as hash key: {}", key.obj_type());
+                Object::error(ErrorCode::UnhashableKey, msg)
             }
+        },
+        (other, _) => {
+            let msg = format!(
+                "argument to 'delete' must be ARRAY or HASH, got {}",
+                other.obj_type()
+            );
+            Object::error(ErrorCode::ArgumentType, msg)
+        }
+    }
+}
+
+// A new array or hash holding the same elements. Other values are returned as
+// they are, since they can't be changed in place.
+fn builtin_copy(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    if args.len() != 1 {
+        let msg = format!("wrong number of arguments. got={}, want=1", args.len());
+        return Err(RuntimeError::new(ErrorCode::ArgumentCount, msg));
+    }
+    let copy = match &args[0] {
+        Object::Array(elements) => Object::array(elements.borrow().clone()),
+        Object::Hash(map) => Object::hash(map.borrow().clone()),
+        other => other.clone(),
+    };
+    ctx.allocate(copy.heap_size())?;
+    Ok(copy)
+}
+
+fn builtin_deep_copy(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    if args.len() != 1 {
+        let msg = format!("wrong number of arguments. got={}, want=1", args.len());
+        return Err(RuntimeError::new(ErrorCode::ArgumentCount, msg));
+    }
+    let copy = args[0].deep_copy();
+    ctx.allocate(copy.deep_heap_size())?;
+    Ok(copy)
+}
+
+// Checks the arguments of a builtin taking an array, `want - 2` other values
+// and then a function. The elements are copied out, so the function can
+// change the array while it's being walked.
+fn callback_args(
+    name: &str,
+    args: &[Object],
+    want: usize,
+) -> Result<(Vec<Object>, Object), RuntimeError> {
+    if args.len() != want {
+        let msg = format!(
+            "wrong number of arguments. got={}, want={}",
+            args.len(),
+            want
+        );
+        return Err(RuntimeError::new(ErrorCode::ArgumentCount, msg));
+    }
+    let elements = match &args[0] {
+        Object::Array(elements) => elements.borrow().clone(),
+        other => {
+            let msg = format!(
+                "argument to '{}' must be ARRAY, got {}",
+                name,
+                other.obj_type()
+            );
+            return Err(RuntimeError::new(ErrorCode::ArgumentType, msg));
+        }
+    };
+    let function = &args[want - 1];
+    match function.obj_type() {
+        ObjectType::Function | ObjectType::BuiltIn => Ok((elements, function.clone())),
+        other => {
+            let msg = format!("argument to '{}' must be FUNCTION, got {}", name, other);
+            Err(RuntimeError::new(ErrorCode::ArgumentType, msg))
+        }
+    }
+}
 
-            let mut push = vec![args[1].clone()];
-            let mut new_elements = elements.clone();
-            new_elements.append(&mut push);
-            Object::Array(new_elements)
+fn builtin_map(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    let (elements, function) = callback_args("map", &args, 2)?;
+    let mut mapped = Vec::with_capacity(elements.len());
+    for element in elements {
+        mapped.push(ctx.apply(&function, vec![element])?);
+    }
+    let mapped = Object::array(mapped);
+    ctx.allocate(mapped.heap_size())?;
+    Ok(mapped)
+}
+
+fn builtin_filter(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    let (elements, function) = callback_args("filter", &args, 2)?;
+    let mut kept = vec![];
+    for element in elements {
+        if evaluator::is_truthy(ctx.apply(&function, vec![element.clone()])?) {
+            kept.push(element);
         }
+    }
+    let kept = Object::array(kept);
+    ctx.allocate(kept.heap_size())?;
+    Ok(kept)
+}
+
+// `reduce(xs, initial, f)` folds with `f(accumulator, element)`.
+fn builtin_reduce(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    let (elements, function) = callback_args("reduce", &args, 3)?;
+    let mut accumulator = args[1].clone();
+    for element in elements {
+        accumulator = ctx.apply(&function, vec![accumulator, element])?;
+    }
+    Ok(accumulator)
+}
+
+// A new array, stably sorted by the key `f` gives each element. Keys are
+// computed once each.
+fn builtin_sort_by(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    let (elements, function) = callback_args("sort_by", &args, 2)?;
+    let mut keyed = Vec::with_capacity(elements.len());
+    for element in elements {
+        keyed.push((ctx.apply(&function, vec![element.clone()])?, element));
+    }
+    // sort_by can't stop early, so the first failed comparison is kept
+    let mut error = None;
+    keyed.sort_by(|(a, _), (b, _)| match compare_keys(a, b) {
+        Ok(ordering) => ordering,
+        Err(err) => {
+            error.get_or_insert(err);
+            Ordering::Equal
+        }
+    });
+    if let Some(err) = error {
+        return Err(err);
+    }
+    let sorted = Object::array(keyed.into_iter().map(|(_, e)| e).collect());
+    ctx.allocate(sorted.heap_size())?;
+    Ok(sorted)
+}
+
+// Numbers compare by value and strings by code point.
+fn compare_keys(a: &Object, b: &Object) -> Result<Ordering, RuntimeError> {
+    if let (Object::String(a), Object::String(b)) = (a, b) {
+        return Ok(a.cmp(b));
+    }
+    let less = |x: &Object, y: &Object| {
+        let overflow = IntegerOverflow::default();
+        evaluator::eval_infix_expression("<", x.clone(), y.clone(), overflow)
+    };
+    match (less(a, b), less(b, a)) {
+        (Object::Boolean(true), _) => Ok(Ordering::Less),
+        (_, Object::Boolean(true)) => Ok(Ordering::Greater),
+        (Object::Boolean(false), Object::Boolean(false)) => Ok(Ordering::Equal),
         _ => {
-            let msg = "argument to 'rest' must be ARRAY".to_string();
-            Object::Error(msg)
+            let msg = format!("cannot compare {} and {}", a.obj_type(), b.obj_type());
+            Err(RuntimeError::new(ErrorCode::TypeMismatch, msg))
         }
     }
 }
 
-fn builtin_puts(args: Vec<Object>) -> Object {
-    let mut output = vec![];
-    for arg in args {
-        output.push(arg.inspect());
+fn builtin_any(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    let (elements, function) = callback_args("any", &args, 2)?;
+    for element in elements {
+        if evaluator::is_truthy(ctx.apply(&function, vec![element])?) {
+            return Ok(Object::Boolean(true));
+        }
+    }
+    Ok(Object::Boolean(false))
+}
+
+fn builtin_all(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    let (elements, function) = callback_args("all", &args, 2)?;
+    for element in elements {
+        if !evaluator::is_truthy(ctx.apply(&function, vec![element])?) {
+            return Ok(Object::Boolean(false));
+        }
+    }
+    Ok(Object::Boolean(true))
+}
+
+// The first element `f` accepts, or null.
+fn builtin_find(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    let (elements, function) = callback_args("find", &args, 2)?;
+    for element in elements {
+        if evaluator::is_truthy(ctx.apply(&function, vec![element.clone()])?) {
+            return Ok(element);
+        }
+    }
+    Ok(Object::Null)
+}
+
+fn builtin_each(args: Vec<Object>, ctx: &mut dyn CallContext) -> Result<Object, RuntimeError> {
+    let (elements, function) = callback_args("each", &args, 2)?;
+    for element in elements {
+        ctx.apply(&function, vec![element])?;
     }
-    Object::String(output.join(" "))
+    Ok(Object::Null)
+}
+
+// The arguments as `puts` and friends write them, separated by spaces.
+fn output_text(args: &[Object]) -> String {
+    let output: Vec<String> = args.iter().map(Object::inspect).collect();
+    output.join(" ")
+}
+
+// Writes the arguments and then a newline.
+fn builtin_puts(args: Vec<Object>, ctx: &mut dyn CallContext) -> Object {
+    ctx.output().write(&format!("{}\n", output_text(&args)));
+    Object::Null
 }
 
+// Like `puts`, without the newline.
+fn builtin_print(args: Vec<Object>, ctx: &mut dyn CallContext) -> Object {
+    ctx.output().write(&output_text(&args));
+    Object::Null
+}
+
+// Like `puts`, but to the error sink.
+fn builtin_eprint(args: Vec<Object>, ctx: &mut dyn CallContext) -> Object {
+    ctx.error_output()
+        .write(&format!("{}\n", output_text(&args)));
+    Object::Null
+}
+
+// Frees the values only cycles keep alive and returns how many there were.
+fn builtin_gc(args: Vec<Object>) -> Object {
+    if !args.is_empty() {
+        let msg = format!("wrong number of arguments. got={}, want=0", args.len());
+        return Object::error(ErrorCode::ArgumentCount, msg);
+    }
+    Object::Integer(gc::collect_garbage() as i64)
+}
+
+// Truncates floats toward zero and parses strings.
+fn builtin_int(args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        let msg = format!("wrong number of arguments. got={}, want=1", args.len());
+        return Object::error(ErrorCode::ArgumentCount, msg);
+    }
+    match &args[0] {
+        Object::Integer(_) | Object::BigInt(_) => args[0].clone(),
+        // i64::MAX as f64 rounds up to 2^63, which is out of range
+        Object::Float(f) if f.is_finite() && *f >= i64::MIN as f64 && *f < i64::MAX as f64 => {
+            Object::Integer(f.trunc() as i64)
+        }
+        Object::Float(f) => {
+            let msg = format!("cannot convert {:?} to INTEGER", f);
+            Object::error(ErrorCode::ArgumentType, msg)
+        }
+        Object::String(s) => match s.trim().parse::<BigInt>() {
+            Ok(i) => Object::from_bigint(i),
+            Err(_) => {
+                let msg = format!("could not parse {:?} as INTEGER", s);
+                Object::error(ErrorCode::ArgumentType, msg)
+            }
+        },
+        other => {
+            let msg = format!("argument to 'int' not supported, got {}", other.obj_type());
+            Object::error(ErrorCode::ArgumentType, msg)
+        }
+    }
+}
+
+fn builtin_float(args: Vec<Object>) -> Object {
+    if args.len() != 1 {
+        let msg = format!("wrong number of arguments. got={}, want=1", args.len());
+        return Object::error(ErrorCode::ArgumentCount, msg);
+    }
+    match &args[0] {
+        Object::Integer(i) => Object::Float(*i as f64),
+        Object::BigInt(i) => Object::Float(i.to_f64()),
+        Object::Float(f) => Object::Float(*f),
+        Object::String(s) => match s.trim().parse::<f64>() {
+            Ok(f) => Object::Float(f),
+            Err(_) => {
+                let msg = format!("could not parse {:?} as FLOAT", s);
+                Object::error(ErrorCode::ArgumentType, msg)
+            }
+        },
+        other => {
+            let msg = format!(
+                "argument to 'float' not supported, got {}",
+                other.obj_type()
+            );
+            Object::error(ErrorCode::ArgumentType, msg)
+        }
+    }
+}
+
+/// The key `obj` is stored under in a HASH, or `None` if it can't be a key.
+/// Arrays and hashes can be keys when everything in them can, and they don't
+/// contain themselves.
 pub fn create_hash_key(obj: Object) -> Option<HashKey> {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
+    create_hash_key_within(obj, &mut vec![])
+}
 
-    let obj_type = obj.obj_type();
+// `open` holds the collections whose keys are being made, like the list
+// `inspect_within` keeps.
+fn create_hash_key_within(obj: Object, open: &mut Vec<*const ()>) -> Option<HashKey> {
     match obj {
-        Object::Boolean(b) => {
-            let value = if b { 1 } else { 0 };
-            Some(HashKey { obj_type, value })
-        }
-        Object::Integer(i) => Some(HashKey {
-            obj_type,
-            value: i as u64,
-        }),
-        Object::String(str) => {
-            let mut s = DefaultHasher::new();
-            str.hash(&mut s);
-            let value = s.finish();
-            Some(HashKey { obj_type, value })
+        Object::Boolean(b) => Some(HashKey::Boolean(b)),
+        Object::Integer(i) => Some(HashKey::Integer(i)),
+        // never equal to an Integer, since it only holds values outside the
+        // i64 range
+        Object::BigInt(i) => Some(HashKey::BigInt(i)),
+        Object::String(s) => Some(HashKey::String(s)),
+        // a snapshot: changing the collection later doesn't change the key
+        Object::Array(elements) => {
+            if open.contains(&id(&elements)) {
+                return None;
+            }
+            open.push(id(&elements));
+            let key = elements
+                .borrow()
+                .iter()
+                .map(|element| create_hash_key_within(element.clone(), open))
+                .collect::<Option<_>>()
+                .map(HashKey::Array);
+            open.pop();
+            key
+        }
+        Object::Hash(map) => {
+            if open.contains(&id(&map)) {
+                return None;
+            }
+            open.push(id(&map));
+            let pairs = map
+                .borrow()
+                .pairs
+                .iter()
+                .map(|(key, pair)| {
+                    Some((
+                        key.clone(),
+                        create_hash_key_within(pair.value.clone(), open)?,
+                    ))
+                })
+                .collect::<Option<Vec<_>>>();
+            open.pop();
+            let mut pairs = pairs?;
+            pairs.sort();
+            Some(HashKey::Hash(pairs))
         }
+        Object::Cell(cell) => create_hash_key_within(cell.borrow().clone(), open),
         _ => None,
     }
 }
@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Where a program's output goes. `puts` and `print` write to the
+/// interpreter's output sink and `eprint` to its error sink.
+#[derive(Clone)]
+pub enum Sink {
+    Stdout,
+    Stderr,
+    /// Keeps the text, e.g. for a test to check.
+    Buffer(Rc<RefCell<String>>),
+    /// Hands each piece of text to the host as it's written.
+    Callback(Rc<dyn Fn(&str)>),
+}
+
+impl Sink {
+    /// An empty in-memory buffer.
+    pub fn buffer() -> Sink {
+        Sink::Buffer(Rc::default())
+    }
+
+    pub fn callback<F>(f: F) -> Sink
+    where
+        F: Fn(&str) + 'static,
+    {
+        Sink::Callback(Rc::new(f))
+    }
+
+    /// Everything written to a buffer so far, or `None` for other sinks.
+    pub fn contents(&self) -> Option<String> {
+        match self {
+            Sink::Buffer(buffer) => Some(buffer.borrow().clone()),
+            _ => None,
+        }
+    }
+
+    pub fn write(&self, text: &str) {
+        // like print!, but a closed stream isn't worth stopping the program for
+        match self {
+            Sink::Stdout => {
+                let mut stdout = io::stdout();
+                let _ = stdout
+                    .write_all(text.as_bytes())
+                    .and_then(|_| stdout.flush());
+            }
+            Sink::Stderr => {
+                let _ = io::stderr().write_all(text.as_bytes());
+            }
+            Sink::Buffer(buffer) => buffer.borrow_mut().push_str(text),
+            Sink::Callback(f) => f(text),
+        }
+    }
+}
+
+impl fmt::Debug for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sink::Stdout => write!(f, "Stdout"),
+            Sink::Stderr => write!(f, "Stderr"),
+            Sink::Buffer(buffer) => write!(f, "Buffer({:?})", buffer.borrow()),
+            Sink::Callback(_) => write!(f, "Callback"),
+        }
+    }
+}
+
+impl PartialEq for Sink {
+    fn eq(&self, other: &Sink) -> bool {
+        match (self, other) {
+            (Sink::Stdout, Sink::Stdout) | (Sink::Stderr, Sink::Stderr) => true,
+            (Sink::Buffer(a), Sink::Buffer(b)) => Rc::ptr_eq(a, b),
+            (Sink::Callback(a), Sink::Callback(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
@@ -1,13 +1,20 @@
 use crate::ast;
-use crate::ast::{BlockStatement, Expression, Identifier, Program, Statement};
-use crate::lexer::Lexer;
-use crate::token::Token;
-
+use crate::ast::{BlockStatement, Expression, Identifier, Program, Slot, Statement};
+use crate::bigint::BigInt;
+use crate::diagnostics::ErrorCode;
+use crate::lexer::{LexError, Lexer};
+use crate::token::{Span, Token};
+use std::fmt;
+use std::path::Path;
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Precedence {
     LOWEST,
+    ASSIGN,      // = or +=
     EQUALS,      // ==
     LESSGREATER, // > or <
+    RANGE,       // ..
     SUM,         // +
     PRODUCT,     // *
     PREFIX,      // -X or !X
@@ -17,25 +24,62 @@ pub enum Precedence {
 
 fn precedences(token: Token) -> Precedence {
     match token {
+        Token::Assign => Precedence::ASSIGN,
+        Token::PlusAssign => Precedence::ASSIGN,
+        Token::MinusAssign => Precedence::ASSIGN,
+        Token::AsteriskAssign => Precedence::ASSIGN,
+        Token::SlashAssign => Precedence::ASSIGN,
+        Token::PercentAssign => Precedence::ASSIGN,
         Token::Eq => Precedence::EQUALS,
         Token::NotEq => Precedence::EQUALS,
         Token::Lt => Precedence::LESSGREATER,
         Token::Gt => Precedence::LESSGREATER,
+        Token::DotDot => Precedence::RANGE,
         Token::Plus => Precedence::SUM,
         Token::Minus => Precedence::SUM,
         Token::Slash => Precedence::PRODUCT,
         Token::Asterisk => Precedence::PRODUCT,
+        Token::Percent => Precedence::PRODUCT,
         Token::LParen => Precedence::CALL,
         Token::LBracket => Precedence::INDEX,
         _ => Precedence::LOWEST,
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+// How the end of the input is described in error messages.
+const END_OF_INPUT: &str = "end of input";
+
+/// Parses a token stream into a `Program`.
+///
+/// Parse functions return `None` after recording an error. The statement loops
+/// then skip ahead to the next statement boundary, so a single mistake produces
+/// a single error and every syntax error in the input is reported in one pass.
+/// A program is only complete when `errors()` is empty.
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_token: Option<Token>,
     peek_token: Option<Token>,
-    errors: Vec<String>,
+    current_span: Span,
+    peek_span: Span,
+    // number of unclosed `{` before the current token; a `}` is counted at the
+    // same depth as its matching `{`
+    depth: usize,
+    // number of enclosing loops in the current function body
+    loop_depth: usize,
+    errors: Vec<ParseError>,
 }
 
 impl<'a> Parser<'a> {
@@ -44,6 +88,10 @@ impl<'a> Parser<'a> {
             lexer,
             current_token: None,
             peek_token: None,
+            current_span: Span::default(),
+            peek_span: Span::default(),
+            depth: 0,
+            loop_depth: 0,
             errors: vec![],
         };
 
@@ -54,77 +102,261 @@ impl<'a> Parser<'a> {
     }
 
     fn next_token(&mut self) {
-        self.current_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next_token();
+        if self.current_token_is(&Token::LBrace) {
+            self.depth += 1;
+        }
+        self.current_token = self.peek_token.take();
+        self.current_span = self.peek_span;
+        if self.current_token_is(&Token::RBrace) {
+            self.depth = self.depth.saturating_sub(1);
+        }
+        match self.lexer.next_token() {
+            Some((token, span)) => {
+                self.peek_token = Some(token);
+                self.peek_span = span;
+            }
+            None => {
+                self.peek_token = None;
+                self.peek_span = self.lexer.position();
+            }
+        }
     }
 
     pub fn parse_program(&mut self) -> Program {
         let mut statements = vec![];
-        let mut not_eof = true;
 
-        while not_eof {
-            match &self.current_token {
-                Some(_token) => {
-                    if let Some(statement) = self.parse_statement() {
-                        statements.push(statement);
-                    }
-                    self.next_token();
-                }
-                None => not_eof = false,
-            };
+        while self.current_token.is_some() {
+            let depth = self.depth;
+            match self.parse_statement() {
+                Some(statement) => statements.push(statement),
+                None => self.synchronize(depth),
+            }
+            self.next_token();
         }
         Program { statements }
     }
 
+    // Skips the rest of a statement that failed to parse. Stops on the `;` that
+    // ends it, on the last token before the next `let`, `return`, `throw`,
+    // `import` or closing `}` at the statement's depth, or on the `}` that
+    // closes the enclosing block.
+    fn synchronize(&mut self, depth: usize) {
+        while self.current_token.is_some() && self.depth >= depth {
+            if self.depth == depth
+                && (self.current_token_is(&Token::Semicolon)
+                    || self.peek_token_is(&Token::RBrace)
+                    || self.peek_token_is(&Token::Let)
+                    || self.peek_token_is(&Token::Return)
+                    || self.peek_token_is(&Token::Throw)
+                    || self.peek_token_is(&Token::Import))
+            {
+                return;
+            }
+            self.next_token();
+        }
+    }
+
     fn parse_statement(&mut self) -> Option<Statement> {
-        if let Some(token) = &self.current_token {
-            let statement = match token {
-                Token::Let => self.parse_let_statement(),
-                Token::Return => self.parse_return_statement(),
-                _ => self.parse_expression_statement(),
-            };
-            return statement;
+        match self.current_token.as_ref()? {
+            Token::Let => self.parse_let_statement(),
+            Token::Return => self.parse_return_statement(),
+            Token::While => self.parse_while_statement(),
+            Token::For => self.parse_for_statement(),
+            Token::Break | Token::Continue => self.parse_loop_control_statement(),
+            Token::Import => self.parse_import_statement(),
+            Token::Throw => self.parse_throw_statement(),
+            _ => self.parse_expression_statement(),
         }
-        None
     }
 
     fn parse_let_statement(&mut self) -> Option<Statement> {
-        if let Some(Token::Ident(identifier)) = self.peek_token.clone() {
+        let start = self.current_span;
+        let identifier = match &self.peek_token {
+            Some(Token::Ident(identifier)) => identifier.clone(),
+            _ => {
+                self.peek_error("IDENT");
+                return None;
+            }
+        };
+        self.next_token();
+
+        if !self.expect_peek(Token::Assign) {
+            return None;
+        }
+
+        self.next_token();
+
+        let expr = self.parse_expression(Precedence::LOWEST)?;
+
+        if self.peek_token_is(&Token::Semicolon) {
             self.next_token();
+        }
+        Some(Statement::Let(
+            identifier,
+            Slot::Unresolved,
+            expr,
+            start.to(self.current_span),
+        ))
+    }
 
-            //let _name = self.get_current_token().to_string();
+    fn parse_return_statement(&mut self) -> Option<Statement> {
+        let start = self.current_span;
+        self.next_token();
 
-            if !self.expect_peek(Token::Assign) {
-                return None;
-            }
+        let expression = self.parse_expression(Precedence::LOWEST)?;
 
+        if self.peek_token_is(&Token::Semicolon) {
             self.next_token();
+        }
 
-            let expr = self.parse_expression(Precedence::LOWEST);
+        Some(Statement::Return(expression, start.to(self.current_span)))
+    }
 
-            if self.peek_token_is(&Token::Semicolon) {
-                self.next_token();
-            }
-            return Some(Statement::Let(identifier, expr));
+    fn parse_throw_statement(&mut self) -> Option<Statement> {
+        let start = self.current_span;
+        self.next_token();
+
+        let expression = self.parse_expression(Precedence::LOWEST)?;
+
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
         }
-        None
+
+        Some(Statement::Throw(expression, start.to(self.current_span)))
     }
 
-    fn parse_return_statement(&mut self) -> Option<Statement> {
+    fn parse_while_statement(&mut self) -> Option<Statement> {
+        let start = self.current_span;
+        if !self.expect_peek(Token::LParen) {
+            return None;
+        }
+
+        self.next_token();
+        let condition = self.parse_expression(Precedence::LOWEST)?;
+        if !self.expect_peek(Token::RParen) {
+            return None;
+        }
+
+        if !self.expect_peek(Token::LBrace) {
+            return None;
+        }
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+        }
+
+        Some(Statement::While(
+            condition,
+            body?,
+            start.to(self.current_span),
+        ))
+    }
+
+    fn parse_for_statement(&mut self) -> Option<Statement> {
+        let start = self.current_span;
+        if !self.expect_peek(Token::LParen) {
+            return None;
+        }
+
+        let mut names = vec![self.expect_peek_ident()?];
+        if self.peek_token_is(&Token::Comma) {
+            self.next_token();
+            names.push(self.expect_peek_ident()?);
+        }
+        if !self.expect_peek(Token::In) {
+            return None;
+        }
+
         self.next_token();
+        let iterable = self.parse_expression(Precedence::LOWEST)?;
+        if !self.expect_peek(Token::RParen) {
+            return None;
+        }
+
+        if !self.expect_peek(Token::LBrace) {
+            return None;
+        }
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+        }
+
+        Some(Statement::For(
+            names,
+            iterable,
+            body?,
+            start.to(self.current_span),
+        ))
+    }
 
-        let expression = self.parse_expression(Precedence::LOWEST);
+    fn parse_loop_control_statement(&mut self) -> Option<Statement> {
+        let span = self.current_span;
+        let is_break = self.current_token_is(&Token::Break);
+        if self.loop_depth == 0 {
+            let msg = format!("'{}' outside of a loop", self.get_current_token());
+            self.error(ErrorCode::MisplacedLoopControl, msg, span);
+            return None;
+        }
 
         if self.peek_token_is(&Token::Semicolon) {
             self.next_token();
         }
 
-        Some(Statement::Return(expression))
+        if is_break {
+            Some(Statement::Break(span))
+        } else {
+            Some(Statement::Continue(span))
+        }
+    }
+
+    // `import "path"` binds the module to the file's name without its
+    // extension, and `import "path" as name` to `name`.
+    fn parse_import_statement(&mut self) -> Option<Statement> {
+        let start = self.current_span;
+        if self.depth > 0 {
+            let msg = "'import' inside a block; modules are imported at the top level".to_string();
+            self.error(ErrorCode::MisplacedImport, msg, start);
+            return None;
+        }
+        let path = match &self.peek_token {
+            Some(Token::String(path)) => path.clone(),
+            _ => {
+                self.peek_error("STRING");
+                return None;
+            }
+        };
+        self.next_token();
+
+        let name = if self.peek_token_is(&Token::As) {
+            self.next_token();
+            self.expect_peek_ident()?
+        } else {
+            match module_name(&path) {
+                Some(name) => name,
+                None => {
+                    let msg = format!("cannot name the module {:?}; add `as <name>`", path);
+                    self.error(ErrorCode::BadImport, msg, self.current_span);
+                    return None;
+                }
+            }
+        };
+
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+        }
+        Some(Statement::Import(path, name, start.to(self.current_span)))
     }
 
     fn parse_expression_statement(&mut self) -> Option<Statement> {
-        let expr = self.parse_expression(Precedence::LOWEST);
-        let statement = Statement::Expression(expr);
+        let expr = self.parse_expression(Precedence::LOWEST)?;
+        let span = expr.span();
+        let statement = Statement::Expression(expr, span);
 
         if self.peek_token_is(&Token::Semicolon) {
             self.next_token();
@@ -133,236 +365,364 @@ impl<'a> Parser<'a> {
         Some(statement)
     }
 
-    fn parse_expression(&mut self, precedence: Precedence) -> Expression {
-        let prefix = self.prefix_parse();
-        //println!("Prefix:{}", prefix);
-        if prefix == Expression::None {
-            println!("prefix == Expression::None");
-            let token = &self.get_current_token();
-            self.no_prefix_parse_error(token.clone());
-            return Expression::None;
-        }
-        let mut peek_precedence = self.peek_precedence();
-        let mut peek_token_is_semi_colon = self.peek_token_is(&Token::Semicolon);
-        let mut left_expr = prefix;
-        while !peek_token_is_semi_colon && precedence < peek_precedence {
-            if left_expr == Expression::None {
-                return left_expr;
-            }
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<Expression> {
+        let mut left_expr = self.prefix_parse()?;
+        while !self.peek_token_is(&Token::Semicolon) && precedence < self.peek_precedence() {
             self.next_token();
-            left_expr = self.infix_parse(Box::new(left_expr));
-            peek_precedence = self.peek_precedence();
-            peek_token_is_semi_colon = self.peek_token_is(&Token::Semicolon);
+            left_expr = self.infix_parse(Box::new(left_expr))?;
         }
-        left_expr
+        Some(left_expr)
     }
 
-    fn parse_identifier(&self) -> Expression {
+    fn parse_identifier(&self) -> Option<Expression> {
         match &self.current_token {
-            Some(Token::Ident(ident)) => Expression::Ident(ident.to_string()),
-            _ => Expression::None,
+            Some(Token::Ident(ident)) => Some(Expression::Ident(
+                ident.to_string(),
+                Slot::Unresolved,
+                self.current_span,
+            )),
+            _ => None,
         }
     }
 
-    fn parse_integer_literal(&self) -> Expression {
-        match &self.current_token {
-            Some(Token::Int(int_string)) => {
-                if let Ok(int) = int_string.parse::<i64>() {
-                    Expression::IntegerLiteral(int)
-                } else {
-                    Expression::None
-                }
+    fn parse_integer_literal(&mut self) -> Option<Expression> {
+        let int_string = match &self.current_token {
+            Some(Token::Int(int_string)) => int_string.clone(),
+            _ => return None,
+        };
+        if let Ok(int) = int_string.parse::<i64>() {
+            return Some(Expression::IntegerLiteral(int, self.current_span));
+        }
+        match int_string.parse::<BigInt>() {
+            Ok(int) => Some(Expression::BigIntLiteral(int, self.current_span)),
+            Err(_) => {
+                let msg = format!("could not parse {} as integer", int_string);
+                let span = self.current_span;
+                self.error(ErrorCode::InvalidLiteral, msg, span);
+                None
             }
-            _ => Expression::None,
         }
     }
 
-    fn parse_string_literal(&self) -> Expression {
+    fn parse_float_literal(&mut self) -> Option<Expression> {
+        let float_string = match &self.current_token {
+            Some(Token::Float(float_string)) => float_string.clone(),
+            _ => return None,
+        };
+        match float_string.parse::<f64>() {
+            Ok(float) => Some(Expression::FloatLiteral(float, self.current_span)),
+            Err(_) => {
+                let msg = format!("could not parse {} as float", float_string);
+                let span = self.current_span;
+                self.error(ErrorCode::InvalidLiteral, msg, span);
+                None
+            }
+        }
+    }
+
+    fn parse_string_literal(&self) -> Option<Expression> {
         match &self.current_token {
-            Some(Token::String(s)) => Expression::StringLiteral(s.to_string()),
-            _ => Expression::None,
+            Some(Token::String(s)) => {
+                Some(Expression::StringLiteral(s.to_string(), self.current_span))
+            }
+            _ => None,
         }
     }
 
-    fn parse_prefix_expression(&mut self) -> Expression {
+    fn parse_prefix_expression(&mut self) -> Option<Expression> {
+        let start = self.current_span;
         let operator = self.get_current_token().to_string();
         self.next_token();
-        let right = self.parse_expression(Precedence::PREFIX);
-        Expression::Prefix(operator, Box::new(right))
+        let right = self.parse_expression(Precedence::PREFIX)?;
+        Some(Expression::Prefix(
+            operator,
+            Box::new(right),
+            start.to(self.current_span),
+        ))
     }
 
-    fn parse_infix_expression(&mut self, left: Box<Expression>) -> Expression {
+    fn parse_infix_expression(&mut self, left: Box<Expression>) -> Option<Expression> {
+        let start = left.span();
         let operator = self.get_current_token().to_string();
         let precedence = self.current_precedence();
         self.next_token();
-        let right = self.parse_expression(precedence);
-        Expression::Infix(left, operator, Box::new(right))
+        let right = self.parse_expression(precedence)?;
+        Some(Expression::Infix(
+            left,
+            operator,
+            Box::new(right),
+            start.to(self.current_span),
+        ))
+    }
+
+    // Assignment is right-associative, so `a = b = 1` assigns 1 to both.
+    fn parse_assign_expression(&mut self, target: Box<Expression>) -> Option<Expression> {
+        if target.assignment_target().is_none() {
+            let msg = format!("invalid assignment target: {}", target);
+            self.error(ErrorCode::InvalidAssignment, msg, target.span());
+            return None;
+        }
+        let start = target.span();
+        let operator = self.get_current_token().to_string();
+        self.next_token();
+        let value = self.parse_expression(Precedence::LOWEST)?;
+        Some(Expression::Assign(
+            target,
+            operator,
+            Box::new(value),
+            start.to(self.current_span),
+        ))
     }
 
-    fn parse_index_expression(&mut self, left: Box<Expression>) -> Expression {
+    fn parse_index_expression(&mut self, left: Box<Expression>) -> Option<Expression> {
+        let start = left.span();
         self.next_token();
-        let index = self.parse_expression(Precedence::LOWEST);
+        let index = self.parse_expression(Precedence::LOWEST)?;
         if !self.expect_peek(Token::RBracket) {
-            return Expression::None;
+            return None;
         }
-        Expression::IndexExpression(left, Box::new(index))
+        Some(Expression::IndexExpression(
+            left,
+            Box::new(index),
+            start.to(self.current_span),
+        ))
     }
 
-    fn parse_call_expression(&mut self, function: Box<Expression>) -> Expression {
-        let args = self.parse_expression_list(Token::RParen);
-        Expression::CallExpression(function, args)
+    fn parse_call_expression(&mut self, function: Box<Expression>) -> Option<Expression> {
+        let start = function.span();
+        let args = self.parse_expression_list(Token::RParen)?;
+        Some(Expression::CallExpression(
+            function,
+            args,
+            start.to(self.current_span),
+        ))
     }
 
-    fn parse_boolean(&mut self) -> Expression {
-        Expression::Boolean(self.current_token_is(&Token::True))
+    fn parse_boolean(&mut self) -> Option<Expression> {
+        Some(Expression::Boolean(
+            self.current_token_is(&Token::True),
+            self.current_span,
+        ))
     }
 
-    fn parse_grouped_expression(&mut self) -> Expression {
+    fn parse_grouped_expression(&mut self) -> Option<Expression> {
         self.next_token();
-        let exp = self.parse_expression(Precedence::LOWEST);
+        let exp = self.parse_expression(Precedence::LOWEST)?;
         if !self.expect_peek(Token::RParen) {
-            return Expression::None;
+            return None;
         }
-        exp
+        Some(exp)
     }
 
-    fn parse_if_expression(&mut self) -> Expression {
+    fn parse_if_expression(&mut self) -> Option<Expression> {
+        let start = self.current_span;
         if !self.expect_peek(Token::LParen) {
-            return Expression::None;
+            return None;
         }
 
         self.next_token();
-        let condition = self.parse_expression(Precedence::LOWEST);
+        let condition = self.parse_expression(Precedence::LOWEST)?;
         if !self.expect_peek(Token::RParen) {
-            return Expression::None;
+            return None;
         }
 
         if !self.expect_peek(Token::LBrace) {
-            return Expression::None;
+            return None;
         }
-        let consequence = self.parse_block_statement();
+        let consequence = self.parse_block_statement()?;
         let alt = if self.peek_token_is(&Token::Else) {
             self.next_token();
 
             if !self.expect_peek(Token::LBrace) {
-                return Expression::None;
+                return None;
             }
 
-            Some(self.parse_block_statement())
+            Some(self.parse_block_statement()?)
+        } else {
+            None
+        };
+        Some(Expression::IfExpression(
+            Box::new(condition),
+            consequence,
+            alt,
+            start.to(self.current_span),
+        ))
+    }
+
+    // `try { } catch (e) { } finally { }`, where either the `catch` or the
+    // `finally` part may be left out.
+    fn parse_try_expression(&mut self) -> Option<Expression> {
+        let start = self.current_span;
+        if !self.expect_peek(Token::LBrace) {
+            return None;
+        }
+        let body = self.parse_block_statement()?;
+
+        let catch = if self.peek_token_is(&Token::Catch) {
+            self.next_token();
+            if !self.expect_peek(Token::LParen) {
+                return None;
+            }
+            let name = self.expect_peek_ident()?;
+            if !self.expect_peek(Token::RParen) || !self.expect_peek(Token::LBrace) {
+                return None;
+            }
+            Some((name, self.parse_block_statement()?))
         } else {
             None
         };
-        Expression::IfExpression(Box::new(condition), consequence, alt)
+        let finally = if self.peek_token_is(&Token::Finally) {
+            self.next_token();
+            if !self.expect_peek(Token::LBrace) {
+                return None;
+            }
+            Some(self.parse_block_statement()?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            self.peek_error("catch or finally");
+            return None;
+        }
+
+        Some(Expression::Try(
+            body,
+            catch,
+            finally,
+            start.to(self.current_span),
+        ))
     }
 
-    fn parse_function_literal(&mut self) -> Expression {
+    fn parse_function_literal(&mut self) -> Option<Expression> {
+        let start = self.current_span;
         if !self.expect_peek(Token::LParen) {
-            return Expression::None;
+            return None;
         }
 
-        let parms = self.parse_function_parameters();
+        let parms = self.parse_function_parameters()?;
 
         if !self.expect_peek(Token::LBrace) {
-            return Expression::None;
+            return None;
         }
 
+        // a function body starts outside of any loop
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
         let body = self.parse_block_statement();
+        self.loop_depth = loop_depth;
+        let body = body?;
 
-        Expression::FunctionLiteral(parms, body)
+        Some(Expression::FunctionLiteral(
+            parms,
+            body,
+            start.to(self.current_span),
+        ))
     }
 
-    fn parse_array_literal(&mut self) -> Expression {
-        let elements = self.parse_expression_list(Token::RBracket);
-        Expression::ArrayLiteral(elements)
+    fn parse_array_literal(&mut self) -> Option<Expression> {
+        let start = self.current_span;
+        let elements = self.parse_expression_list(Token::RBracket)?;
+        Some(Expression::ArrayLiteral(
+            elements,
+            start.to(self.current_span),
+        ))
     }
 
-    fn parse_hash_literal(&mut self) -> Expression {
+    fn parse_hash_literal(&mut self) -> Option<Expression> {
+        let start = self.current_span;
         let mut pairs = vec![];
 
         while !self.peek_token_is(&Token::RBrace) {
             self.next_token();
-            let key = self.parse_expression(Precedence::LOWEST);
+            let key = self.parse_expression(Precedence::LOWEST)?;
             if !self.expect_peek(Token::Colon) {
-                return Expression::None;
+                return None;
             }
 
             self.next_token();
-            let value = self.parse_expression(Precedence::LOWEST);
+            let value = self.parse_expression(Precedence::LOWEST)?;
             ast::hash_put(&mut pairs, key, value);
             if !self.peek_token_is(&Token::RBrace) && !self.expect_peek(Token::Comma) {
-                return Expression::None;
+                return None;
             }
         }
 
         if !self.expect_peek(Token::RBrace) {
-            return Expression::None;
+            return None;
         }
-        Expression::HashLiteral(pairs)
+        Some(Expression::HashLiteral(pairs, start.to(self.current_span)))
     }
 
-    fn parse_expression_list(&mut self, token: Token) -> Vec<Expression> {
+    fn parse_expression_list(&mut self, token: Token) -> Option<Vec<Expression>> {
         let mut args = vec![];
         if self.peek_token_is(&token) {
             self.next_token();
-            return args;
+            return Some(args);
         }
 
         self.next_token();
-        args.push(self.parse_expression(Precedence::LOWEST));
+        args.push(self.parse_expression(Precedence::LOWEST)?);
 
         while self.peek_token_is(&Token::Comma) {
             self.next_token();
             self.next_token();
-            args.push(self.parse_expression(Precedence::LOWEST));
+            args.push(self.parse_expression(Precedence::LOWEST)?);
         }
 
         if !self.expect_peek(token) {
-            return vec![];
+            return None;
         }
-        args
+        Some(args)
     }
 
-    fn parse_function_parameters(&mut self) -> Vec<Identifier> {
+    fn parse_function_parameters(&mut self) -> Option<Vec<Identifier>> {
         let mut identifiers = vec![];
 
         if self.peek_token_is(&Token::RParen) {
             self.next_token();
-            return identifiers;
+            return Some(identifiers);
         }
 
-        self.next_token();
-
-        identifiers.push(self.get_current_token().to_string());
+        identifiers.push(self.expect_peek_ident()?);
 
         while self.peek_token_is(&Token::Comma) {
             self.next_token();
-            self.next_token();
-            identifiers.push(self.get_current_token().to_string());
+            identifiers.push(self.expect_peek_ident()?);
         }
 
         if !self.expect_peek(Token::RParen) {
-            return vec![];
+            return None;
         }
-        identifiers
+        Some(identifiers)
     }
 
-    fn parse_block_statement(&mut self) -> BlockStatement {
+    fn parse_block_statement(&mut self) -> Option<BlockStatement> {
         let mut statements = vec![];
+        let block_depth = self.depth;
         self.next_token();
 
-        let mut eof = false;
-
-        while !self.current_token_is(&Token::RBrace) && !eof {
-            if let Some(statement) = self.parse_statement() {
-                statements.push(statement);
+        while !self.current_token_is(&Token::RBrace) {
+            if self.current_token.is_none() {
+                self.error(
+                    ErrorCode::UnexpectedToken,
+                    format!("expected }}, got {} instead", END_OF_INPUT),
+                    self.current_span,
+                );
+                return None;
             }
-            self.next_token();
-            if self.current_token == None {
-                eof = true;
+            let depth = self.depth;
+            match self.parse_statement() {
+                Some(statement) => statements.push(statement),
+                None => {
+                    self.synchronize(depth);
+                    if self.current_token_is(&Token::RBrace) && self.depth == block_depth {
+                        break;
+                    }
+                }
             }
+            self.next_token();
         }
 
-        BlockStatement { statements }
+        Some(BlockStatement { statements })
     }
 
     //convenience method to retrieve token
@@ -382,17 +742,11 @@ impl<'a> Parser<'a> {
     }
 
     fn current_token_is(&self, t: &Token) -> bool {
-        match &self.current_token {
-            Some(token) if token == t => true,
-            _ => false,
-        }
+        matches!(&self.current_token, Some(token) if token == t)
     }
 
     fn peek_token_is(&self, t: &Token) -> bool {
-        match &self.peek_token {
-            Some(token) if token == t => true,
-            _ => false,
-        }
+        matches!(&self.peek_token, Some(token) if token == t)
     }
 
     fn expect_peek(&mut self, t: Token) -> bool {
@@ -400,59 +754,111 @@ impl<'a> Parser<'a> {
             self.next_token();
             return true;
         }
-        self.peek_error(&t);
+        self.peek_error(&t.to_string());
         false
     }
 
-    fn peek_error(&mut self, t: &Token) {
-        let token = match &self.peek_token {
-            Some(val) => val,
-            None => &Token::Illegal,
+    fn expect_peek_ident(&mut self) -> Option<Identifier> {
+        match &self.peek_token {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.next_token();
+                Some(ident)
+            }
+            _ => {
+                self.peek_error("IDENT");
+                None
+            }
+        }
+    }
+
+    fn peek_error(&mut self, expected: &str) {
+        let got = match &self.peek_token {
+            // the lexer has already reported it
+            Some(Token::Illegal) => return,
+            Some(val) => val.to_string(),
+            None => END_OF_INPUT.to_string(),
         };
-        let msg = format!("expected next token to be {}, got {} instead", t, token);
-        self.errors.push(msg);
+        let msg = format!(
+            "expected next token to be {}, got {} instead",
+            expected, got
+        );
+        let span = self.peek_span;
+        self.error(ErrorCode::UnexpectedToken, msg, span);
+    }
+
+    fn error(&mut self, code: ErrorCode, message: String, span: Span) {
+        self.errors.push(ParseError {
+            code,
+            message,
+            span,
+        });
     }
 
-    pub fn errors(&self) -> &Vec<String> {
+    pub fn errors(&self) -> &Vec<ParseError> {
         &self.errors
     }
 
-    pub fn prefix_parse(&mut self) -> Expression {
+    /// Errors found while splitting the input into tokens.
+    pub fn lexer_errors(&self) -> &[LexError] {
+        self.lexer.errors()
+    }
+
+    pub fn prefix_parse(&mut self) -> Option<Expression> {
         match &self.current_token {
             Some(Token::Ident(_)) => self.parse_identifier(),
             Some(Token::Int(_)) => self.parse_integer_literal(),
+            Some(Token::Float(_)) => self.parse_float_literal(),
             Some(Token::Bang) => self.parse_prefix_expression(),
             Some(Token::Minus) => self.parse_prefix_expression(),
             Some(Token::True) => self.parse_boolean(),
             Some(Token::False) => self.parse_boolean(),
             Some(Token::LParen) => self.parse_grouped_expression(),
             Some(Token::If) => self.parse_if_expression(),
+            Some(Token::Try) => self.parse_try_expression(),
             Some(Token::Function) => self.parse_function_literal(),
             Some(Token::String(_)) => self.parse_string_literal(),
             Some(Token::LBracket) => self.parse_array_literal(),
             Some(Token::LBrace) => self.parse_hash_literal(),
-            _ => Expression::None,
+            _ => {
+                self.no_prefix_parse_error();
+                None
+            }
         }
     }
 
-    fn no_prefix_parse_error(&mut self, token: Token) {
-        let msg = format!("no prefix parse function for {} found", token);
-        self.errors.push(msg);
+    fn no_prefix_parse_error(&mut self) {
+        let msg = match &self.current_token {
+            // the lexer has already reported it
+            Some(Token::Illegal) => return,
+            Some(token) => format!("no prefix parse function for {} found", token),
+            None => format!("expected an expression, got {} instead", END_OF_INPUT),
+        };
+        let span = self.current_span;
+        self.error(ErrorCode::ExpectedExpression, msg, span);
     }
 
-    pub fn infix_parse(&mut self, left_expression: Box<Expression>) -> Expression {
+    pub fn infix_parse(&mut self, left_expression: Box<Expression>) -> Option<Expression> {
         match &self.current_token {
             Some(Token::Plus) => self.parse_infix_expression(left_expression),
             Some(Token::Minus) => self.parse_infix_expression(left_expression),
             Some(Token::Slash) => self.parse_infix_expression(left_expression),
             Some(Token::Asterisk) => self.parse_infix_expression(left_expression),
+            Some(Token::Percent) => self.parse_infix_expression(left_expression),
             Some(Token::Eq) => self.parse_infix_expression(left_expression),
             Some(Token::NotEq) => self.parse_infix_expression(left_expression),
             Some(Token::Lt) => self.parse_infix_expression(left_expression),
             Some(Token::Gt) => self.parse_infix_expression(left_expression),
+            Some(Token::DotDot) => self.parse_infix_expression(left_expression),
+            Some(Token::Assign)
+            | Some(Token::PlusAssign)
+            | Some(Token::MinusAssign)
+            | Some(Token::AsteriskAssign)
+            | Some(Token::SlashAssign)
+            | Some(Token::PercentAssign) => self.parse_assign_expression(left_expression),
             Some(Token::LParen) => self.parse_call_expression(left_expression),
             Some(Token::LBracket) => self.parse_index_expression(left_expression),
-            _ => Expression::None,
+            _ => Some(*left_expression),
         }
     }
 
@@ -464,3 +870,13 @@ impl<'a> Parser<'a> {
         precedences(self.get_current_token())
     }
 }
+
+// The name a module is bound to when its import doesn't give one: the file's
+// name without its extension, if that's an identifier.
+fn module_name(path: &str) -> Option<Identifier> {
+    let stem = Path::new(path).file_stem()?.to_str()?;
+    match Token::lookup_ident(stem.to_string()) {
+        Token::Ident(name) if name.chars().all(|c| c.is_alphabetic() || c == '_') => Some(name),
+        _ => None,
+    }
+}
@@ -1,35 +1,23 @@
-use crate::environment::Environment;
-use crate::evaluator;
-use crate::lexer::Lexer;
-use crate::parser::Parser;
-use std::cell::RefCell;
+use crate::diagnostics::Style;
+use crate::{Backend, Interpreter};
 use std::error::Error;
 use std::io::{self, Write};
-use std::rc::Rc;
 
 const PROMPT: &str = ">> ";
+const SOURCE_NAME: &str = "<repl>";
 
-pub fn start() -> Result<(), Box<dyn Error>> {
-    let env = Rc::new(RefCell::new(Environment::default()));
+pub fn start(backend: Backend) -> Result<(), Box<dyn Error>> {
+    let mut interpreter = Interpreter::with_backend(backend);
+    let style = Style::detect();
     loop {
         print!("{}", PROMPT);
         io::stdout().flush()?;
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
 
-        let lexer = Lexer::new(&input);
-        let mut parser = Parser::new(lexer);
-        let program = parser.parse_program();
-        if !parser.errors().is_empty() {
-            println!("Woops! We ran into some monkey business here!");
-            println!(" parser errors: ");
-            for s in parser.errors() {
-                println!("{}", s);
-            }
-            continue;
+        let output = interpreter.interpret(SOURCE_NAME, &input, style);
+        if !output.is_empty() {
+            println!("{}", output);
         }
-
-        let evaluated = evaluator::eval(program, env.clone());
-        println!("{}", evaluated.inspect());
     }
 }
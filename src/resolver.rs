@@ -0,0 +1,301 @@
+use crate::ast::{BlockStatement, Expression, Identifier, Program, Slot, Statement};
+use crate::environment::Environment;
+use crate::object::BuiltIn;
+use std::collections::{HashMap, HashSet};
+
+/// Works out where each name in `program` is kept before the evaluator runs
+/// it in `env`, filling in the slots of its identifiers and `let`s. A name
+/// bound nowhere is left unresolved, and is an error only if the code using
+/// it runs, as in the compiler, so `try` can catch it.
+///
+/// Code sees a local once its `let` has run, but a function sees every local
+/// of the scopes around it, since it may run after they're bound: it can
+/// call itself by the name it's bound to, or a function bound after it. The
+/// program's top-level names are seen throughout, so top-level functions can
+/// call each other whatever order they're defined in. The compiler's symbol
+/// table follows the same rule.
+pub(crate) fn resolve(program: &mut Program, env: &mut Environment) {
+    let mut resolver = Resolver {
+        env,
+        globals: HashSet::new(),
+        scopes: vec![],
+    };
+    for statement in &program.statements {
+        if let Statement::Let(name, _, _, _) | Statement::Import(_, name, _) = statement {
+            resolver.env.global_slot(name);
+            resolver.globals.insert(name.clone());
+        }
+    }
+    for statement in &mut program.statements {
+        resolver.statement(statement);
+    }
+}
+
+// The locals of a function call, loop iteration or catch block, which each
+// run in an environment of their own. Every name the scope binds has a slot
+// from the start, but it's only defined once its `let` is reached.
+#[derive(Default)]
+struct Scope {
+    slots: HashMap<Identifier, usize>,
+    defined: HashSet<Identifier>,
+}
+
+impl Scope {
+    // Redefining a name reuses its slot, as `let` replaces a binding.
+    fn declare(&mut self, name: &str) -> usize {
+        let next = self.slots.len();
+        *self.slots.entry(name.to_string()).or_insert(next)
+    }
+
+    fn define(&mut self, name: &str) -> usize {
+        self.defined.insert(name.to_string());
+        self.declare(name)
+    }
+}
+
+struct Resolver<'a> {
+    // the environment the program runs in, with the names bound before it,
+    // which gives the program's top-level names their slots
+    env: &'a mut Environment,
+    // the names the program binds at the top level
+    globals: HashSet<Identifier>,
+    // innermost last
+    scopes: Vec<Scope>,
+}
+
+impl Resolver<'_> {
+    fn statement(&mut self, statement: &mut Statement) {
+        match statement {
+            Statement::Expression(expr, _)
+            | Statement::Return(expr, _)
+            | Statement::Throw(expr, _) => self.expression(expr),
+            Statement::Let(name, slot, expr, _) => {
+                self.expression(expr);
+                *slot = self.define(name);
+            }
+            Statement::While(condition, body, _) => {
+                self.expression(condition);
+                self.block(body);
+            }
+            Statement::For(names, iterable, body, _) => {
+                self.expression(iterable);
+                self.scope(names.iter(), body);
+            }
+            Statement::Import(_, name, _) => {
+                self.define(name);
+            }
+            Statement::Break(_) | Statement::Continue(_) => (),
+        }
+    }
+
+    fn expression(&mut self, expression: &mut Expression) {
+        match expression {
+            Expression::Ident(name, slot, _) => *slot = self.lookup(name),
+            Expression::IntegerLiteral(..)
+            | Expression::BigIntLiteral(..)
+            | Expression::FloatLiteral(..)
+            | Expression::StringLiteral(..)
+            | Expression::Boolean(..) => (),
+            Expression::Prefix(_, right, _) => self.expression(right),
+            Expression::Infix(left, _, right, _) | Expression::IndexExpression(left, right, _) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expression::IfExpression(condition, consequence, alternative, _) => {
+                self.expression(condition);
+                self.block(consequence);
+                if let Some(alternative) = alternative {
+                    self.block(alternative);
+                }
+            }
+            Expression::FunctionLiteral(parms, body, _) => self.scope(parms.iter(), body),
+            Expression::CallExpression(function, arguments, _) => {
+                self.expression(function);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expression::ArrayLiteral(elements, _) => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expression::HashLiteral(pairs, _) => {
+                for (key, value) in pairs {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            Expression::Assign(target, _, value, _) => {
+                self.assignment_target(target);
+                self.expression(value);
+            }
+            Expression::Try(body, catch, finally, _) => {
+                self.block(body);
+                if let Some((name, handler)) = catch {
+                    self.scope(std::iter::once(&*name), handler);
+                }
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            }
+        }
+    }
+
+    // Resolves the variable an assignment updates and the indexes leading to
+    // the element it replaces. Only variables can be assigned to, so a
+    // builtin's name is left unresolved, like a name bound nowhere, and fails
+    // when it's assigned to, as does a target that isn't a variable or element.
+    fn assignment_target(&mut self, target: &mut Expression) {
+        match target {
+            Expression::Ident(name, slot, _) => {
+                *slot = match self.lookup(name) {
+                    Slot::Builtin => Slot::Unresolved,
+                    found => found,
+                };
+            }
+            Expression::IndexExpression(left, index, _) => {
+                self.assignment_target(left);
+                self.expression(index);
+            }
+            _ => (),
+        }
+    }
+
+    fn block(&mut self, block: &mut BlockStatement) {
+        for statement in &mut block.statements {
+            self.statement(statement);
+        }
+    }
+
+    // Resolves `body` in a new scope whose first slots hold `names`, followed
+    // by the names it binds.
+    fn scope<'n>(
+        &mut self,
+        names: impl Iterator<Item = &'n Identifier>,
+        body: &mut BlockStatement,
+    ) {
+        let mut scope = Scope::default();
+        for name in names {
+            scope.define(name);
+        }
+        let mut bound = vec![];
+        block_bindings(body, &mut bound);
+        for name in &bound {
+            scope.declare(name);
+        }
+        self.scopes.push(scope);
+        self.block(body);
+        self.scopes.pop();
+    }
+
+    fn define(&mut self, name: &str) -> Slot {
+        match self.scopes.last_mut() {
+            Some(scope) => Slot::Local {
+                depth: 0,
+                index: scope.define(name),
+            },
+            None => {
+                self.globals.insert(name.to_string());
+                Slot::Global {
+                    index: self.env.global_slot(name),
+                }
+            }
+        }
+    }
+
+    // Code running in the innermost scope only sees the names it has defined
+    // so far, while the scopes around it may define theirs before it runs.
+    fn lookup(&mut self, name: &str) -> Slot {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if depth == 0 && !scope.defined.contains(name) {
+                continue;
+            }
+            if let Some(&index) = scope.slots.get(name) {
+                return Slot::Local { depth, index };
+            }
+        }
+        if self.is_global_variable(name) {
+            Slot::Global {
+                index: self.env.global_slot(name),
+            }
+        } else if self.env.get_native(name).is_some() || BuiltIn::lookup_builtin(name).is_some() {
+            Slot::Builtin
+        } else {
+            Slot::Unresolved
+        }
+    }
+
+    fn is_global_variable(&self, name: &str) -> bool {
+        self.globals.contains(name) || self.env.is_global(name)
+    }
+}
+
+// The names `block` binds in the scope it runs in, in the order they're bound:
+// not those of the functions, loops and catch blocks in it, which have scopes
+// of their own.
+pub(crate) fn block_bindings(block: &BlockStatement, names: &mut Vec<Identifier>) {
+    for statement in &block.statements {
+        match statement {
+            Statement::Let(name, _, expr, _) => {
+                expression_bindings(expr, names);
+                names.push(name.clone());
+            }
+            Statement::Import(_, name, _) => names.push(name.clone()),
+            Statement::Expression(expr, _)
+            | Statement::Return(expr, _)
+            | Statement::Throw(expr, _)
+            | Statement::For(_, expr, _, _) => expression_bindings(expr, names),
+            Statement::While(condition, body, _) => {
+                expression_bindings(condition, names);
+                block_bindings(body, names);
+            }
+            Statement::Break(_) | Statement::Continue(_) => (),
+        }
+    }
+}
+
+fn expression_bindings(expression: &Expression, names: &mut Vec<Identifier>) {
+    match expression {
+        Expression::Prefix(_, right, _) => expression_bindings(right, names),
+        Expression::Infix(left, _, right, _)
+        | Expression::IndexExpression(left, right, _)
+        | Expression::Assign(left, _, right, _) => {
+            expression_bindings(left, names);
+            expression_bindings(right, names);
+        }
+        Expression::IfExpression(condition, consequence, alternative, _) => {
+            expression_bindings(condition, names);
+            block_bindings(consequence, names);
+            if let Some(alternative) = alternative {
+                block_bindings(alternative, names);
+            }
+        }
+        Expression::CallExpression(function, arguments, _) => {
+            expression_bindings(function, names);
+            arguments
+                .iter()
+                .for_each(|argument| expression_bindings(argument, names));
+        }
+        Expression::ArrayLiteral(elements, _) => elements
+            .iter()
+            .for_each(|element| expression_bindings(element, names)),
+        Expression::HashLiteral(pairs, _) => pairs.iter().for_each(|(key, value)| {
+            expression_bindings(key, names);
+            expression_bindings(value, names);
+        }),
+        Expression::Try(body, _, finally, _) => {
+            block_bindings(body, names);
+            if let Some(finally) = finally {
+                block_bindings(finally, names);
+            }
+        }
+        Expression::Ident(..)
+        | Expression::IntegerLiteral(..)
+        | Expression::BigIntLiteral(..)
+        | Expression::FloatLiteral(..)
+        | Expression::StringLiteral(..)
+        | Expression::Boolean(..)
+        | Expression::FunctionLiteral(..) => (),
+    }
+}
@@ -0,0 +1,225 @@
+// `serde` support for runtime values, enabled by the `serde` feature.
+// Integers, floats, booleans, strings, arrays, hashes and null map onto the
+// serde data model directly, except for integers beyond 128 bits. Functions
+// and builtins have no data representation and fail to serialize.
+
+use crate::bigint::BigInt;
+use crate::object::{self, HashPair, HashTable, Object};
+use crate::value::Value;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let open = RefCell::new(vec![]);
+        Within {
+            obj: self,
+            open: &open,
+        }
+        .serialize(serializer)
+    }
+}
+
+// An object inside the collections in `open`, which are being serialized, so
+// one that contains itself fails to serialize instead of recursing forever.
+struct Within<'a> {
+    obj: &'a Object,
+    open: &'a RefCell<Vec<*const ()>>,
+}
+
+impl Within<'_> {
+    fn within<'a>(&'a self, obj: &'a Object) -> Within<'a> {
+        Within {
+            obj,
+            open: self.open,
+        }
+    }
+
+    // Serializes the collection `id` with `f`, unless it's inside itself.
+    fn collection<S: Serializer>(
+        &self,
+        id: *const (),
+        f: impl FnOnce() -> Result<S::Ok, S::Error>,
+    ) -> Result<S::Ok, S::Error> {
+        if self.open.borrow().contains(&id) {
+            return Err(ser::Error::custom(format!(
+                "cannot serialize a {} that contains itself",
+                self.obj.obj_type()
+            )));
+        }
+        self.open.borrow_mut().push(id);
+        let result = f();
+        self.open.borrow_mut().pop();
+        result
+    }
+}
+
+impl Serialize for Within<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.obj {
+            Object::Integer(i) => serializer.serialize_i64(*i),
+            Object::BigInt(i) => serialize_bigint(i, serializer),
+            Object::Float(f) => serializer.serialize_f64(*f),
+            Object::Boolean(b) => serializer.serialize_bool(*b),
+            Object::String(s) => serializer.serialize_str(s),
+            Object::Null => serializer.serialize_unit(),
+            Object::ReturnValue(value) => self.within(value).serialize(serializer),
+            Object::Cell(cell) => self.within(&cell.borrow()).serialize(serializer),
+            Object::Array(elements) => {
+                self.collection::<S>(Rc::as_ptr(elements) as *const (), || {
+                    let elements = elements.borrow();
+                    let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                    for element in elements.iter() {
+                        seq.serialize_element(&self.within(element))?;
+                    }
+                    seq.end()
+                })
+            }
+            Object::Hash(pairs) => self.collection::<S>(Rc::as_ptr(pairs) as *const (), || {
+                let pairs = pairs.borrow();
+                let mut map = serializer.serialize_map(Some(pairs.len()))?;
+                for pair in pairs.values() {
+                    map.serialize_entry(&self.within(&pair.key), &self.within(&pair.value))?;
+                }
+                map.end()
+            }),
+            Object::Error(err) => Err(ser::Error::custom(format!(
+                "cannot serialize an error: {}",
+                err.message
+            ))),
+            _ => Err(ser::Error::custom(format!(
+                "cannot serialize a {}",
+                self.obj.obj_type()
+            ))),
+        }
+    }
+}
+
+// serde's widest integer is 128 bits.
+fn serialize_bigint<S: Serializer>(i: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+    match i.to_i128() {
+        Some(i) => serializer.serialize_i128(i),
+        None => Err(ser::Error::custom(format!(
+            "cannot serialize {}, which is outside the 128-bit range",
+            i
+        ))),
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Integer(i) => serializer.serialize_i64(*i),
+            Value::BigInt(i) => serialize_bigint(i, serializer),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Null => serializer.serialize_unit(),
+            Value::Array(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            Value::Hash(pairs) => {
+                let mut map = serializer.serialize_map(Some(pairs.len()))?;
+                for (key, value) in pairs {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+            Value::Function(function) => function.object().serialize(serializer),
+        }
+    }
+}
+
+struct ObjectVisitor;
+
+impl<'de> Visitor<'de> for ObjectVisitor {
+    type Value = Object;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a number, boolean, string, array, hash or null")
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Object, E> {
+        Ok(Object::Boolean(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, i: i64) -> Result<Object, E> {
+        Ok(Object::Integer(i))
+    }
+
+    fn visit_u64<E: de::Error>(self, u: u64) -> Result<Object, E> {
+        Ok(Object::from_bigint(BigInt::from(u)))
+    }
+
+    fn visit_i128<E: de::Error>(self, i: i128) -> Result<Object, E> {
+        Ok(Object::from_bigint(BigInt::from(i)))
+    }
+
+    fn visit_u128<E: de::Error>(self, u: u128) -> Result<Object, E> {
+        Ok(Object::from_bigint(BigInt::from(u)))
+    }
+
+    fn visit_f64<E: de::Error>(self, f: f64) -> Result<Object, E> {
+        Ok(Object::Float(f))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Object, E> {
+        Ok(Object::String(s.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, s: String) -> Result<Object, E> {
+        Ok(Object::String(s))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Object, D::Error> {
+        Object::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Object, A::Error> {
+        let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Object::array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Object, A::Error> {
+        let mut map = HashTable::new();
+        while let Some((key, value)) = access.next_entry::<Object, Object>()? {
+            let hash_key = object::create_hash_key(key.clone()).ok_or_else(|| {
+                de::Error::custom(format!("unusable as hash key: {}", key.obj_type()))
+            })?;
+            map.insert(hash_key, HashPair { key, value });
+        }
+        Ok(Object::hash(map))
+    }
+}
+
+impl<'de> Deserialize<'de> for Object {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Object, D::Error> {
+        deserializer.deserialize_any(ObjectVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        let obj = Object::deserialize(deserializer)?;
+        Value::try_from(obj).map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    Builtin,
+    Free,
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: SymbolScope,
+    pub index: usize,
+}
+
+/// Maps identifiers to storage slots at compile time. Each function literal
+/// gets its own table enclosing the table of the surrounding code.
+///
+/// Names bound inside a block, such as the body of a `for` loop, are locals
+/// that go out of scope when the block ends, even at the top level.
+///
+/// A function's or block's locals can be declared before they're defined:
+/// code running in the innermost block only sees the names defined so far,
+/// while the blocks and functions around it see every name they declare,
+/// since a function may run after they're bound. The resolver gives the
+/// evaluator the same rule.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    pub outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    // the locals declared here that aren't defined yet
+    declared: HashMap<String, Symbol>,
+    pub num_definitions: usize,
+    pub free_symbols: Vec<Symbol>,
+    // innermost last
+    blocks: Vec<Block>,
+    // the local slots the top level uses for its blocks
+    pub num_top_level_locals: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Block {
+    symbols: HashMap<String, Symbol>,
+    declared: HashMap<String, Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new_enclosed(outer: SymbolTable) -> SymbolTable {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..SymbolTable::default()
+        }
+    }
+
+    /// Defines `name` in this table, or in the innermost block if one is open.
+    /// Redefining a name reuses its slot, as does defining a declared one.
+    pub fn define(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.defined(name) {
+            return symbol;
+        }
+        let symbol = match self.declared_mut().remove(name) {
+            Some(symbol) => symbol,
+            None => self.new_symbol(name),
+        };
+        match self.blocks.last_mut() {
+            Some(block) => block.symbols.insert(name.to_string(), symbol.clone()),
+            None => self.store.insert(name.to_string(), symbol.clone()),
+        };
+        symbol
+    }
+
+    /// Gives `name` a slot where `define` would put it, before it's defined
+    /// there, so the functions in the block can refer to it.
+    pub fn declare(&mut self, name: &str) {
+        if self.defined(name).is_some() || self.declared_mut().contains_key(name) {
+            return;
+        }
+        let symbol = self.new_symbol(name);
+        self.declared_mut().insert(name.to_string(), symbol);
+    }
+
+    // The variable `name` refers to in the innermost block, or in this table
+    // if no block is open.
+    fn defined(&self, name: &str) -> Option<Symbol> {
+        let symbol = match self.blocks.last() {
+            Some(block) => block.symbols.get(name),
+            None => self.store.get(name).filter(|symbol| {
+                symbol.scope == SymbolScope::Global || symbol.scope == SymbolScope::Local
+            }),
+        };
+        symbol.cloned()
+    }
+
+    fn declared_mut(&mut self) -> &mut HashMap<String, Symbol> {
+        match self.blocks.last_mut() {
+            Some(block) => &mut block.declared,
+            None => &mut self.declared,
+        }
+    }
+
+    fn new_symbol(&mut self, name: &str) -> Symbol {
+        if !self.blocks.is_empty() {
+            return self.define_block_local(name);
+        }
+        let scope = if self.outer.is_none() {
+            SymbolScope::Global
+        } else {
+            SymbolScope::Local
+        };
+        self.num_definitions += 1;
+        Symbol {
+            name: name.to_string(),
+            scope,
+            index: self.num_definitions - 1,
+        }
+    }
+
+    /// Reserves a local slot in the innermost block that no name refers to.
+    pub fn define_hidden(&mut self) -> Symbol {
+        self.define_block_local("")
+    }
+
+    fn define_block_local(&mut self, name: &str) -> Symbol {
+        let counter = if self.outer.is_none() {
+            &mut self.num_top_level_locals
+        } else {
+            &mut self.num_definitions
+        };
+        let index = *counter;
+        *counter += 1;
+        Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Local,
+            index,
+        }
+    }
+
+    /// Opens a block and returns the first local slot it will use. A block's
+    /// slots run from there up to the slot returned by `leave_block`.
+    pub fn enter_block(&mut self) -> usize {
+        self.blocks.push(Block::default());
+        self.next_local()
+    }
+
+    /// Closes the innermost block and returns the end of its local slots.
+    pub fn leave_block(&mut self) -> usize {
+        self.blocks.pop();
+        self.next_local()
+    }
+
+    /// The slot the next block local will get.
+    pub fn next_local(&self) -> usize {
+        if self.outer.is_none() {
+            self.num_top_level_locals
+        } else {
+            self.num_definitions
+        }
+    }
+
+    /// Reserves a global slot that no name refers to.
+    pub fn define_hidden_global(&mut self) -> usize {
+        self.num_definitions += 1;
+        self.num_definitions - 1
+    }
+
+    /// A global table for a module compiled alongside this one. It knows the
+    /// builtins but none of the globals, whose slots it leaves alone.
+    pub fn new_module(&self) -> SymbolTable {
+        let builtins = self
+            .store
+            .iter()
+            .filter(|(_, symbol)| symbol.scope == SymbolScope::Builtin)
+            .map(|(name, symbol)| (name.clone(), symbol.clone()))
+            .collect();
+        SymbolTable {
+            store: builtins,
+            num_definitions: self.num_definitions,
+            ..SymbolTable::default()
+        }
+    }
+
+    /// The globals this table defines, by name.
+    pub fn globals(&self) -> Vec<Symbol> {
+        let mut globals: Vec<Symbol> = self
+            .store
+            .values()
+            .filter(|symbol| symbol.scope == SymbolScope::Global)
+            .cloned()
+            .collect();
+        globals.sort_by(|a, b| a.name.cmp(&b.name));
+        globals
+    }
+
+    pub fn define_builtin(&mut self, index: usize, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Builtin,
+            index,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    /// Defines the name a function literal is bound to, so the function can refer to itself.
+    pub fn define_function_name(&mut self, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Function,
+            index: 0,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let symbol = Symbol {
+            name: original.name.clone(),
+            scope: SymbolScope::Free,
+            index: self.free_symbols.len(),
+        };
+        self.free_symbols.push(original);
+        self.store.insert(symbol.name.clone(), symbol.clone());
+        symbol
+    }
+
+    /// Looks `name` up here and then in the enclosing tables. Locals of an
+    /// enclosing function are turned into free variables of this one.
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        self.resolve_from(name, true)
+    }
+
+    // `innermost` is whether the code using `name` runs in this table's
+    // innermost block, where only the names defined so far are seen.
+    fn resolve_from(&mut self, name: &str, innermost: bool) -> Option<Symbol> {
+        for (depth, block) in self.blocks.iter().rev().enumerate() {
+            if let Some(symbol) = block.symbols.get(name) {
+                return Some(symbol.clone());
+            }
+            if let Some(symbol) = block.declared.get(name) {
+                if !innermost || depth > 0 {
+                    return Some(symbol.clone());
+                }
+            }
+        }
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+        if let Some(symbol) = self.declared.get(name) {
+            if !innermost || !self.blocks.is_empty() {
+                return Some(symbol.clone());
+            }
+        }
+        let symbol = self.outer.as_mut()?.resolve_from(name, false)?;
+        match symbol.scope {
+            SymbolScope::Global | SymbolScope::Builtin => Some(symbol),
+            _ => Some(self.define_free(symbol)),
+        }
+    }
+
+    /// Names of the global slots, indexed by slot.
+    pub fn global_names(&self) -> Vec<String> {
+        match &self.outer {
+            Some(outer) => outer.global_names(),
+            None => {
+                let mut names = vec![String::new(); self.num_definitions];
+                for symbol in self.store.values() {
+                    if symbol.scope == SymbolScope::Global {
+                        names[symbol.index] = symbol.name.clone();
+                    }
+                }
+                names
+            }
+        }
+    }
+}
@@ -1,12 +1,14 @@
 #[cfg(test)]
 mod tests {
-    use crate::ast::{Expression, Program, Statement};
+    use crate::ast::{Expression, Program, Slot, Statement};
+    use crate::token::Span;
     #[test]
     fn test_strings() {
         let output = "let myVar = anotherVar;";
 
-        let expr = Expression::Ident("anotherVar".to_string());
-        let let_statement = Statement::Let("myVar".to_string(), expr);
+        let expr = Expression::Ident("anotherVar".to_string(), Slot::Unresolved, Span::default());
+        let let_statement =
+            Statement::Let("myVar".to_string(), Slot::Unresolved, expr, Span::default());
         let statements = vec![let_statement];
         let program = Program { statements };
         assert_eq!(output, program.to_string());
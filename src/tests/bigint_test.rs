@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+    use crate::BigInt;
+
+    fn big(digits: &str) -> BigInt {
+        digits.parse().unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!((&a + &b).to_string(), "-864197532086419753208641975320");
+        assert_eq!((&a - &b).to_string(), "1111111110111111111011111111100");
+        assert_eq!(
+            (&a * &b).to_string(),
+            "-121932631137021795226185032733622923332237463801111263526900"
+        );
+        assert_eq!((&b - &b).to_string(), "0");
+        assert_eq!(BigInt::from(i64::MIN).to_string(), "-9223372036854775808");
+    }
+
+    #[test]
+    fn test_division() {
+        let tests = vec![
+            (
+                "121932631137021795226185032733622923332237463801111263526900",
+                "123456789012345678901234567890",
+                "987654321098765432109876543210",
+            ),
+            (
+                "1000000000000000000000000000",
+                "-7",
+                "-142857142857142857142857142",
+            ),
+            ("-99999999999999999999", "100000000000", "-999999999"),
+            ("5", "1000000000000000000000", "0"),
+            ("18446744073709551616", "4294967296", "4294967296"),
+        ];
+        for (dividend, divisor, quotient) in tests {
+            let result = big(dividend).div(&big(divisor)).unwrap();
+            assert_eq!(result.to_string(), quotient, "{} / {}", dividend, divisor);
+        }
+        assert_eq!(big("12345678901234567890").div(&BigInt::from(0i64)), None);
+
+        let tests = vec![
+            ("1000000000000000000000000000", "-7", "6"),
+            ("-1000000000000000000000000000", "7", "-6"),
+            (
+                "123456789012345678901234567890",
+                "1000000000000",
+                "901234567890",
+            ),
+            ("5", "1000000000000000000000", "5"),
+        ];
+        for (dividend, divisor, remainder) in tests {
+            let result = big(dividend).rem(&big(divisor)).unwrap();
+            assert_eq!(result.to_string(), remainder, "{} % {}", dividend, divisor);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(big("+000123").to_string(), "123");
+        assert_eq!(big("-0").to_string(), "0");
+        assert_eq!(big("1000000000").to_string(), "1000000000");
+        assert_eq!(
+            "12a".parse::<BigInt>(),
+            Err("invalid digit in \"12a\"".to_string())
+        );
+        assert!("".parse::<BigInt>().is_err());
+        assert!("-".parse::<BigInt>().is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(big("9223372036854775807").to_i64(), Some(i64::MAX));
+        assert_eq!(big("-9223372036854775808").to_i64(), Some(i64::MIN));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(
+            big("-9223372036854775809").to_i128(),
+            Some(-9223372036854775809)
+        );
+        assert_eq!(big("1").to_i128(), Some(1));
+        assert_eq!(
+            big("1000000000000000000000000000000000000000").to_i128(),
+            None
+        );
+        assert_eq!(
+            big("170141183460469231731687303715884105727").to_i128(),
+            Some(i128::MAX)
+        );
+        assert_eq!(
+            big("-170141183460469231731687303715884105728").to_i128(),
+            Some(i128::MIN)
+        );
+        assert_eq!(
+            big("170141183460469231731687303715884105728").to_i128(),
+            None
+        );
+        assert_eq!(
+            big("340282366920938463463374607431768211455").to_u128(),
+            Some(u128::MAX)
+        );
+        assert_eq!(big("-1").to_u128(), None);
+        assert_eq!(
+            big("-18446744073709551616").to_f64(),
+            -18446744073709551616.0
+        );
+        assert!(big("-2") < big("1"));
+        assert!(big("-20000000000") < big("-10000000000"));
+        assert!(big("20000000000") > big("10000000000"));
+    }
+}
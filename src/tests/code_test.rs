@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use crate::code::{self, Opcode};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_make() {
+        let tests = vec![
+            (
+                Opcode::Constant,
+                vec![65534],
+                vec![Opcode::Constant as u8, 255, 254],
+            ),
+            (Opcode::Add, vec![], vec![Opcode::Add as u8]),
+            (
+                Opcode::GetLocal,
+                vec![255],
+                vec![Opcode::GetLocal as u8, 255],
+            ),
+            (
+                Opcode::Closure,
+                vec![65534, 255],
+                vec![Opcode::Closure as u8, 255, 254, 255],
+            ),
+        ];
+
+        for (op, operands, expected) in tests {
+            assert_eq!(code::make(op, &operands), expected);
+        }
+    }
+
+    #[test]
+    fn test_read_operands() {
+        let tests = vec![
+            (Opcode::Constant, vec![65535], 2),
+            (Opcode::GetLocal, vec![255], 1),
+            (Opcode::Closure, vec![65535, 255], 3),
+            (Opcode::IterNext, vec![2, 1, 65535], 4),
+        ];
+
+        for (op, operands, bytes_read) in tests {
+            let instruction = code::make(op, &operands);
+            let (read, n) = code::read_operands(op, &instruction[1..]);
+            assert_eq!(n, bytes_read);
+            assert_eq!(read, operands);
+        }
+    }
+
+    #[test]
+    fn test_opcode_round_trip() {
+        for byte in 0..=Opcode::Unbound as u8 {
+            let op = Opcode::try_from(byte).unwrap();
+            assert_eq!(op as u8, byte);
+        }
+        assert!(Opcode::try_from(Opcode::Unbound as u8 + 1).is_err());
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut instructions = vec![];
+        instructions.extend(code::make(Opcode::Add, &[]));
+        instructions.extend(code::make(Opcode::GetLocal, &[1]));
+        instructions.extend(code::make(Opcode::Constant, &[2]));
+        instructions.extend(code::make(Opcode::Constant, &[65535]));
+        instructions.extend(code::make(Opcode::Closure, &[65535, 255]));
+
+        let expected = "0000 OpAdd
+0001 OpGetLocal 1
+0003 OpConstant 2
+0006 OpConstant 65535
+0009 OpClosure 65535 255
+";
+        assert_eq!(code::disassemble(&instructions), expected);
+    }
+}
@@ -0,0 +1,390 @@
+#[cfg(test)]
+mod tests {
+    use crate::code::{self, Opcode};
+    use crate::compiler::{Bytecode, Compiler};
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use crate::symbol_table::{Symbol, SymbolScope, SymbolTable};
+
+    fn compile(input: &str) -> Bytecode {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        match Compiler::new().compile(&program) {
+            Ok(bytecode) => bytecode,
+            Err(err) => panic!("compiler error: {}", err),
+        }
+    }
+
+    fn concat(instructions: Vec<Vec<u8>>) -> String {
+        code::disassemble(&instructions.concat())
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let tests = vec![
+            (
+                "1 + 2",
+                vec![
+                    code::make(Opcode::Constant, &[0]),
+                    code::make(Opcode::Constant, &[1]),
+                    code::make(Opcode::Add, &[]),
+                    code::make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "1 < 2",
+                vec![
+                    code::make(Opcode::Constant, &[0]),
+                    code::make(Opcode::Constant, &[1]),
+                    code::make(Opcode::LessThan, &[]),
+                    code::make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "-1; !true",
+                vec![
+                    code::make(Opcode::Constant, &[0]),
+                    code::make(Opcode::Minus, &[]),
+                    code::make(Opcode::Pop, &[]),
+                    code::make(Opcode::True, &[]),
+                    code::make(Opcode::Bang, &[]),
+                    code::make(Opcode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let bytecode = compile(input);
+            assert_eq!(code::disassemble(&bytecode.instructions), concat(expected));
+        }
+        let bytecode = compile("1 + 2");
+        assert_eq!(
+            bytecode.constants,
+            vec![Object::Integer(1), Object::Integer(2)]
+        );
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let bytecode = compile("if (true) { 10 }; 3333;");
+        let expected = vec![
+            code::make(Opcode::True, &[]),
+            code::make(Opcode::JumpNotTruthy, &[10]),
+            code::make(Opcode::Constant, &[0]),
+            code::make(Opcode::Jump, &[11]),
+            code::make(Opcode::Null, &[]),
+            code::make(Opcode::Pop, &[]),
+            code::make(Opcode::Constant, &[1]),
+            code::make(Opcode::Pop, &[]),
+        ];
+        assert_eq!(code::disassemble(&bytecode.instructions), concat(expected));
+    }
+
+    #[test]
+    fn test_global_let_statements() {
+        let bytecode = compile("let one = 1; let two = one;");
+        let expected = vec![
+            code::make(Opcode::Constant, &[0]),
+            code::make(Opcode::SetGlobal, &[0]),
+            code::make(Opcode::GetGlobal, &[0]),
+            code::make(Opcode::SetGlobal, &[1]),
+            code::make(Opcode::GetGlobal, &[1]),
+            code::make(Opcode::Pop, &[]),
+        ];
+        assert_eq!(code::disassemble(&bytecode.instructions), concat(expected));
+        assert_eq!(bytecode.global_names, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_closures() {
+        let bytecode = compile("fn(a) { fn(b) { a + b } }");
+        let inner = match &bytecode.constants[0] {
+            Object::CompiledFunction(function) => function.clone(),
+            other => panic!("constant is not a function. got={:?}", other),
+        };
+        let expected = vec![
+            code::make(Opcode::GetFree, &[0]),
+            code::make(Opcode::GetLocal, &[0]),
+            code::make(Opcode::Add, &[]),
+            code::make(Opcode::ReturnValue, &[]),
+        ];
+        assert_eq!(code::disassemble(&inner.instructions), concat(expected));
+
+        let outer = match &bytecode.constants[1] {
+            Object::CompiledFunction(function) => function.clone(),
+            other => panic!("constant is not a function. got={:?}", other),
+        };
+        let expected = vec![
+            code::make(Opcode::CaptureLocal, &[0]),
+            code::make(Opcode::Closure, &[0, 1]),
+            code::make(Opcode::ReturnValue, &[]),
+        ];
+        assert_eq!(code::disassemble(&outer.instructions), concat(expected));
+        assert_eq!(outer.num_locals, 1);
+    }
+
+    #[test]
+    fn test_recursive_function() {
+        let bytecode =
+            compile("let wrapper = fn() { let countDown = fn(x) { countDown(x - 1); }; }");
+        let count_down = match &bytecode.constants[1] {
+            Object::CompiledFunction(function) => function.clone(),
+            other => panic!("constant is not a function. got={:?}", other),
+        };
+        let expected = vec![
+            code::make(Opcode::CurrentClosure, &[]),
+            code::make(Opcode::GetLocal, &[0]),
+            code::make(Opcode::Constant, &[0]),
+            code::make(Opcode::Sub, &[]),
+            code::make(Opcode::Call, &[1]),
+            code::make(Opcode::ReturnValue, &[]),
+        ];
+        assert_eq!(
+            code::disassemble(&count_down.instructions),
+            concat(expected)
+        );
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let tests = vec![(
+            "let f = fn() { f = 1 }",
+            "1:16: cannot assign to f inside its own definition",
+        )];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+            match Compiler::new().compile(&program) {
+                Err(err) => assert_eq!(err.to_string(), expected),
+                Ok(_) => {
+                    println!("no compile error for {}", input);
+                    assert!(false);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unbound_names() {
+        let tests = vec![
+            ("foobar", "identifier not found: foobar"),
+            ("len = 1", "cannot assign to undefined variable: len"),
+        ];
+
+        for (input, message) in tests {
+            let bytecode = compile(input);
+            let expected = vec![
+                code::make(Opcode::Unbound, &[0]),
+                code::make(Opcode::Pop, &[]),
+            ];
+            assert_eq!(
+                code::disassemble(&bytecode.instructions),
+                concat(expected),
+                "{}",
+                input
+            );
+            assert_eq!(
+                bytecode.constants[0],
+                Object::String(message.to_string()),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_free() {
+        let mut global = SymbolTable::default();
+        global.define("a");
+        let mut first = SymbolTable::new_enclosed(global);
+        first.define("c");
+        let mut second = SymbolTable::new_enclosed(first);
+        second.define("e");
+
+        let tests = vec![
+            ("a", SymbolScope::Global, 0),
+            ("c", SymbolScope::Free, 0),
+            ("e", SymbolScope::Local, 0),
+        ];
+        for (name, scope, index) in tests {
+            let expected = Symbol {
+                name: name.to_string(),
+                scope,
+                index,
+            };
+            assert_eq!(second.resolve(name), Some(expected));
+        }
+        assert_eq!(second.free_symbols[0].scope, SymbolScope::Local);
+        assert_eq!(second.resolve("f"), None);
+    }
+
+    #[test]
+    fn test_resolve_declared() {
+        let mut outer = SymbolTable::new_enclosed(SymbolTable::default());
+        outer.define("a");
+        outer.declare("b");
+        // only functions inside see a local before it's defined
+        assert_eq!(outer.resolve("b"), None);
+        let mut inner = SymbolTable::new_enclosed(outer);
+        let free = Symbol {
+            name: "b".to_string(),
+            scope: SymbolScope::Free,
+            index: 0,
+        };
+        assert_eq!(inner.resolve("b"), Some(free));
+
+        let mut outer = *inner.outer.take().unwrap();
+        let local = Symbol {
+            name: "b".to_string(),
+            scope: SymbolScope::Local,
+            index: 1,
+        };
+        assert_eq!(outer.define("b"), local);
+        assert_eq!(outer.resolve("b"), Some(local));
+        assert_eq!(outer.num_definitions, 2);
+    }
+
+    #[test]
+    fn test_while_loops() {
+        let bytecode = compile("while (true) { if (false) { break; }; continue; }");
+        let expected = vec![
+            // 0000
+            code::make(Opcode::True, &[]),
+            // 0001
+            code::make(Opcode::JumpNotTruthy, &[22]),
+            // 0004
+            code::make(Opcode::False, &[]),
+            // 0005
+            code::make(Opcode::JumpNotTruthy, &[14]),
+            // 0008
+            code::make(Opcode::Jump, &[22]),
+            // 0011
+            code::make(Opcode::Jump, &[15]),
+            // 0014
+            code::make(Opcode::Null, &[]),
+            // 0015
+            code::make(Opcode::Pop, &[]),
+            // 0016
+            code::make(Opcode::Jump, &[0]),
+            // 0019
+            code::make(Opcode::Jump, &[0]),
+            // 0022
+            code::make(Opcode::Null, &[]),
+            // 0023
+            code::make(Opcode::Pop, &[]),
+        ];
+        assert_eq!(code::disassemble(&bytecode.instructions), concat(expected));
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let bytecode = compile("for (x in [1]) { if (x) { continue; } let y = x; }");
+        let expected = vec![
+            // 0000
+            code::make(Opcode::Constant, &[0]),
+            // 0003
+            code::make(Opcode::Array, &[1]),
+            // 0006
+            code::make(Opcode::Iter, &[0, 1]),
+            // 0009
+            code::make(Opcode::IterNext, &[0, 1, 39]),
+            // 0014
+            code::make(Opcode::SetLocal, &[2]),
+            // 0016
+            code::make(Opcode::GetLocal, &[2]),
+            // 0018
+            code::make(Opcode::JumpNotTruthy, &[27]),
+            // 0021
+            code::make(Opcode::Jump, &[33]),
+            // 0024
+            code::make(Opcode::Jump, &[28]),
+            // 0027
+            code::make(Opcode::Null, &[]),
+            // 0028
+            code::make(Opcode::Pop, &[]),
+            // 0029
+            code::make(Opcode::GetLocal, &[2]),
+            // 0031
+            code::make(Opcode::SetLocal, &[3]),
+            // 0033
+            code::make(Opcode::ResetLocals, &[2, 2]),
+            // 0036
+            code::make(Opcode::Jump, &[9]),
+            // 0039
+            code::make(Opcode::ResetLocals, &[0, 4]),
+            // 0042
+            code::make(Opcode::Null, &[]),
+            // 0043
+            code::make(Opcode::Pop, &[]),
+        ];
+        assert_eq!(code::disassemble(&bytecode.instructions), concat(expected));
+        assert_eq!(bytecode.num_locals, 4);
+
+        let bytecode = compile("fn() { for (i in 0..2) { i } }");
+        let function = match &bytecode.constants[2] {
+            Object::CompiledFunction(function) => function.clone(),
+            other => panic!("constant is not a function. got={:?}", other),
+        };
+        let expected = vec![
+            // 0000
+            code::make(Opcode::Constant, &[0]),
+            // 0003
+            code::make(Opcode::Constant, &[1]),
+            // 0006
+            code::make(Opcode::IterRange, &[0]),
+            // 0008
+            code::make(Opcode::IterNext, &[0, 1, 24]),
+            // 0013
+            code::make(Opcode::SetLocal, &[2]),
+            // 0015
+            code::make(Opcode::GetLocal, &[2]),
+            // 0017
+            code::make(Opcode::Pop, &[]),
+            // 0018
+            code::make(Opcode::ResetLocals, &[2, 1]),
+            // 0021
+            code::make(Opcode::Jump, &[8]),
+            // 0024
+            code::make(Opcode::ResetLocals, &[0, 3]),
+            // 0027
+            code::make(Opcode::Return, &[]),
+        ];
+        assert_eq!(code::disassemble(&function.instructions), concat(expected));
+        assert_eq!(function.num_locals, 3);
+    }
+
+    #[test]
+    fn test_assignment() {
+        let bytecode = compile("let a = [1]; a[0] += 2");
+        let expected = vec![
+            code::make(Opcode::Constant, &[0]),
+            code::make(Opcode::Array, &[1]),
+            code::make(Opcode::SetGlobal, &[0]),
+            code::make(Opcode::GetGlobal, &[0]),
+            code::make(Opcode::Constant, &[1]),
+            code::make(Opcode::Constant, &[2]),
+            code::make(Opcode::SetIndex, &[1, 1]),
+            code::make(Opcode::SetGlobal, &[0]),
+            code::make(Opcode::Pop, &[]),
+        ];
+        assert_eq!(code::disassemble(&bytecode.instructions), concat(expected));
+
+        let bytecode = compile("fn() { let n = 0; fn() { n = 1 } }");
+        let inner = match &bytecode.constants[2] {
+            Object::CompiledFunction(function) => function.clone(),
+            other => panic!("constant is not a function. got={:?}", other),
+        };
+        let expected = vec![
+            code::make(Opcode::GetFree, &[0]),
+            code::make(Opcode::Constant, &[1]),
+            code::make(Opcode::SetIndex, &[0, 0]),
+            code::make(Opcode::SetFree, &[0]),
+            code::make(Opcode::ReturnValue, &[]),
+        ];
+        assert_eq!(code::disassemble(&inner.instructions), concat(expected));
+    }
+}
@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod tests {
+    use crate::convert::{ConversionError, FromMonkey, IntoMonkey};
+    use crate::object::Object;
+    use crate::{Backend, BigInt, Interpreter, MonkeyError};
+    use std::collections::HashMap;
+
+    fn round_trip<T>(value: T)
+    where
+        T: IntoMonkey + FromMonkey + Clone + PartialEq + std::fmt::Debug,
+    {
+        let obj = value.clone().into_monkey();
+        assert_eq!(T::from_monkey(&obj), Ok(value));
+    }
+
+    #[test]
+    fn test_round_trips() {
+        round_trip(42i64);
+        round_trip(-7i32);
+        round_trip(255u8);
+        round_trip(true);
+        round_trip(2.5f64);
+        round_trip(BigInt::from(i64::MIN));
+        round_trip(u64::MAX);
+        round_trip(7u64);
+        round_trip(usize::MAX);
+        round_trip(isize::MIN);
+        round_trip(u128::MAX);
+        round_trip(i128::MIN);
+        round_trip(i128::MAX);
+        round_trip(-3i128);
+        round_trip("monkey".to_string());
+        round_trip(vec![1i64, 2, 3]);
+        round_trip(Some(5i64));
+        round_trip(None::<i64>);
+        round_trip((1i64, "one".to_string(), false));
+        round_trip(vec![(1i64, vec![true]), (2, vec![])]);
+
+        let mut map = HashMap::new();
+        map.insert("a".to_string(), vec![1i64]);
+        map.insert("b".to_string(), vec![]);
+        round_trip(map);
+    }
+
+    #[test]
+    fn test_into_monkey() {
+        assert_eq!("hi".into_monkey(), Object::String("hi".to_string()));
+        assert_eq!(().into_monkey(), Object::Null);
+        assert_eq!(0.5f32.into_monkey(), Object::Float(0.5));
+        assert_eq!(f64::from_monkey(&Object::Integer(3)), Ok(3.0));
+        // wide integers are only big when they have to be
+        assert_eq!(5u64.into_monkey(), Object::Integer(5));
+        assert_eq!(
+            u64::MAX.into_monkey(),
+            Object::BigInt(BigInt::from(u64::MAX))
+        );
+        assert_eq!(
+            u64::MAX.into_monkey().inspect(),
+            "18446744073709551615".to_string()
+        );
+        assert_eq!((1u8, "x").into_monkey().inspect(), "[1, x]".to_string());
+
+        let mut map = HashMap::new();
+        map.insert(3i64, "three");
+        assert_eq!(map.into_monkey().inspect(), "{3: three}");
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        let obj = vec![1i64.into_monkey(), "two".into_monkey()].into_monkey();
+        let err = Vec::<i64>::from_monkey(&obj).unwrap_err();
+        assert_eq!(err.to_string(), "[1]: expected INTEGER, got STRING");
+
+        let err = u8::from_monkey(&300i64.into_monkey()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected an integer between 0 and 255, got 300"
+        );
+
+        let err = u64::from_monkey(&(-1i64).into_monkey()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected an integer between 0 and 18446744073709551615, got -1"
+        );
+
+        let err = u64::from_monkey(&u128::MAX.into_monkey()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected an integer between 0 and 18446744073709551615, \
+             got 340282366920938463463374607431768211455"
+        );
+
+        let big = &BigInt::from(i64::MAX) + &BigInt::from(1i64);
+        let err = i64::from_monkey(&big.into_monkey()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected an integer between -9223372036854775808 and 9223372036854775807, \
+             got 9223372036854775808"
+        );
+
+        let err = <(i64, i64)>::from_monkey(&vec![1i64].into_monkey()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected ARRAY of length 2, got ARRAY of length 1"
+        );
+
+        let mut map = HashMap::new();
+        map.insert("name", vec![Some(1i64), None]);
+        let err = HashMap::<String, Vec<i64>>::from_monkey(&map.into_monkey()).unwrap_err();
+        assert_eq!(err.to_string(), "[\"name\"][1]: expected INTEGER, got NULL");
+
+        let mut map = HashMap::new();
+        map.insert(1i64, 1i64);
+        let err = HashMap::<String, i64>::from_monkey(&map.into_monkey()).unwrap_err();
+        assert_eq!(err.to_string(), "key [1]: expected STRING, got INTEGER");
+    }
+
+    #[test]
+    fn test_host_exchange() {
+        for backend in &[Backend::Evaluator, Backend::Vm] {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            let mut scores = HashMap::new();
+            scores.insert("ann", 3i64);
+            scores.insert("bob", 5);
+            interpreter.set("scores", scores);
+            interpreter.set("names", vec!["ann", "bob"]);
+
+            let total: i64 = interpreter
+                .eval_as("scores[names[0]] + scores[names[1]]")
+                .unwrap();
+            assert_eq!(total, 8);
+
+            let pair: (String, Option<i64>) =
+                interpreter.eval_as("[names[1], scores[\"eve\"]]").unwrap();
+            assert_eq!(pair, ("bob".to_string(), None));
+
+            match interpreter.eval_as::<bool>("names") {
+                Err(MonkeyError::Conversion(err)) => assert_eq!(
+                    err,
+                    ConversionError {
+                        expected: "BOOLEAN".to_string(),
+                        found: "ARRAY".to_string(),
+                        path: String::new(),
+                    }
+                ),
+                other => panic!("expected a conversion error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_typed_native_arguments() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn("total", 1, |args| {
+            let values: Vec<i64> = args.get_as(0)?;
+            Ok(values.iter().sum::<i64>().into_monkey())
+        });
+
+        assert_eq!(interpreter.eval_as::<i64>("total([1, 2, 3])"), Ok(6));
+        match interpreter.eval("total([1, \"2\"])") {
+            Err(MonkeyError::Runtime(err)) => assert_eq!(
+                err.message,
+                "[1] of argument 1 to 'total' must be INTEGER, got STRING"
+            ),
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::{self, Diagnostic, ErrorCode, Style};
+    use crate::environment::Environment;
+    use crate::evaluator;
+    use crate::lexer::Lexer;
+    use crate::object::Object;
+    use crate::parser::Parser;
+    use crate::token::Span;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_render_runtime_error() {
+        let input = "let a = 1;\nlet b = a + true;";
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        let env = Rc::new(RefCell::new(Environment::default()));
+        let err = match evaluator::eval(program, env) {
+            Object::Error(err) => err,
+            _ => {
+                println!("no error object returned");
+                assert!(false);
+                return;
+            }
+        };
+        assert_eq!(err.code, ErrorCode::TypeMismatch);
+
+        let expected = "error[E0003]: type mismatch: INTEGER + BOOLEAN
+ --> test.mk:2:9
+  |
+2 | let b = a + true;
+  |         ^^^^^^^^
+";
+        let rendered = Diagnostic::from(&err).render("test.mk", input, Style::Plain);
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn test_render_parse_errors() {
+        let input = "let x 5;\nlet y 10;";
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse_program();
+        let diagnostics: Vec<Diagnostic> = parser.errors().iter().map(Diagnostic::from).collect();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].code, ErrorCode::UnexpectedToken);
+
+        let expected = "error[E0001]: expected next token to be =, got 5 instead
+ --> test.mk:1:7
+  |
+1 | let x 5;
+  |       ^
+
+error[E0001]: expected next token to be =, got 10 instead
+ --> test.mk:2:7
+  |
+2 | let y 10;
+  |       ^^
+";
+        let rendered = diagnostics::render_all(&diagnostics, "test.mk", input, Style::Plain);
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn test_render_notes_and_tabs() {
+        let input = "\tfoo(1)";
+        let diagnostic = Diagnostic::error(
+            ErrorCode::UnknownIdentifier,
+            "identifier not found: foo".to_string(),
+            Some(Span::new(1, 4, 1, 2)),
+        )
+        .with_note("`foo` is defined in another file");
+
+        let expected = "error[E0005]: identifier not found: foo
+ --> test.mk:1:2
+  |
+1 | \tfoo(1)
+  | \t^^^
+  = help: bind the name with `let` before it is used, or check its spelling
+  = help: `foo` is defined in another file
+";
+        assert_eq!(diagnostic.render("test.mk", input, Style::Plain), expected);
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let diagnostic =
+            Diagnostic::error(ErrorCode::NotCallable, "not a function".to_string(), None);
+        let expected = "error[E0006]: not a function\n --> test.mk\n";
+        assert_eq!(diagnostic.render("test.mk", "", Style::Plain), expected);
+    }
+
+    #[test]
+    fn test_render_color() {
+        let input = "-true";
+        let diagnostic = Diagnostic::error(
+            ErrorCode::UnknownOperator,
+            "unknown operator: -BOOLEAN".to_string(),
+            Some(Span::new(0, 5, 1, 1)),
+        );
+        let rendered = diagnostic.render("test.mk", input, Style::Color);
+        assert!(rendered.starts_with("\x1b[1;31merror[E0004]\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^^^^^\x1b[0m"));
+        assert!(!diagnostic
+            .render("test.mk", input, Style::Plain)
+            .contains('\x1b'));
+    }
+}
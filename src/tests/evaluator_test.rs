@@ -1,23 +1,36 @@
 #[cfg(test)]
 mod tests {
+    use crate::compiler::Compiler;
+    use crate::diagnostics::ErrorCode;
     use crate::environment::Environment;
     use crate::evaluator;
     use crate::lexer::Lexer;
     use crate::object;
-    use crate::object::Object;
+    use crate::object::{Object, RuntimeError};
     use crate::parser::Parser;
+    use crate::vm::Vm;
     use std::cell::RefCell;
     use std::collections::HashMap;
     use std::rc::Rc;
 
-    fn test_eval(input: &str) -> Object {
-        let lexer = Lexer::new(&input);
+    // Every case runs on both backends: first the evaluator, then the compiler and VM.
+    fn test_eval(input: &str) -> Vec<Object> {
+        let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
         let program = parser.parse_program();
+        let compiled = match Compiler::new().compile(&program) {
+            Ok(bytecode) => Vm::new().run(bytecode),
+            Err(err) => Object::Error(RuntimeError {
+                code: err.code,
+                message: err.message,
+                span: Some(err.span),
+                thrown: None,
+            }),
+        };
         let env = Rc::new(RefCell::new(Environment::default()));
-        return evaluator::eval(program, env);
+        vec![evaluator::eval(program, env), compiled]
     }
-    //#[test]
+    #[test]
     fn test_eval_integer_expression() {
         let tests = vec![
             ("5", 5),
@@ -38,8 +51,9 @@ mod tests {
         ];
 
         for (input, expected) in tests {
-            let evaluated = test_eval(input);
-            test_integer_object(&evaluated, expected);
+            for evaluated in test_eval(input) {
+                test_integer_object(&evaluated, expected);
+            }
         }
     }
 
@@ -57,6 +71,248 @@ mod tests {
         }
     }
 
+    fn test_float_object(obj: &Object, expected: f64) {
+        match obj {
+            Object::Float(f) => assert_eq!(*f, expected),
+            other => panic!("object is not float. got={:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_float_expression() {
+        let tests = vec![
+            ("2.75", 2.75),
+            ("-2.5", -2.5),
+            ("1e3", 1000.0),
+            ("0.1 + 0.2", 0.1 + 0.2),
+            ("1.5 * 2", 3.0),
+            ("7 / 2.0", 3.5),
+            ("10 - 0.5", 9.5),
+            ("let avg = fn(xs) { let sum = 0; for (x in xs) { sum += x; } sum / float(len(xs)) };
+              avg([1, 2, 4])", 7.0 / 3.0),
+            ("let x = 1; x += 0.5; x", 1.5),
+            ("1.0 / 0", f64::INFINITY),
+        ];
+
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                test_float_object(&evaluated, expected);
+            }
+        }
+
+        for evaluated in test_eval("7 / 2") {
+            test_integer_object(&evaluated, 3);
+        }
+        for evaluated in test_eval("[1.0, 2.5e-7, 1e21, 3.0 * 2]") {
+            assert_eq!(evaluated.inspect(), "[1.0, 2.5e-7, 1e21, 6.0]");
+        }
+    }
+
+    #[test]
+    fn test_float_comparisons() {
+        let tests = vec![
+            ("1.5 < 2", true),
+            ("2 > 1.5", true),
+            ("1 == 1.0", true),
+            ("1.0 != 1", false),
+            ("0.1 + 0.2 == 0.3", false),
+            ("let nan = 0.0 / 0; nan == nan", false),
+            ("[1.0] == [1]", false),
+        ];
+
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                assert_eq!(evaluated, Object::Boolean(expected), "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_checked_integer_arithmetic() {
+        let tests = vec![
+            ("7 / 2", 3),
+            ("-7 / 2", -3),
+            ("9223372036854775807 - 1 + 1", i64::MAX),
+            ("-9223372036854775807 - 1", i64::MIN),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                test_integer_object(&evaluated, expected);
+            }
+        }
+
+        let errors = vec![
+            (
+                "1 / 0",
+                ErrorCode::DivisionByZero,
+                "division by zero: 1 / 0",
+            ),
+            (
+                "let x = 9223372036854775807; x + 1",
+                ErrorCode::IntegerOverflow,
+                "integer overflow in +: 9223372036854775807 + 1",
+            ),
+            (
+                "-9223372036854775807 - 2",
+                ErrorCode::IntegerOverflow,
+                "integer overflow in -: -9223372036854775807 - 2",
+            ),
+            (
+                "4611686018427387904 * 2",
+                ErrorCode::IntegerOverflow,
+                "integer overflow in *: 4611686018427387904 * 2",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; min / -1",
+                ErrorCode::IntegerOverflow,
+                "integer overflow in /: -9223372036854775808 / -1",
+            ),
+            (
+                "let min = -9223372036854775807 - 1; -min",
+                ErrorCode::IntegerOverflow,
+                "integer overflow in -: -(-9223372036854775808)",
+            ),
+            (
+                "let a = [1]; a[0] /= 0",
+                ErrorCode::DivisionByZero,
+                "division by zero: 1 / 0",
+            ),
+        ];
+        for (input, code, expected) in errors {
+            for evaluated in test_eval(input) {
+                match evaluated {
+                    Object::Error(err) => {
+                        assert_eq!(err.message, expected, "{}", input);
+                        assert_eq!(err.code, code, "{}", input);
+                    }
+                    other => panic!("expected an error for {}, got {:?}", input, other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_modulo() {
+        let tests = vec![
+            ("7 % 3", 1),
+            ("-7 % 3", -1),
+            ("7 % -3", 1),
+            ("let x = 10; x %= 4; x", 2),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                test_integer_object(&evaluated, expected);
+            }
+        }
+        for evaluated in test_eval("7.5 % 2") {
+            test_float_object(&evaluated, 1.5);
+        }
+        for evaluated in test_eval("let min = -9223372036854775807 - 1; min % -1") {
+            test_integer_object(&evaluated, 0);
+        }
+        for evaluated in test_eval("5 % 0") {
+            match evaluated {
+                Object::Error(err) => {
+                    assert_eq!(err.message, "division by zero: 5 % 0");
+                    assert_eq!(err.code, ErrorCode::DivisionByZero);
+                }
+                other => panic!("expected an error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_big_integers() {
+        let tests = vec![
+            ("99999999999999999999", "99999999999999999999"),
+            ("-99999999999999999999", "-99999999999999999999"),
+            ("-9223372036854775808", "-9223372036854775808"),
+            ("99999999999999999999 + 1", "100000000000000000000"),
+            ("99999999999999999999 - 99999999999999999998", "1"),
+            (
+                "123456789012345678901234567890 * 1000000007",
+                "123456789876543201987654320198641975230",
+            ),
+            ("100000000000000000000 / 3", "33333333333333333333"),
+            ("100000000000000000000 % 7", "2"),
+            ("-100000000000000000000 % 7", "-2"),
+            ("100000000000000000000 > 99999999999999999999", "true"),
+            ("100000000000000000000 < 5", "false"),
+            ("100000000000000000000 == 100000000000000000000", "true"),
+            ("100000000000000000000 != 100000000000000000000", "false"),
+            ("[100000000000000000000, 1]", "[100000000000000000000, 1]"),
+            (
+                "{100000000000000000000: \"big\"}[100000000000000000000]",
+                "big",
+            ),
+            (
+                "{100000000000000000000: \"big\"}[99999999999999999999 + 1]",
+                "big",
+            ),
+            ("{100000000000000000000: \"big\"}[1]", ""),
+            (
+                "int(\"123456789012345678901234567890\")",
+                "123456789012345678901234567890",
+            ),
+            ("float(100000000000000000000)", "1e20"),
+            ("100000000000000000000 * 1.5", "1.5e20"),
+            (
+                "100000000000000000000 % 0",
+                "ERROR: division by zero: 100000000000000000000 % 0",
+            ),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                assert_eq!(evaluated.inspect(), expected, "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_numeric_conversions() {
+        let tests = vec![
+            ("int(3.9)", 3),
+            ("int(-3.9)", -3),
+            ("int(\" 42 \")", 42),
+            ("int(7)", 7),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                test_integer_object(&evaluated, expected);
+            }
+        }
+        let tests = vec![
+            ("float(3)", 3.0),
+            ("float(\"2.5\")", 2.5),
+            ("float(0.5)", 0.5),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                test_float_object(&evaluated, expected);
+            }
+        }
+
+        let errors = vec![
+            ("int(1e19)", "cannot convert 1e19 to INTEGER"),
+            ("int(0.0 / 0)", "cannot convert NaN to INTEGER"),
+            ("int(\"4.5\")", "could not parse \"4.5\" as INTEGER"),
+            ("float(\"abc\")", "could not parse \"abc\" as FLOAT"),
+            ("int(true)", "argument to 'int' not supported, got BOOLEAN"),
+            ("float(1, 2)", "wrong number of arguments. got=2, want=1"),
+            ("1.5 .. 3", "unknown operator: FLOAT .. INTEGER"),
+            ("1.5 + \"a\"", "type mismatch: FLOAT + STRING"),
+            ("{1.5: 1}", "unusable as hash key: FLOAT"),
+        ];
+        for (input, expected) in errors {
+            for evaluated in test_eval(input) {
+                match evaluated {
+                    Object::Error(err) => assert_eq!(err.message, expected, "{}", input),
+                    other => panic!("expected an error for {}, got {:?}", input, other),
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_boolean_expression() {
         let tests = vec![
@@ -81,8 +337,9 @@ mod tests {
         ];
 
         for (input, expected) in tests {
-            let evaluated = test_eval(input);
-            test_boolean_object(evaluated, expected);
+            for evaluated in test_eval(input) {
+                test_boolean_object(evaluated, expected);
+            }
         }
     }
 
@@ -112,8 +369,9 @@ mod tests {
         ];
 
         for (input, expected) in tests {
-            let evaluated = test_eval(input);
-            test_boolean_object(evaluated, expected);
+            for evaluated in test_eval(input) {
+                test_boolean_object(evaluated, expected);
+            }
         }
     }
 
@@ -130,10 +388,11 @@ mod tests {
         ];
 
         for (input, expected) in tests {
-            let evaluated = test_eval(input);
-            match expected {
-                Some(i) => test_integer_object(&evaluated, i),
-                None => test_null_object(evaluated),
+            for evaluated in test_eval(input) {
+                match expected {
+                    Some(i) => test_integer_object(&evaluated, i),
+                    None => test_null_object(evaluated),
+                }
             }
         }
     }
@@ -166,8 +425,9 @@ mod tests {
         ];
 
         for (input, expected) in tests {
-            let evaluated = test_eval(input);
-            test_integer_object(&evaluated, expected);
+            for evaluated in test_eval(input) {
+                test_integer_object(&evaluated, expected);
+            }
         }
     }
 
@@ -201,18 +461,22 @@ mod tests {
         ];
 
         for (input, expected) in tests {
-            let evaluated = test_eval(input);
-            match evaluated {
-                Object::Error(msg) => {
-                    if msg != expected {
-                        println!("wrong error message, expected={}, got={}", expected, msg);
+            for evaluated in test_eval(input) {
+                match evaluated {
+                    Object::Error(err) => {
+                        if err.message != expected {
+                            println!(
+                                "wrong error message, expected={}, got={}",
+                                expected, err.message
+                            );
+                            assert!(false);
+                        }
+                    }
+                    _ => {
+                        println!("no error object reteurned");
                         assert!(false);
                     }
                 }
-                _ => {
-                    println!("no error object reteurned");
-                    assert!(false);
-                }
             }
         }
     }
@@ -227,33 +491,39 @@ mod tests {
         ];
 
         for (input, expected) in tests {
-            let obj = test_eval(input);
-            dbg!(&obj);
-            test_integer_object(&obj, expected);
+            for obj in test_eval(input) {
+                dbg!(&obj);
+                test_integer_object(&obj, expected);
+            }
         }
     }
 
     #[test]
     fn test_function_object() {
         let input = "fn(x) { x + 2; };";
-        let evaluated = test_eval(input);
-        match evaluated {
-            Object::Function(parms, body, env) => {
-                assert_eq!(parms.len(), 1);
-                if parms[0].to_string() != "x" {
-                    println!("paramater is not 'x'. got={}", parms[0].to_string());
+        for evaluated in test_eval(input) {
+            let (parms, body) = match evaluated {
+                Object::Function(parms, body, _env) => (parms, body.to_string()),
+                Object::Closure(closure) => (
+                    closure.function.parameters.clone(),
+                    closure.function.body.clone(),
+                ),
+                _ => {
+                    println!("object is not function");
                     assert!(false);
+                    continue;
                 }
+            };
+            assert_eq!(parms.len(), 1);
+            if parms[0] != "x" {
+                println!("paramater is not 'x'. got={}", parms[0]);
+                assert!(false);
+            }
 
-                let expected_body = String::from("(x + 2)");
+            let expected_body = String::from("(x + 2)");
 
-                if body.to_string() != expected_body {
-                    println!("body is not {}. got={}", expected_body, body);
-                    assert!(false);
-                }
-            }
-            _ => {
-                println!("object is not function");
+            if body != expected_body {
+                println!("body is not {}. got={}", expected_body, body);
                 assert!(false);
             }
         }
@@ -268,12 +538,18 @@ mod tests {
             ("let add = fn(x, y) { x + y; }; add(5, 5);", 10),
             ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
             ("fn(x) { x; }(5)", 5),
+            // a function can call one bound after it in the same function
+            (
+                "let f = fn() { let g = fn() { h() }; let h = fn() { 42 }; g() }; f()",
+                42,
+            ),
         ];
 
         for (input, expected) in tests {
-            let obj = test_eval(input);
-            dbg!(&obj);
-            test_integer_object(&obj, expected);
+            for obj in test_eval(input) {
+                dbg!(&obj);
+                test_integer_object(&obj, expected);
+            }
         }
     }
 
@@ -290,18 +566,19 @@ mod tests {
     }
 
     fn test_string(input: String) {
-        let evaluated = test_eval(&input);
-        match evaluated {
-            Object::String(s) => {
-                if s != "Hello World!" {
-                    println!("string has wrong value. got={}", s);
+        for evaluated in test_eval(&input) {
+            match evaluated {
+                Object::String(s) => {
+                    if s != "Hello World!" {
+                        println!("string has wrong value. got={}", s);
+                        assert!(false);
+                    }
+                }
+                _ => {
+                    println!("object is not String. got={}", evaluated.obj_type());
                     assert!(false);
                 }
             }
-            _ => {
-                println!("object is not String. got={}", evaluated.obj_type());
-                assert!(false);
-            }
         }
     }
 
@@ -332,37 +609,42 @@ mod tests {
         ];
 
         for (input, expected) in tests {
-            let evaluated = test_eval(&input);
-            match expected {
-                Mixed::Int(i) => {
-                    test_integer_object(&evaluated, i);
-                }
-                Mixed::Text(s) => match evaluated {
-                    Object::Error(err) => {
-                        if err != s {
-                            println!("wrong error message. expected={}, got={}", s, err);
+            for evaluated in test_eval(input) {
+                match &expected {
+                    Mixed::Int(i) => {
+                        test_integer_object(&evaluated, *i);
+                    }
+                    Mixed::Text(s) => match evaluated {
+                        Object::Error(err) => {
+                            if &err.message != s {
+                                println!(
+                                    "wrong error message. expected={}, got={}",
+                                    s, err.message
+                                );
+                                assert!(false);
+                            }
+                        }
+                        _ => {
+                            println!("object is not error.");
                             assert!(false);
                         }
-                    }
-                    _ => {
-                        println!("object is not error.");
-                        assert!(false);
-                    }
-                },
-                Mixed::Array(vec) => match evaluated {
-                    Object::Array(elements) => {
-                        assert_eq!(elements.len(), vec.len());
-                        let mut i = 0;
-                        for item in vec {
-                            test_integer_object(&elements[i], item);
-                            i += 1;
+                    },
+                    Mixed::Array(vec) => match evaluated {
+                        Object::Array(elements) => {
+                            let elements = elements.borrow();
+                            assert_eq!(elements.len(), vec.len());
+                            let mut i = 0;
+                            for item in vec {
+                                test_integer_object(&elements[i], *item);
+                                i += 1;
+                            }
                         }
-                    }
-                    _ => {
-                        println!("object is not array.");
-                        assert!(false);
-                    }
-                },
+                        _ => {
+                            println!("object is not array.");
+                            assert!(false);
+                        }
+                    },
+                }
             }
         }
     }
@@ -370,17 +652,19 @@ mod tests {
     #[test]
     fn test_array_literals() {
         let input = "[1, 2 * 2, 3 + 3]";
-        let evaluated = test_eval(input);
-        match evaluated {
-            Object::Array(elements) => {
-                assert_eq!(elements.len(), 3);
-                test_integer_object(&elements[0], 1);
-                test_integer_object(&elements[1], 4);
-                test_integer_object(&elements[2], 6);
-            }
-            _ => {
-                println!("object is not array");
-                assert!(false);
+        for evaluated in test_eval(input) {
+            match evaluated {
+                Object::Array(elements) => {
+                    let elements = elements.borrow();
+                    assert_eq!(elements.len(), 3);
+                    test_integer_object(&elements[0], 1);
+                    test_integer_object(&elements[1], 4);
+                    test_integer_object(&elements[2], 6);
+                }
+                _ => {
+                    println!("object is not array");
+                    assert!(false);
+                }
             }
         }
     }
@@ -406,10 +690,10 @@ mod tests {
             ("[1, 2, 3][-1]", None),
         ];
         for (input, expected) in tests {
-            let evaluated = test_eval(input);
-            match expected {
-                Some(i) => test_integer_object(&evaluated, i),
-                None => (),
+            for evaluated in test_eval(input) {
+                if let Some(i) = expected {
+                    test_integer_object(&evaluated, i)
+                }
             }
         }
     }
@@ -426,47 +710,49 @@ mod tests {
             false: 6
         }";
 
-        let evaluated = test_eval(input);
-        match evaluated {
-            Object::Hash(pairs) => {
-                let mut expected = HashMap::new();
-                expected.insert(
-                    object::create_hash_key(Object::String("one".to_string())),
-                    1,
-                );
-                expected.insert(
-                    object::create_hash_key(Object::String("two".to_string())),
-                    2,
-                );
-                expected.insert(
-                    object::create_hash_key(Object::String("three".to_string())),
-                    3,
-                );
-                expected.insert(object::create_hash_key(Object::Integer(4)), 4);
-                expected.insert(object::create_hash_key(Object::Boolean(true)), 5);
-                expected.insert(object::create_hash_key(Object::Boolean(false)), 6);
-
-                assert_eq!(pairs.len(), expected.len());
-                for (key, v) in &expected {
-                    let pc = pairs.clone();
-                    match key {
-                        Some(k) => match pc.get(k) {
-                            Some(val) => test_integer_object(&val.value, *v),
+        for evaluated in test_eval(input) {
+            match evaluated {
+                Object::Hash(pairs) => {
+                    let pairs = pairs.borrow();
+                    let mut expected = HashMap::new();
+                    expected.insert(
+                        object::create_hash_key(Object::String("one".to_string())),
+                        1,
+                    );
+                    expected.insert(
+                        object::create_hash_key(Object::String("two".to_string())),
+                        2,
+                    );
+                    expected.insert(
+                        object::create_hash_key(Object::String("three".to_string())),
+                        3,
+                    );
+                    expected.insert(object::create_hash_key(Object::Integer(4)), 4);
+                    expected.insert(object::create_hash_key(Object::Boolean(true)), 5);
+                    expected.insert(object::create_hash_key(Object::Boolean(false)), 6);
+
+                    assert_eq!(pairs.len(), expected.len());
+                    for (key, v) in &expected {
+                        let pc = pairs.clone();
+                        match key {
+                            Some(k) => match pc.get(k) {
+                                Some(val) => test_integer_object(&val.value, *v),
+                                None => {
+                                    println!("can't find key");
+                                    assert!(false);
+                                }
+                            },
                             None => {
-                                println!("can't find key");
+                                println!("key is none");
                                 assert!(false);
                             }
-                        },
-                        None => {
-                            println!("key is none");
-                            assert!(false);
                         }
                     }
                 }
-            }
-            _ => {
-                println!("object is not hash");
-                assert!(false);
+                _ => {
+                    println!("object is not hash");
+                    assert!(false);
+                }
             }
         }
     }
@@ -483,10 +769,789 @@ mod tests {
             ("{false: 5}[false]", Some(5)),
         ];
         for (input, expected) in tests {
-            let evaluated = test_eval(input);
-            match expected {
-                Some(i) => test_integer_object(&evaluated, i),
-                None => (),
+            for evaluated in test_eval(input) {
+                if let Some(i) = expected {
+                    test_integer_object(&evaluated, i)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash_keys() {
+        let tests = vec![
+            // distinct keys whose old 64-bit digests could collide are kept apart
+            (
+                "{\"a\": 1, \"b\": 2, 1: 3, true: 4}",
+                "{a: 1, b: 2, 1: 3, true: 4}",
+            ),
+            ("{\"z\": 1, \"a\": 2, \"m\": 3}", "{z: 1, a: 2, m: 3}"),
+            (
+                "let h = {\"x\": 1, \"y\": 2}; h[\"x\"] += 10; h",
+                "{x: 11, y: 2}",
+            ),
+            ("let h = {\"x\": 1}; h[\"w\"] = 0; h", "{x: 1, w: 0}"),
+            ("{1: \"a\", 1: \"b\"}", "{1: b}"),
+            ("{[1, 2]: \"pair\"}[[1, 2]]", "pair"),
+            ("{[1, 2]: \"pair\"}[[2, 1]]", ""),
+            ("{[1, [true]]: 5}[[1, [true]]]", "5"),
+            ("{{\"a\": 1, \"b\": 2}: \"h\"}[{\"b\": 2, \"a\": 1}]", "h"),
+            ("{{\"a\": 1}: \"h\"}[{\"a\": 2}]", ""),
+            ("{{\"a\": [1]}: 1, [{}]: 2}", "{{a: [1]}: 1, [{}]: 2}"),
+            ("{\"1\": \"s\", 1: \"i\"}[1]", "i"),
+            ("{[1]: 1} == {[1]: 1}", "true"),
+            ("{\"a\": 1, \"b\": 2} == {\"b\": 2, \"a\": 1}", "true"),
+            ("{[fn(x) { x }]: 1}", "ERROR: unusable as hash key: ARRAY"),
+            ("{{\"f\": len}: 1}", "ERROR: unusable as hash key: HASH"),
+            // keys are copied, so the pair is shown as it's found
+            (
+                "let a = [1]; let h = {}; h[a] = 1; push(a, 2); h",
+                "{[1]: 1}",
+            ),
+            (
+                "let a = [1]; let h = {a: 1}; push(a, 2); [h, h[[1]]]",
+                "[{[1]: 1}, 1]",
+            ),
+            (
+                "let a = [1]; let h = set({}, a, 1); push(a, 2); h",
+                "{[1]: 1}",
+            ),
+            (
+                "let a = []; push(a, a); let h = {}; h[a] = 1",
+                "ERROR: unusable as hash key: ARRAY",
+            ),
+            (
+                "let h = {}; h[\"h\"] = h; {h: 1}",
+                "ERROR: unusable as hash key: HASH",
+            ),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                assert_eq!(evaluated.inspect(), expected, "{}", input);
+            }
+        }
+
+        let keys =
+            "let h = {}; for (k in [\"q\", \"w\", \"e\", \"r\", \"t\", \"y\"]) { h[k] = 1 }; \
+                    let ks = \"\"; for (k in h) { ks += k }; ks";
+        for evaluated in test_eval(keys) {
+            assert_eq!(evaluated.inspect(), "qwerty");
+        }
+    }
+
+    #[test]
+    fn test_higher_order_builtins() {
+        let tests = vec![
+            ("map([1, 2, 3], fn(x) { x * 2 })", "[2, 4, 6]"),
+            ("map([\"a\", \"bb\"], len)", "[1, 2]"),
+            ("let n = 10; map([1, 2], fn(x) { x + n })", "[11, 12]"),
+            (
+                "map([[1], [2, 3]], fn(xs) { map(xs, fn(x) { -x }) })",
+                "[[-1], [-2, -3]]",
+            ),
+            ("filter([1, 2, 3, 4], fn(x) { x % 2 == 0 })", "[2, 4]"),
+            ("reduce([1, 2, 3], 10, fn(acc, x) { acc + x })", "16"),
+            ("reduce([], 0, fn(acc, x) { acc + x })", "0"),
+            ("sort_by([3, 1, 2], fn(x) { x })", "[1, 2, 3]"),
+            (
+                "sort_by([\"bb\", \"a\", \"ccc\"], fn(s) { -len(s) })",
+                "[ccc, bb, a]",
+            ),
+            (
+                "sort_by([[2, \"x\"], [1, \"y\"], [2, \"z\"]], first)",
+                "[[1, y], [2, x], [2, z]]",
+            ),
+            ("sort_by([\"b\", \"a\"], fn(s) { s })", "[a, b]"),
+            ("sort_by([1.5, 1, 2], fn(x) { x })", "[1, 1.5, 2]"),
+            ("let xs = [2, 1]; sort_by(xs, fn(x) { x }); xs", "[2, 1]"),
+            ("any([1, 2], fn(x) { x > 1 })", "true"),
+            ("any([], fn(x) { true })", "false"),
+            ("all([1, 2], fn(x) { x > 1 })", "false"),
+            ("all([], fn(x) { false })", "true"),
+            ("find([1, 2, 3], fn(x) { x > 1 })", "2"),
+            ("find([1], fn(x) { false })", ""),
+            (
+                "let sum = [0]; each([1, 2, 3], fn(x) { sum[0] += x }); sum[0]",
+                "6",
+            ),
+            (
+                "let xs = [1, 2]; each(xs, fn(x) { push(xs, x) }); xs",
+                "[1, 2, 1, 2]",
+            ),
+            (
+                "let f = fn(x) { if (x > 1) { return x; } 0 }; map([1, 2], f)",
+                "[0, 2]",
+            ),
+            (
+                "map(1, len)",
+                "ERROR: argument to 'map' must be ARRAY, got INTEGER",
+            ),
+            (
+                "map([1], 2)",
+                "ERROR: argument to 'map' must be FUNCTION, got INTEGER",
+            ),
+            (
+                "reduce([1], fn(a, b) { a })",
+                "ERROR: wrong number of arguments. got=2, want=3",
+            ),
+            (
+                "map([1], fn() { 1 })",
+                "ERROR: wrong number of arguments. got=1, want=0",
+            ),
+            (
+                "map([1, 0], fn(x) { 1 / x })",
+                "ERROR: division by zero: 1 / 0",
+            ),
+            (
+                "sort_by([1, \"a\"], fn(x) { x })",
+                "ERROR: cannot compare STRING and INTEGER",
+            ),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                assert_eq!(evaluated.inspect(), expected, "{}", input);
+            }
+        }
+
+        // callbacks run in a loop, so long arrays don't deepen the stack
+        let input = "let xs = map(0..100000, fn(x) { x + 1 }); \
+                     reduce(filter(xs, fn(x) { x % 2 == 0 }), 0, fn(a, x) { a + x })";
+        for evaluated in test_eval(input) {
+            assert_eq!(evaluated.inspect(), "2500050000");
+        }
+    }
+
+    #[test]
+    fn test_shared_collections() {
+        let tests = vec![
+            ("let a = [1]; let b = a; push(b, 2); a", "[1, 2]"),
+            (
+                "let a = []; let add = fn(xs) { push(xs, 1) }; add(a); add(a); a",
+                "[1, 1]",
+            ),
+            ("let h = {}; let g = h; g[\"k\"] = 1; h", "{k: 1}"),
+            ("let a = [[1]]; let b = a[0]; b[0] = 2; a", "[[2]]"),
+            ("push([1], 2)", "[1, 2]"),
+            ("let a = [1, 2]; [pop(a), a]", "[2, [1]]"),
+            ("pop([])", ""),
+            ("let a = [1, 2]; set(a, 0, 5); a", "[5, 2]"),
+            ("let h = {}; set(h, \"x\", 1)", "{x: 1}"),
+            ("let a = [1, 2, 3]; [delete(a, 1), a]", "[2, [1, 3]]"),
+            (
+                "let h = {\"a\": 1, \"b\": 2}; [delete(h, \"a\"), h]",
+                "[1, {b: 2}]",
+            ),
+            ("delete({}, \"a\")", ""),
+            (
+                "let a = [[1]]; let b = copy(a); push(b, 2); b[0][0] = 9; a",
+                "[[9]]",
+            ),
+            (
+                "let a = [[1]]; let b = deep_copy(a); b[0][0] = 9; a",
+                "[[1]]",
+            ),
+            (
+                "let h = {\"a\": [1]}; let g = deep_copy(h); push(g[\"a\"], 2); h",
+                "{a: [1]}",
+            ),
+            ("copy(5)", "5"),
+            ("let a = []; push(a, a); a", "[[...]]"),
+            ("let h = {}; h[\"self\"] = h; h", "{self: {...}}"),
+            // collections that contain themselves compare without recursing forever
+            (
+                "let a = []; push(a, a); let b = []; push(b, b); a == b",
+                "true",
+            ),
+            (
+                "let a = [1]; push(a, a); let b = [2]; push(b, b); a == b",
+                "false",
+            ),
+            (
+                "let g = {}; g[\"me\"] = g; let h = {}; h[\"me\"] = h; g == h",
+                "true",
+            ),
+            (
+                "let a = [1]; push(a, a); let b = deep_copy(a); push(b, 2); b[1]",
+                "[1, [...], 2]",
+            ),
+            ("pop(1)", "ERROR: argument to 'pop' must be ARRAY"),
+            ("set([1], 1, 2)", "ERROR: index out of range: 1 (length 1)"),
+            ("delete([1], 3)", "ERROR: index out of range: 3 (length 1)"),
+            (
+                "delete(\"s\", 0)",
+                "ERROR: argument to 'delete' must be ARRAY or HASH, got STRING",
+            ),
+            ("delete({}, [len])", "ERROR: unusable as hash key: ARRAY"),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                assert_eq!(evaluated.inspect(), expected, "{}", input);
+            }
+        }
+
+        // each push adds to the same array, so this takes linear time
+        let input = "let xs = []; for (i in 0..100000) { push(xs, i) }; [len(xs), xs[99999]]";
+        for evaluated in test_eval(input) {
+            assert_eq!(evaluated.inspect(), "[100000, 99999]");
+        }
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let tests = vec![
+            ("5 + true;", "1:1: type mismatch: INTEGER + BOOLEAN"),
+            (
+                "let a = 1;\nlet b = a + foobar;",
+                "2:13: identifier not found: foobar",
+            ),
+            (
+                "let f = fn(x) {\n  x + true\n};\nf(1)",
+                "2:3: type mismatch: INTEGER + BOOLEAN",
+            ),
+            ("len(1, 2)", "1:1: wrong number of arguments. got=2, want=1"),
+            // a tail call fails where it's written, not where it's made
+            (
+                "let g = fn() { 1 };\nlet f = fn() {\n  g(2)\n};\nf()",
+                "3:3: wrong number of arguments. got=1, want=0",
+            ),
+            (
+                "let g = fn(x) { x + true };\nlet f = fn() {\n  return g(2)\n};\nf()",
+                "1:17: type mismatch: INTEGER + BOOLEAN",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                match evaluated {
+                    Object::Error(err) => assert_eq!(err.to_string(), expected),
+                    _ => {
+                        println!("no error object returned");
+                        assert!(false);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_while_loops() {
+        let tests = vec![
+            ("let i = 0; while (i < 10) { let i = i + 1; } i", Some(10)),
+            ("let i = 0; while (i < 10) { let i = i + 1; }", None),
+            (
+                "let i = 0; while (true) { if (i == 3) { break; } let i = i + 1; } i",
+                Some(3),
+            ),
+            (
+                "let i = 0; let sum = 0;
+                while (i < 10) {
+                    let i = i + 1;
+                    if (i - (i / 2) * 2 == 0) { continue; }
+                    let sum = sum + i;
+                }
+                sum",
+                Some(25),
+            ),
+            (
+                "let i = 0; let n = 0;
+                while (i < 3) {
+                    let j = 0;
+                    while (true) { if (j == 2) { break; } let j = j + 1; let n = n + 1; }
+                    let i = i + 1;
+                }
+                n",
+                Some(6),
+            ),
+            (
+                "let f = fn(n) { let i = 0; while (true) { if (i == n) { return i * 2; } let i = i + 1; } };
+                f(4)",
+                Some(8),
+            ),
+            (
+                "let f = fn() { let i = 0; while (i < 2) { let i = i + 1; } }; f()",
+                None,
+            ),
+            ("let i = 0; while (i < 100000) { let i = i + 1; } i", Some(100000)),
+            // a local whose `let` never ran is null
+            ("fn() { while (false) { let q = 1; } q }()", None),
+        ];
+
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                match expected {
+                    Some(i) => test_integer_object(&evaluated, i),
+                    None => test_null_object(evaluated),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_while_errors() {
+        let input = "let i = 0;\nwhile (i < 3) {\n  let i = i + true;\n}";
+        for evaluated in test_eval(input) {
+            match evaluated {
+                Object::Error(err) => {
+                    assert_eq!(err.to_string(), "3:11: type mismatch: INTEGER + BOOLEAN")
+                }
+                other => panic!("expected an error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let tests = vec![
+            (
+                "let sum = 0; for (x in [1, 2, 3]) { sum += x; } sum",
+                Some(6),
+            ),
+            (
+                "let sum = 0; for (i, x in [10, 20]) { sum += i * x; } sum",
+                Some(20),
+            ),
+            ("let sum = 0; for (i in 0..5) { sum += i; } sum", Some(10)),
+            ("let sum = 0; for (i in 5..0) { sum += 1; } sum", Some(0)),
+            (
+                "let sum = 0; for (i, x in 3..6) { sum += i * x; } sum",
+                Some(14),
+            ),
+            ("let n = 0; for (c in \"héllo\") { n += 1; } n", Some(5)),
+            (
+                "let s = \"\"; for (i, c in \"abc\") { if (i != 1) { s = s + c; } } len(s)",
+                Some(2),
+            ),
+            (
+                "let h = {\"a\": 1, \"b\": 2, \"c\": 3}; let n = 0;
+                for (k in h) { n += h[k]; }
+                for (k, v in h) { n += v; }
+                n",
+                Some(12),
+            ),
+            (
+                "let sum = 0;
+                for (i in 0..10) {
+                    if (i == 6) { break; }
+                    if (i - (i / 2) * 2 == 0) { continue; }
+                    sum += i;
+                }
+                sum",
+                Some(9),
+            ),
+            (
+                "let n = 0; for (i in 0..3) { for (j in 0..i) { n += 1; } } n",
+                Some(3),
+            ),
+            (
+                "let find = fn(xs, y) { for (i, x in xs) { if (x == y) { return i; } } -1 };
+                find([5, 6, 7], 7) + find([5], 1)",
+                Some(1),
+            ),
+            ("let x = 1; for (x in [7]) { let y = x; } x", Some(1)),
+            ("for (x in [1, 2]) { x }", None),
+            ("let f = fn() { for (x in []) { x } }; f()", None),
+            (
+                "let n = 0; for (i in 0..100000) { n += 1; } n",
+                Some(100000),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                match expected {
+                    Some(i) => test_integer_object(&evaluated, i),
+                    None => test_null_object(evaluated),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_for_loop_scope() {
+        // every iteration binds fresh variables, so each closure keeps its own
+        let input = "let fs = [0];
+            for (i in 0..3) { let j = i * 10; fs = push(fs, fn() { i + j }); }
+            fs[1]() + fs[3]()";
+        for evaluated in test_eval(input) {
+            test_integer_object(&evaluated, 22);
+        }
+
+        let input = "let f = fn() {
+                let fs = [0];
+                for (x in [1, 2]) { fs = push(fs, fn() { x }); }
+                fs[1]() * 10 + fs[2]()
+            };
+            f()";
+        for evaluated in test_eval(input) {
+            test_integer_object(&evaluated, 12);
+        }
+
+        for evaluated in test_eval("for (x in [1]) { let y = x; } y") {
+            match evaluated {
+                Object::Error(err) => assert_eq!(err.message, "identifier not found: y"),
+                other => panic!("expected an error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ranges() {
+        let tests = vec![
+            ("0..3", vec![0, 1, 2]),
+            ("let n = 2; n - 1..n + 2", vec![1, 2, 3]),
+            ("3..3", vec![]),
+            ("3..1", vec![]),
+        ];
+
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                match evaluated {
+                    Object::Array(elements) => {
+                        let elements = elements.borrow();
+                        assert_eq!(elements.len(), expected.len(), "{}", input);
+                        for (element, i) in elements.iter().zip(&expected) {
+                            test_integer_object(element, *i);
+                        }
+                    }
+                    other => panic!("expected an array, got {:?}", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_for_errors() {
+        let tests = vec![
+            ("for (x in 5) { x }", "1:11: cannot iterate over INTEGER"),
+            (
+                "for (x in 0..true) { x }",
+                "1:11: type mismatch: INTEGER .. BOOLEAN",
+            ),
+            ("\"a\"..\"c\"", "1:1: unknown operator: STRING .. STRING"),
+            (
+                "0..100000000000000",
+                "1:1: range too large: 0..100000000000000",
+            ),
+            (
+                "map(0..100000000, fn(x) { x })",
+                "1:5: range too large: 0..100000000",
+            ),
+            (
+                "for (x in [1]) {\n  x + true;\n}",
+                "2:3: type mismatch: INTEGER + BOOLEAN",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                match evaluated {
+                    Object::Error(err) => assert_eq!(err.to_string(), expected, "{}", input),
+                    other => panic!("expected an error for {}, got {:?}", input, other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_assignment() {
+        let tests = vec![
+            ("let x = 1; x = 5; x", 5),
+            ("let x = 1; x = 5", 5),
+            ("let x = 1; let y = 2; x = y = 7; x + y", 14),
+            ("let x = 10; x += 5; x -= 3; x *= 2; x /= 4; x", 6),
+            ("let x = 1; let f = fn() { x = x + 1; }; f(); f(); x", 3),
+            ("let x = 1; let f = fn() { let x = 10; x = 20; }; f(); x", 1),
+            (
+                "let counter = fn() { let n = 0; fn() { n += 1 } };
+                let c = counter(); c(); c(); c()",
+                3,
+            ),
+            (
+                "let f = fn() { let n = 1; let g = fn() { n * 10 }; n = 2; g() }; f()",
+                20,
+            ),
+            (
+                "let f = fn() { let n = 0; let inc = fn() { fn() { n += 1 } }; inc()(); inc()(); n }; f()",
+                2,
+            ),
+            ("let i = 0; let sum = 0; while (i < 5) { i += 1; sum += i; } sum", 15),
+            ("let a = [1, 2, 3]; a[1] = 20; a[1] + a[2]", 23),
+            ("let a = [1, [2, 3]]; a[1][0] += 40; a[1][0]", 42),
+            ("let a = [1, 2]; let b = a; a[0] = 9; b[0]", 9),
+            ("let a = [1, 2]; let b = copy(a); a[0] = 9; b[0]", 1),
+            ("let h = {\"k\": 1}; h[\"k\"] = 2; h[\"new\"] = 3; h[\"k\"] + h[\"new\"]", 5),
+            ("let h = {\"a\": [1]}; h[\"a\"][0] *= 7; h[\"a\"][0]", 7),
+        ];
+
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                test_integer_object(&evaluated, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_assignment_errors() {
+        let tests = vec![
+            ("x = 1", "1:1: cannot assign to undefined variable: x"),
+            ("x += 1", "1:1: cannot assign to undefined variable: x"),
+            ("len = 1", "1:1: cannot assign to undefined variable: len"),
+            (
+                "let x = true; x += 1",
+                "1:15: type mismatch: BOOLEAN + INTEGER",
+            ),
+            (
+                "let a = [1, 2];\na[2] = 3",
+                "2:1: index out of range: 2 (length 2)",
+            ),
+            (
+                "let a = [1];\na[-1] = 3",
+                "2:1: index out of range: -1 (length 1)",
+            ),
+            (
+                "let a = [1]; a[\"x\"] = 3",
+                "1:14: array index must be INTEGER, got STRING",
+            ),
+            (
+                "let h = {}; h[[len]] = 3",
+                "1:13: unusable as hash key: ARRAY",
+            ),
+            (
+                "let s = \"abc\"; s[0] = 1",
+                "1:16: index assignment not supported: STRING",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                match evaluated {
+                    Object::Error(err) => assert_eq!(err.to_string(), expected, "{}", input),
+                    other => panic!("expected an error for {}, got {:?}", input, other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let tests = vec![
+            ("try { 1 } catch (e) { 2 }", "1"),
+            ("try { 1 / 0 } catch (e) { 2 }", "2"),
+            ("1 + try { throw \"x\" } catch (e) { 2 }", "3"),
+            (
+                "try { 1 / 0 } catch (e) { e }",
+                "{kind: DivisionByZero, message: division by zero: 1 / 0, line: 1, column: 7}",
+            ),
+            (
+                "try {\n  5 + true\n} catch (e) { [e[\"kind\"], e[\"line\"], e[\"column\"]] }",
+                "[TypeMismatch, 2, 3]",
+            ),
+            ("try { [1][\"a\"] } catch (e) { e[\"kind\"] }", "IndexNotSupported"),
+            ("try { 0..1000000000 } catch (e) { e[\"kind\"] }", "IntegerOverflow"),
+            ("try { 1[0] } catch (e) { e[\"message\"] }", "index operator not supported: INTEGER"),
+            ("try { x } catch (e) { \"caught\" }", "caught"),
+            ("try { len = 1 } catch (e) { e[\"kind\"] }", "UnknownIdentifier"),
+            (
+                "let f = fn() { g() }; \
+                 let r = try { f() } catch (e) { e[\"message\"] }; \
+                 let g = fn() { 1 }; r",
+                "identifier not found: g",
+            ),
+            (
+                "try { throw \"bad\" } catch (e) { e }",
+                "{kind: Error, message: bad, line: 1, column: 7}",
+            ),
+            ("try { throw [1, 2] } catch (e) { e[\"message\"] }", "[1, 2]"),
+            // a thrown hash is caught as it was
+            (
+                "let err = {\"kind\": \"Mine\", \"code\": 7}; \
+                 try { throw err } catch (e) { [e == err, e[\"code\"]] }",
+                "[true, 7]",
+            ),
+            (
+                "let f = fn(x) { if (x > 2) { throw \"too big\" } x }; \
+                 try { f(1) + f(3) } catch (e) { e[\"message\"] }",
+                "too big",
+            ),
+            (
+                "try { map([1, 0], fn(x) { 1 / x }) } catch (e) { e[\"kind\"] }",
+                "DivisionByZero",
+            ),
+            (
+                "each([1], fn(x) { try { throw \"in\" } catch (e) { x } })",
+                "",
+            ),
+            (
+                "try { try { throw \"inner\" } catch (e) { throw e[\"message\"] + \"!\" } } \
+                 catch (e) { e[\"message\"] }",
+                "inner!",
+            ),
+            (
+                "let depth = fn(n) { if (n == 0) { throw \"bottom\" } depth(n - 1) }; \
+                 try { depth(50) } catch (e) { e[\"message\"] }",
+                "bottom",
+            ),
+            // the catch variable belongs to the catch block
+            ("let e = 1; try { throw \"x\" } catch (e) { e }; e", "1"),
+            (
+                "let fs = []; \
+                 for (i in 0..3) { try { throw i } catch (e) { push(fs, fn() { e[\"message\"] }) } } \
+                 map(fs, fn(f) { f() })",
+                "[0, 1, 2]",
+            ),
+            ("try { throw \"x\" } catch (e) { throw e }", "ERROR: x"),
+            ("try { 1 } catch (e) { 1 / 0 }", "1"),
+            (
+                "try { 1 / 0 } catch (e) { [][0] + 1 }",
+                "ERROR: type mismatch: NULL + INTEGER",
+            ),
+            ("throw \"oops\"", "ERROR: oops"),
+            ("throw {\"message\": \"from a hash\"}", "ERROR: from a hash"),
+            ("throw 1 / 0", "ERROR: division by zero: 1 / 0"),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                assert_eq!(evaluated.inspect(), expected, "{}", input);
+            }
+        }
+
+        for evaluated in test_eval("let f = fn() { throw \"up\" };\nf()") {
+            match evaluated {
+                Object::Error(err) => {
+                    assert_eq!(err.code, ErrorCode::Thrown);
+                    assert_eq!(err.to_string(), "1:16: up");
+                }
+                other => panic!("expected an error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_finally() {
+        let tests =
+            vec![
+            ("let log = []; try { push(log, 1) } finally { push(log, 2) }; log", "[1, 2]"),
+            // the finally block doesn't change the value
+            ("let c = 0; let r = try { c = 5; c } finally { c = 6 }; [r, c]", "[5, 6]"),
+            (
+                "let log = []; try { 1 / 0 } catch (e) { push(log, \"catch\") } \
+                 finally { push(log, \"finally\") }; log",
+                "[catch, finally]",
+            ),
+            (
+                "let log = []; \
+                 let r = try { try { 1 / 0 } finally { push(log, \"inner\") } } \
+                 catch (e) { e[\"kind\"] }; [r, log]",
+                "[DivisionByZero, [inner]]",
+            ),
+            (
+                "let log = []; \
+                 try { try { throw \"a\" } catch (e) { throw \"b\" } finally { push(log, 1) } } \
+                 catch (e) { push(log, e[\"message\"]) }; log",
+                "[1, b]",
+            ),
+            (
+                "let log = []; let f = fn() { try { return 1 } finally { push(log, \"f\") } }; \
+                 [f(), log]",
+                "[1, [f]]",
+            ),
+            (
+                "let f = fn() { try { return 1 } finally { return 2 } }; f()",
+                "2",
+            ),
+            (
+                "let f = fn() { try { 1 / 0 } finally { return 3 } }; f()",
+                "3",
+            ),
+            (
+                "let log = []; let i = 0; \
+                 while (i < 5) { \
+                   i += 1; \
+                   try { if (i == 2) { continue } if (i == 4) { break } push(log, i) } \
+                   finally { push(log, \"f\") } \
+                 } log",
+                "[1, f, f, 3, f, f]",
+            ),
+            (
+                "let log = []; \
+                 let f = fn() { \
+                   for (x in [1, 2]) { \
+                     try { try { if (x == 2) { return x * 10 } } finally { push(log, \"in\") } } \
+                     finally { push(log, \"out\") } \
+                   } \
+                 }; [f(), log]",
+                "[20, [in, out, in, out]]",
+            ),
+            (
+                "let log = []; \
+                 for (x in [1, 2, 3]) { \
+                   try { if (x == 2) { throw x } push(log, x) } \
+                   catch (e) { continue } \
+                   finally { push(log, \"f\") } \
+                 } log",
+                "[1, f, f, 3, f]",
+            ),
+            (
+                "let log = []; try { throw \"lost\" } finally { push(log, 1) }",
+                "ERROR: lost",
+            ),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                assert_eq!(evaluated.inspect(), expected, "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_tail_calls() {
+        let tests = vec![
+            (
+                "let count = fn(n, acc) { if (n == 0) { acc } else { count(n - 1, acc + 1) } }; \
+                 count(1000000, 0)",
+                "1000000",
+            ),
+            (
+                "let down = fn(n) { if (n > 0) { return down(n - 1); } \"done\" }; down(100000)",
+                "done",
+            ),
+            (
+                "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } }; \
+                 let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } }; \
+                 odd(100001)",
+                "true",
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { \"zero\" } \
+                 else { if (n > 0) { f(n - 1) } else { f(-n) } } }; f(-100000)",
+                "zero",
+            ),
+            // builtins and calls that aren't last still return to their caller
+            (
+                "let sum = fn(n) { if (n == 0) { 0 } else { n + sum(n - 1) } }; sum(100)",
+                "5050",
+            ),
+            ("let f = fn(xs) { len(xs) }; f([1, 2, 3])", "3"),
+            ("let f = fn() { 1 }; let g = fn() { f(); 2 }; g()", "2"),
+            // a tail call in a try body still runs inside it
+            (
+                "let fail = fn() { throw \"no\" }; \
+                 let f = fn() { try { return fail() } catch (e) { e[\"message\"] } }; f()",
+                "no",
+            ),
+            (
+                "let log = []; let g = fn() { push(log, \"g\"); 1 }; \
+                 let f = fn() { try { g() } finally { push(log, \"finally\") } }; [f(), log]",
+                "[1, [g, finally]]",
+            ),
+            (
+                "let f = fn(n) { if (n == 0) { 1 / 0 } else { f(n - 1) } }; f(10)",
+                "ERROR: division by zero: 1 / 0",
+            ),
+        ];
+        for (input, expected) in tests {
+            for evaluated in test_eval(input) {
+                assert_eq!(evaluated.inspect(), expected, "{}", input);
             }
         }
     }
@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use crate::{collect_garbage, gc_stats, Backend, Interpreter, Value};
+
+    const BACKENDS: [Backend; 2] = [Backend::Evaluator, Backend::Vm];
+
+    // Defines `make`, which leaves behind a function kept in an array it
+    // closes over each time it's called.
+    const MAKE: &str = "let make = fn() { let fs = []; push(fs, fn() { fs }); len(fs) };";
+
+    #[test]
+    fn test_collect_cycles() {
+        let tests = vec![
+            (
+                "let f = fn() { let xs = []; push(xs, xs); len(xs) }; f(); gc()",
+                1,
+            ),
+            (
+                "let f = fn() { let h = {}; h[\"me\"] = h; 0 }; f(); gc()",
+                1,
+            ),
+            (
+                "let f = fn() { let a = [0]; let b = [a]; a[0] = b; 0 }; f(); f(); gc()",
+                4,
+            ),
+            ("let xs = []; push(xs, xs); gc()", 0),
+            ("gc()", 0),
+        ];
+        for backend in &BACKENDS {
+            for (input, expected) in &tests {
+                let mut interpreter = Interpreter::with_backend(*backend);
+                collect_garbage();
+                assert_eq!(
+                    interpreter.eval(input),
+                    Ok(Value::Integer(*expected)),
+                    "{}: {}",
+                    backend,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_collect_closures() {
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            interpreter.eval(MAKE).unwrap();
+            collect_garbage();
+            let live = gc_stats().live;
+            interpreter.eval("for (i in 0..10) { make() }").unwrap();
+            // the array and the environment or cell holding it each time, and
+            // the VM's closure
+            let expected = match backend {
+                Backend::Evaluator => 20,
+                Backend::Vm => 30,
+            };
+            assert_eq!(collect_garbage(), expected, "{}", backend);
+            assert_eq!(gc_stats().live, live, "{}", backend);
+            assert_eq!(collect_garbage(), 0, "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_collect_recursive_functions() {
+        let input = "let make = fn() { \
+                       let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }; f(3) \
+                     }; make(); make(); gc()";
+        // the evaluator binds `f` in the environment it closes over, while the
+        // VM's closures call themselves without capturing anything
+        for (backend, expected) in &[(Backend::Evaluator, 2), (Backend::Vm, 0)] {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            collect_garbage();
+            assert_eq!(
+                interpreter.eval(input),
+                Ok(Value::Integer(*expected)),
+                "{}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_values_in_use_survive() {
+        let tests = vec![
+            ("let xs = []; push(xs, xs); gc(); len(xs[0][0])", 1),
+            (
+                "let counter = fn() { let c = 0; fn() { c += 1; c } }; \
+                 let next = counter(); next(); gc(); next()",
+                2,
+            ),
+            (
+                "let f = fn(n) { let g = fn(m) { if (m == 0) { n } else { g(m - 1) } }; g }; \
+                 let g = f(7); gc(); g(3)",
+                7,
+            ),
+            // collecting while the program is inside a cycle
+            (
+                "let f = fn(n) { let xs = [n]; push(xs, xs); gc(); xs[1][1][0] }; f(4) + f(5)",
+                9,
+            ),
+            (
+                "let xs = [1, 2, 3]; let h = {\"xs\": xs}; h[\"h\"] = h; \
+                 map(xs, fn(x) { gc(); h[\"h\"][\"xs\"][x - 1] })[2]",
+                3,
+            ),
+        ];
+        for backend in &BACKENDS {
+            for (input, expected) in &tests {
+                let mut interpreter = Interpreter::with_backend(*backend);
+                assert_eq!(
+                    interpreter.eval(input),
+                    Ok(Value::Integer(*expected)),
+                    "{}: {}",
+                    backend,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_automatic_collection() {
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            interpreter.eval(MAKE).unwrap();
+            let before = gc_stats();
+            interpreter.eval("for (i in 0..20000) { make() }").unwrap();
+            let after = gc_stats();
+            assert!(after.collections > before.collections, "{}", backend);
+            assert!(after.live < 20000, "{}: {:?}", backend, after);
+        }
+    }
+
+    #[test]
+    fn test_dropped_interpreter() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .eval("let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } }")
+            .unwrap();
+        collect_garbage();
+        drop(interpreter);
+        // the global environment, which `f` closes over
+        assert_eq!(collect_garbage(), 1);
+    }
+
+    #[test]
+    fn test_gc_errors() {
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            assert!(interpreter.eval("gc(1)").is_err(), "{}", backend);
+        }
+    }
+}
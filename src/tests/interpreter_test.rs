@@ -0,0 +1,209 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::{ErrorCode, Style};
+    use crate::token::Span;
+    use crate::{Backend, IntegerOverflow, Interpreter, MonkeyError, Sink, Value};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const BACKENDS: [Backend; 2] = [Backend::Evaluator, Backend::Vm];
+
+    #[test]
+    fn test_eval_values() {
+        let tests = vec![
+            ("5", Value::Integer(5)),
+            ("\"5\"", Value::String("5".to_string())),
+            ("1 < 2", Value::Boolean(true)),
+            ("if (false) { 1 }", Value::Null),
+            (
+                "[1, \"two\", [true]]",
+                Value::Array(vec![
+                    Value::Integer(1),
+                    Value::String("two".to_string()),
+                    Value::Array(vec![Value::Boolean(true)]),
+                ]),
+            ),
+            (
+                "{\"a\": 1}",
+                Value::Hash(vec![(Value::String("a".to_string()), Value::Integer(1))]),
+            ),
+        ];
+
+        for backend in &BACKENDS {
+            for (input, expected) in &tests {
+                let mut interpreter = Interpreter::with_backend(*backend);
+                assert_eq!(interpreter.eval(input), Ok(expected.clone()), "{}", backend);
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_functions() {
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            match interpreter.eval("fn(x, y) { x + y }") {
+                Ok(Value::Function(function)) => {
+                    assert_eq!(function.parameters(), vec!["x", "y"]);
+                    assert!(!function.is_builtin());
+                    assert_eq!(function.to_string(), "fn(x, y) {\n(x + y)\n}");
+                }
+                other => panic!("expected a function, got {:?}", other),
+            }
+            match interpreter.eval("len") {
+                Ok(Value::Function(function)) => assert!(function.is_builtin()),
+                other => panic!("expected a builtin, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bindings_persist() {
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            assert_eq!(interpreter.eval("let a = 2;"), Ok(Value::Integer(2)));
+            assert!(interpreter.eval("let f = fn(x) { x * a };").is_ok());
+            assert_eq!(interpreter.eval("f(21)"), Ok(Value::Integer(42)));
+        }
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+
+            match interpreter.eval("let a = 1 @ 2;") {
+                Err(MonkeyError::Lex(errors)) => {
+                    assert_eq!(errors.len(), 1);
+                    assert_eq!(errors[0].code, ErrorCode::IllegalCharacter);
+                    assert_eq!(errors[0].to_string(), "1:11: illegal character '@'");
+                }
+                other => panic!("expected a lex error, got {:?}", other),
+            }
+
+            match interpreter.eval("let a = ;") {
+                Err(MonkeyError::Parse(errors)) => {
+                    assert_eq!(errors[0].code, ErrorCode::ExpectedExpression);
+                    assert_eq!(errors[0].span, Span::new(8, 9, 1, 9));
+                }
+                other => panic!("expected a parse error, got {:?}", other),
+            }
+
+            match interpreter.eval("let a = 1;\na + true") {
+                Err(err @ MonkeyError::Runtime(_)) => {
+                    assert_eq!(err.span(), Some(Span::new(11, 19, 2, 1)));
+                    assert_eq!(err.to_string(), "2:1: type mismatch: INTEGER + BOOLEAN");
+                }
+                other => panic!("expected a runtime error, got {:?}", other),
+            }
+
+            match interpreter.eval("missing") {
+                Err(MonkeyError::Runtime(err)) => {
+                    assert_eq!(err.code, ErrorCode::UnknownIdentifier)
+                }
+                other => panic!("expected a runtime error, got {:?}", other),
+            }
+
+            // a Value can't hold a collection that contains itself
+            match interpreter.eval("let xs = []; push(xs, xs); xs") {
+                Err(MonkeyError::Conversion(err)) => assert_eq!(
+                    err.to_string(),
+                    "expected a value that doesn't contain itself, got ARRAY that does"
+                ),
+                other => panic!("expected a conversion error, got {:?}", other),
+            }
+
+            match interpreter.eval("try { 1 } finally { throw \"oops\" }") {
+                Err(err @ MonkeyError::Runtime(_)) => {
+                    assert_eq!(err.to_string(), "1:21: oops");
+                    let rendered =
+                        err.render("main", "try { 1 } finally { throw \"oops\" }", Style::Plain);
+                    assert!(rendered.starts_with("error[E0023]: oops"), "{}", rendered);
+                }
+                other => panic!("expected a runtime error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_integer_promotion() {
+        let tests = vec![
+            ("9223372036854775807 + 1", "9223372036854775808"),
+            ("-9223372036854775807 - 2", "-9223372036854775809"),
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(25)",
+                "15511210043330985984000000",
+            ),
+            ("(9223372036854775807 + 1) / 2", "4611686018427387904"),
+            ("9223372036854775807 * 10 / 10 - 1", "9223372036854775806"),
+            ("(9223372036854775807 + 1) > 9223372036854775807", "true"),
+            ("-(-9223372036854775807 - 1)", "9223372036854775808"),
+            ("let x = 9223372036854775807 * 4; x == x", "true"),
+            ("(9223372036854775807 + 1) * 0.5", "4.611686018427388e18"),
+            (
+                "(9223372036854775807 + 1) / 0",
+                "division by zero: 9223372036854775808 / 0",
+            ),
+        ];
+
+        for backend in &BACKENDS {
+            for (input, expected) in &tests {
+                let mut interpreter = Interpreter::with_backend(*backend);
+                interpreter.set_integer_overflow(IntegerOverflow::Promote);
+                let output = match interpreter.eval(input) {
+                    Ok(value) => value.to_string(),
+                    Err(MonkeyError::Runtime(err)) => err.message,
+                    Err(err) => panic!("unexpected error for {}: {}", input, err),
+                };
+                assert_eq!(&output, expected, "{} on {}", input, backend);
+            }
+        }
+
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            interpreter.set_integer_overflow(IntegerOverflow::Promote);
+            let value = interpreter.eval("9223372036854775807 + 1 - 1").unwrap();
+            assert_eq!(value, Value::Integer(i64::MAX), "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_output_sinks() {
+        let input = "let greet = fn(name) { puts(\"hi\", name) }; greet(\"bo\"); \
+                     each([1, 2], fn(x) { print(x) }); eprint(\"oops\", [3]); print(); 7";
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            let (output, errors) = (Sink::buffer(), Sink::buffer());
+            interpreter.set_output(output.clone());
+            interpreter.set_error_output(errors.clone());
+            // writing doesn't change the program's value
+            assert_eq!(
+                interpreter.eval(input),
+                Ok(Value::Integer(7)),
+                "{}",
+                backend
+            );
+            assert_eq!(output.contents().unwrap(), "hi bo\n12", "{}", backend);
+            assert_eq!(errors.contents().unwrap(), "oops [3]\n", "{}", backend);
+            assert_eq!(interpreter.eval("puts(1)"), Ok(Value::Null), "{}", backend);
+        }
+
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            let written = Rc::new(RefCell::new(vec![]));
+            let log = written.clone();
+            interpreter.set_output(Sink::callback(move |text| {
+                log.borrow_mut().push(text.to_string())
+            }));
+            interpreter.eval("puts(1); puts(2)").unwrap();
+            assert_eq!(*written.borrow(), vec!["1\n", "2\n"], "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_value_display() {
+        let mut interpreter = Interpreter::new();
+        let value = interpreter.eval("[1, \"a\", true, {2: 3}]").unwrap();
+        assert_eq!(value.to_string(), "[1, a, true, {2: 3}]");
+        assert_eq!(value.type_name(), "ARRAY");
+    }
+}
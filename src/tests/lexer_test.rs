@@ -1,7 +1,8 @@
 #[cfg(test)]
 mod tests {
+    use crate::diagnostics::ErrorCode;
     use crate::lexer::Lexer;
-    use crate::token::Token;
+    use crate::token::{Span, Token};
 
     #[test]
     fn test_next_token() {
@@ -125,9 +126,145 @@ if (5 < 10) {
         let mut l = Lexer::new(input);
 
         for test in tests {
-            let tok = l.next_token();
+            let tok = l.next_token().map(|(token, _)| token);
             //println!("expected {:?}, lexed {:?} ", test, tok);
             assert_eq!(tok, test);
         }
     }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "let x = 10;\n  \"héllo\" == y;";
+        let tests = vec![
+            (Token::Let, Span::new(0, 3, 1, 1)),
+            (Token::Ident("x".to_string()), Span::new(4, 5, 1, 5)),
+            (Token::Assign, Span::new(6, 7, 1, 7)),
+            (Token::Int("10".to_string()), Span::new(8, 10, 1, 9)),
+            (Token::Semicolon, Span::new(10, 11, 1, 11)),
+            (Token::String("héllo".to_string()), Span::new(14, 22, 2, 3)),
+            (Token::Eq, Span::new(23, 25, 2, 11)),
+            (Token::Ident("y".to_string()), Span::new(26, 27, 2, 14)),
+            (Token::Semicolon, Span::new(27, 28, 2, 15)),
+        ];
+        let mut l = Lexer::new(input);
+
+        for (token, span) in tests {
+            assert_eq!(l.next_token(), Some((token, span)));
+        }
+        assert_eq!(l.next_token(), None);
+        assert_eq!(l.position(), Span::new(28, 28, 2, 16));
+    }
+
+    #[test]
+    fn test_lexer_errors() {
+        let input = "let a = 1 # 2;\nlet b = \"open";
+        let mut l = Lexer::new(input);
+        while l.next_token().is_some() {}
+
+        let errors: Vec<String> = l.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "1:11: illegal character '#'",
+                "2:9: unterminated string literal"
+            ]
+        );
+        assert_eq!(l.errors()[0].code, ErrorCode::IllegalCharacter);
+        assert_eq!(l.errors()[1].code, ErrorCode::UnterminatedString);
+    }
+
+    #[test]
+    fn test_assignment_operators() {
+        let input = "x = 1; x += 2 -= 3 *= 4 /= 5 %= 6; x+1 % 2";
+        let tests = vec![
+            Token::Ident("x".to_string()),
+            Token::Assign,
+            Token::Int("1".to_string()),
+            Token::Semicolon,
+            Token::Ident("x".to_string()),
+            Token::PlusAssign,
+            Token::Int("2".to_string()),
+            Token::MinusAssign,
+            Token::Int("3".to_string()),
+            Token::AsteriskAssign,
+            Token::Int("4".to_string()),
+            Token::SlashAssign,
+            Token::Int("5".to_string()),
+            Token::PercentAssign,
+            Token::Int("6".to_string()),
+            Token::Semicolon,
+            Token::Ident("x".to_string()),
+            Token::Plus,
+            Token::Int("1".to_string()),
+            Token::Percent,
+            Token::Int("2".to_string()),
+        ];
+        let mut l = Lexer::new(input);
+
+        for test in tests {
+            assert_eq!(l.next_token().map(|(token, _)| token), Some(test));
+        }
+        assert_eq!(l.next_token(), None);
+    }
+
+    #[test]
+    fn test_float_literals() {
+        let input = "3.14 1e-9 2.5E+3 7e 1..2 4.";
+        let tests = vec![
+            Token::Float("3.14".to_string()),
+            Token::Float("1e-9".to_string()),
+            Token::Float("2.5E+3".to_string()),
+            Token::Int("7".to_string()),
+            Token::Ident("e".to_string()),
+            Token::Int("1".to_string()),
+            Token::DotDot,
+            Token::Int("2".to_string()),
+            Token::Int("4".to_string()),
+            Token::Illegal,
+        ];
+        let mut l = Lexer::new(input);
+
+        for test in tests {
+            assert_eq!(l.next_token().map(|(token, _)| token), Some(test));
+        }
+        assert_eq!(l.next_token(), None);
+    }
+
+    #[test]
+    fn test_for_in_range() {
+        let input = "for (i in 0..n) {}";
+        let tests = vec![
+            Token::For,
+            Token::LParen,
+            Token::Ident("i".to_string()),
+            Token::In,
+            Token::Int("0".to_string()),
+            Token::DotDot,
+            Token::Ident("n".to_string()),
+            Token::RParen,
+            Token::LBrace,
+            Token::RBrace,
+        ];
+        let mut l = Lexer::new(input);
+
+        for test in tests {
+            assert_eq!(l.next_token().map(|(token, _)| token), Some(test));
+        }
+        assert_eq!(l.next_token(), None);
+
+        let mut l = Lexer::new("a.b");
+        l.next_token();
+        assert_eq!(l.next_token().map(|(token, _)| token), Some(Token::Illegal));
+        assert_eq!(l.errors()[0].message, "illegal character '.'");
+    }
+
+    #[test]
+    fn test_underscore_identifiers() {
+        let mut l = Lexer::new("deep_copy _x a_");
+        for name in &["deep_copy", "_x", "a_"] {
+            let token = l.next_token().map(|(token, _)| token);
+            assert_eq!(token, Some(Token::Ident(name.to_string())));
+        }
+        assert_eq!(l.next_token(), None);
+    }
 }
@@ -0,0 +1,257 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::ErrorCode;
+    use crate::{Backend, Interpreter, Limits, MonkeyError, Sink, Value};
+    use std::thread;
+    use std::time::Duration;
+
+    const BACKENDS: [Backend; 2] = [Backend::Evaluator, Backend::Vm];
+
+    fn limited(backend: Backend, limits: Limits) -> Interpreter {
+        let mut interpreter = Interpreter::with_backend(backend);
+        interpreter.set_limits(limits);
+        interpreter
+    }
+
+    fn error_code(result: Result<Value, MonkeyError>) -> ErrorCode {
+        match result {
+            Err(MonkeyError::Runtime(err)) => err.code,
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fuel() {
+        let limits = Limits {
+            fuel: Some(10_000),
+            ..Limits::default()
+        };
+        for backend in &BACKENDS {
+            let mut interpreter = limited(*backend, limits);
+            let output = Sink::buffer();
+            interpreter.set_output(output.clone());
+            assert_eq!(
+                error_code(interpreter.eval("while (true) {}")),
+                ErrorCode::OutOfFuel,
+                "{}",
+                backend
+            );
+            assert_eq!(
+                error_code(interpreter.eval("for (i in 0..100000) {}")),
+                ErrorCode::OutOfFuel,
+                "{}",
+                backend
+            );
+            // each program gets the full amount
+            assert_eq!(
+                interpreter.eval("let total = 0; for (i in 0..100) { total += i } total"),
+                Ok(Value::Integer(4950)),
+                "{}",
+                backend
+            );
+            // and can't get away by catching the error
+            let input = "try { while (true) {} } catch (e) { 1 } finally { puts(\"done\") }";
+            assert_eq!(
+                error_code(interpreter.eval(input)),
+                ErrorCode::OutOfFuel,
+                "{}",
+                backend
+            );
+            assert_eq!(output.contents().unwrap(), "", "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_call_depth() {
+        let limits = Limits {
+            max_call_depth: Some(50),
+            ..Limits::default()
+        };
+        let countdown = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+        for backend in &BACKENDS {
+            let mut interpreter = limited(*backend, limits);
+            interpreter.eval(countdown).unwrap();
+            assert_eq!(interpreter.eval("f(49)"), Ok(Value::Integer(49)));
+            match interpreter.eval("f(50)") {
+                Err(MonkeyError::Runtime(err)) => {
+                    assert_eq!(err.code, ErrorCode::StackOverflow);
+                    assert_eq!(err.message, "stack overflow: more than 50 nested calls");
+                }
+                other => panic!("{}: expected a runtime error, got {:?}", backend, other),
+            }
+            // calls made by builtins count too
+            assert_eq!(
+                error_code(interpreter.eval("let g = fn(n) { map([n], g) }; g(1)")),
+                ErrorCode::StackOverflow,
+                "{}",
+                backend
+            );
+            // unlike the other limits, a program may recover from this one
+            assert_eq!(
+                interpreter.eval("try { f(100) } catch (e) { e[\"kind\"] }"),
+                Ok(Value::String("StackOverflow".to_string())),
+                "{}",
+                backend
+            );
+            assert_eq!(interpreter.eval("f(10)"), Ok(Value::Integer(10)));
+        }
+    }
+
+    #[test]
+    fn test_default_call_depth() {
+        // each backend's default allows deep calls in a stack the size of the
+        // main thread's, and stops runaway ones with an error
+        let countdown = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } };";
+        let runaway = thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(move || {
+                BACKENDS
+                    .iter()
+                    .map(|backend| {
+                        let mut interpreter = Interpreter::with_backend(*backend);
+                        interpreter.eval(countdown).unwrap();
+                        let deep = interpreter.eval("f(500)") == Ok(Value::Integer(500));
+                        (deep, error_code(interpreter.eval("f(1000000)")))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap();
+        assert_eq!(
+            runaway.join().unwrap(),
+            vec![
+                (true, ErrorCode::StackOverflow),
+                (true, ErrorCode::StackOverflow)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_call_depth_past_the_stack() {
+        // however deep the host lets calls nest, running out of the thread's
+        // stack is an error, whether the evaluator nests Rust calls for Monkey
+        // ones or a builtin calls back into Monkey
+        let limits = Limits {
+            max_call_depth: Some(usize::MAX),
+            ..Limits::default()
+        };
+        let programs = [
+            "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(1000000)",
+            "let g = fn(n) { map([n], fn(x) { g(x - 1) })[0] }; g(1000000)",
+        ];
+        let runaway = thread::Builder::new()
+            .stack_size(1024 * 1024)
+            .spawn(move || {
+                let mut codes = vec![];
+                for backend in &BACKENDS {
+                    for program in &programs {
+                        let mut interpreter = limited(*backend, limits);
+                        let result = interpreter.eval(program);
+                        match &result {
+                            Err(MonkeyError::Runtime(err)) => {
+                                assert!(err.message.starts_with("stack overflow"), "{}", err)
+                            }
+                            other => panic!("expected a runtime error, got {:?}", other),
+                        }
+                        codes.push(error_code(result));
+                    }
+                }
+                codes
+            })
+            .unwrap();
+        assert_eq!(runaway.join().unwrap(), vec![ErrorCode::StackOverflow; 4]);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let limits = Limits {
+            timeout: Some(Duration::from_millis(20)),
+            ..Limits::default()
+        };
+        for backend in &BACKENDS {
+            let mut interpreter = limited(*backend, limits);
+            match interpreter
+                .eval("let f = fn() { f() }; while (true) { try { f() } catch (e) {} }")
+            {
+                Err(MonkeyError::Runtime(err)) => {
+                    assert_eq!(err.code, ErrorCode::Timeout, "{}", backend);
+                    assert_eq!(err.message, "time limit of 20ms exceeded");
+                }
+                other => panic!("{}: expected a runtime error, got {:?}", backend, other),
+            }
+            assert_eq!(interpreter.eval("1 + 1"), Ok(Value::Integer(2)));
+        }
+    }
+
+    #[test]
+    fn test_memory() {
+        let limits = Limits {
+            max_memory: Some(1 << 20),
+            ..Limits::default()
+        };
+        let runaway = vec![
+            "let s = \"ab\"; while (true) { s = s + s }",
+            "let s = \"ab\"; while (true) { s += s }",
+            "let xs = []; while (true) { push(xs, \"abc\") }",
+            "let xs = 0..100; let kept = []; while (true) { push(kept, rest(xs)) }",
+            "let xs = [[1, 2], {\"a\": 3}]; while (true) { xs = [deep_copy(xs), copy(xs)] }",
+            "let xs = 0..1000; while (true) { xs = map(xs, fn(x) { x }) }",
+            "0..1000000000000",
+            "let h = {}; let i = 0; while (true) { h[i] = i; i += 1 }",
+            "let h = {}; let i = 0; while (true) { set(h, i, i); i += 1 }",
+            "let h = {\"h\": {}}; let i = 0; while (true) { h[\"h\"][i] = true; i += 1 }",
+            "try { let s = \"ab\"; while (true) { s = s + s } } catch (e) { 0 }",
+        ];
+        for backend in &BACKENDS {
+            let mut interpreter = limited(*backend, limits);
+            for input in &runaway {
+                match interpreter.eval(input) {
+                    Err(MonkeyError::Runtime(err)) => {
+                        assert_eq!(err.code, ErrorCode::OutOfMemory, "{}: {}", backend, input);
+                        assert_eq!(
+                            err.message,
+                            "memory limit exceeded: more than 1048576 bytes allocated"
+                        );
+                    }
+                    other => panic!("{}: {}: expected an error, got {:?}", backend, input, other),
+                }
+            }
+            // each program starts from nothing
+            assert_eq!(
+                interpreter.eval("let xs = []; for (i in 0..100) { push(xs, i) } len(xs)"),
+                Ok(Value::Integer(100)),
+                "{}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_cancel() {
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            let cancel = interpreter.cancel_handle();
+            let canceller = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                cancel.cancel();
+            });
+            assert_eq!(
+                error_code(interpreter.eval("let i = 0; while (true) { i += 1 }")),
+                ErrorCode::Cancelled,
+                "{}",
+                backend
+            );
+            canceller.join().unwrap();
+            // the cancellation only stops the run it interrupted
+            assert_eq!(interpreter.eval("i > 0"), Ok(Value::Boolean(true)));
+
+            interpreter.cancel_handle().cancel();
+            assert_eq!(
+                error_code(interpreter.eval("1")),
+                ErrorCode::Cancelled,
+                "{}",
+                backend
+            );
+            assert_eq!(interpreter.eval("1"), Ok(Value::Integer(1)));
+        }
+    }
+}
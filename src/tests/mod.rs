@@ -1,5 +1,26 @@
+// The tests report failures with `println!` followed by `assert!(false)`.
+#![allow(
+    clippy::assertions_on_constants,
+    clippy::bool_assert_comparison,
+    clippy::explicit_counter_loop,
+    clippy::vec_init_then_push
+)]
+
 mod ast_test;
+mod bigint_test;
+mod code_test;
+mod compiler_test;
+mod convert_test;
+mod diagnostics_test;
 mod evaluator_test;
+mod gc_test;
+mod interpreter_test;
 mod lexer_test;
+mod limits_test;
+mod module_test;
+mod native_test;
 mod object_test;
 mod parser_test;
+mod resolver_test;
+mod serialization_test;
+mod vm_test;
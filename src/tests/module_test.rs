@@ -0,0 +1,313 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::Style;
+    use crate::native::{Args, NativeResult};
+    use crate::object::Object;
+    use crate::{Backend, Interpreter, MonkeyError, Sink, Value};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    const BACKENDS: [Backend; 2] = [Backend::Evaluator, Backend::Vm];
+
+    // Writes `files` into a fresh directory for one test and returns it.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("monkey_modules_{}_{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, source) in files {
+            let file = dir.join(name);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, source).unwrap();
+        }
+        dir
+    }
+
+    fn script_interpreter(backend: Backend, dir: &Path) -> Interpreter {
+        let mut interpreter = Interpreter::with_backend(backend);
+        interpreter.set_script_path(dir.join("main.monkey"));
+        interpreter
+    }
+
+    fn runtime_error(result: Result<Value, MonkeyError>) -> String {
+        match result {
+            Err(MonkeyError::Runtime(err)) => err.message,
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_import_namespaces() {
+        let dir = write_files(
+            "namespaces",
+            &[(
+                "math.monkey",
+                "let base = 10;\n\
+                 let scale = fn(x) { x * base };\n\
+                 let square = fn(x) { x * x };",
+            )],
+        );
+        let tests = vec![
+            ("import \"math\"; math[\"square\"](4)", Value::Integer(16)),
+            (
+                "import \"math.monkey\"; math[\"scale\"](3)",
+                Value::Integer(30),
+            ),
+            ("import \"math\" as m; m[\"base\"]", Value::Integer(10)),
+            (
+                "import \"math\" as m; let names = []; \
+                 for (name, _ in m) { push(names, name) } names",
+                Value::Array(vec![
+                    Value::String("base".to_string()),
+                    Value::String("scale".to_string()),
+                    Value::String("square".to_string()),
+                ]),
+            ),
+            (
+                "import \"math\"; import \"math\" as m; math == m",
+                Value::Boolean(true),
+            ),
+        ];
+
+        for backend in &BACKENDS {
+            for (input, expected) in &tests {
+                let mut interpreter = script_interpreter(*backend, &dir);
+                assert_eq!(
+                    interpreter.eval(input),
+                    Ok(expected.clone()),
+                    "{}: {}",
+                    backend,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_modules_run_once() {
+        let dir = write_files(
+            "run_once",
+            &[
+                ("lib/counter.monkey", "import \"log\"; let count = 1;"),
+                ("lib/log.monkey", "puts(\"loading log\"); let level = 2;"),
+            ],
+        );
+        let input = "import \"lib/counter\"; import \"lib/log\"; \
+                     import \"lib/counter\" as again; counter[\"count\"] + log[\"level\"]";
+
+        for backend in &BACKENDS {
+            let mut interpreter = script_interpreter(*backend, &dir);
+            let output = Sink::buffer();
+            interpreter.set_output(output.clone());
+            assert_eq!(
+                interpreter.eval(input),
+                Ok(Value::Integer(3)),
+                "{}",
+                backend
+            );
+            // and once per interpreter, not per program
+            interpreter
+                .eval("import \"lib/log\" as l; l[\"level\"]")
+                .unwrap();
+            assert_eq!(output.contents().unwrap(), "loading log\n", "{}", backend);
+        }
+    }
+
+    #[test]
+    fn test_search_path() {
+        let dir = write_files(
+            "search_path",
+            &[
+                ("vendor/strings.monkey", "let shout = fn(s) { s + \"!\" };"),
+                ("strings.monkey", "let shout = fn(s) { s };"),
+            ],
+        );
+
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            interpreter.add_search_dir(dir.join("vendor"));
+            assert_eq!(
+                interpreter.eval("import \"strings\"; strings[\"shout\"](\"hi\")"),
+                Ok(Value::String("hi!".to_string())),
+                "{}",
+                backend
+            );
+
+            // without a script, the working directory comes first
+            let cwd = Path::new("target").join(format!("monkey_cwd_{}", std::process::id()));
+            fs::create_dir_all(&cwd).unwrap();
+            fs::write(cwd.join("here.monkey"), "let n = 7;").unwrap();
+            let input = format!("import \"{}/here\"; here[\"n\"]", cwd.display());
+            assert_eq!(
+                interpreter.eval(&input),
+                Ok(Value::Integer(7)),
+                "{}",
+                backend
+            );
+            let _ = fs::remove_dir_all(&cwd);
+
+            // the script's directory comes before the search path
+            let mut interpreter = script_interpreter(*backend, &dir);
+            interpreter.add_search_dir(dir.join("vendor"));
+            assert_eq!(
+                interpreter.eval("import \"strings\"; strings[\"shout\"](\"hi\")"),
+                Ok(Value::String("hi".to_string())),
+                "{}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_module_scope() {
+        let dir = write_files(
+            "scope",
+            &[
+                ("secret.monkey", "let hidden = 1; let value = double(21);"),
+                ("peek.monkey", "let seen = outside;"),
+            ],
+        );
+
+        for backend in &BACKENDS {
+            let mut interpreter = script_interpreter(*backend, &dir);
+            interpreter.register_fn("double", 1, |args: &Args| -> NativeResult {
+                Ok(Object::Integer(args.integer(0)? * 2))
+            });
+            assert_eq!(
+                interpreter.eval("import \"secret\"; secret[\"value\"]"),
+                Ok(Value::Integer(42)),
+                "{}",
+                backend
+            );
+            // a module's bindings stay in its namespace...
+            assert_eq!(
+                runtime_error(interpreter.eval("hidden")),
+                "identifier not found: hidden",
+                "{}",
+                backend
+            );
+            // ...and it can't see the importer's
+            let message = runtime_error(interpreter.eval("let outside = 1; import \"peek\"; 1"));
+            assert_eq!(
+                message, "in module \"peek\": identifier not found: outside",
+                "{}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors_in_module_functions() {
+        let dir = write_files(
+            "function_errors",
+            &[("lib.monkey", "let f = fn(x) {\n  x + true\n};")],
+        );
+        let input = "import \"lib\";\nlet y = 2;\nlet z = 3;\nlib[\"f\"](1)";
+
+        for backend in &BACKENDS {
+            let mut interpreter = script_interpreter(*backend, &dir);
+            match interpreter.eval(input) {
+                Err(err) => assert_eq!(
+                    err.to_string(),
+                    "lib.monkey:2:3: type mismatch: INTEGER + BOOLEAN",
+                    "{}",
+                    backend
+                ),
+                other => panic!("expected an error, got {:?}", other),
+            }
+            // shown in the module's source, not the importer's
+            let rendered = interpreter.interpret("main.monkey", input, Style::Plain);
+            assert!(rendered.contains("--> lib.monkey:2:3"), "{}", rendered);
+            assert!(rendered.contains("2 |   x + true"), "{}", rendered);
+            assert!(!rendered.contains("main.monkey"), "{}", rendered);
+        }
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let dir = write_files(
+            "errors",
+            &[
+                ("a.monkey", "import \"b\"; let x = 1;"),
+                ("b.monkey", "import \"a\"; let y = 2;"),
+                ("broken.monkey", "let x = 1;\nlet = 2;"),
+                ("early.monkey", "let x = 1; return x;"),
+                ("bad.monkey", "let ok = 1; let oops = 1 / 0;"),
+                ("unbound.monkey", "let z = 1;\nlet w = nope;"),
+            ],
+        );
+        let tests = vec![
+            (
+                "import \"a\"",
+                "in module \"a\": in module \"b\": \
+                 circular import: a.monkey -> b.monkey -> a.monkey",
+            ),
+            ("import \"nope\"", "module not found: \"nope\""),
+            (
+                "import \"broken\"",
+                "broken.monkey:2:5: \
+                 expected next token to be IDENT, got = instead",
+            ),
+            (
+                "import \"early\"",
+                "early.monkey:1:12: \
+                 'return' outside of a function in a module",
+            ),
+            (
+                "import \"bad\"",
+                "in module \"bad\": division by zero: 1 / 0",
+            ),
+        ];
+        // an error in the module's code is reported where it is there
+        for backend in &BACKENDS {
+            let mut interpreter = script_interpreter(*backend, &dir);
+            match interpreter.eval("let a = 1;\nimport \"bad\"") {
+                Err(err) => assert_eq!(
+                    err.to_string(),
+                    "bad.monkey:1:24: in module \"bad\": division by zero: 1 / 0",
+                    "{}",
+                    backend
+                ),
+                other => panic!("expected an error, got {:?}", other),
+            }
+        }
+
+        // as are errors found before the module runs, even by the compiler
+        let located = vec![
+            (
+                "let a = 1;\nimport \"a\"",
+                "b.monkey:1:1: in module \"a\": in module \"b\": \
+                 circular import: a.monkey -> b.monkey -> a.monkey",
+                "--> b.monkey:1:1",
+            ),
+            (
+                "let a = 1;\nimport \"unbound\"",
+                "unbound.monkey:2:9: in module \"unbound\": identifier not found: nope",
+                "--> unbound.monkey:2:9",
+            ),
+        ];
+        for backend in &BACKENDS {
+            for (input, expected, rendered_at) in &located {
+                let mut interpreter = script_interpreter(*backend, &dir);
+                match interpreter.eval(input) {
+                    Err(err) => assert_eq!(err.to_string(), *expected, "{}", backend),
+                    other => panic!("expected an error, got {:?}", other),
+                }
+                let rendered = interpreter.interpret("main.monkey", input, Style::Plain);
+                assert!(rendered.contains(rendered_at), "{}: {}", backend, rendered);
+            }
+        }
+
+        for backend in &BACKENDS {
+            for (input, expected) in &tests {
+                let mut interpreter = script_interpreter(*backend, &dir);
+                assert_eq!(
+                    runtime_error(interpreter.eval(input)),
+                    *expected,
+                    "{}: {}",
+                    backend,
+                    input
+                );
+            }
+        }
+    }
+}
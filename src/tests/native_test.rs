@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::ErrorCode;
+    use crate::object::Object;
+    use crate::{Backend, Interpreter, MonkeyError, Value};
+
+    fn interpreter(backend: Backend) -> Interpreter {
+        let mut interpreter = Interpreter::with_backend(backend);
+        interpreter.register_fn("repeat", 2, |args| {
+            let s = args.string(0)?;
+            let n = args.integer(1)?;
+            if n < 0 {
+                return Err(args.error("count must not be negative"));
+            }
+            Ok(Object::String(s.repeat(n as usize)))
+        });
+        interpreter.register_fn("sum", 0.., |args| {
+            let mut total = 0;
+            for i in 0..args.len() {
+                total += args.integer(i)?;
+            }
+            Ok(Object::Integer(total))
+        });
+        interpreter.register_fn("pick", 1..=2, |args| match args.len() {
+            1 => Ok(args.as_slice()[0].clone()),
+            _ => Ok(args.array(1)?[args.integer(0)? as usize].clone()),
+        });
+        interpreter
+    }
+
+    fn runtime_error(result: Result<Value, MonkeyError>) -> (ErrorCode, String) {
+        match result {
+            Err(MonkeyError::Runtime(err)) => (err.code, err.to_string()),
+            other => panic!("expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_native_calls() {
+        let tests = vec![
+            ("repeat(\"ab\", 3)", Value::String("ababab".to_string())),
+            ("sum()", Value::Integer(0)),
+            ("sum(1, 2, 3)", Value::Integer(6)),
+            ("pick(7)", Value::Integer(7)),
+            ("pick(1, [10, 20])", Value::Integer(20)),
+            ("let f = fn(x) { sum(x, x) }; f(4)", Value::Integer(8)),
+            ("let s = sum; s(1, 1)", Value::Integer(2)),
+            ("let sum = fn(a) { a }; sum(5)", Value::Integer(5)),
+        ];
+
+        for backend in &[Backend::Evaluator, Backend::Vm] {
+            for (input, expected) in &tests {
+                let result = interpreter(*backend).eval(input);
+                assert_eq!(result, Ok(expected.clone()), "{}: {}", backend, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_native_errors() {
+        let tests = vec![
+            (
+                "repeat(\"ab\")",
+                ErrorCode::ArgumentCount,
+                "1:1: wrong number of arguments. got=1, want=2",
+            ),
+            (
+                "pick(1, 2, 3)",
+                ErrorCode::ArgumentCount,
+                "1:1: wrong number of arguments. got=3, want=1..=2",
+            ),
+            (
+                "let n = 2;\nrepeat(n, \"ab\")",
+                ErrorCode::ArgumentType,
+                "2:1: argument 1 to 'repeat' must be STRING, got INTEGER",
+            ),
+            (
+                "sum(1, true)",
+                ErrorCode::ArgumentType,
+                "1:1: argument 2 to 'sum' must be INTEGER, got BOOLEAN",
+            ),
+            (
+                "repeat(\"ab\", -1)",
+                ErrorCode::ArgumentType,
+                "1:1: repeat: count must not be negative",
+            ),
+        ];
+
+        for backend in &[Backend::Evaluator, Backend::Vm] {
+            for (input, code, message) in &tests {
+                let result = interpreter(*backend).eval(input);
+                assert_eq!(runtime_error(result), (*code, message.to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_natives_are_per_interpreter() {
+        let mut plain = Interpreter::new();
+        let (code, _) = runtime_error(plain.eval("sum(1)"));
+        assert_eq!(code, ErrorCode::UnknownIdentifier);
+    }
+}
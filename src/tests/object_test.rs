@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
     use crate::object;
-    use crate::object::{Object, ObjectType};
+    use crate::object::{HashKey, HashPair, HashTable, Object};
     //use crate::token::Token;
 
     #[test]
@@ -60,4 +60,65 @@ mod tests {
             assert!(false);
         }
     }
+
+    #[test]
+    fn test_compound_hash_key() {
+        let array = Object::array(vec![Object::Integer(1), Object::String("a".to_string())]);
+        assert_eq!(
+            object::create_hash_key(array),
+            Some(HashKey::Array(vec![
+                HashKey::Integer(1),
+                HashKey::String("a".to_string())
+            ]))
+        );
+        let unhashable = Object::array(vec![Object::Float(1.5)]);
+        assert_eq!(object::create_hash_key(unhashable), None);
+    }
+
+    #[test]
+    fn test_hash_table_order() {
+        let pair = |key: &str, value: i64| {
+            let key = Object::String(key.to_string());
+            let hash_key = object::create_hash_key(key.clone()).unwrap();
+            let value = Object::Integer(value);
+            (hash_key, HashPair { key, value })
+        };
+        let mut table = HashTable::new();
+        for (key, value) in [("c", 1), ("a", 2), ("b", 3)].iter() {
+            let (hash_key, pair) = pair(key, *value);
+            assert_eq!(table.insert(hash_key, pair), None);
+        }
+        let (hash_key, replacement) = pair("a", 4);
+        assert_eq!(
+            table.insert(hash_key, replacement).unwrap().value,
+            Object::Integer(2)
+        );
+        let (c, _) = pair("c", 0);
+        assert_eq!(table.remove(&c).unwrap().value, Object::Integer(1));
+        assert_eq!(table.remove(&c), None);
+
+        assert_eq!(Object::hash(table.clone()).inspect(), "{a: 4, b: 3}");
+        let (b, _) = pair("b", 0);
+        assert_eq!(table.get(&b).unwrap().value, Object::Integer(3));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_heap_size() {
+        let element = std::mem::size_of::<Object>();
+        let string = Object::String("abc".to_string());
+        assert_eq!(string.heap_size(), 3);
+        assert_eq!(Object::Integer(1).heap_size(), 0);
+
+        let inner = Object::array(vec![string.clone(), string]);
+        let outer = Object::array(vec![inner.clone(), inner.clone()]);
+        assert_eq!(outer.heap_size(), 2 * element);
+        // the shared array is only counted once
+        assert_eq!(outer.deep_heap_size(), 4 * element + 6);
+
+        if let Object::Array(elements) = &inner {
+            elements.borrow_mut().push(outer.clone());
+        }
+        assert_eq!(outer.deep_heap_size(), 5 * element + 6);
+    }
 }
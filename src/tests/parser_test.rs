@@ -1,9 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use crate::ast;
     use crate::ast::{Expression, Statement};
     use crate::lexer::Lexer;
     use crate::parser::Parser;
+    use crate::token::Span;
     //use crate::token::Token;
 
     fn check_parser_errors(parser: &Parser) {
@@ -26,7 +26,7 @@ mod tests {
         let statement = &program.statements[0];
         test_let_statement(statement, expected_identifier);
         match statement {
-            Statement::Let(_ident, expr) => {
+            Statement::Let(_ident, _, expr, _) => {
                 assert_eq!(test_integer_literal(expr, expected_value), true);
             }
             _ => {
@@ -36,6 +36,7 @@ mod tests {
         }
     }
 
+    #[test]
     fn test_let_statements_bool() {
         let (input, expected_identifier, expected_value) = ("let y = true;", "y", true);
 
@@ -48,7 +49,7 @@ mod tests {
         let statement = &program.statements[0];
         test_let_statement(statement, expected_identifier);
         match statement {
-            Statement::Let(_ident, expr) => {
+            Statement::Let(_ident, _, expr, _) => {
                 assert_eq!(test_bool_literal(expr, expected_value), true);
             }
             _ => {
@@ -58,6 +59,7 @@ mod tests {
         }
     }
 
+    #[test]
     fn test_let_statements_string() {
         let (input, expected_identifier, expected_value) = ("let foobar = y;", "foobar", "y");
 
@@ -70,7 +72,7 @@ mod tests {
         let statement = &program.statements[0];
         test_let_statement(statement, expected_identifier);
         match statement {
-            Statement::Let(_ident, expr) => {
+            Statement::Let(_ident, _, expr, _) => {
                 assert_eq!(test_identifier(expr, expected_value), true);
             }
             _ => {
@@ -82,7 +84,7 @@ mod tests {
 
     fn test_let_statement(s: &Statement, name: &str) {
         match s {
-            Statement::Let(ident, _) => {
+            Statement::Let(ident, _, _, _) => {
                 assert_eq!(ident, name);
             }
             _ => {
@@ -103,7 +105,7 @@ mod tests {
         assert_eq!(1, program.statements.len());
         for s in program.statements {
             match s {
-                Statement::Return(expr) => {
+                Statement::Return(expr, _) => {
                     test_identifier(&expr, expected_value);
                 }
                 _ => {
@@ -127,8 +129,8 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::Ident(ident) if ident == "foobar" => (),
+                Statement::Expression(expr, _) => match expr {
+                    Expression::Ident(ident, _, _) if ident == "foobar" => (),
                     _ => {
                         println!("Expected ident, got something else.");
                         assert!(false);
@@ -155,8 +157,8 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::IntegerLiteral(int) if int == 5 => (),
+                Statement::Expression(expr, _) => match expr {
+                    Expression::IntegerLiteral(5, _) => (),
                     _ => {
                         println!("Expected 5, got something else.");
                         assert!(false);
@@ -170,6 +172,53 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_float_literal_expression() {
+        let tests = vec![
+            ("2.75", 2.75, "2.75"),
+            ("1e-9", 1e-9, "1e-9"),
+            ("2.0", 2.0, "2.0"),
+            ("-0.5", 0.5, "(-0.5)"),
+        ];
+
+        for (input, expected, expected_string) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+            let expr = match &program.statements[0] {
+                Statement::Expression(Expression::Prefix(_, right, _), _) => &**right,
+                Statement::Expression(expr, _) => expr,
+                other => panic!("expected expression statement, got {:?}", other),
+            };
+            assert_eq!(expr, &Expression::FloatLiteral(expected, expr.span()));
+            assert_eq!(program.to_string(), expected_string);
+        }
+    }
+
+    #[test]
+    fn test_big_integer_literal_expression() {
+        let tests = vec![
+            "9223372036854775808",
+            "123456789012345678901234567890",
+            "1000000000000000000000000000000000000",
+        ];
+
+        for input in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+            match &program.statements[0] {
+                Statement::Expression(Expression::BigIntLiteral(int, _), _) => {
+                    assert_eq!(int.to_string(), input)
+                }
+                other => panic!("expected big integer literal, got {:?}", other),
+            }
+            assert_eq!(program.to_string(), input);
+        }
+    }
+
     #[test]
     fn test_boolean_expression() {
         let input = "false;";
@@ -183,8 +232,8 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::Boolean(b) if b == false => (),
+                Statement::Expression(expr, _) => match expr {
+                    Expression::Boolean(b, _) if !b => (),
                     _ => {
                         println!("Expected false, got something else.");
                         assert!(false);
@@ -211,15 +260,15 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::IfExpression(condition, consequence, alt) => {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::IfExpression(condition, consequence, alt, _) => {
                         test_infix_expression(&condition.to_string(), "x", "<", "y");
 
                         assert_eq!(consequence.statements.len(), 1);
                         let s = &consequence.statements[0];
                         //if let Some(c) = consequence.statements.iter().next() {
                         match s {
-                            Statement::Expression(ex) => {
+                            Statement::Expression(ex, _) => {
                                 if !test_identifier(ex, "x") {
                                     println!("identifier was not x");
                                     assert!(false);
@@ -231,7 +280,7 @@ mod tests {
                             }
                         }
 
-                        if let Some(_) = alt {
+                        if alt.is_some() {
                             println!("alt sttements was not None");
                             assert!(false);
                         }
@@ -262,15 +311,15 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::IfExpression(condition, consequence, alt) => {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::IfExpression(condition, consequence, alt, _) => {
                         test_infix_expression(&condition.to_string(), "x", "<", "y");
 
                         assert_eq!(consequence.statements.len(), 1);
                         let s = &consequence.statements[0];
                         //if let Some(c) = consequence.statements.iter().next() {
                         match s {
-                            Statement::Expression(ex) => {
+                            Statement::Expression(ex, _) => {
                                 if !test_identifier(ex, "x") {
                                     println!("identifier was not x");
                                     assert!(false);
@@ -286,7 +335,7 @@ mod tests {
                             assert_eq!(alternate.statements.len(), 1);
                             let s = &alternate.statements[0];
                             match s {
-                                Statement::Expression(ex) => {
+                                Statement::Expression(ex, _) => {
                                     if !test_identifier(ex, "y") {
                                         println!("identifier was not x");
                                         assert!(false);
@@ -328,15 +377,15 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::FunctionLiteral(parms, body) => {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::FunctionLiteral(parms, body, _) => {
                         assert_eq!(parms.len(), 2);
                         assert_eq!(parms[0], "x");
                         assert_eq!(parms[1], "y");
                         assert_eq!(body.statements.len(), 1);
                         let s = &body.statements[0];
                         match s {
-                            Statement::Expression(ex) => {
+                            Statement::Expression(ex, _) => {
                                 test_infix_expression(&ex.to_string(), "x", "+", "y");
                             }
                             _ => {
@@ -374,8 +423,8 @@ mod tests {
 
             if let Some(statement) = program.statements.into_iter().next() {
                 match statement {
-                    Statement::Expression(expr) => match expr {
-                        Expression::FunctionLiteral(parms, body) => {
+                    Statement::Expression(expr, _) => match expr {
+                        Expression::FunctionLiteral(parms, _body, _) => {
                             assert_eq!(parms.len(), expected_parms.len());
                             let mut i = 0;
                             for p in expected_parms {
@@ -408,9 +457,9 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::CallExpression(function, args) => {
-                        assert_eq!(test_identifier(&*function, "add"), true);
+                Statement::Expression(expr, _) => match expr {
+                    Expression::CallExpression(function, args, _) => {
+                        assert_eq!(test_identifier(&function, "add"), true);
                         assert_eq!(args.len(), 3);
                         assert_eq!(test_integer_literal(&args[0], 1), true);
                         test_infix_expression_int(&args[1].to_string(), 2, "*", 3);
@@ -442,14 +491,14 @@ mod tests {
 
             if let Some(statement) = program.statements.into_iter().next() {
                 match statement {
-                    Statement::Expression(expr) => match expr {
-                        Expression::Prefix(operator, right) => {
+                    Statement::Expression(expr, _) => match expr {
+                        Expression::Prefix(operator, right, _) => {
                             if operator != test_operator {
                                 println!("operator is not {}, got {}", test_operator, operator);
                                 assert!(false);
                             }
 
-                            if !test_integer_literal(&*right, test_int) {
+                            if !test_integer_literal(&right, test_int) {
                                 println!("right not equal to integer test");
                                 assert!(false);
                             }
@@ -481,14 +530,14 @@ mod tests {
 
             if let Some(statement) = program.statements.into_iter().next() {
                 match statement {
-                    Statement::Expression(expr) => match expr {
-                        Expression::Prefix(operator, right) => {
+                    Statement::Expression(expr, _) => match expr {
+                        Expression::Prefix(operator, right, _) => {
                             if operator != test_operator {
                                 println!("operator is not {}, got {}", test_operator, operator);
                                 assert!(false);
                             }
 
-                            if !test_bool_literal(&*right, test_bool) {
+                            if !test_bool_literal(&right, test_bool) {
                                 println!("right not equal to integer test");
                                 assert!(false);
                             }
@@ -509,8 +558,8 @@ mod tests {
 
     fn test_integer_literal(expression: &Expression, value: i64) -> bool {
         match expression {
-            Expression::IntegerLiteral(i) if i == &value => true,
-            Expression::IntegerLiteral(i) => {
+            Expression::IntegerLiteral(i, _) if i == &value => true,
+            Expression::IntegerLiteral(i, _) => {
                 println!("integer value not {}, got {} ", value, i);
                 false
             }
@@ -523,7 +572,7 @@ mod tests {
 
     fn test_bool_literal(expression: &Expression, value: bool) -> bool {
         match expression {
-            Expression::Boolean(b) if b == &value => true,
+            Expression::Boolean(b, _) if b == &value => true,
             _ => {
                 println!("bool literal not correct");
                 false
@@ -533,12 +582,12 @@ mod tests {
 
     fn test_identifier(expression: &Expression, value: &str) -> bool {
         match expression {
-            Expression::Ident(s) if s == value => true,
-            Expression::Ident(s) => {
+            Expression::Ident(s, _, _) if s == value => true,
+            Expression::Ident(s, _, _) => {
                 println!("identifier value not {}, got {}", value, s);
                 false
             }
-            Expression::IntegerLiteral(i) => {
+            Expression::IntegerLiteral(i, _) => {
                 println!("got an integer here for some reason: {}", i);
                 false
             }
@@ -563,9 +612,9 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::Infix(left, operator, right) => {
-                        if !test_integer_literal(&*left, test_left) {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::Infix(left, operator, right, _) => {
+                        if !test_integer_literal(&left, test_left) {
                             println!("left not equal to integer test");
                             assert!(false);
                         }
@@ -575,7 +624,7 @@ mod tests {
                             assert!(false);
                         }
 
-                        if !test_integer_literal(&*right, test_right) {
+                        if !test_integer_literal(&right, test_right) {
                             println!("right not equal to integer test");
                             assert!(false);
                         }
@@ -607,9 +656,9 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::Infix(left, operator, right) => {
-                        if !test_identifier(&*left, test_left) {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::Infix(left, operator, right, _) => {
+                        if !test_identifier(&left, test_left) {
                             println!("left not equal to identifier test");
                             assert!(false);
                         }
@@ -619,7 +668,7 @@ mod tests {
                             assert!(false);
                         }
 
-                        if !test_identifier(&*right, test_right) {
+                        if !test_identifier(&right, test_right) {
                             println!("right not equal to identifier test");
                             assert!(false);
                         }
@@ -670,9 +719,9 @@ mod tests {
 
             if let Some(statement) = program.statements.into_iter().next() {
                 match statement {
-                    Statement::Expression(expr) => match expr {
-                        Expression::Infix(left, operator, right) => {
-                            if !test_bool_literal(&*left, test_left) {
+                    Statement::Expression(expr, _) => match expr {
+                        Expression::Infix(left, operator, right, _) => {
+                            if !test_bool_literal(&left, test_left) {
                                 println!("left not equal to bool test");
                                 assert!(false);
                             }
@@ -682,7 +731,7 @@ mod tests {
                                 assert!(false);
                             }
 
-                            if !test_bool_literal(&*right, test_right) {
+                            if !test_bool_literal(&right, test_right) {
                                 println!("right not equal to bool test");
                                 assert!(false);
                             }
@@ -711,6 +760,7 @@ mod tests {
             ("a * b * c", "((a * b) * c)"),
             ("a * b / c", "((a * b) / c)"),
             ("a + b / c", "(a + (b / c))"),
+            ("a + b % c * d", "(a + ((b % c) * d))"),
             ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
             ("3 + 4; -5 * 5", "(3 + 4)((-5) * 5)"),
             ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
@@ -751,6 +801,14 @@ mod tests {
             ("return 2 * 4 + 5;", "return ((2 * 4) + 5);"),
             ("let x = 3", "let x = 3;"),
             ("let x = 3 + f * 8;", "let x = (3 + (f * 8));"),
+            ("x = 1 + 2", "(x = (1 + 2))"),
+            ("a = b = c", "(a = (b = c))"),
+            ("x += y * 2", "(x += (y * 2))"),
+            ("x %= 3", "(x %= 3)"),
+            ("a[i + 1][\"k\"] -= 1", "(((a[(i + 1)])[k]) -= 1)"),
+            ("0..n + 1", "(0 .. (n + 1))"),
+            ("a < 0..n == b", "((a < (0 .. n)) == b)"),
+            ("x = y == 1", "(x = (y == 1))"),
         ];
         for (input, expected) in tests {
             let lexer = Lexer::new(input);
@@ -774,14 +832,14 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::StringLiteral(s) => {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::StringLiteral(s, _) => {
                         if s != "hello world" {
                             println!("literal value not hello world, got={}", s);
                             assert!(false);
                         }
                     }
-                    Expression::Ident(i) => {
+                    Expression::Ident(_i, _, _) => {
                         dbg!("got ident and wanted string!");
                         assert!(false);
                     }
@@ -809,8 +867,8 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::ArrayLiteral(elements) => {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::ArrayLiteral(elements, _) => {
                         assert_eq!(elements.len(), 3);
                         test_integer_literal(&elements[0], 1);
                         test_infix_expression_int(&elements[1].to_string(), 2, "*", 2);
@@ -840,14 +898,14 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::IndexExpression(left, index) => {
-                        if !test_identifier(&*left, "myArray") {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::IndexExpression(left, index, _) => {
+                        if !test_identifier(&left, "myArray") {
                             println!("left != myArray");
                             assert!(false);
                         }
 
-                        test_infix_expression_int(&*index.to_string(), 1, "+", 1);
+                        test_infix_expression_int(&index.to_string(), 1, "+", 1);
                     }
                     _ => {
                         println!("Expected index expression, got something else.");
@@ -873,28 +931,28 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::HashLiteral(pairs) => {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::HashLiteral(pairs, _) => {
                         assert_eq!(pairs.len(), 3);
                         let expected = vec![
                             (
-                                Expression::StringLiteral("one".to_string()),
-                                Expression::IntegerLiteral(1),
+                                Expression::StringLiteral("one".to_string(), Span::default()),
+                                Expression::IntegerLiteral(1, Span::default()),
                             ),
                             (
-                                Expression::StringLiteral("two".to_string()),
-                                Expression::IntegerLiteral(2),
+                                Expression::StringLiteral("two".to_string(), Span::default()),
+                                Expression::IntegerLiteral(2, Span::default()),
                             ),
                             (
-                                Expression::StringLiteral("three".to_string()),
-                                Expression::IntegerLiteral(3),
+                                Expression::StringLiteral("three".to_string(), Span::default()),
+                                Expression::IntegerLiteral(3, Span::default()),
                             ),
                         ];
                         for (k, v) in pairs {
                             match &k {
-                                Expression::StringLiteral(_literal) => {
+                                Expression::StringLiteral(_literal, _) => {
                                     match hash_get(&expected, &k) {
-                                        Expression::IntegerLiteral(i) => {
+                                        Some(Expression::IntegerLiteral(i, _)) => {
                                             test_integer_literal(&v, i);
                                         }
                                         _ => {
@@ -923,13 +981,13 @@ mod tests {
         }
     }
 
-    fn hash_get(map: &Vec<(Expression, Expression)>, key: &Expression) -> Expression {
+    fn hash_get(map: &Vec<(Expression, Expression)>, key: &Expression) -> Option<Expression> {
         for (k, v) in map {
-            if k == key {
-                return v.clone();
+            if k.to_string() == key.to_string() {
+                return Some(v.clone());
             }
         }
-        Expression::None
+        None
     }
 
     #[test]
@@ -943,8 +1001,8 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::HashLiteral(pairs) => assert_eq!(pairs.len(), 0),
+                Statement::Expression(expr, _) => match expr {
+                    Expression::HashLiteral(pairs, _) => assert_eq!(pairs.len(), 0),
                     _ => {
                         println!("Expected hash literal, got something else.");
                         assert!(false);
@@ -969,19 +1027,28 @@ mod tests {
 
         if let Some(statement) = program.statements.into_iter().next() {
             match statement {
-                Statement::Expression(expr) => match expr {
-                    Expression::HashLiteral(pairs) => {
+                Statement::Expression(expr, _) => match expr {
+                    Expression::HashLiteral(pairs, _) => {
                         assert_eq!(pairs.len(), 3);
-                        let expected = vec![
-                            (Expression::StringLiteral("one".to_string()), (0, "+", 1)),
-                            (Expression::StringLiteral("two".to_string()), (10, "-", 8)),
-                            (Expression::StringLiteral("three".to_string()), (15, "/", 5)),
+                        let expected = [
+                            (
+                                Expression::StringLiteral("one".to_string(), Span::default()),
+                                (0, "+", 1),
+                            ),
+                            (
+                                Expression::StringLiteral("two".to_string(), Span::default()),
+                                (10, "-", 8),
+                            ),
+                            (
+                                Expression::StringLiteral("three".to_string(), Span::default()),
+                                (15, "/", 5),
+                            ),
                         ];
                         let mut i = 0;
                         for (k, v) in pairs {
                             let (_, e) = expected[i];
                             match &k {
-                                Expression::StringLiteral(_literal) => {
+                                Expression::StringLiteral(_literal, _) => {
                                     test_infix_expression_int(&v.to_string(), e.0, e.1, e.2);
                                 }
                                 _ => {
@@ -1004,4 +1071,386 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_node_spans() {
+        let input = "let x = 5;\nadd(x, 10 * 2);";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        check_parser_errors(&parser);
+        assert_eq!(program.statements.len(), 2);
+
+        let let_span = program.statements[0].span();
+        assert_eq!(let_span, Span::new(0, 10, 1, 1));
+
+        match &program.statements[1] {
+            Statement::Expression(Expression::CallExpression(_, args, span), _) => {
+                assert_eq!(*span, Span::new(11, 25, 2, 1));
+                assert_eq!(&input[span.start..span.end], "add(x, 10 * 2)");
+                let arg = args[1].span();
+                assert_eq!(arg, Span::new(18, 24, 2, 8));
+                assert_eq!(&input[arg.start..arg.end], "10 * 2");
+            }
+            _ => {
+                println!("Expected call expression, got something else.");
+                assert!(false);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parser_error_positions() {
+        let tests = vec![
+            (
+                "let x 5;",
+                "1:7: expected next token to be =, got 5 instead",
+            ),
+            (
+                "let x = 1;\nif (x { x }",
+                "2:7: expected next token to be ), got { instead",
+            ),
+            (
+                "add(1, 2",
+                "1:9: expected next token to be ), got end of input instead",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            parser.parse_program();
+            assert!(!parser.errors().is_empty());
+            assert_eq!(parser.errors()[0].to_string(), expected);
+        }
+    }
+
+    fn parse_errors(input: &str) -> (usize, Vec<String>) {
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        let errors = parser.errors().iter().map(|e| e.to_string()).collect();
+        (program.statements.len(), errors)
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let tests = vec![
+            (
+                "let a = (1;\nlet b = 2;",
+                1,
+                vec!["1:11: expected next token to be ), got ; instead"],
+            ),
+            (
+                "let = 5;\nlet x 5;\nlet y = 10;",
+                1,
+                vec![
+                    "1:5: expected next token to be IDENT, got = instead",
+                    "2:7: expected next token to be =, got 5 instead",
+                ],
+            ),
+            (
+                "if (x { x }\nlet y = 1;",
+                1,
+                vec!["1:7: expected next token to be ), got { instead"],
+            ),
+            (
+                "let f = fn(x) {\n  let y = x +;\n  y\n};\nf(1)",
+                2,
+                vec!["2:14: no prefix parse function for ; found"],
+            ),
+            (
+                "let f = fn(x) { x + };\nlet g = fn(1) { 2 };",
+                1,
+                vec![
+                    "1:21: no prefix parse function for } found",
+                    "2:12: expected next token to be IDENT, got 1 instead",
+                ],
+            ),
+            (
+                "add(1, 2; let x = [1, 2;\nx",
+                1,
+                vec![
+                    "1:9: expected next token to be ), got ; instead",
+                    "1:24: expected next token to be ], got ; instead",
+                ],
+            ),
+            (
+                "{1: 2 3}; 5",
+                1,
+                vec!["1:7: expected next token to be ,, got 3 instead"],
+            ),
+            (
+                "fn(x) { x",
+                0,
+                vec!["1:10: expected }, got end of input instead"],
+            ),
+            (
+                "let x = ",
+                0,
+                vec!["1:9: expected an expression, got end of input instead"],
+            ),
+        ];
+
+        for (input, statements, expected) in tests {
+            let (len, errors) = parse_errors(input);
+            assert_eq!(errors, expected, "input: {}", input);
+            assert_eq!(len, statements, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_while_statement() {
+        let input = "while (x < 10) { if (x == 5) { break; } continue }";
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        check_parser_errors(&parser);
+        assert_eq!(program.statements.len(), 1);
+        match &program.statements[0] {
+            Statement::While(condition, body, span) => {
+                assert_eq!(condition.to_string(), "(x < 10)");
+                assert_eq!(body.statements.len(), 2);
+                assert!(matches!(body.statements[1], Statement::Continue(_)));
+                assert_eq!(span.end, input.len());
+            }
+            other => panic!("expected while statement, got {:?}", other),
+        }
+        assert_eq!(
+            program.to_string(),
+            "while(x < 10) if(x == 5) break;continue;"
+        );
+
+        // like other statements, it may end with a semicolon
+        let input = "while (x < 10) { x = x + 1 }; x";
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        check_parser_errors(&parser);
+        assert_eq!(program.statements.len(), 2);
+        match &program.statements[0] {
+            Statement::While(_, _, span) => assert_eq!(span.end, input.find(';').unwrap() + 1),
+            other => panic!("expected while statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_for_statement() {
+        let tests = vec![
+            ("for (x in xs) { x }", vec!["x"], "xs", "for(x in xs) x"),
+            (
+                "for (k, v in h) { continue; }",
+                vec!["k", "v"],
+                "h",
+                "for(k, v in h) continue;",
+            ),
+            (
+                "for (i in 0..n) {}",
+                vec!["i"],
+                "(0 .. n)",
+                "for(i in (0 .. n)) ",
+            ),
+            // like other statements, it may end with a semicolon
+            ("for (x in xs) { x };", vec!["x"], "xs", "for(x in xs) x"),
+        ];
+
+        for (input, expected_names, expected_iterable, expected_string) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+            assert_eq!(program.statements.len(), 1);
+            match &program.statements[0] {
+                Statement::For(names, iterable, _, span) => {
+                    assert_eq!(names, &expected_names);
+                    assert_eq!(iterable.to_string(), expected_iterable);
+                    assert_eq!(span.end, input.len());
+                }
+                other => panic!("expected for statement, got {:?}", other),
+            }
+            assert_eq!(program.to_string(), expected_string);
+        }
+
+        let (_, errors) = parse_errors("for (x, y, z in h) {}");
+        assert_eq!(
+            errors.first().map(String::as_str),
+            Some("1:10: expected next token to be in, got , instead")
+        );
+    }
+
+    #[test]
+    fn test_loop_control_outside_loop() {
+        let tests = vec![
+            ("break;", vec!["1:1: 'break' outside of a loop"]),
+            (
+                "if (true) { continue; }",
+                vec!["1:13: 'continue' outside of a loop"],
+            ),
+            (
+                "while (true) { let f = fn() { break; }; f() }",
+                vec!["1:31: 'break' outside of a loop"],
+            ),
+            (
+                "while (true) { while (false) { continue; } break; }",
+                vec![],
+            ),
+            ("for (x in xs) { break; }", vec![]),
+            (
+                "for (x in xs) { fn() { continue; } }",
+                vec!["1:24: 'continue' outside of a loop"],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let (_, errors) = parse_errors(input);
+            assert_eq!(errors, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_invalid_assignment_targets() {
+        let tests = vec![
+            ("1 = 2", "1:1: invalid assignment target: 1"),
+            ("f() = 2", "1:1: invalid assignment target: f()"),
+            ("a == b = c", "1:1: invalid assignment target: (a == b)"),
+            ("f()[0] += 1", "1:1: invalid assignment target: (f()[0])"),
+        ];
+
+        for (input, expected) in tests {
+            let (_, errors) = parse_errors(input);
+            assert_eq!(errors, vec![expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_import_statements() {
+        let tests = vec![
+            (
+                "import \"math\";",
+                "math",
+                "math",
+                "import \"math\" as math;",
+            ),
+            (
+                "import \"lib/str_utils.monkey\"",
+                "lib/str_utils.monkey",
+                "str_utils",
+                "import \"lib/str_utils.monkey\" as str_utils;",
+            ),
+            (
+                "import \"2d-vectors\" as vec;",
+                "2d-vectors",
+                "vec",
+                "import \"2d-vectors\" as vec;",
+            ),
+        ];
+
+        for (input, expected_path, expected_name, expected_string) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+            assert_eq!(program.statements.len(), 1);
+            match &program.statements[0] {
+                Statement::Import(path, name, _) => {
+                    assert_eq!(path, expected_path);
+                    assert_eq!(name, expected_name);
+                }
+                other => panic!("expected import statement, got {:?}", other),
+            }
+            assert_eq!(program.to_string(), expected_string);
+        }
+
+        let tests = vec![
+            (
+                "if (true) { import \"math\"; }",
+                "1:13: 'import' inside a block; modules are imported at the top level",
+            ),
+            (
+                "let f = fn() { import \"math\" };",
+                "1:16: 'import' inside a block; modules are imported at the top level",
+            ),
+            (
+                "import \"2d-vectors\";",
+                "1:8: cannot name the module \"2d-vectors\"; add `as <name>`",
+            ),
+            (
+                "import \"lib/if\";",
+                "1:8: cannot name the module \"lib/if\"; add `as <name>`",
+            ),
+            (
+                "import math;",
+                "1:8: expected next token to be STRING, got math instead",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let (_, errors) = parse_errors(input);
+            assert_eq!(
+                errors.first().map(String::as_str),
+                Some(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_expressions() {
+        let tests = vec![
+            ("try { x } catch (e) { y }", "try xcatch(e) y"),
+            ("try { x } finally { y; }", "try xfinally y"),
+            (
+                "let r = try { f(1) } catch (err) { 0 } finally { g() };",
+                "let r = try f(1)catch(err) 0finally g();",
+            ),
+            ("throw x + 1;", "throw (x + 1);"),
+            ("throw {\"kind\": k}", "throw {kind:k};"),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+            check_parser_errors(&parser);
+            assert_eq!(program.statements.len(), 1, "input: {}", input);
+            assert_eq!(program.to_string(), expected);
+            assert_eq!(
+                program.statements[0].span().end,
+                input.len(),
+                "input: {}",
+                input
+            );
+        }
+
+        let tests = vec![
+            (
+                "try { x }",
+                "1:10: expected next token to be catch or finally, got end of input instead",
+            ),
+            (
+                "try { x } catch { y }",
+                "1:17: expected next token to be (, got { instead",
+            ),
+            (
+                "try { x } catch (1) { y }",
+                "1:18: expected next token to be IDENT, got 1 instead",
+            ),
+            (
+                "try x catch (e) { y }",
+                "1:5: expected next token to be {, got x instead",
+            ),
+            ("throw;", "1:6: no prefix parse function for ; found"),
+        ];
+
+        for (input, expected) in tests {
+            let (_, errors) = parse_errors(input);
+            assert_eq!(
+                errors.first().map(String::as_str),
+                Some(expected),
+                "input: {}",
+                input
+            );
+        }
+    }
 }
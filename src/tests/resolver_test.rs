@@ -0,0 +1,436 @@
+#[cfg(test)]
+mod tests {
+    use crate::ast::{BlockStatement, Expression, Program, Slot, Statement};
+    use crate::environment::Environment;
+    use crate::lexer::Lexer;
+    use crate::object::{IntegerOverflow, Object};
+    use crate::output::Sink;
+    use crate::parser::Parser;
+    use crate::resolver::resolve;
+    use crate::{Backend, Interpreter, Value};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const BACKENDS: [Backend; 2] = [Backend::Evaluator, Backend::Vm];
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert_eq!(parser.errors().len(), 0, "{}", input);
+        program
+    }
+
+    // The slots of the identifiers in `block`, in the order they're written.
+    fn block_slots(block: &BlockStatement, slots: &mut Vec<(String, Slot)>) {
+        for statement in &block.statements {
+            statement_slots(statement, slots);
+        }
+    }
+
+    fn statement_slots(statement: &Statement, slots: &mut Vec<(String, Slot)>) {
+        match statement {
+            Statement::Let(name, slot, expr, _) => {
+                slots.push((format!("let {}", name), *slot));
+                expression_slots(expr, slots);
+            }
+            Statement::Expression(expr, _) | Statement::Return(expr, _) => {
+                expression_slots(expr, slots)
+            }
+            Statement::For(_, iterable, body, _) => {
+                expression_slots(iterable, slots);
+                block_slots(body, slots);
+            }
+            _ => (),
+        }
+    }
+
+    fn expression_slots(expression: &Expression, slots: &mut Vec<(String, Slot)>) {
+        match expression {
+            Expression::Ident(name, slot, _) => slots.push((name.clone(), *slot)),
+            Expression::Infix(left, _, right, _) => {
+                expression_slots(left, slots);
+                expression_slots(right, slots);
+            }
+            Expression::FunctionLiteral(_, body, _) => block_slots(body, slots),
+            Expression::CallExpression(function, arguments, _) => {
+                expression_slots(function, slots);
+                arguments
+                    .iter()
+                    .for_each(|arg| expression_slots(arg, slots));
+            }
+            Expression::Assign(target, _, value, _) => {
+                expression_slots(target, slots);
+                expression_slots(value, slots);
+            }
+            Expression::Try(body, catch, _, _) => {
+                block_slots(body, slots);
+                if let Some((_, handler)) = catch {
+                    block_slots(handler, slots);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn local(depth: usize, index: usize) -> Slot {
+        Slot::Local { depth, index }
+    }
+
+    fn global(index: usize) -> Slot {
+        Slot::Global { index }
+    }
+
+    #[test]
+    fn test_resolve_slots() {
+        let tests = vec![
+            ("let a = 1; a", vec![("let a", global(0)), ("a", global(0))]),
+            (
+                "let f = fn(x, y) { let z = x; y + z }",
+                vec![
+                    ("let f", global(0)),
+                    ("let z", local(0, 2)),
+                    ("x", local(0, 0)),
+                    ("y", local(0, 1)),
+                    ("z", local(0, 2)),
+                ],
+            ),
+            (
+                "fn(x) { fn(y) { fn() { x + y + len } } }",
+                vec![
+                    ("x", local(2, 0)),
+                    ("y", local(1, 0)),
+                    ("len", Slot::Builtin),
+                ],
+            ),
+            // redefining a local reuses its slot
+            (
+                "fn() { let a = 1; let b = 2; let a = b; a }",
+                vec![
+                    ("let a", local(0, 0)),
+                    ("let b", local(0, 1)),
+                    ("let a", local(0, 0)),
+                    ("b", local(0, 1)),
+                    ("a", local(0, 0)),
+                ],
+            ),
+            // a function sees the locals around it bound after it
+            (
+                "fn() { let a = fn() { b }; let b = 1; }",
+                vec![
+                    ("let a", local(0, 0)),
+                    ("b", local(1, 1)),
+                    ("let b", local(0, 1)),
+                ],
+            ),
+            // a function sees the name it's bound to, other values don't
+            (
+                "fn(n) { let f = fn() { f() }; let n = n; }",
+                vec![
+                    ("let f", local(0, 1)),
+                    ("f", local(1, 1)),
+                    ("let n", local(0, 0)),
+                    ("n", local(0, 0)),
+                ],
+            ),
+            (
+                "fn(xs) { for (i, x in xs) { let y = x; i + y } }",
+                vec![
+                    ("xs", local(0, 0)),
+                    ("let y", local(0, 2)),
+                    ("x", local(0, 1)),
+                    ("i", local(0, 0)),
+                    ("y", local(0, 2)),
+                ],
+            ),
+            (
+                "let total = 0; for (x in [1]) { total += x }",
+                vec![
+                    ("let total", global(0)),
+                    ("total", global(0)),
+                    ("x", local(0, 0)),
+                ],
+            ),
+            (
+                "fn(a) { try { a } catch (e) { let b = e; a } }",
+                vec![
+                    ("a", local(0, 0)),
+                    ("let b", local(0, 1)),
+                    ("e", local(0, 0)),
+                    ("a", local(1, 0)),
+                ],
+            ),
+            // top-level names are seen before they're defined
+            (
+                "let f = fn() { g() }; let g = fn() { 1 };",
+                vec![("let f", global(0)), ("g", global(1)), ("let g", global(1))],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut program = parse(input);
+            resolve(&mut program, &mut Environment::default());
+            let mut slots = vec![];
+            for statement in &program.statements {
+                statement_slots(statement, &mut slots);
+            }
+            let expected: Vec<(String, Slot)> = expected
+                .into_iter()
+                .map(|(name, slot)| (name.to_string(), slot))
+                .collect();
+            assert_eq!(slots, expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_resolve_environment() {
+        let mut env = Environment::default();
+        env.set("x".to_string(), Object::Integer(1));
+        let mut program = parse("x = x + 1; y; len = y; len(y)");
+        resolve(&mut program, &mut env);
+        let mut slots = vec![];
+        for statement in &program.statements {
+            statement_slots(statement, &mut slots);
+        }
+        let expected = vec![
+            ("x", global(0)),
+            ("x", global(0)),
+            // bound nowhere, or not a variable, so they fail if they're reached
+            ("y", Slot::Unresolved),
+            ("len", Slot::Unresolved),
+            ("y", Slot::Unresolved),
+            ("len", Slot::Builtin),
+            ("y", Slot::Unresolved),
+        ];
+        let expected: Vec<(String, Slot)> = expected
+            .into_iter()
+            .map(|(name, slot)| (name.to_string(), slot))
+            .collect();
+        assert_eq!(slots, expected);
+    }
+
+    #[test]
+    fn test_enclosed_environments() {
+        let outer = Rc::new(RefCell::new(Environment::default()));
+        outer.borrow_mut().set("x".to_string(), Object::Integer(1));
+        let middle = Rc::new(RefCell::new(Environment::new_enclosed_environment(
+            outer.clone(),
+        )));
+        let mut inner = Environment::new_enclosed_environment(middle);
+
+        // the top-level names and the settings are the outermost environment's
+        let index = inner.global_slot("x");
+        assert_eq!(inner.get_global(index), Some(Object::Integer(1)));
+        inner.set_global(index, Object::Integer(2));
+        inner.set("y".to_string(), Object::Integer(3));
+        inner.set_integer_overflow(IntegerOverflow::Promote);
+        let outer = outer.borrow();
+        assert_eq!(outer.get("x".to_string()), Some(Object::Integer(2)));
+        assert_eq!(outer.get("y".to_string()), Some(Object::Integer(3)));
+        assert_eq!(outer.integer_overflow(), IntegerOverflow::Promote);
+        assert!(inner.slots.is_empty() && inner.globals.is_empty());
+
+        // a module has top-level names of its own
+        let module = outer.new_module_environment();
+        assert_eq!(module.get("x".to_string()), None);
+        assert_eq!(module.integer_overflow(), IntegerOverflow::Promote);
+    }
+
+    #[test]
+    fn test_unresolved_identifiers() {
+        let tests = vec![
+            (
+                "puts(1); foobar",
+                "1:10: identifier not found: foobar",
+                "1\n",
+            ),
+            (
+                "puts(1); let f = fn() { puts(2); x }; f()",
+                "1:34: identifier not found: x",
+                "1\n2\n",
+            ),
+            (
+                "let f = fn() { let a = b; let b = 1; }; f()",
+                "1:24: identifier not found: b",
+                "",
+            ),
+            (
+                "for (x in [1]) { puts(x) } x",
+                "1:28: identifier not found: x",
+                "1\n",
+            ),
+            (
+                "try { 1 } catch (e) { 2 }; e",
+                "1:28: identifier not found: e",
+                "",
+            ),
+            (
+                "puts(1); y = 2",
+                "1:10: cannot assign to undefined variable: y",
+                "1\n",
+            ),
+            (
+                "puts(1); len = 2",
+                "1:10: cannot assign to undefined variable: len",
+                "1\n",
+            ),
+            (
+                "puts(1); fn() { z[0] += 1 }()",
+                "1:17: cannot assign to undefined variable: z",
+                "1\n",
+            ),
+        ];
+
+        for backend in &BACKENDS {
+            for (input, expected, printed) in &tests {
+                let mut interpreter = Interpreter::with_backend(*backend);
+                let output = Sink::buffer();
+                interpreter.set_output(output.clone());
+                let err = interpreter.eval(input).unwrap_err();
+                assert_eq!(err.to_string(), *expected, "{}: {}", backend, input);
+                // only once the code using the name runs
+                assert_eq!(
+                    output.contents().unwrap(),
+                    *printed,
+                    "{}: {}",
+                    backend,
+                    input
+                );
+            }
+
+            // code that never runs can use any name
+            let mut interpreter = Interpreter::with_backend(*backend);
+            let input = "let f = fn() { x }; if (false) { missing() } 1";
+            assert_eq!(
+                interpreter.eval(input),
+                Ok(Value::Integer(1)),
+                "{}",
+                backend
+            );
+        }
+    }
+
+    #[test]
+    fn test_slot_variables() {
+        let tests = vec![
+            (
+                "let f = fn(a, b) { let c = a * b; let a = c + 1; a }; f(3, 4)",
+                13,
+            ),
+            (
+                "let counter = fn() { let n = 0; fn() { n += 1; n } }; \
+                 let c = counter(); c(); c(); c()",
+                3,
+            ),
+            (
+                "let f = fn(n) { let fact = fn(k) { if (k < 2) { 1 } else { k * fact(k - 1) } }; \
+                 fact(n) }; f(5)",
+                120,
+            ),
+            (
+                "let sum = fn(xs) { let total = 0; for (i, x in xs) { total += i * x } total }; \
+                 sum([1, 2, 3])",
+                8,
+            ),
+            (
+                "let f = fn() { let xs = [[1]]; xs[0][0] = 5; xs[0][0] }; f()",
+                5,
+            ),
+            (
+                "let f = fn(x) { try { throw {\"n\": x} } catch (e) { let y = e[\"n\"]; y + x } }; \
+                 f(21)",
+                42,
+            ),
+            (
+                "let fs = []; for (x in [1, 2, 3]) { push(fs, fn() { x }) } \
+                 fs[0]() + fs[1]() * 10 + fs[2]() * 100",
+                321,
+            ),
+            ("let x = 1; let f = fn() { x = x + 10 }; f(); f(); x", 21),
+        ];
+
+        for backend in &BACKENDS {
+            for (input, expected) in &tests {
+                let mut interpreter = Interpreter::with_backend(*backend);
+                assert_eq!(
+                    interpreter.eval(input),
+                    Ok(Value::Integer(*expected)),
+                    "{}: {}",
+                    backend,
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_later_locals() {
+        // a function sees the locals of the function around it, even those
+        // bound after it
+        let tests = vec![
+            (
+                "let f = fn() { let a = fn() { b() }; let b = fn() { 7 }; a() }; f()",
+                Ok(Value::Integer(7)),
+            ),
+            (
+                "let f = fn() { let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } }; \
+                 let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } }; even(10) }; f()",
+                Ok(Value::Boolean(true)),
+            ),
+            // until it's bound, the local is null, even if the name is bound
+            // outside the function
+            (
+                "let b = 1; let f = fn() { let a = fn() { b }; \
+                 let x = a(); let b = 2; [x, a()] }; push(f(), b)",
+                Ok(Value::Array(vec![
+                    Value::Null,
+                    Value::Integer(2),
+                    Value::Integer(1),
+                ])),
+            ),
+            (
+                "let f = fn() { let a = fn() { b = 5 }; a(); let c = b; let b = c + 1; b }; f()",
+                Err("1:53: identifier not found: b".to_string()),
+            ),
+            (
+                "let f = fn() { let a = fn() { b += 1 }; let b = 5; a(); b }; f()",
+                Ok(Value::Integer(6)),
+            ),
+            (
+                "for (i in [1]) { let a = fn() { i + j }; let j = 2; puts(a()) }",
+                Ok(Value::Null),
+            ),
+        ];
+
+        for backend in &BACKENDS {
+            for (input, expected) in &tests {
+                let mut interpreter = Interpreter::with_backend(*backend);
+                let result = interpreter.eval(input).map_err(|err| err.to_string());
+                assert_eq!(result, *expected, "{}: {}", backend, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_globals_across_programs() {
+        for backend in &BACKENDS {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            interpreter
+                .eval("let x = 2; let double = fn(n) { n * x }")
+                .unwrap();
+            interpreter.eval("x = 5").unwrap();
+            assert_eq!(
+                interpreter.eval("double(3)"),
+                Ok(Value::Integer(15)),
+                "{}",
+                backend
+            );
+            let err = interpreter.eval("double(y)").unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "1:8: identifier not found: y",
+                "{}",
+                backend
+            );
+        }
+    }
+}
@@ -0,0 +1,119 @@
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use crate::object::Object;
+    use crate::{Backend, Interpreter, Value};
+    use serde_json::json;
+
+    #[test]
+    fn test_serialize_values() {
+        for backend in &[Backend::Evaluator, Backend::Vm] {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            let value = interpreter
+                .eval(r#"{"name": "monkey", "legs": [1, 2], "tail": true, "owner": if (false) { 1 }}"#)
+                .unwrap();
+            let json = serde_json::to_value(&value).unwrap();
+            assert_eq!(
+                json,
+                json!({"name": "monkey", "legs": [1, 2], "tail": true, "owner": null})
+            );
+
+            let value = interpreter.eval("{1: \"one\", true: \"yes\"}").unwrap();
+            let json = serde_json::to_value(&value).unwrap();
+            assert_eq!(json, json!({"1": "one", "true": "yes"}));
+        }
+    }
+
+    #[test]
+    fn test_serialize_functions_fails() {
+        let tests = vec![
+            ("fn(x) { x }", "cannot serialize a FUNCTION"),
+            ("[1, len]", "cannot serialize a BUILTIN"),
+        ];
+
+        let mut interpreter = Interpreter::new();
+        for (input, expected) in tests {
+            let value = interpreter.eval(input).unwrap();
+            let err = serde_json::to_string(&value).unwrap_err();
+            assert_eq!(err.to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_serialize_cycles_fails() {
+        let tests = vec![
+            (
+                "let a = []; push(a, a); a",
+                "cannot serialize a ARRAY that contains itself",
+            ),
+            (
+                "let h = {}; h[\"in\"] = [h]; h",
+                "cannot serialize a HASH that contains itself",
+            ),
+        ];
+
+        for backend in &[Backend::Evaluator, Backend::Vm] {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            for (input, expected) in &tests {
+                let value: Object = interpreter.eval_as(input).unwrap();
+                let err = serde_json::to_string(&value).unwrap_err();
+                assert_eq!(err.to_string(), *expected, "{}", input);
+            }
+            // shared without a cycle is fine
+            let value: Object = interpreter.eval_as("let b = [1]; [b, b]").unwrap();
+            assert_eq!(serde_json::to_string(&value).unwrap(), "[[1],[1]]");
+        }
+    }
+
+    #[test]
+    fn test_deserialize_values() {
+        let config: Object = serde_json::from_str(
+            r#"{"retries": 3, "hosts": ["a", "b"], "debug": false, "proxy": null}"#,
+        )
+        .unwrap();
+
+        for backend in &[Backend::Evaluator, Backend::Vm] {
+            let mut interpreter = Interpreter::with_backend(*backend);
+            interpreter.set("config", config.clone());
+            let result = interpreter
+                .eval(r#"[config["retries"] * 2, len(config["hosts"]), config["debug"], config["proxy"]]"#)
+                .unwrap();
+            assert_eq!(
+                result,
+                Value::Array(vec![
+                    Value::Integer(6),
+                    Value::Integer(2),
+                    Value::Boolean(false),
+                    Value::Null,
+                ])
+            );
+        }
+
+        let value: Value = serde_json::from_str("[1, \"two\", [true]]").unwrap();
+        assert_eq!(value.to_string(), "[1, two, [true]]");
+
+        let value: Value = serde_json::from_str("[1.5, 2]").unwrap();
+        assert_eq!(
+            value,
+            Value::Array(vec![Value::Float(1.5), Value::Integer(2)])
+        );
+        let value = Interpreter::new().eval("[1 / 4, 1.0 / 4]").unwrap();
+        assert_eq!(serde_json::to_value(&value).unwrap(), json!([0, 0.25]));
+    }
+
+    #[test]
+    fn test_large_integers() {
+        let obj = serde_json::from_str::<Object>("18446744073709551615").unwrap();
+        assert_eq!(obj.inspect(), "18446744073709551615");
+        assert_eq!(serde_json::to_string(&obj).unwrap(), "18446744073709551615");
+
+        let value = Interpreter::new()
+            .eval("99999999999999999999999 * 10000000000000000")
+            .unwrap();
+        let err = serde_json::to_string(&value).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot serialize 999999999999999999999990000000000000000, \
+             which is outside the 128-bit range"
+        );
+    }
+}
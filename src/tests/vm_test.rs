@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::Style;
+    use crate::{Backend, Interpreter};
+
+    fn run_vm(input: &str) -> String {
+        Interpreter::with_backend(Backend::Vm).interpret("<input>", input, Style::Plain)
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = vec![
+            (
+                "let newAdder = fn(a, b) { fn(c) { a + b + c } }; newAdder(1, 2)(8);",
+                "11",
+            ),
+            (
+                "let newClosure = fn(a, b) {
+                    let one = fn() { a; };
+                    let two = fn() { b; };
+                    fn() { one() + two(); };
+                };
+                newClosure(9, 90)();",
+                "99",
+            ),
+            (
+                "let wrapper = fn() {
+                    let countDown = fn(x) { if (x == 0) { return 0; } else { countDown(x - 1); } };
+                    countDown(1);
+                };
+                wrapper();",
+                "0",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(run_vm(input), expected);
+        }
+    }
+
+    #[test]
+    fn test_recursive_fibonacci() {
+        let input = "let fibonacci = fn(x) {
+            if (x == 0) { return 0; }
+            if (x == 1) { return 1; }
+            fibonacci(x - 1) + fibonacci(x - 2);
+        };
+        fibonacci(15);";
+        assert_eq!(run_vm(input), "610");
+    }
+
+    #[test]
+    fn test_calling_with_wrong_arguments() {
+        let tests = vec![
+            (
+                "fn() { 1; }(1);",
+                "wrong number of arguments. got=1, want=0",
+            ),
+            (
+                "fn(a) { a; }();",
+                "wrong number of arguments. got=0, want=1",
+            ),
+            ("1(1);", "not a function"),
+        ];
+
+        for (input, expected) in tests {
+            for backend in &[Backend::Evaluator, Backend::Vm] {
+                let output =
+                    Interpreter::with_backend(*backend).interpret("<input>", input, Style::Plain);
+                assert!(output.contains(expected), "{}: {}", backend, output);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let output = run_vm("let f = fn(x) { 1 + f(x + 1) }; f(0);");
+        assert!(
+            output.starts_with("error[E0012]: stack overflow: more than 2048 values"),
+            "{}",
+            output
+        );
+        let output = run_vm("let f = fn() { f() + 1 }; f();");
+        assert!(
+            output.starts_with("error[E0012]: stack overflow: more than 1024 nested calls"),
+            "{}",
+            output
+        );
+    }
+
+    #[test]
+    fn test_session_keeps_globals() {
+        let mut session = Interpreter::with_backend(Backend::Vm);
+        assert_eq!(session.interpret("<repl>", "let a = 5;", Style::Plain), "5");
+        assert_eq!(
+            session.interpret("<repl>", "let add = fn(x) { x + a };", Style::Plain),
+            "fn(x) {\n(x + a)\n}"
+        );
+        assert!(session
+            .interpret("<repl>", "add(b);", Style::Plain)
+            .starts_with("error[E0005]: identifier not found: b"));
+        assert_eq!(session.interpret("<repl>", "add(2);", Style::Plain), "7");
+    }
+
+    #[test]
+    fn test_forward_reference() {
+        let input = "let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } };
+        let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } };
+        isEven(10);";
+        assert_eq!(run_vm(input), "true");
+        assert!(run_vm("let a = b; let b = 1;").contains("identifier not found: b"));
+    }
+}
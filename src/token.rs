@@ -1,5 +1,44 @@
 use std::fmt;
 
+/// A region of source text. `start` and `end` are byte offsets into the input,
+/// `line` and `column` are the 1-based location of `start`. `file` is 0 in
+/// the program being run, and otherwise numbers the imported module's file
+/// the text is in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub file: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+            file: 0,
+        }
+    }
+
+    /// Returns a span covering everything from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Illegal,
@@ -8,6 +47,7 @@ pub enum Token {
     // Identifiers + literals
     Ident(String),
     Int(String),
+    Float(String),
     String(String),
 
     // Operators
@@ -17,6 +57,13 @@ pub enum Token {
     Minus,
     Plus,
     Slash,
+    Percent,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    PercentAssign,
+    DotDot,
 
     // Comparison
     Gt,
@@ -38,25 +85,47 @@ pub enum Token {
     RBracket,
 
     // Keywords
+    As,
+    Break,
+    Catch,
+    Continue,
     Else,
     False,
+    Finally,
+    For,
     Function,
     If,
+    Import,
+    In,
     Let,
     Return,
+    Throw,
     True,
+    Try,
+    While,
 }
 
 impl Token {
     pub fn lookup_ident(ident: String) -> Token {
         match ident.as_str() {
+            "as" => Token::As,
+            "break" => Token::Break,
+            "catch" => Token::Catch,
+            "continue" => Token::Continue,
             "else" => Token::Else,
             "false" => Token::False,
+            "finally" => Token::Finally,
             "fn" => Token::Function,
+            "for" => Token::For,
             "if" => Token::If,
+            "import" => Token::Import,
+            "in" => Token::In,
             "let" => Token::Let,
             "return" => Token::Return,
+            "throw" => Token::Throw,
             "true" => Token::True,
+            "try" => Token::Try,
+            "while" => Token::While,
             _ => Token::Ident(ident),
         }
     }
@@ -67,6 +136,7 @@ impl fmt::Display for Token {
         let output = match &self {
             Token::Ident(s) => s,
             Token::Int(s) => s,
+            Token::Float(s) => s,
             Token::String(s) => s,
             Token::Assign => "=",
             Token::Asterisk => "*",
@@ -74,8 +144,15 @@ impl fmt::Display for Token {
             Token::Minus => "-",
             Token::Plus => "+",
             Token::Slash => "/",
+            Token::Percent => "%",
+            Token::PlusAssign => "+=",
+            Token::MinusAssign => "-=",
+            Token::AsteriskAssign => "*=",
+            Token::SlashAssign => "/=",
+            Token::PercentAssign => "%=",
             Token::Gt => ">",
             Token::Lt => "<",
+            Token::DotDot => "..",
             Token::Eq => "==",
             Token::NotEq => "!=",
             Token::Comma => ",",
@@ -86,13 +163,24 @@ impl fmt::Display for Token {
             Token::RBracket => "]",
             Token::RBrace => "}",
             Token::RParen => ")",
+            Token::As => "as",
+            Token::Break => "break",
+            Token::Catch => "catch",
+            Token::Continue => "continue",
             Token::Else => "else",
             Token::False => "false",
+            Token::Finally => "finally",
+            Token::For => "for",
             Token::Function => "fn",
             Token::If => "if",
+            Token::Import => "import",
+            Token::In => "in",
             Token::Let => "let",
             Token::Return => "return",
+            Token::Throw => "throw",
             Token::True => "true",
+            Token::Try => "try",
+            Token::While => "while",
             Token::Colon => ":",
             _ => "ILLEGAL",
         };
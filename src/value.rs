@@ -0,0 +1,162 @@
+use crate::bigint::BigInt;
+use crate::convert::ConversionError;
+use crate::object::Object;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+/// A Monkey value as seen by embedding code. Unlike `Object`, it never holds
+/// the interpreter's control-flow markers (return values and errors).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    /// An INTEGER too large for an `i64`.
+    BigInt(BigInt),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Array(Vec<Value>),
+    Hash(Vec<(Value, Value)>),
+    Function(Function),
+    Null,
+}
+
+/// A function or builtin returned from a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    object: Object,
+}
+
+impl Function {
+    /// The parameter names of a user-defined function. Builtins have none.
+    pub fn parameters(&self) -> Vec<String> {
+        match &self.object {
+            Object::Function(parms, _, _) => parms.clone(),
+            Object::Closure(closure) => closure.function.parameters.clone(),
+            _ => vec![],
+        }
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        matches!(self.object, Object::BuiltIn(_) | Object::Native(_))
+    }
+
+    pub fn object(&self) -> &Object {
+        &self.object
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.object.inspect())
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) | Value::BigInt(_) => "INTEGER",
+            Value::Float(_) => "FLOAT",
+            Value::Boolean(_) => "BOOLEAN",
+            Value::String(_) => "STRING",
+            Value::Array(_) => "ARRAY",
+            Value::Hash(_) => "HASH",
+            Value::Function(_) => "FUNCTION",
+            Value::Null => "NULL",
+        }
+    }
+}
+
+// Fails on an array or hash that contains itself, which a `Value` can't.
+impl TryFrom<Object> for Value {
+    type Error = ConversionError;
+
+    fn try_from(obj: Object) -> Result<Value, ConversionError> {
+        value_within(obj, &mut vec![])
+    }
+}
+
+// `open` holds the collections being converted, like the list
+// `Object::inspect` keeps.
+fn value_within(obj: Object, open: &mut Vec<*const ()>) -> Result<Value, ConversionError> {
+    let id = match &obj {
+        Object::Array(elements) => Rc::as_ptr(elements) as *const (),
+        Object::Hash(map) => Rc::as_ptr(map) as *const (),
+        _ => std::ptr::null(),
+    };
+    if !id.is_null() {
+        if open.contains(&id) {
+            return Err(ConversionError {
+                expected: "a value that doesn't contain itself".to_string(),
+                found: format!("{} that does", obj.obj_type()),
+                path: String::new(),
+            });
+        }
+        open.push(id);
+    }
+    let value = match obj {
+        Object::Integer(i) => Value::Integer(i),
+        Object::BigInt(i) => Value::BigInt(i),
+        Object::Float(f) => Value::Float(f),
+        Object::Boolean(b) => Value::Boolean(b),
+        Object::String(s) => Value::String(s),
+        Object::Array(elements) => Value::Array(
+            elements
+                .borrow()
+                .iter()
+                .map(|element| value_within(element.clone(), open))
+                .collect::<Result<_, _>>()?,
+        ),
+        Object::Hash(map) => Value::Hash(
+            map.borrow()
+                .values()
+                .map(|pair| {
+                    Ok((
+                        value_within(pair.key.clone(), open)?,
+                        value_within(pair.value.clone(), open)?,
+                    ))
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        Object::ReturnValue(value) => value_within(*value, open)?,
+        Object::Cell(cell) => value_within(cell.borrow().clone(), open)?,
+        Object::Function(_, _, _)
+        | Object::BuiltIn(_)
+        | Object::Native(_)
+        | Object::CompiledFunction(_)
+        | Object::Closure(_) => Value::Function(Function { object: obj }),
+        Object::Error(_)
+        | Object::TailCall(_, _, _)
+        | Object::Break
+        | Object::Continue
+        | Object::Null => Value::Null,
+    };
+    if !id.is_null() {
+        open.pop();
+    }
+    Ok(value)
+}
+
+// Values print the way the REPL prints the objects they mirror.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::BigInt(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::Hash(pairs) => {
+                let pairs: Vec<String> =
+                    pairs.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", pairs.join(", "))
+            }
+            Value::Function(function) => write!(f, "{}", function),
+            Value::Null => Ok(()),
+        }
+    }
+}
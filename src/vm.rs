@@ -0,0 +1,696 @@
+use crate::code::{self, Opcode};
+use crate::compiler::Bytecode;
+use crate::diagnostics::ErrorCode;
+use crate::evaluator;
+use crate::gc;
+use crate::limits::{self, Budget, CancelHandle, Limits};
+use crate::module;
+use crate::object::{
+    self, BuiltIn, CallContext, Closure, CompiledFunction, HashPair, HashTable, IntegerOverflow,
+    Object, RuntimeError,
+};
+use crate::output::Sink;
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+const STACK_SIZE: usize = 2048;
+const MAX_FRAMES: usize = 1024;
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base_pointer: usize,
+}
+
+impl Frame {
+    fn new(closure: Rc<Closure>, base_pointer: usize) -> Frame {
+        Frame {
+            closure,
+            ip: 0,
+            base_pointer,
+        }
+    }
+}
+
+// A `try` block being run: the frame and stack height to unwind to when an
+// error reaches it, and where its catch or cleanup code starts.
+struct Handler {
+    frames: usize,
+    stack: usize,
+    target: usize,
+}
+
+/// A stack machine running compiled bytecode. Globals outlive a single `run`,
+/// so consecutive programs from the same `Compiler` can share bindings.
+///
+/// Operators, indexing and builtins are shared with the evaluator, so both
+/// backends produce the same values and the same error messages.
+pub struct Vm {
+    constants: Vec<Object>,
+    globals: Vec<Option<Object>>,
+    global_names: Vec<String>,
+    builtins: Vec<Object>,
+    stack: Vec<Object>,
+    frames: Vec<Frame>,
+    // innermost last
+    handlers: Vec<Handler>,
+    last_popped: Object,
+    integer_overflow: IntegerOverflow,
+    output: Sink,
+    error_output: Sink,
+    budget: Budget,
+}
+
+impl Default for Vm {
+    fn default() -> Vm {
+        Vm::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm {
+            constants: vec![],
+            globals: vec![],
+            global_names: vec![],
+            builtins: BuiltIn::NAMES
+                .iter()
+                .filter_map(|name| BuiltIn::lookup_builtin(name))
+                .map(Object::BuiltIn)
+                .collect(),
+            stack: Vec::with_capacity(STACK_SIZE),
+            frames: vec![],
+            handlers: vec![],
+            last_popped: Object::Null,
+            integer_overflow: IntegerOverflow::default(),
+            output: Sink::Stdout,
+            error_output: Sink::Stderr,
+            budget: Budget::default(),
+        }
+    }
+
+    pub fn set_integer_overflow(&mut self, mode: IntegerOverflow) {
+        self.integer_overflow = mode;
+    }
+
+    pub fn set_output(&mut self, sink: Sink) {
+        self.output = sink;
+    }
+
+    pub fn set_error_output(&mut self, sink: Sink) {
+        self.error_output = sink;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.budget.limits()
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget.set_limits(limits);
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.budget.cancel_handle()
+    }
+
+    /// Adds a builtin and returns the index the compiler should give its name.
+    pub fn define_builtin(&mut self, builtin: Object) -> usize {
+        self.builtins.push(builtin);
+        self.builtins.len() - 1
+    }
+
+    pub fn set_global(&mut self, index: usize, value: Object) {
+        if self.globals.len() <= index {
+            self.globals.resize(index + 1, None);
+        }
+        self.globals[index] = Some(value);
+    }
+
+    /// Runs `bytecode` and returns the value of its last expression statement,
+    /// or the error that stopped it.
+    pub fn run(&mut self, bytecode: Bytecode) -> Object {
+        let main = CompiledFunction {
+            instructions: bytecode.instructions,
+            positions: bytecode.positions,
+            num_locals: bytecode.num_locals,
+            parameters: vec![],
+            body: String::new(),
+        };
+        let closure = Closure {
+            function: Rc::new(main),
+            free: vec![],
+        };
+        self.constants = bytecode.constants;
+        self.global_names = bytecode.global_names;
+        if self.globals.len() < self.global_names.len() {
+            self.globals.resize(self.global_names.len(), None);
+        }
+        self.stack.clear();
+        self.stack.resize(closure.function.num_locals, Object::Null);
+        self.frames = vec![Frame::new(Rc::new(closure), 0)];
+        self.handlers.clear();
+        self.last_popped = Object::Null;
+        self.budget.start();
+
+        match self.execute(0) {
+            Ok(()) => std::mem::replace(&mut self.last_popped, Object::Null),
+            Err(err) => Object::Error(err),
+        }
+    }
+
+    // Runs until the program stops or returns from the frame at `depth`, which
+    // is how functions called by builtins run.
+    fn execute(&mut self, depth: usize) -> Result<(), RuntimeError> {
+        loop {
+            if self.frames.len() <= depth {
+                return Ok(());
+            }
+            let frame = self.frames.last_mut().expect("vm has no frame");
+            let instructions = &frame.closure.function.instructions;
+            let start = frame.ip;
+            if start >= instructions.len() {
+                return Ok(());
+            }
+            let op = Opcode::try_from(instructions[start]).expect("undefined opcode");
+            let (operands, read) = code::read_operands(op, &instructions[start + 1..]);
+            frame.ip += 1 + read;
+
+            let result = match self.budget.step() {
+                Ok(()) => self.execute_instruction(op, &operands),
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(true) => (),
+                Ok(false) => return Ok(()),
+                Err(mut err) => {
+                    // the error belongs to the instruction that was running
+                    if err.span.is_none() {
+                        let frame = self.frames.last().expect("vm has no frame");
+                        err.span = frame.closure.function.span_at(start);
+                    }
+                    // handlers in frames below `depth` belong to whoever
+                    // called the function being run, so the error goes there
+                    match self.handlers.last() {
+                        Some(handler) if handler.frames > depth && err.code.is_catchable() => {
+                            self.unwind(err)
+                        }
+                        _ => return Err(err),
+                    }
+                }
+            }
+        }
+    }
+
+    // Resumes at the innermost handler with the error on the stack.
+    fn unwind(&mut self, err: RuntimeError) {
+        let handler = self.handlers.pop().expect("vm has no handler");
+        self.frames.truncate(handler.frames);
+        self.stack.truncate(handler.stack);
+        self.current_frame().ip = handler.target;
+        self.stack.push(Object::Error(err));
+    }
+
+    // Returns whether execution should continue.
+    fn execute_instruction(
+        &mut self,
+        op: Opcode,
+        operands: &[usize],
+    ) -> Result<bool, RuntimeError> {
+        match op {
+            Opcode::Constant => {
+                let constant = self.constants[operands[0]].clone();
+                // strings are copied out of the constants, like literals in
+                // the evaluator
+                self.budget.allocate(constant.heap_size())?;
+                self.push(constant)?;
+            }
+            Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::Mod
+            | Opcode::Equal
+            | Opcode::NotEqual
+            | Opcode::GreaterThan
+            | Opcode::LessThan
+            | Opcode::Range => {
+                let operator = match op {
+                    Opcode::Add => "+",
+                    Opcode::Sub => "-",
+                    Opcode::Mul => "*",
+                    Opcode::Div => "/",
+                    Opcode::Mod => "%",
+                    Opcode::Equal => "==",
+                    Opcode::NotEqual => "!=",
+                    Opcode::GreaterThan => ">",
+                    Opcode::LessThan => "<",
+                    _ => "..",
+                };
+                let right = self.pop();
+                let left = self.pop();
+                self.budget
+                    .allocate(evaluator::infix_allocation(operator, &left, &right))?;
+                let result = check(evaluator::eval_infix_expression(
+                    operator,
+                    left,
+                    right,
+                    self.integer_overflow,
+                ))?;
+                self.push(result)?;
+            }
+            Opcode::Minus | Opcode::Bang => {
+                let operator = if op == Opcode::Minus { "-" } else { "!" };
+                let right = self.pop();
+                let result = check(evaluator::eval_prefix_expression(
+                    operator,
+                    right,
+                    self.integer_overflow,
+                ))?;
+                self.push(result)?;
+            }
+            Opcode::Pop => self.last_popped = self.pop(),
+            Opcode::True => self.push(Object::Boolean(true))?,
+            Opcode::False => self.push(Object::Boolean(false))?,
+            Opcode::Null => self.push(Object::Null)?,
+            Opcode::JumpNotTruthy => {
+                let condition = self.pop();
+                if !evaluator::is_truthy(condition) {
+                    self.current_frame().ip = operands[0];
+                }
+            }
+            Opcode::Jump => self.current_frame().ip = operands[0],
+            Opcode::Try => self.handlers.push(Handler {
+                frames: self.frames.len(),
+                stack: self.stack.len(),
+                target: operands[0],
+            }),
+            Opcode::EndTry => {
+                self.handlers.pop();
+            }
+            Opcode::Catch => {
+                let caught = match self.pop() {
+                    Object::Error(err) => err.to_object(),
+                    value => value,
+                };
+                self.push(caught)?;
+            }
+            Opcode::Throw => {
+                return Err(match self.pop() {
+                    // an error the cleanup code of a `try` throws again
+                    Object::Error(err) => err,
+                    value => RuntimeError::thrown(value),
+                });
+            }
+            Opcode::Unbound => {
+                let msg = self.constants[operands[0]].inspect();
+                return Err(RuntimeError::new(ErrorCode::UnknownIdentifier, msg));
+            }
+            Opcode::Import => {
+                let namespace = match &self.globals[operands[1]] {
+                    Some(namespace) => namespace.clone(),
+                    None => {
+                        let function = match &self.constants[operands[0]] {
+                            Object::CompiledFunction(function) => function.clone(),
+                            _ => unreachable!("modules are compiled to functions"),
+                        };
+                        let module = Object::Closure(Rc::new(Closure {
+                            function,
+                            free: vec![],
+                        }));
+                        let namespace = self.apply(&module, vec![]).map_err(|err| {
+                            let path = self.constants[operands[2]].inspect();
+                            module::in_module(&path, err)
+                        })?;
+                        self.globals[operands[1]] = Some(namespace.clone());
+                        namespace
+                    }
+                };
+                self.push(namespace)?;
+            }
+            Opcode::GetGlobal => match self.globals.get(operands[0]) {
+                Some(Some(value)) => {
+                    let value = value.clone();
+                    self.push(value)?;
+                }
+                _ => {
+                    let name = self
+                        .global_names
+                        .get(operands[0])
+                        .cloned()
+                        .unwrap_or_default();
+                    let msg = format!("identifier not found: {}", name);
+                    return Err(RuntimeError::new(ErrorCode::UnknownIdentifier, msg));
+                }
+            },
+            Opcode::SetGlobal => {
+                let value = self.pop();
+                self.globals[operands[0]] = Some(value);
+            }
+            Opcode::GetLocal => {
+                let base_pointer = self.current_frame().base_pointer;
+                let value = read_cell(&self.stack[base_pointer + operands[0]]);
+                self.push(value)?;
+            }
+            Opcode::SetLocal => {
+                let base_pointer = self.current_frame().base_pointer;
+                let value = self.pop();
+                match &self.stack[base_pointer + operands[0]] {
+                    Object::Cell(cell) => *cell.borrow_mut() = value,
+                    _ => self.stack[base_pointer + operands[0]] = value,
+                }
+            }
+            Opcode::CaptureLocal => {
+                let slot = self.current_frame().base_pointer + operands[0];
+                if !matches!(self.stack[slot], Object::Cell(_)) {
+                    let value = std::mem::replace(&mut self.stack[slot], Object::Null);
+                    self.stack[slot] = gc::track(Object::Cell(Rc::new(RefCell::new(value))));
+                }
+                let cell = self.stack[slot].clone();
+                self.push(cell)?;
+            }
+            Opcode::GetBuiltin => {
+                let builtin = self.builtins[operands[0]].clone();
+                self.push(builtin)?;
+            }
+            Opcode::GetFree => {
+                let value = read_cell(&self.current_frame().closure.free[operands[0]]);
+                self.push(value)?;
+            }
+            Opcode::SetFree => {
+                let value = self.pop();
+                match &self.current_frame().closure.free[operands[0]] {
+                    Object::Cell(cell) => *cell.borrow_mut() = value,
+                    _ => {
+                        let msg = "cannot assign to a function inside its own definition";
+                        return Err(RuntimeError::new(
+                            ErrorCode::InvalidAssignment,
+                            msg.to_string(),
+                        ));
+                    }
+                }
+            }
+            Opcode::CaptureFree => {
+                let cell = self.current_frame().closure.free[operands[0]].clone();
+                self.push(cell)?;
+            }
+            Opcode::CurrentClosure => {
+                let closure = self.current_frame().closure.clone();
+                self.push(Object::Closure(closure))?;
+            }
+            Opcode::Array => {
+                let elements = self.stack.split_off(self.stack.len() - operands[0]);
+                let array = Object::array(elements);
+                self.budget.allocate(array.heap_size())?;
+                self.push(array)?;
+            }
+            Opcode::Hash => {
+                let elements = self.stack.split_off(self.stack.len() - operands[0]);
+                let hash = build_hash(elements)?;
+                self.budget.allocate(hash.heap_size())?;
+                self.push(hash)?;
+            }
+            Opcode::Index => {
+                let index = self.pop();
+                let left = self.pop();
+                let result = check(evaluator::eval_index_expression(left, index))?;
+                self.push(result)?;
+            }
+            Opcode::SetIndex => {
+                let value = self.pop();
+                let indexes = self.stack.split_off(self.stack.len() - operands[0]);
+                let container = self.pop();
+                let operator = code::ASSIGN_OPERATORS[operands[1]];
+                let (updated, value, allocated) = evaluator::assign_index(
+                    container,
+                    &indexes,
+                    operator,
+                    value,
+                    self.integer_overflow,
+                )?;
+                self.budget.allocate(allocated)?;
+                self.push(value)?;
+                self.push(updated)?;
+            }
+            Opcode::Iter => {
+                let iterable = self.pop();
+                let entries = evaluator::iteration_entries(iterable, operands[1])?;
+                let slot = self.current_frame().base_pointer + operands[0];
+                self.stack[slot] = Object::array(entries);
+                self.stack[slot + 1] = Object::Integer(0);
+            }
+            Opcode::IterRange => {
+                let end = self.pop();
+                let start = self.pop();
+                let (start, end) = match (start, end) {
+                    (Object::Integer(start), Object::Integer(end)) => (start, end),
+                    (start, end) => {
+                        check(evaluator::eval_infix_expression(
+                            "..",
+                            start,
+                            end,
+                            self.integer_overflow,
+                        ))?;
+                        unreachable!("ranges of anything but integers are errors")
+                    }
+                };
+                // a range keeps its end where an array would be
+                let slot = self.current_frame().base_pointer + operands[0];
+                self.stack[slot] = Object::Integer(end);
+                self.stack[slot + 1] = Object::Integer(start);
+            }
+            Opcode::IterNext => {
+                let slot = self.current_frame().base_pointer + operands[0];
+                let position = match self.stack[slot + 1] {
+                    Object::Integer(position) => position,
+                    _ => unreachable!("loop position is always an integer"),
+                };
+                let entry = match &self.stack[slot] {
+                    Object::Integer(end) if position < *end => Some(Object::Integer(position)),
+                    Object::Array(entries) => entries.borrow().get(position as usize).cloned(),
+                    _ => None,
+                };
+                match entry {
+                    Some(Object::Array(pair)) if operands[1] == 2 => {
+                        self.stack[slot + 1] = Object::Integer(position + 1);
+                        let pair = pair.borrow().clone();
+                        for value in pair {
+                            self.push(value)?;
+                        }
+                    }
+                    Some(entry) => {
+                        self.stack[slot + 1] = Object::Integer(position + 1);
+                        self.push(entry)?;
+                    }
+                    None => self.current_frame().ip = operands[2],
+                }
+            }
+            Opcode::ResetLocals => {
+                let first = self.current_frame().base_pointer + operands[0];
+                for slot in &mut self.stack[first..first + operands[1]] {
+                    *slot = Object::Null;
+                }
+            }
+            Opcode::Closure => {
+                let function = match &self.constants[operands[0]] {
+                    Object::CompiledFunction(function) => function.clone(),
+                    other => panic!("not a function: {}", other.inspect()),
+                };
+                let free = self.stack.split_off(self.stack.len() - operands[1]);
+                self.push(gc::track(Object::Closure(Rc::new(Closure {
+                    function,
+                    free,
+                }))))?;
+            }
+            Opcode::Call => self.call(operands[0], true)?,
+            Opcode::ReturnValue | Opcode::Return => {
+                let value = if op == Opcode::ReturnValue {
+                    self.pop()
+                } else {
+                    Object::Null
+                };
+                if self.frames.len() == 1 {
+                    // a top-level return stops the program
+                    self.last_popped = value;
+                    return Ok(false);
+                }
+                let frame = self.frames.pop().expect("vm has no frame");
+                self.stack.truncate(frame.base_pointer - 1);
+                self.push(value)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn call(&mut self, num_args: usize, from_code: bool) -> Result<(), RuntimeError> {
+        let callee_index = self.stack.len() - 1 - num_args;
+        match self.stack[callee_index].clone() {
+            Object::Closure(closure) => {
+                let function = &closure.function;
+                if num_args != function.parameters.len() {
+                    let msg = format!(
+                        "wrong number of arguments. got={}, want={}",
+                        num_args,
+                        function.parameters.len()
+                    );
+                    return Err(RuntimeError::new(ErrorCode::ArgumentCount, msg));
+                }
+                if from_code && self.frames.len() > 1 && self.returns_next() {
+                    return self.tail_call(closure, callee_index);
+                }
+                // the main program's frame doesn't count as a call
+                let max = self.budget.max_call_depth(MAX_FRAMES);
+                if self.frames.len() > max {
+                    return Err(limits::too_deep(max));
+                }
+                let base_pointer = callee_index + 1;
+                let stack_top = base_pointer + function.num_locals;
+                if stack_top > STACK_SIZE {
+                    return Err(stack_overflow());
+                }
+                self.stack.resize(stack_top, Object::Null);
+                self.frames.push(Frame::new(closure, base_pointer));
+                Ok(())
+            }
+            Object::BuiltIn(builtin) => {
+                let args = self.stack.split_off(callee_index + 1);
+                self.stack.pop();
+                let result = check(builtin.get_fn(args, self))?;
+                self.push(result)
+            }
+            Object::Native(native) => {
+                let args = self.stack.split_off(callee_index + 1);
+                self.stack.pop();
+                let result = check(native.call(args))?;
+                self.push(result)
+            }
+            _ => Err(RuntimeError::new(
+                ErrorCode::NotCallable,
+                "not a function".to_string(),
+            )),
+        }
+    }
+
+    // Whether the current function returns straight after the instruction
+    // being run, perhaps after jumping out of an `if`. It can't inside a
+    // `try`, whose handler needs the frame.
+    fn returns_next(&self) -> bool {
+        if matches!(self.handlers.last(), Some(handler) if handler.frames == self.frames.len()) {
+            return false;
+        }
+        let frame = self.frames.last().expect("vm has no frame");
+        let instructions = &frame.closure.function.instructions;
+        let mut ip = frame.ip;
+        // jumps only go backwards to loop, so there are at most this many
+        for _ in 0..instructions.len() {
+            match instructions.get(ip).map(|&op| Opcode::try_from(op)) {
+                Some(Ok(Opcode::ReturnValue)) => return true,
+                Some(Ok(Opcode::Jump)) => ip = code::read_u16(&instructions[ip + 1..]) as usize,
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    // Runs a call the current function returns the value of in that
+    // function's frame, which the call's value would only pass through, so
+    // tail recursion runs in constant space.
+    fn tail_call(&mut self, closure: Rc<Closure>, callee_index: usize) -> Result<(), RuntimeError> {
+        let base_pointer = self.current_frame().base_pointer;
+        let call = self.stack.split_off(callee_index);
+        self.stack.truncate(base_pointer - 1);
+        self.stack.extend(call);
+        let stack_top = base_pointer + closure.function.num_locals;
+        if stack_top > STACK_SIZE {
+            return Err(stack_overflow());
+        }
+        self.stack.resize(stack_top, Object::Null);
+        *self.current_frame() = Frame::new(closure, base_pointer);
+        Ok(())
+    }
+
+    fn current_frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("vm has no frame")
+    }
+
+    fn push(&mut self, obj: Object) -> Result<(), RuntimeError> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(stack_overflow());
+        }
+        self.stack.push(obj);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Object {
+        self.stack.pop().expect("vm stack underflow")
+    }
+}
+
+impl CallContext for Vm {
+    fn apply(&mut self, function: &Object, args: Vec<Object>) -> Result<Object, RuntimeError> {
+        let depth = self.frames.len();
+        // a builtin calling back nests Rust calls, like the evaluator does
+        limits::check_stack(depth)?;
+        let num_args = args.len();
+        self.push(function.clone())?;
+        for arg in args {
+            self.push(arg)?;
+        }
+        // the frame on top is the builtin's caller, which still needs it
+        self.call(num_args, false)?;
+        self.execute(depth)?;
+        Ok(self.pop())
+    }
+
+    fn output(&self) -> Sink {
+        self.output.clone()
+    }
+
+    fn error_output(&self) -> Sink {
+        self.error_output.clone()
+    }
+
+    fn allocate(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        self.budget.allocate(bytes)
+    }
+}
+
+fn build_hash(elements: Vec<Object>) -> Result<Object, RuntimeError> {
+    let mut map = HashTable::new();
+    let mut elements = elements.into_iter();
+    while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+        match object::create_hash_key(key.clone()) {
+            Some(hash_key) => {
+                map.insert(hash_key, HashPair::new(key, value));
+            }
+            None => {
+                let msg = format!("unusable as hash key: {}", key.obj_type());
+                return Err(RuntimeError::new(ErrorCode::UnhashableKey, msg));
+            }
+        }
+    }
+    Ok(Object::hash(map))
+}
+
+// Variables captured by a closure live in cells; reading one yields its value.
+fn read_cell(obj: &Object) -> Object {
+    match obj {
+        Object::Cell(cell) => cell.borrow().clone(),
+        _ => obj.clone(),
+    }
+}
+
+fn check(obj: Object) -> Result<Object, RuntimeError> {
+    match obj {
+        Object::Error(err) => Err(err),
+        _ => Ok(obj),
+    }
+}
+
+fn stack_overflow() -> RuntimeError {
+    let msg = format!(
+        "stack overflow: more than {} values on the stack",
+        STACK_SIZE
+    );
+    RuntimeError::new(ErrorCode::StackOverflow, msg)
+}